@@ -1,11 +1,13 @@
-use super::production::Production;
-use super::symbol::{SymbolDb};
+use std::fmt;
 
-#[derive(Debug,Eq,PartialEq)]
+use super::grammar::Grammar;
+use super::production::ProductionId;
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
 pub enum Action {
     Accept,
     Shift(u32),
-    Reduce(Production)
+    Reduce(ProductionId)
 }
 
 impl Action {
@@ -17,16 +19,27 @@ impl Action {
         Action::Shift(state)
     }
 
-    pub fn reduce(p: Production) -> Action {
+    pub fn reduce(p: ProductionId) -> Action {
         Action::Reduce(p)
     }
 
-    #[allow(dead_code)]
-    pub fn to_string(&self, symbol_db: &SymbolDb) -> String {
-        match self {
-            Action::Accept => "Accept".to_string(),
-            Action::Shift(n) => format!("Shift({})", n),
-            Action::Reduce(p) => format!("Reduce({})", p.to_string(symbol_db)),
+    // something that formats the action, naming the production of a reduction
+    pub fn display<'a>(&'a self, grammar: &'a Grammar) -> ActionDisplay<'a> {
+        ActionDisplay { action: self, grammar }
+    }
+}
+
+pub struct ActionDisplay<'a> {
+    action: &'a Action,
+    grammar: &'a Grammar,
+}
+
+impl fmt::Display for ActionDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.action {
+            Action::Accept => write!(f, "Accept"),
+            Action::Shift(n) => write!(f, "Shift({})", n),
+            Action::Reduce(p) => write!(f, "Reduce({})", self.grammar.display_production(*p)),
         }
     }
 }
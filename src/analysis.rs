@@ -0,0 +1,23 @@
+pub mod ambiguity;
+pub mod animation;
+pub mod conflict_profile;
+pub mod cyk;
+pub mod diff;
+pub mod equivalence;
+pub mod explain;
+pub mod explorer;
+pub mod highlight;
+pub mod sentences;
+pub mod usage;
+
+pub use self::ambiguity::{find_ambiguities,Ambiguity,AmbiguityReport};
+pub use self::animation::{ParseTrace,TraceFrame};
+pub use self::conflict_profile::{conflict_profile,conflict_profile_text,ConflictProfile};
+pub use self::cyk::{count_parses,recognizes,ParseCount};
+pub use self::diff::{diff,table_diff,ActionChange,GrammarDiff,TableDiff};
+pub use self::equivalence::{check_equivalence,EquivalenceReport};
+pub use self::explain::{explain_first,explain_follow,explain_item,Explanation,Step};
+pub use self::explorer::automaton_html;
+pub use self::highlight::{HighlightInfo,TokenCategory};
+pub use self::sentences::sentences;
+pub use self::usage::{symbol_usage,ReduceSite,ShiftSite,SymbolUsage};
@@ -0,0 +1,213 @@
+use std::fmt::Write;
+
+use crate::analysis::cyk::{Chart,ParseCount};
+use crate::analysis::sentences::sentences;
+use crate::grammar::Grammar;
+use crate::parse_tables::{Conflict,ParseTables};
+use crate::parse_tree::ParseTree;
+use crate::symbol::{Symbol,SymbolDb};
+
+#[derive(Debug)]
+pub struct Ambiguity {
+    pub sentence: Vec<Symbol>,
+    pub derivations: (ParseTree<()>, ParseTree<()>),
+}
+
+impl Ambiguity {
+    pub fn to_string(&self, symbol_db: &SymbolDb) -> String {
+        let mut result = String::new();
+        let sentence: Vec<&str> = self.sentence.iter().map(|s| symbol_db.label(s).unwrap().as_str()).collect();
+        writeln!(&mut result, "ambiguous sentence: {}", sentence.join(" ")).unwrap();
+        writeln!(&mut result, "  {}", self.derivations.0.display(symbol_db)).unwrap();
+        writeln!(&mut result, "  {}", self.derivations.1.display(symbol_db)).unwrap();
+        result
+    }
+}
+
+#[derive(Debug)]
+pub struct AmbiguityReport {
+    pub conflicts: Vec<Conflict>,
+    pub ambiguities: Vec<Ambiguity>,
+}
+
+// look for concrete evidence of ambiguity. a grammar whose lr(1) tables have no conflicts is
+// unambiguous, so the search only happens when there are conflicts. in that case the sentences of
+// up to max_length symbols are enumerated (shortest first) and each one is checked for more than
+// one parse. at most max_reports ambiguous sentences are reported, each with two of its
+// derivations. not finding anything doesn't prove the grammar is unambiguous, since the
+// ambiguity may only show up in longer sentences
+pub fn find_ambiguities(grammar: &Grammar, max_length: usize, max_reports: usize) -> AmbiguityReport {
+    let conflicts = ParseTables::new(grammar).conflicts().clone();
+    let mut ambiguities = Vec::new();
+
+    if !conflicts.is_empty() {
+        for sentence in sentences(grammar, max_length) {
+            if ambiguities.len() >= max_reports {
+                break;
+            }
+            let chart = Chart::new(grammar, &sentence);
+            if !chart.count(grammar.start_symbol(), 0, sentence.len()).is_ambiguous() {
+                continue;
+            }
+            let finder = DerivationFinder::new(grammar, &chart, sentence.len());
+            let mut trees = finder.trees(grammar.start_symbol(), 0, sentence.len(), finder.max_depth);
+            if trees.len() >= 2 {
+                let second = trees.pop().unwrap();
+                let first = trees.pop().unwrap();
+                ambiguities.push(Ambiguity { sentence, derivations: (first, second) });
+            }
+        }
+    }
+
+    AmbiguityReport { conflicts, ambiguities }
+}
+
+// pulls (up to two) distinct derivation trees out of a filled in chart
+struct DerivationFinder<'a> {
+    grammar: &'a Grammar,
+    chart: &'a Chart<'a>,
+    // cyclic grammars have derivations of unbounded depth, so the search has to stop somewhere
+    max_depth: usize,
+}
+
+const LIMIT: usize = 2;
+
+impl<'a> DerivationFinder<'a> {
+    fn new(grammar: &'a Grammar, chart: &'a Chart<'a>, length: usize) -> DerivationFinder<'a> {
+        let max_depth = (grammar.nonterminals().len() + 1) * (length + 1);
+        DerivationFinder { grammar, chart, max_depth }
+    }
+
+    fn trees(&self, symbol: &Symbol, i: usize, j: usize, depth: usize) -> Vec<ParseTree<()>> {
+        let mut result = Vec::new();
+        if depth == 0 || self.chart.count(symbol, i, j) == ParseCount::Finite(0) {
+            return result;
+        }
+        if self.grammar.symbol_db().is_terminal(symbol) {
+            result.push(ParseTree::new(*symbol, ()));
+            return result;
+        }
+        if let Some(ps) = self.grammar.productions(symbol) {
+            for p in ps {
+                for children in self.sequences(p.rhs(), i, j, depth - 1) {
+                    let mut t = ParseTree::new(*symbol, ());
+                    for c in children {
+                        t.add_child(c);
+                    }
+                    result.push(t);
+                    if result.len() >= LIMIT {
+                        return result;
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    // the ways the symbols rhs derive the span i..j, as lists of subtrees. ε contributes no subtree
+    fn sequences(&self, rhs: &[Symbol], i: usize, j: usize, depth: usize) -> Vec<Vec<ParseTree<()>>> {
+        let mut result = Vec::new();
+        if rhs.is_empty() {
+            if i == j {
+                result.push(Vec::new());
+            }
+            return result;
+        }
+        let x = &rhs[0];
+        for k in i..=j {
+            if self.chart.count_sequence(&rhs[1..], k, j) == ParseCount::Finite(0) {
+                continue;
+            }
+            for head in self.trees(x, i, k, depth) {
+                for rest in self.sequences(&rhs[1..], k, j, depth) {
+                    let mut children = vec![head.clone()];
+                    children.extend(rest);
+                    result.push(children);
+                    if result.len() >= LIMIT {
+                        return result;
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::production::Production;
+
+    /* grammar:
+     *   e -> e + e | x
+     */
+    #[test]
+    fn find_ambiguities_01() {
+        let mut symbol_db = SymbolDb::new();
+        let e = symbol_db.new_nonterminal("e");
+        let plus = symbol_db.new_terminal("+");
+        let x = symbol_db.new_terminal("x");
+        let productions = vec![
+            Production::new(e, vec![e, plus, e]),
+            Production::new(e, vec![x]),
+        ];
+        let g = Grammar::new(symbol_db, e, productions);
+        let report = find_ambiguities(&g, 5, 1);
+        assert!(!report.conflicts.is_empty());
+        assert_eq!(report.ambiguities.len(), 1);
+        let ambiguity = &report.ambiguities[0];
+        assert_eq!(ambiguity.sentence, vec![x, plus, x, plus, x]);
+        assert_ne!(ambiguity.derivations.0, ambiguity.derivations.1);
+        let text = ambiguity.to_string(g.symbol_db());
+        assert!(text.contains("e(e(e(x) + e(x)) + e(x))"));
+        assert!(text.contains("e(e(x) + e(e(x) + e(x)))"));
+    }
+
+    /* grammar:
+     *   list -> list pair | pair
+     *   pair -> ( pair ) | ( )
+     */
+    #[test]
+    fn find_ambiguities_02() {
+        let mut symbol_db = SymbolDb::new();
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let productions = vec![
+            Production::new(list, vec![list, pair]),
+            Production::new(list, vec![pair]),
+            Production::new(pair, vec![lp, pair, rp]),
+            Production::new(pair, vec![lp, rp]),
+        ];
+        let g = Grammar::new(symbol_db, list, productions);
+        let report = find_ambiguities(&g, 6, 10);
+        assert!(report.conflicts.is_empty());
+        assert!(report.ambiguities.is_empty());
+    }
+
+    /* grammar:
+     *   S -> X X
+     *   X -> a | ε
+     */
+    #[test]
+    fn find_ambiguities_03() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let x = symbol_db.new_nonterminal("X");
+        let a = symbol_db.new_terminal("a");
+        let productions = vec![
+            Production::new(s, vec![x, x]),
+            Production::new(x, vec![a]),
+            Production::new(x, vec![]),
+        ];
+        let g = Grammar::new(symbol_db, s, productions);
+        let report = find_ambiguities(&g, 3, 10);
+        assert_eq!(report.ambiguities.len(), 1);
+        let ambiguity = &report.ambiguities[0];
+        assert_eq!(ambiguity.sentence, vec![a]);
+        let text = ambiguity.to_string(g.symbol_db());
+        assert!(text.contains("S(X(a) X)"));
+        assert!(text.contains("S(X X(a))"));
+    }
+}
@@ -0,0 +1,233 @@
+use std::fmt::Write;
+
+use crate::parse_error::ParseError;
+use crate::parser::{ParseObserver,ParseStack};
+use crate::production::Production;
+use crate::symbol::{Symbol,SymbolDb};
+
+// one step of a recorded parse: what the parser did, the stacks after it, and how many of the
+// tokens had been shifted, which is where the remaining input starts
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct TraceFrame {
+    pub step: String,
+    pub states: Vec<u32>,
+    pub symbols: Vec<String>,
+    pub position: usize,
+}
+
+// a recording of a parse, made by registering it as an observer of Parser::parse_observed, for
+// playing the parse back a step at a time. it starts with a frame for the empty stack, and records
+// a single parse
+pub struct ParseTrace<'a> {
+    symbol_db: &'a SymbolDb,
+    frames: Vec<TraceFrame>,
+}
+
+impl<'a> ParseTrace<'a> {
+    pub fn new(symbol_db: &'a SymbolDb) -> ParseTrace<'a> {
+        let start = TraceFrame { step: "start".to_string(), states: vec![0], symbols: Vec::new(), position: 0 };
+        ParseTrace { symbol_db, frames: vec![start] }
+    }
+
+    pub fn frames(&self) -> &[TraceFrame] {
+        &self.frames
+    }
+
+    fn label(&self, s: &Symbol) -> String {
+        self.symbol_db.label(s).cloned().unwrap_or_else(|| format!("{:?}", s))
+    }
+
+    fn record<T>(&mut self, step: String, stack: &ParseStack<T>, position: usize) {
+        let symbols = stack.trees().iter().map(|t| self.label(t.symbol())).collect();
+        self.frames.push(TraceFrame { step, states: stack.states().to_vec(), symbols, position });
+    }
+
+    fn position(&self) -> usize {
+        self.frames.last().map_or(0, |f| f.position)
+    }
+
+    // a picture of a frame: the stack as a row of cells, each with a state over the symbol that
+    // led to it, and below it the input that's left, given the labels of all the tokens
+    pub fn frame_svg(&self, frame: usize, input: &[String]) -> String {
+        let frame = &self.frames[frame];
+        let width = |s: &str| 16 + 9 * s.chars().count().max(2);
+        let mut cells = String::new();
+        let mut x = 70;
+        for (i, state) in frame.states.iter().enumerate() {
+            let symbol = if i == 0 { "" } else { frame.symbols[i - 1].as_str() };
+            let w = width(symbol).max(width(&state.to_string()));
+            writeln!(&mut cells, "<rect x=\"{}\" y=\"40\" width=\"{}\" height=\"50\" fill=\"#def\" stroke=\"#369\"/>", x, w).unwrap();
+            writeln!(&mut cells, "<text x=\"{}\" y=\"58\" text-anchor=\"middle\" fill=\"#369\">{}</text>", x + w / 2, state).unwrap();
+            writeln!(&mut cells, "<text x=\"{}\" y=\"80\" text-anchor=\"middle\">{}</text>", x + w / 2, xml_text(symbol)).unwrap();
+            x += w + 4;
+        }
+        let stack_end = x;
+        x = 70;
+        for (i, token) in input.iter().enumerate().skip(frame.position) {
+            let w = width(token);
+            let fill = if i == frame.position { "#fed" } else { "#fff" };
+            writeln!(&mut cells, "<rect x=\"{}\" y=\"110\" width=\"{}\" height=\"30\" fill=\"{}\" stroke=\"#963\"/>", x, w, fill).unwrap();
+            writeln!(&mut cells, "<text x=\"{}\" y=\"130\" text-anchor=\"middle\">{}</text>", x + w / 2, xml_text(token)).unwrap();
+            x += w + 4;
+        }
+        let end = stack_end.max(x).max(10 + 9 * frame.step.chars().count());
+        format!("\
+<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"160\" font-family=\"monospace\" font-size=\"14\">
+<text x=\"10\" y=\"22\">{}</text>
+<text x=\"10\" y=\"70\">stack</text>
+<text x=\"10\" y=\"130\">input</text>
+{}</svg>
+", end + 10, xml_text(&frame.step), cells)
+    }
+
+    // a self-contained html page that plays the parse back: a picture of each frame (see
+    // frame_svg), with buttons to step through them or play them in turn. input is the labels of
+    // the tokens that were parsed, for showing what's left of the input
+    pub fn to_html(&self, input: &[String]) -> String {
+        let mut frames = String::new();
+        for i in 0..self.frames.len() {
+            writeln!(&mut frames, "<div class=\"frame\">\n<p>step {} of {}</p>\n{}</div>", i, self.frames.len() - 1, self.frame_svg(i, input)).unwrap();
+        }
+        format!("{}{}{}", PAGE_START, frames, PAGE_END)
+    }
+}
+
+impl<T> ParseObserver<T> for ParseTrace<'_> {
+    fn shift(&mut self, stack: &ParseStack<T>, _: &T) {
+        let symbol = self.label(stack.trees().last().unwrap().symbol());
+        let state = stack.states().last().unwrap();
+        let position = self.position() + 1;
+        self.record(format!("shift {}, go to state {}", symbol, state), stack, position);
+    }
+
+    fn reduce(&mut self, stack: &ParseStack<T>, production: &Production, _: &T) {
+        let rhs: Vec<String> = production.rhs().iter().map(|s| self.label(s)).collect();
+        let rhs = if rhs.is_empty() { self.label(&self.symbol_db.epsilon()) } else { rhs.join(" ") };
+        let step = format!("reduce {} -> {}, go to state {}", self.label(production.lhs()), rhs, stack.states().last().unwrap());
+        let position = self.position();
+        self.record(step, stack, position);
+    }
+
+    fn error(&mut self, stack: &ParseStack<T>, error: &ParseError, _: Option<&T>) {
+        self.record(error.to_string(self.symbol_db), stack, error.position());
+    }
+}
+
+// text for an svg or html element
+fn xml_text(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+const PAGE_START: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>LR(1) parse</title>
+<style>
+body { font-family: sans-serif; margin: 1em; }
+.frame { display: none; }
+.frame.shown { display: block; }
+button { margin-right: 0.5em; }
+</style>
+</head>
+<body>
+<div>
+<button id="first">first</button><button id="back">back</button><button id="play">play</button><button id="next">next</button><button id="last">last</button>
+</div>
+"#;
+
+const PAGE_END: &str = r#"<script>
+const frames = document.getElementsByClassName("frame");
+let shown = 0;
+let timer = null;
+
+function show(n) {
+  frames[shown].classList.remove("shown");
+  shown = Math.max(0, Math.min(frames.length - 1, n));
+  frames[shown].classList.add("shown");
+}
+
+function stop() {
+  clearInterval(timer);
+  timer = null;
+  document.getElementById("play").textContent = "play";
+}
+
+document.getElementById("first").onclick = () => { stop(); show(0); };
+document.getElementById("back").onclick = () => { stop(); show(shown - 1); };
+document.getElementById("next").onclick = () => { stop(); show(shown + 1); };
+document.getElementById("last").onclick = () => { stop(); show(frames.length - 1); };
+document.getElementById("play").onclick = () => {
+  if (timer !== null) {
+    stop();
+    return;
+  }
+  if (shown === frames.length - 1) {
+    show(0);
+  }
+  document.getElementById("play").textContent = "pause";
+  timer = setInterval(() => {
+    show(shown + 1);
+    if (shown === frames.length - 1) {
+      stop();
+    }
+  }, 1000);
+};
+show(0);
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::parser::{ParseObservers,Parser};
+
+    /* grammar:
+     *   S -> ( S ) | x
+     */
+    #[test]
+    fn parse_trace_01() {
+        let p = Parser::new(Grammar::from_bnf("S -> ( S ) | x", "g").unwrap());
+        let db = p.grammar().symbol_db();
+        let labels: Vec<String> = "( x ) $".split(' ').map(|s| s.to_string()).collect();
+        let tokens_of = |text: &str| -> Vec<Symbol> { text.split(' ').map(|l| db.symbol(l).unwrap()).collect() };
+        let tokens = tokens_of("( x ) $");
+        let mut trace = ParseTrace::new(db);
+        let mut observers = ParseObservers::new();
+        observers.register(&mut trace);
+        p.parse_observed(tokens, |s| *s, &mut observers).unwrap();
+        drop(observers);
+
+        let frames = trace.frames();
+        assert_eq!(frames.len(), 6);
+        assert_eq!(frames[0].states, vec![0]);
+        assert!(frames[1].step.starts_with("shift (, go to state "));
+        assert_eq!(frames[3].symbols, vec!["(", "S"]);
+        assert!(frames[3].step.starts_with("reduce S -> x"));
+        assert_eq!(frames[3].position, 2);
+        assert_eq!(frames[5].symbols, vec!["S"]);
+        assert_eq!(frames[5].position, 3);
+
+        let svg = trace.frame_svg(2, &labels);
+        // the stack is the start state and two cells, and ) and $ are left
+        assert_eq!(svg.matches("<rect").count(), 3 + 2);
+        assert!(svg.contains("y=\"130\" text-anchor=\"middle\">)</text>"));
+        assert!(!svg.contains("y=\"130\" text-anchor=\"middle\">x</text>"));
+
+        let html = trace.to_html(&labels);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert_eq!(html.matches("<svg").count(), 6);
+        assert!(html.contains("<p>step 5 of 5</p>"));
+
+        let mut trace = ParseTrace::new(db);
+        let mut observers = ParseObservers::new();
+        observers.register(&mut trace);
+        assert!(p.parse_observed(vec![tokens_of("(")[0], db.eoi()], |s| *s, &mut observers).is_err());
+        drop(observers);
+        assert!(trace.frames().last().unwrap().step.starts_with("unexpected $ at position 1"));
+        assert_eq!(xml_text("a<b&c"), "a&lt;b&amp;c");
+    }
+}
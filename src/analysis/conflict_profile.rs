@@ -0,0 +1,117 @@
+use std::collections::{BTreeMap,BTreeSet};
+use std::fmt::Write;
+
+use crate::canonical_collection::CanonicalCollection;
+use crate::diagnostics::Diagnostics;
+use crate::grammar::Grammar;
+use crate::parse_tables::{self,Conflict};
+use crate::production::ProductionId;
+
+// how often a production takes part in the conflicts of the lr(1) tables. a production takes part
+// in a conflict when it's one of the reductions, or when one of its items in the state shifts the
+// lookahead the conflict is on
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct ConflictProfile {
+    pub production: ProductionId,
+    pub conflicts: usize,
+    // the ones where it's reduced, kept or dropped
+    pub reductions: usize,
+    // the ones where it's shifted
+    pub shifts: usize,
+    pub states: BTreeSet<u32>,
+}
+
+// the productions that take part in conflicts, the ones in the most conflicts first (and in the
+// order of the grammar when they're in the same number)
+pub fn conflict_profile(grammar: &Grammar) -> Vec<ConflictProfile> {
+    let cc = CanonicalCollection::new(grammar);
+    let tables = parse_tables::from_collection(grammar, &cc, &mut Diagnostics::new());
+
+    let mut profiles: BTreeMap<ProductionId,ConflictProfile> = BTreeMap::new();
+    let mut count = |production: ProductionId, state: u32, shifted: bool, reduced: bool| {
+        let profile = profiles.entry(production).or_insert_with(|| {
+            ConflictProfile { production, conflicts: 0, reductions: 0, shifts: 0, states: BTreeSet::new() }
+        });
+        profile.conflicts += 1;
+        profile.shifts += shifted as usize;
+        profile.reductions += reduced as usize;
+        profile.states.insert(state);
+    };
+    for c in tables.conflicts() {
+        let (shifted, reduced, state) = match c {
+            Conflict::ShiftReduce { state, symbol, production } => {
+                // the productions whose items in the state have the dot before the lookahead
+                let shifted: BTreeSet<ProductionId> = cc.sets()[state].iter()
+                    .filter(|item| item.symbols_after_dot().first() == Some(symbol))
+                    .filter_map(|item| grammar.production_id(item.production()))
+                    .collect();
+                (shifted, BTreeSet::from([*production]), *state)
+            },
+            Conflict::ReduceReduce { state, kept, dropped, .. } => (BTreeSet::new(), BTreeSet::from([*kept, *dropped]), *state),
+        };
+        // a production that's both shifted and reduced in the conflict still only counts once
+        for p in shifted.union(&reduced) {
+            count(*p, state, shifted.contains(p), reduced.contains(p));
+        }
+    }
+
+    let mut result: Vec<ConflictProfile> = profiles.into_values().collect();
+    // stable, so ties stay in the order of the grammar
+    result.sort_by_key(|profile| std::cmp::Reverse(profile.conflicts));
+    result
+}
+
+// the profile as a table, a production to a line
+pub fn conflict_profile_text(grammar: &Grammar, profile: &[ConflictProfile]) -> String {
+    let mut result = String::new();
+    writeln!(&mut result, "conflicts  shifts  reductions  states  production").unwrap();
+    for p in profile {
+        writeln!(&mut result, "{:>9}  {:>6}  {:>10}  {:>6}  {}",
+                 p.conflicts, p.shifts, p.reductions, p.states.len(), grammar.display_production(p.production)).unwrap();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_tables::ParseTables;
+
+    /* grammar:
+     *   e -> e + e | e * e | ( e ) | x
+     */
+    #[test]
+    fn conflict_profile_01() {
+        let g = Grammar::from_bnf("e -> e + e | e * e | ( e ) | x", "g").unwrap();
+        let profile = conflict_profile(&g);
+        let text = conflict_profile_text(&g, &profile);
+        // a state after e + e and one after e * e, each with a conflict on + and on *, inside
+        // parentheses and outside them
+        let conflicts = ParseTables::new(&g).conflicts().len();
+        assert_eq!(conflicts, 8);
+
+        // only the two binary productions are in conflicts
+        assert_eq!(profile.len(), 2);
+        let plus = g.production_id(&g.productions(g.start_symbol()).unwrap()[0]).unwrap();
+        assert!(profile.iter().any(|p| p.production == plus));
+        for p in &profile {
+            assert!(p.conflicts <= conflicts);
+            assert!(p.shifts + p.reductions >= p.conflicts);
+            assert!(!p.states.is_empty());
+        }
+        // e -> e + e is reduced in the conflicts of the states after it, and shifted in the
+        // conflicts on + in the states after e * e as well as after itself
+        let p = profile.iter().find(|p| p.production == plus).unwrap();
+        assert_eq!((p.conflicts, p.shifts, p.reductions, p.states.len()), (6, 4, 4, 4));
+        assert_eq!(text.lines().count(), 3);
+    }
+
+    /* grammar:
+     *   S -> a
+     */
+    #[test]
+    fn conflict_profile_02() {
+        let g = Grammar::from_bnf("S -> a", "g").unwrap();
+        assert!(conflict_profile(&g).is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::ops::{Add,Mul};
+
+use crate::grammar::Grammar;
+use crate::symbol::Symbol;
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum ParseCount {
+    Finite(u128),
+    // the sentence has infinitely many parses (the grammar has a cycle of unit or ε productions
+    // that can be pumped), or more parses than can be counted
+    Unbounded,
+}
+
+impl ParseCount {
+    pub fn is_ambiguous(&self) -> bool {
+        *self != ParseCount::Finite(0) && *self != ParseCount::Finite(1)
+    }
+}
+
+impl Add for ParseCount {
+    type Output = ParseCount;
+    fn add(self, other: ParseCount) -> ParseCount {
+        match (self, other) {
+            (ParseCount::Finite(a), ParseCount::Finite(b)) => {
+                a.checked_add(b).map(ParseCount::Finite).unwrap_or(ParseCount::Unbounded)
+            },
+            _ => ParseCount::Unbounded,
+        }
+    }
+}
+
+impl Mul for ParseCount {
+    type Output = ParseCount;
+    fn mul(self, other: ParseCount) -> ParseCount {
+        match (self, other) {
+            (ParseCount::Finite(0), _) | (_, ParseCount::Finite(0)) => ParseCount::Finite(0),
+            (ParseCount::Finite(a), ParseCount::Finite(b)) => {
+                a.checked_mul(b).map(ParseCount::Finite).unwrap_or(ParseCount::Unbounded)
+            },
+            _ => ParseCount::Unbounded,
+        }
+    }
+}
+
+// count the distinct parse trees of the sentence (without the trailing $) for the grammar's start
+// symbol. a count greater than one proves that the grammar is ambiguous
+pub fn count_parses(grammar: &Grammar, sentence: &[Symbol]) -> ParseCount {
+    let chart = Chart::new(grammar, sentence);
+    chart.count(grammar.start_symbol(), 0, sentence.len())
+}
+
+pub fn recognizes(grammar: &Grammar, sentence: &[Symbol]) -> bool {
+    count_parses(grammar, sentence) != ParseCount::Finite(0)
+}
+
+// a cyk style chart holding, for every nonterminal A and every span i..j of the sentence, the
+// number of ways A derives sentence[i..j]. rather than requiring the grammar to be in chomsky
+// normal form, productions of any length are handled by splitting the span across the right hand
+// side, and unit and ε productions are handled by iterating each span to a fixed point
+pub(crate) struct Chart<'a> {
+    grammar: &'a Grammar,
+    sentence: &'a [Symbol],
+    index: HashMap<Symbol,usize>,
+    table: Vec<ParseCount>,
+}
+
+impl<'a> Chart<'a> {
+    pub(crate) fn new(grammar: &'a Grammar, sentence: &'a [Symbol]) -> Chart<'a> {
+        let nonterminals: Vec<Symbol> = grammar.nonterminals().iter().collect();
+        let index = nonterminals.iter().enumerate().map(|(i, s)| (*s, i)).collect();
+        let n = sentence.len() + 1;
+        let mut chart = Chart {
+            grammar,
+            sentence,
+            index,
+            table: vec![ParseCount::Finite(0); nonterminals.len() * n * n],
+        };
+
+        // process spans from shortest to longest so that every span strictly inside the current
+        // one already has its final count
+        for length in 0..n {
+            for i in 0..(n - length) {
+                chart.fill(&nonterminals, i, i + length);
+            }
+        }
+
+        chart
+    }
+
+    fn slot(&self, nt: usize, i: usize, j: usize) -> usize {
+        let n = self.sentence.len() + 1;
+        (nt * n + i) * n + j
+    }
+
+    pub(crate) fn count(&self, symbol: &Symbol, i: usize, j: usize) -> ParseCount {
+        let symbol_db = self.grammar.symbol_db();
+        if symbol_db.is_terminal(symbol) {
+            ParseCount::Finite(if j == i + 1 && self.sentence[i] == *symbol { 1 } else { 0 })
+        } else {
+            match self.index.get(symbol) {
+                Some(&nt) => self.table[self.slot(nt, i, j)],
+                None => ParseCount::Finite(0),
+            }
+        }
+    }
+
+    // the number of ways the symbols rhs derive sentence[i..j]
+    pub(crate) fn count_sequence(&self, rhs: &[Symbol], i: usize, j: usize) -> ParseCount {
+        // ways[k - i] is the number of ways the symbols processed so far derive sentence[i..k]
+        let mut ways = vec![ParseCount::Finite(0); j - i + 1];
+        ways[0] = ParseCount::Finite(1);
+        for x in rhs {
+            let mut next = vec![ParseCount::Finite(0); j - i + 1];
+            for k in i..=j {
+                if ways[k - i] == ParseCount::Finite(0) {
+                    continue;
+                }
+                for l in k..=j {
+                    next[l - i] = next[l - i] + ways[k - i] * self.count(x, k, l);
+                }
+            }
+            ways = next;
+        }
+        ways[j - i]
+    }
+
+    fn fill(&mut self, nonterminals: &[Symbol], i: usize, j: usize) {
+        // a nonterminal's count for this span can depend on the counts of other nonterminals for
+        // the same span (through unit productions, or productions where everything else derives
+        // ε). without cycles, the counts settle after at most one round per nonterminal. if they
+        // are still changing after that, then any count that keeps changing sits on or after a
+        // cycle and is unbounded
+        let rounds = nonterminals.len() + 1;
+        let mut settled = false;
+        for _ in 0..rounds {
+            if !self.round(nonterminals, i, j, false) {
+                settled = true;
+                break;
+            }
+        }
+        if !settled {
+            for _ in 0..rounds {
+                self.round(nonterminals, i, j, true);
+            }
+        }
+    }
+
+    fn round(&mut self, nonterminals: &[Symbol], i: usize, j: usize, mark_unbounded: bool) -> bool {
+        let mut changed = false;
+        for (n, nt) in nonterminals.iter().enumerate() {
+            let mut total = ParseCount::Finite(0);
+            if let Some(ps) = self.grammar.productions(nt) {
+                for p in ps {
+                    total = total + self.count_sequence(p.rhs(), i, j);
+                }
+            }
+            let slot = self.slot(n, i, j);
+            if self.table[slot] != total {
+                changed = true;
+                self.table[slot] = if mark_unbounded { ParseCount::Unbounded } else { total };
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::production::Production;
+    use crate::symbol::SymbolDb;
+
+    /* grammar:
+     *   e -> e + e | x
+     */
+    #[test]
+    fn count_parses_01() {
+        let mut symbol_db = SymbolDb::new();
+        let e = symbol_db.new_nonterminal("e");
+        let plus = symbol_db.new_terminal("+");
+        let x = symbol_db.new_terminal("x");
+        let productions = vec![
+            Production::new(e, vec![e, plus, e]),
+            Production::new(e, vec![x]),
+        ];
+        let g = Grammar::new(symbol_db, e, productions);
+        assert_eq!(count_parses(&g, &[x]), ParseCount::Finite(1));
+        assert_eq!(count_parses(&g, &[x, plus, x]), ParseCount::Finite(1));
+        assert_eq!(count_parses(&g, &[x, plus, x, plus, x]), ParseCount::Finite(2));
+        // the catalan numbers
+        assert_eq!(count_parses(&g, &[x, plus, x, plus, x, plus, x]), ParseCount::Finite(5));
+        assert_eq!(count_parses(&g, &[x, plus]), ParseCount::Finite(0));
+        assert!(count_parses(&g, &[x, plus, x, plus, x]).is_ambiguous());
+        assert!(!recognizes(&g, &[plus]));
+    }
+
+    /* grammar:
+     *   S -> X X
+     *   X -> a | ε
+     */
+    #[test]
+    fn count_parses_02() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let x = symbol_db.new_nonterminal("X");
+        let a = symbol_db.new_terminal("a");
+        let productions = vec![
+            Production::new(s, vec![x, x]),
+            Production::new(x, vec![a]),
+            Production::new(x, vec![]),
+        ];
+        let g = Grammar::new(symbol_db, s, productions);
+        assert_eq!(count_parses(&g, &[]), ParseCount::Finite(1));
+        assert_eq!(count_parses(&g, &[a]), ParseCount::Finite(2));
+        assert_eq!(count_parses(&g, &[a, a]), ParseCount::Finite(1));
+        assert_eq!(count_parses(&g, &[a, a, a]), ParseCount::Finite(0));
+    }
+
+    /* grammar:
+     *   S -> S | a | T b
+     *   T -> a
+     */
+    #[test]
+    fn count_parses_03() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let t = symbol_db.new_nonterminal("T");
+        let a = symbol_db.new_terminal("a");
+        let b = symbol_db.new_terminal("b");
+        let productions = vec![
+            Production::new(s, vec![s]),
+            Production::new(s, vec![a]),
+            Production::new(s, vec![t, b]),
+            Production::new(t, vec![a]),
+        ];
+        let g = Grammar::new(symbol_db, s, productions);
+        assert_eq!(count_parses(&g, &[a]), ParseCount::Unbounded);
+        assert_eq!(count_parses(&g, &[b]), ParseCount::Finite(0));
+        assert!(recognizes(&g, &[a, b]));
+    }
+}
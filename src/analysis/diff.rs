@@ -0,0 +1,436 @@
+use std::collections::{BTreeMap,BTreeSet,VecDeque};
+use std::fmt;
+
+use crate::action::Action;
+use crate::grammar::Grammar;
+use crate::parse_tables::ParseTables;
+use crate::production::rules;
+use crate::symbol::Symbol;
+
+// the two grammars being compared have their own symbol databases, so productions are matched up
+// by the labels of their symbols rather than by the symbols themselves
+type LabeledProduction = (String, Vec<String>);
+
+#[derive(Debug,Eq,PartialEq)]
+pub struct GrammarDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+    pub start_symbols: (String, String),
+    pub states: (usize, usize),
+    pub conflicts: (usize, usize),
+}
+
+impl GrammarDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() &&
+        self.removed.is_empty() &&
+        self.start_symbols.0 == self.start_symbols.1 &&
+        self.states.0 == self.states.1 &&
+        self.conflicts.0 == self.conflicts.1
+    }
+}
+
+// compare two versions of a grammar. productions are reported as added or removed, nonterminals
+// whose set of alternatives differs are reported as changed, and both grammars are compiled so
+// that the effect of the change on the automaton can be seen as well
+pub fn diff(old: &Grammar, new: &Grammar) -> GrammarDiff {
+    let old_productions = labeled_productions(old);
+    let new_productions = labeled_productions(new);
+
+    let added: Vec<String> = new_productions.difference(&old_productions).map(render).collect();
+    let removed: Vec<String> = old_productions.difference(&new_productions).map(render).collect();
+
+    // a nonterminal has changed if it appears in both grammars but its alternatives don't agree
+    let old_lhs: BTreeSet<&String> = old_productions.iter().map(|(lhs,_)| lhs).collect();
+    let new_lhs: BTreeSet<&String> = new_productions.iter().map(|(lhs,_)| lhs).collect();
+    let changed: Vec<String> = old_lhs.intersection(&new_lhs)
+        .filter(|lhs| {
+            let a = old_productions.iter().filter(|(l,_)| l == **lhs);
+            let b = new_productions.iter().filter(|(l,_)| l == **lhs);
+            !a.eq(b)
+        })
+        .map(|lhs| lhs.to_string())
+        .collect();
+
+    let label = |g: &Grammar| g.symbol_db().label(g.start_symbol()).unwrap().clone();
+    let start_symbols = (label(old), label(new));
+
+    let old_tables = ParseTables::new(old);
+    let new_tables = ParseTables::new(new);
+
+    GrammarDiff {
+        added,
+        removed,
+        changed,
+        start_symbols,
+        states: (old_tables.state_count(), new_tables.state_count()),
+        conflicts: (old_tables.conflicts().len(), new_tables.conflicts().len()),
+    }
+}
+
+fn labeled_productions(grammar: &Grammar) -> BTreeSet<LabeledProduction> {
+    let symbol_db = grammar.symbol_db();
+    let mut result = BTreeSet::new();
+    for p in grammar.all_productions() {
+        // the goal production is synthesized from the start symbol, which is compared separately
+        if *p.lhs() == symbol_db.goal() {
+            continue;
+        }
+        let lhs = symbol_db.label(p.lhs()).unwrap().clone();
+        let rhs = p.rhs().iter().map(|s| symbol_db.label(s).unwrap().clone()).collect();
+        result.insert((lhs, rhs));
+    }
+    result
+}
+
+fn render(p: &LabeledProduction) -> String {
+    if p.1.is_empty() {
+        format!("{} -> ε", p.0)
+    } else {
+        format!("{} -> {}", p.0, p.1.join("  "))
+    }
+}
+
+// the productions as rules, each on its own line
+fn write_rules(f: &mut fmt::Formatter, productions: &[String]) -> fmt::Result {
+    for rule in rules(productions.iter().filter_map(|p| p.split_once(" -> "))) {
+        writeln!(f, "    {}", rule)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for GrammarDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "grammar diff:")?;
+        if self.start_symbols.0 != self.start_symbols.1 {
+            writeln!(f, "  start symbol = {} -> {}", self.start_symbols.0, self.start_symbols.1)?;
+        }
+        writeln!(f, "  added =")?;
+        write_rules(f, &self.added)?;
+        writeln!(f, "  removed =")?;
+        write_rules(f, &self.removed)?;
+        writeln!(f, "  changed = {}", self.changed.join(", "))?;
+        writeln!(f, "  states = {} -> {}", self.states.0, self.states.1)?;
+        writeln!(f, "  conflicts = {} -> {}", self.conflicts.0, self.conflicts.1)?;
+        Ok(())
+    }
+}
+
+// an action of a state the two automata have in common that isn't the same in both. the action
+// is described by the production it reduces by, so it doesn't depend on how the productions or
+// the states are numbered. None means there's no action, so the lookahead is an error
+#[derive(Debug,Eq,PartialEq)]
+pub struct ActionChange {
+    pub states: (u32, u32),
+    pub lookahead: String,
+    pub actions: (Option<String>, Option<String>),
+}
+
+#[derive(Debug,Eq,PartialEq)]
+pub struct TableDiff {
+    pub added_states: Vec<u32>,
+    pub removed_states: Vec<u32>,
+    pub changed: Vec<ActionChange>,
+}
+
+impl TableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_states.is_empty() && self.removed_states.is_empty() && self.changed.is_empty()
+    }
+}
+
+// compare the tables compiled from two versions of a grammar. the states of the two automata are
+// numbered independently, so they're matched up by walking both at once from the start state:
+// states reached by the same symbols correspond to each other. a state only one automaton has is
+// reported as added or removed, and the actions of matching states are compared lookahead by
+// lookahead
+pub fn table_diff(old: &Grammar, old_tables: &ParseTables, new: &Grammar, new_tables: &ParseTables) -> TableDiff {
+    let old_symbols = symbols_by_label(old);
+    let new_symbols = symbols_by_label(new);
+
+    // the states that correspond to each other, both ways round
+    let mut pairs: BTreeMap<u32,u32> = BTreeMap::new();
+    let mut matched: BTreeSet<u32> = BTreeSet::new();
+    let mut queue = VecDeque::new();
+    pairs.insert(0, 0);
+    matched.insert(0);
+    queue.push_back((0, 0));
+    while let Some((a, b)) = queue.pop_front() {
+        for (label, &(x, _)) in &old_symbols {
+            let Some(&(y, _)) = new_symbols.get(label) else { continue };
+            let successors = (successor(old_tables, a, x), successor(new_tables, b, y));
+            if let (Some(c), Some(d)) = successors {
+                if !pairs.contains_key(&c) && !matched.contains(&d) {
+                    pairs.insert(c, d);
+                    matched.insert(d);
+                    queue.push_back((c, d));
+                }
+            }
+        }
+    }
+
+    let added_states = (0..new_tables.state_count() as u32).filter(|s| !matched.contains(s)).collect();
+    let removed_states = (0..old_tables.state_count() as u32).filter(|s| !pairs.contains_key(s)).collect();
+
+    let lookaheads: BTreeSet<&String> = old_symbols.iter().chain(&new_symbols)
+        .filter(|(_, (_, terminal))| *terminal)
+        .map(|(label, _)| label)
+        .collect();
+    let mut changed = vec![];
+    for (&a, &b) in &pairs {
+        for &label in &lookaheads {
+            let describe = |g: &Grammar, tables: &ParseTables, symbols: &BTreeMap<String,(Symbol,bool)>, state: u32| {
+                let (symbol, _) = symbols.get(label)?;
+                tables.action(state, *symbol).map(|a| describe_action(g, a))
+            };
+            let actions = (describe(old, old_tables, &old_symbols, a), describe(new, new_tables, &new_symbols, b));
+            if actions.0 != actions.1 {
+                changed.push(ActionChange { states: (a, b), lookahead: label.clone(), actions });
+            }
+        }
+    }
+
+    TableDiff { added_states, removed_states, changed }
+}
+
+// every symbol that can label a transition or be a lookahead, by its label, and whether it's a
+// terminal
+fn symbols_by_label(grammar: &Grammar) -> BTreeMap<String,(Symbol,bool)> {
+    let symbol_db = grammar.symbol_db();
+    let mut symbols: Vec<(Symbol,bool)> = symbol_db.declared_terminals().iter().map(|s| (s, true)).collect();
+    symbols.push((symbol_db.eoi(), true));
+    symbols.extend(symbol_db.declared_nonterminals().iter().map(|s| (s, false)));
+    symbols.into_iter()
+        .map(|(s, terminal)| (symbol_db.label(&s).unwrap().clone(), (s, terminal)))
+        .collect()
+}
+
+// the state reached from a state on a symbol, by a shift or a goto
+fn successor(tables: &ParseTables, state: u32, symbol: Symbol) -> Option<u32> {
+    match tables.action(state, symbol) {
+        Some(Action::Shift(to)) => Some(*to),
+        _ => tables.transition(state, symbol).copied(),
+    }
+}
+
+fn describe_action(grammar: &Grammar, action: &Action) -> String {
+    match action {
+        Action::Accept => "accept".to_string(),
+        Action::Shift(_) => "shift".to_string(),
+        Action::Reduce(p) => format!("reduce {}", grammar.display_production(*p)),
+    }
+}
+
+impl fmt::Display for TableDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let states = |states: &[u32]| states.iter().map(|s| s.to_string()).collect::<Vec<String>>().join(", ");
+        let action = |a: &Option<String>| a.clone().unwrap_or_else(|| "error".to_string());
+        writeln!(f, "table diff:")?;
+        writeln!(f, "  added states = {}", states(&self.added_states))?;
+        writeln!(f, "  removed states = {}", states(&self.removed_states))?;
+        writeln!(f, "  changed =")?;
+        for c in &self.changed {
+            writeln!(f, "    state {} -> {} on {}: was {}, now {}", c.states.0, c.states.1, c.lookahead, action(&c.actions.0), action(&c.actions.1))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::production::Production;
+    use crate::symbol::SymbolDb;
+
+    /* grammar:
+     *   list -> list pair | pair
+     *   pair -> ( pair ) | ( )
+     */
+    fn list_grammar() -> Grammar {
+        let mut symbol_db = SymbolDb::new();
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let productions = vec![
+            Production::new(list, vec![list, pair]),
+            Production::new(list, vec![pair]),
+            Production::new(pair, vec![lp, pair, rp]),
+            Production::new(pair, vec![lp, rp]),
+        ];
+        Grammar::new(symbol_db, list, productions)
+    }
+
+    #[test]
+    fn diff_01() {
+        let result = diff(&list_grammar(), &list_grammar());
+        assert!(result.is_empty());
+        assert!(result.changed.is_empty());
+    }
+
+    /* grammar:
+     *   list -> pair list | pair
+     *   pair -> ( pair ) | ( ) | x
+     */
+    #[test]
+    fn diff_02() {
+        let mut symbol_db = SymbolDb::new();
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let x = symbol_db.new_terminal("x");
+        let productions = vec![
+            Production::new(list, vec![pair, list]),
+            Production::new(list, vec![pair]),
+            Production::new(pair, vec![lp, pair, rp]),
+            Production::new(pair, vec![lp, rp]),
+            Production::new(pair, vec![x]),
+        ];
+        let g = Grammar::new(symbol_db, list, productions);
+
+        let result = diff(&list_grammar(), &g);
+        assert!(!result.is_empty());
+        assert_eq!(result.added, vec!["list -> pair  list", "pair -> x"]);
+        assert_eq!(result.removed, vec!["list -> list  pair"]);
+        assert_eq!(result.changed, vec!["list", "pair"]);
+        assert_eq!(result.conflicts, (0, 0));
+    }
+
+    /* grammar:
+     *   list -> list pair | pair | ε
+     *   pair -> ( pair ) | ( ) | [ ]
+     */
+    #[test]
+    fn diff_04() {
+        let mut symbol_db = SymbolDb::new();
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let ls = symbol_db.new_terminal("[");
+        let rs = symbol_db.new_terminal("]");
+        let productions = vec![
+            Production::new(list, vec![list, pair]),
+            Production::new(list, vec![pair]),
+            Production::new(list, vec![]),
+            Production::new(pair, vec![lp, pair, rp]),
+            Production::new(pair, vec![lp, rp]),
+            Production::new(pair, vec![ls, rs]),
+            Production::new(pair, vec![ls, pair, rs]),
+        ];
+        let g = Grammar::new(symbol_db, list, productions);
+
+        let result = diff(&list_grammar(), &g);
+        assert_eq!(result.added, vec!["list -> ε", "pair -> [  ]", "pair -> [  pair  ]"]);
+        // the alternatives of a nonterminal are shown as one rule
+        let text = result.to_string();
+        assert!(text.contains("  added =\n    list -> ε\n    pair -> [  ] | [  pair  ]\n  removed =\n  changed"));
+    }
+
+    /* grammar:
+     *   e -> e + e | x
+     */
+    #[test]
+    fn diff_03() {
+        let mut symbol_db = SymbolDb::new();
+        let e = symbol_db.new_nonterminal("e");
+        let plus = symbol_db.new_terminal("+");
+        let x = symbol_db.new_terminal("x");
+        let productions = vec![
+            Production::new(e, vec![e, plus, e]),
+            Production::new(e, vec![x]),
+        ];
+        let g = Grammar::new(symbol_db, e, productions);
+
+        let result = diff(&list_grammar(), &g);
+        assert_eq!(result.start_symbols, ("list".to_string(), "e".to_string()));
+        assert_eq!(result.conflicts.0, 0);
+        assert!(result.conflicts.1 > 0);
+        assert!(result.changed.is_empty());
+    }
+
+    /* grammar:
+     *   list -> list pair | pair
+     *   pair -> ( pair ) | ( ) | ( , )
+     */
+    #[test]
+    fn table_diff_01() {
+        let old = list_grammar();
+        let old_tables = ParseTables::new(&old);
+        assert!(table_diff(&old, &old_tables, &old, &old_tables).is_empty());
+
+        // the same grammar with its symbols declared in another order numbers the productions
+        // differently, but its automaton is the same
+        let mut symbol_db = SymbolDb::new();
+        let rp = symbol_db.new_terminal(")");
+        let lp = symbol_db.new_terminal("(");
+        let pair = symbol_db.new_nonterminal("pair");
+        let list = symbol_db.new_nonterminal("list");
+        let productions = vec![
+            Production::new(pair, vec![lp, rp]),
+            Production::new(pair, vec![lp, pair, rp]),
+            Production::new(list, vec![pair]),
+            Production::new(list, vec![list, pair]),
+        ];
+        let same = Grammar::new(symbol_db, list, productions);
+        assert!(table_diff(&old, &old_tables, &same, &ParseTables::new(&same)).is_empty());
+
+        let mut symbol_db = SymbolDb::new();
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let comma = symbol_db.new_terminal(",");
+        let productions = vec![
+            Production::new(list, vec![list, pair]),
+            Production::new(list, vec![pair]),
+            Production::new(pair, vec![lp, pair, rp]),
+            Production::new(pair, vec![lp, rp]),
+            Production::new(pair, vec![lp, comma, rp]),
+        ];
+        let new = Grammar::new(symbol_db, list, productions);
+        let new_tables = ParseTables::new(&new);
+        let result = table_diff(&old, &old_tables, &new, &new_tables);
+        assert!(result.removed_states.is_empty());
+        // after ( and after ( ( there's a shift on , and a state with the comma on its stack
+        assert_eq!(result.added_states.len(), 4);
+        assert_eq!(result.added_states.len(), new_tables.state_count() - old_tables.state_count());
+        assert_eq!(result.changed.len(), 2);
+        assert!(result.changed.iter().all(|c| c.lookahead == "," && c.actions == (None, Some("shift".to_string()))));
+        assert!(result.to_string().contains(&format!("    state {} -> {} on ,: was error, now shift", result.changed[0].states.0, result.changed[0].states.1)));
+
+        // and the other way round the states are removed
+        let result = table_diff(&new, &new_tables, &old, &old_tables);
+        assert_eq!(result.removed_states.len(), 4);
+        assert!(result.added_states.is_empty());
+    }
+
+    /* grammar:
+     *   e -> e + t | t
+     *   t -> x
+     */
+    #[test]
+    fn table_diff_02() {
+        let build = |left: bool| {
+            let mut symbol_db = SymbolDb::new();
+            let e = symbol_db.new_nonterminal("e");
+            let t = symbol_db.new_nonterminal("t");
+            let plus = symbol_db.new_terminal("+");
+            let x = symbol_db.new_terminal("x");
+            let sum = if left { vec![e, plus, t] } else { vec![t, plus, e] };
+            Grammar::new(symbol_db, e, vec![Production::new(e, sum), Production::new(e, vec![t]), Production::new(t, vec![x])])
+        };
+        let (old, new) = (build(true), build(false));
+        let (old_tables, new_tables) = (ParseTables::new(&old), ParseTables::new(&new));
+        let result = table_diff(&old, &old_tables, &new, &new_tables);
+        assert!(!result.is_empty());
+        // after a t the left recursive grammar reduces to e where the right recursive one shifts
+        // the +, and the reductions are described by their productions
+        assert!(result.changed.iter().any(|c| c.lookahead == "+" && c.actions == (Some("reduce e -> t".to_string()), Some("shift".to_string()))));
+        // and after an e only the left recursive grammar has more to shift
+        assert!(result.changed.iter().any(|c| c.lookahead == "+" && c.actions == (Some("shift".to_string()), None)));
+        assert!(result.to_string().contains(": was reduce e -> t, now shift\n"));
+    }
+}
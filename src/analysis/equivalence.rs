@@ -0,0 +1,133 @@
+use crate::analysis::sentences::sentences;
+use crate::parser::Parser;
+use crate::symbol::Symbol;
+
+#[derive(Debug,Eq,PartialEq)]
+pub struct EquivalenceReport {
+    // sentences derivable from the first grammar that the second grammar rejects
+    pub only_in_a: Vec<Vec<String>>,
+    // sentences derivable from the second grammar that the first grammar rejects
+    pub only_in_b: Vec<Vec<String>>,
+}
+
+impl EquivalenceReport {
+    pub fn is_equivalent(&self) -> bool {
+        self.only_in_a.is_empty() && self.only_in_b.is_empty()
+    }
+}
+
+// check that two grammars accept the same sentences up to max_length symbols. every sentence
+// derivable from one grammar is fed to the parser of the other, and the ones that fail to parse
+// are reported as counterexamples. this is not a proof of equivalence, but it is a practical way
+// to catch mistakes when refactoring a grammar (e.g. by left-factoring or removing left recursion)
+pub fn check_equivalence(a: &Parser, b: &Parser, max_length: usize) -> EquivalenceReport {
+    EquivalenceReport {
+        only_in_a: counterexamples(a, b, max_length),
+        only_in_b: counterexamples(b, a, max_length),
+    }
+}
+
+fn counterexamples(from: &Parser, to: &Parser, max_length: usize) -> Vec<Vec<String>> {
+    let from_db = from.grammar().symbol_db();
+    let to_db = to.grammar().symbol_db();
+    let mut result = Vec::new();
+    for sentence in sentences(from.grammar(), max_length) {
+        let labels: Vec<String> = sentence.iter().map(|s| from_db.label(s).unwrap().clone()).collect();
+        // translate the sentence into the symbols of the other grammar. a terminal that the other
+        // grammar doesn't know about means the sentence can't possibly be accepted
+        let tokens: Option<Vec<Symbol>> = labels.iter()
+            .map(|l| to_db.symbol(l).filter(|s| to_db.is_terminal(s)))
+            .chain(std::iter::once(Some(to_db.eoi())))
+            .collect();
+        let accepted = match tokens {
+            Some(tokens) => to.parse(tokens, |s: &Symbol| *s).is_ok(),
+            None => false,
+        };
+        if !accepted {
+            result.push(labels);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::production::Production;
+    use crate::symbol::SymbolDb;
+
+    /* grammar:
+     *   list -> list pair | pair
+     *   pair -> ( pair ) | ( )
+     */
+    fn left_recursive() -> Parser {
+        let mut symbol_db = SymbolDb::new();
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let productions = vec![
+            Production::new(list, vec![list, pair]),
+            Production::new(list, vec![pair]),
+            Production::new(pair, vec![lp, pair, rp]),
+            Production::new(pair, vec![lp, rp]),
+        ];
+        Parser::new(Grammar::new(symbol_db, list, productions))
+    }
+
+    /* grammar:
+     *   list -> pair list | pair
+     *   pair -> ( rest
+     *   rest -> pair ) | )
+     */
+    #[test]
+    fn check_equivalence_01() {
+        let mut symbol_db = SymbolDb::new();
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let rest = symbol_db.new_nonterminal("rest");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let productions = vec![
+            Production::new(list, vec![pair, list]),
+            Production::new(list, vec![pair]),
+            Production::new(pair, vec![lp, rest]),
+            Production::new(rest, vec![pair, rp]),
+            Production::new(rest, vec![rp]),
+        ];
+        let b = Parser::new(Grammar::new(symbol_db, list, productions));
+        let report = check_equivalence(&left_recursive(), &b, 6);
+        assert!(report.is_equivalent());
+    }
+
+    /* grammar:
+     *   list -> list pair | ε
+     *   pair -> ( pair ) | ( ) | x
+     */
+    #[test]
+    fn check_equivalence_02() {
+        let mut symbol_db = SymbolDb::new();
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let x = symbol_db.new_terminal("x");
+        let productions = vec![
+            Production::new(list, vec![list, pair]),
+            Production::new(list, vec![]),
+            Production::new(pair, vec![lp, pair, rp]),
+            Production::new(pair, vec![lp, rp]),
+            Production::new(pair, vec![x]),
+        ];
+        let b = Parser::new(Grammar::new(symbol_db, list, productions));
+        let report = check_equivalence(&left_recursive(), &b, 2);
+        assert!(!report.is_equivalent());
+        assert!(report.only_in_a.is_empty());
+        assert_eq!(report.only_in_b, vec![
+            vec![],
+            vec!["x".to_string()],
+            vec!["x".to_string(), "x".to_string()],
+        ]);
+    }
+}
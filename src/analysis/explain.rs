@@ -0,0 +1,397 @@
+use std::collections::{BTreeSet,HashMap,HashSet,VecDeque};
+use std::fmt;
+
+use super::explorer::item_text;
+use crate::canonical_collection::CanonicalCollection;
+use crate::grammar::Grammar;
+use crate::lr1_item::LR1Item;
+use crate::production::{Production,ProductionId};
+use crate::symbol::{Symbol,SymbolDb};
+
+// a step in the derivation of a fact: the fact, e.g. ( ∈ FIRST(pair), and why the algorithm
+// added it, in terms of a production and facts explained by earlier steps
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct Step {
+    pub fact: String,
+    pub reason: String,
+}
+
+// how a fact about a grammar was derived, as the steps that lead up to it, each relying only on
+// the steps before it. the last step is the fact asked about
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct Explanation {
+    pub steps: Vec<Step>,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}\n    {}", step.fact, step.reason)?;
+        }
+        Ok(())
+    }
+}
+
+// why a terminal (or ε) is in the FIRST set of a nonterminal, for checking a FIRST set worked out
+// by hand against the one the parser uses. None if it isn't in the set
+pub fn explain_first(grammar: &Grammar, nonterminal: Symbol, terminal: Symbol) -> Option<Explanation> {
+    Derivations::new(grammar).explain(Fact::First(nonterminal, terminal))
+}
+
+// why a terminal is in the FOLLOW set of a nonterminal. None if it isn't in the set
+pub fn explain_follow(grammar: &Grammar, nonterminal: Symbol, terminal: Symbol) -> Option<Explanation> {
+    Derivations::new(grammar).explain(Fact::Follow(nonterminal, terminal))
+}
+
+// why the item [production, with the dot before the symbol at dot, lookahead] is in a state of
+// the lr(1) automaton: the item the state was entered with, and the closure steps from it. None
+// if the item isn't in the state
+pub fn explain_item(grammar: &Grammar, state: u32, production: ProductionId, dot: usize, lookahead: Symbol) -> Option<Explanation> {
+    let symbol_db = grammar.symbol_db();
+    let cc = CanonicalCollection::new(grammar);
+    let items = cc.sets().get(&state)?;
+    let target = LR1Item::new(grammar.production(production).clone(), dot, lookahead);
+    if !items.contains(&target) {
+        return None;
+    }
+    let derivations = Derivations::new(grammar);
+
+    // the closure of the kernel, recording the item that added each item
+    let kernel: Vec<&LR1Item> = items.iter()
+        .filter(|item| item.dot_position() > 0 || item.production().lhs() == &symbol_db.goal())
+        .collect();
+    let mut parents: HashMap<LR1Item,LR1Item> = HashMap::new();
+    let mut queue: VecDeque<LR1Item> = kernel.iter().map(|item| (*item).clone()).collect();
+    let mut seen: HashSet<LR1Item> = queue.iter().cloned().collect();
+    while let Some(item) = queue.pop_front() {
+        let after = item.symbols_after_dot();
+        let Some(b) = after.first().filter(|s| !symbol_db.is_terminal(s)) else {
+            continue;
+        };
+        let mut rest = after[1..].to_vec();
+        rest.push(*item.lookahead());
+        for p in grammar.productions(b).into_iter().flatten() {
+            for t in derivations.first_of(&rest) {
+                let added = LR1Item::new(p.clone(), 0, t);
+                if seen.insert(added.clone()) {
+                    parents.insert(added.clone(), item.clone());
+                    queue.push_back(added);
+                }
+            }
+        }
+    }
+
+    let mut chain = vec![target];
+    while let Some(parent) = parents.get(chain.last().unwrap()) {
+        chain.push(parent.clone());
+    }
+    chain.reverse();
+
+    let mut steps = Vec::new();
+    let first = &chain[0];
+    let reason = if first.dot_position() == 0 {
+        "the start state begins with the item for the goal".to_string()
+    } else {
+        let x = first.production().rhs()[first.dot_position() - 1];
+        let before = LR1Item::new(first.production().clone(), first.dot_position() - 1, *first.lookahead());
+        let from = cc.transitions().iter()
+            .filter(|((from, s), to)| **to == state && *s == x && cc.sets()[from].contains(&before))
+            .map(|((from, _), _)| *from)
+            .min()
+            .unwrap();
+        format!("state {} has {} and goes to state {} on {}", from, item_text(&before, symbol_db), state, label(symbol_db, &x))
+    };
+    steps.push(Step { fact: format!("{} is in state {}", item_text(first, symbol_db), state), reason });
+    for pair in chain.windows(2) {
+        let (parent, item) = (&pair[0], &pair[1]);
+        let after = parent.symbols_after_dot();
+        let mut rest: Vec<String> = after[1..].iter().map(|s| label(symbol_db, s)).collect();
+        rest.push(label(symbol_db, parent.lookahead()));
+        let reason = format!("the closure of {}, which has {} after the dot, and {} ∈ FIRST({})",
+            item_text(parent, symbol_db), label(symbol_db, &after[0]), label(symbol_db, item.lookahead()), rest.join(" "));
+        steps.push(Step { fact: format!("{} is in state {}", item_text(item, symbol_db), state), reason });
+    }
+    Some(Explanation { steps })
+}
+
+#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq)]
+enum Fact {
+    First(Symbol,Symbol),
+    Follow(Symbol,Symbol),
+}
+
+// the reason a fact was added, and the facts the reason depends on
+struct Derivation {
+    reason: String,
+    premises: Vec<Fact>,
+}
+
+// the FIRST and FOLLOW sets, worked out the same way as for the parse tables but keeping the
+// first reason found for each member, so that every fact rests on facts found before it
+struct Derivations<'g> {
+    grammar: &'g Grammar,
+    first: HashMap<Symbol,BTreeSet<Symbol>>,
+    follow: HashMap<Symbol,BTreeSet<Symbol>>,
+    derivations: HashMap<Fact,Derivation>,
+}
+
+impl<'g> Derivations<'g> {
+    fn new(grammar: &'g Grammar) -> Derivations<'g> {
+        let mut d = Derivations { grammar, first: HashMap::new(), follow: HashMap::new(), derivations: HashMap::new() };
+        d.derive_first();
+        d.derive_follow();
+        d
+    }
+
+    fn label(&self, s: &Symbol) -> String {
+        label(self.grammar.symbol_db(), s)
+    }
+
+    fn production(&self, p: &Production) -> String {
+        let rhs: Vec<String> = p.rhs().iter().map(|s| self.label(s)).collect();
+        format!("{} -> {}", self.label(p.lhs()), if rhs.is_empty() { self.label(&self.grammar.symbol_db().epsilon()) } else { rhs.join(" ") })
+    }
+
+    fn sequence(&self, rhs: &[Symbol]) -> String {
+        rhs.iter().map(|s| self.label(s)).collect::<Vec<String>>().join(" ")
+    }
+
+    fn add(&mut self, fact: Fact, reason: String, premises: Vec<Fact>) -> bool {
+        let (map, a, b) = match fact {
+            Fact::First(a, b) => (&mut self.first, a, b),
+            Fact::Follow(a, b) => (&mut self.follow, a, b),
+        };
+        if !map.entry(a).or_default().insert(b) {
+            return false;
+        }
+        self.derivations.insert(fact, Derivation { reason, premises });
+        true
+    }
+
+    fn firsts(&self, s: &Symbol) -> Vec<Symbol> {
+        self.first.get(s).map(|f| f.iter().copied().collect()).unwrap_or_default()
+    }
+
+    fn derive_first(&mut self) {
+        let symbol_db = self.grammar.symbol_db();
+        let epsilon = symbol_db.epsilon();
+        let productions: Vec<&Production> = self.grammar.all_productions().iter().collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for p in &productions {
+                let a = *p.lhs();
+                let name = self.production(p);
+                let mut premises = Vec::new();
+                let mut nullable = true;
+                for (i, x) in p.rhs().iter().enumerate() {
+                    let after = if i == 0 {
+                        format!("{} starts with {}", name, self.label(x))
+                    } else {
+                        format!("{}, where {} can derive ε, so {} can come first", name, self.sequence(&p.rhs()[..i]), self.label(x))
+                    };
+                    if symbol_db.is_terminal(x) {
+                        changed |= self.add(Fact::First(a, *x), after, premises.clone());
+                        nullable = false;
+                        break;
+                    }
+                    let firsts = self.firsts(x);
+                    for t in firsts.iter().filter(|t| **t != epsilon) {
+                        let reason = format!("{}, and {} ∈ FIRST({})", after, self.label(t), self.label(x));
+                        let mut premises = premises.clone();
+                        premises.push(Fact::First(*x, *t));
+                        changed |= self.add(Fact::First(a, *t), reason, premises);
+                    }
+                    if !firsts.contains(&epsilon) {
+                        nullable = false;
+                        break;
+                    }
+                    premises.push(Fact::First(*x, epsilon));
+                }
+                if nullable {
+                    let reason = if p.rhs().is_empty() {
+                        format!("{} derives ε directly", name)
+                    } else {
+                        format!("{}, where every symbol of the right hand side can derive ε", name)
+                    };
+                    changed |= self.add(Fact::First(a, epsilon), reason, premises);
+                }
+            }
+        }
+    }
+
+    fn derive_follow(&mut self) {
+        let symbol_db = self.grammar.symbol_db();
+        let epsilon = symbol_db.epsilon();
+        self.add(Fact::Follow(symbol_db.goal(), symbol_db.eoi()), "the end of input follows the goal".to_string(), Vec::new());
+        let productions: Vec<&Production> = self.grammar.all_productions().iter().collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for p in &productions {
+                let a = *p.lhs();
+                let name = self.production(p);
+                for (i, b) in p.rhs().iter().enumerate() {
+                    if symbol_db.is_terminal(b) {
+                        continue;
+                    }
+                    let beta = &p.rhs()[i + 1..];
+                    let mut premises = Vec::new();
+                    let mut nullable = true;
+                    for (j, y) in beta.iter().enumerate() {
+                        let context = if j == 0 {
+                            format!("{}, where {} comes after {}", name, self.label(y), self.label(b))
+                        } else {
+                            format!("{}, where {} can derive ε, so {} can come after {}", name, self.sequence(&beta[..j]), self.label(y), self.label(b))
+                        };
+                        if symbol_db.is_terminal(y) {
+                            changed |= self.add(Fact::Follow(*b, *y), context, premises.clone());
+                            nullable = false;
+                            break;
+                        }
+                        let firsts = self.firsts(y);
+                        for t in firsts.iter().filter(|t| **t != epsilon) {
+                            let reason = format!("{}, and {} ∈ FIRST({})", context, self.label(t), self.label(y));
+                            let mut premises = premises.clone();
+                            premises.push(Fact::First(*y, *t));
+                            changed |= self.add(Fact::Follow(*b, *t), reason, premises);
+                        }
+                        if !firsts.contains(&epsilon) {
+                            nullable = false;
+                            break;
+                        }
+                        premises.push(Fact::First(*y, epsilon));
+                    }
+                    if nullable {
+                        let context = if beta.is_empty() {
+                            format!("{}, which ends with {}", name, self.label(b))
+                        } else {
+                            format!("{}, where {} can derive ε", name, self.sequence(beta))
+                        };
+                        let follows: Vec<Symbol> = self.follow.get(&a).map(|f| f.iter().copied().collect()).unwrap_or_default();
+                        for t in follows {
+                            let reason = format!("{}, so FOLLOW({}) includes FOLLOW({}), which has {}", context, self.label(b), self.label(&a), self.label(&t));
+                            let mut premises = premises.clone();
+                            premises.push(Fact::Follow(a, t));
+                            changed |= self.add(Fact::Follow(*b, t), reason, premises);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // FIRST of a sequence of symbols, less ε
+    fn first_of(&self, symbols: &[Symbol]) -> BTreeSet<Symbol> {
+        let epsilon = self.grammar.symbol_db().epsilon();
+        let mut result = BTreeSet::new();
+        for s in symbols {
+            let firsts = if self.grammar.symbol_db().is_terminal(s) { vec![*s] } else { self.firsts(s) };
+            result.extend(firsts.iter().filter(|t| **t != epsilon));
+            if !firsts.contains(&epsilon) {
+                break;
+            }
+        }
+        result
+    }
+
+    fn fact_text(&self, fact: &Fact) -> String {
+        match fact {
+            Fact::First(a, t) => format!("{} ∈ FIRST({})", self.label(t), self.label(a)),
+            Fact::Follow(a, t) => format!("{} ∈ FOLLOW({})", self.label(t), self.label(a)),
+        }
+    }
+
+    // the steps for the fact, after the steps for its premises (each fact once)
+    fn explain(&self, fact: Fact) -> Option<Explanation> {
+        self.derivations.get(&fact)?;
+        let mut steps = Vec::new();
+        self.add_steps(fact, &mut steps, &mut HashSet::new());
+        Some(Explanation { steps })
+    }
+
+    fn add_steps(&self, fact: Fact, steps: &mut Vec<Step>, done: &mut HashSet<Fact>) {
+        if !done.insert(fact) {
+            return;
+        }
+        let derivation = &self.derivations[&fact];
+        for premise in &derivation.premises {
+            self.add_steps(*premise, steps, done);
+        }
+        steps.push(Step { fact: self.fact_text(&fact), reason: derivation.reason.clone() });
+    }
+}
+
+fn label(symbol_db: &SymbolDb, s: &Symbol) -> String {
+    symbol_db.label(s).cloned().unwrap_or_else(|| format!("{:?}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* grammar:
+     *   s -> a b c
+     *   a -> x | ε
+     *   b -> y | ε
+     *   c -> z
+     */
+    #[test]
+    fn explain_first_01() {
+        let g = Grammar::from_bnf("s -> a b c\na -> x | ε\nb -> y | ε\nc -> z", "g").unwrap();
+        let db = g.symbol_db();
+        let symbol = |label: &str| db.symbol(label).unwrap();
+
+        let e = explain_first(&g, symbol("s"), symbol("z")).unwrap();
+        let facts: Vec<&str> = e.steps.iter().map(|s| s.fact.as_str()).collect();
+        assert_eq!(facts, vec!["ε ∈ FIRST(a)", "ε ∈ FIRST(b)", "z ∈ FIRST(c)", "z ∈ FIRST(s)"]);
+        assert_eq!(e.steps[0].reason, "a -> ε derives ε directly");
+        assert_eq!(e.steps[2].reason, "c -> z starts with z");
+        assert_eq!(e.steps[3].reason, "s -> a b c, where a b can derive ε, so c can come first, and z ∈ FIRST(c)");
+        assert!(explain_first(&g, symbol("s"), symbol("y")).is_some());
+        assert!(explain_first(&g, symbol("c"), symbol("x")).is_none());
+
+        let e = explain_follow(&g, symbol("a"), symbol("z")).unwrap();
+        assert_eq!(e.steps.last().unwrap().reason, "s -> a b c, where b can derive ε, so c can come after a, and z ∈ FIRST(c)");
+        let e = explain_follow(&g, symbol("c"), db.eoi()).unwrap();
+        assert_eq!(e.to_string(), "\
+$ ∈ FOLLOW(GOAL)
+    the end of input follows the goal
+$ ∈ FOLLOW(s)
+    GOAL -> s, which ends with s, so FOLLOW(s) includes FOLLOW(GOAL), which has $
+$ ∈ FOLLOW(c)
+    s -> a b c, which ends with c, so FOLLOW(c) includes FOLLOW(s), which has $
+");
+        assert!(explain_follow(&g, symbol("a"), symbol("x")).is_none());
+    }
+
+    /* grammar:
+     *   list -> list pair | pair
+     *   pair -> ( pair ) | ( )
+     */
+    #[test]
+    fn explain_item_01() {
+        let g = Grammar::from_bnf("list -> list pair | pair\npair -> ( pair ) | ( )", "g").unwrap();
+        let db = g.symbol_db();
+        let symbol = |label: &str| db.symbol(label).unwrap();
+        let (pair, left, right) = (symbol("pair"), symbol("("), symbol(")"));
+        let nested = g.production_id(&Production::new(pair, vec![left, pair, right])).unwrap();
+
+        let e = explain_item(&g, 0, nested, 0, left).unwrap();
+        let facts: Vec<&str> = e.steps.iter().map(|s| s.fact.as_str()).collect();
+        assert_eq!(facts, vec![
+            "[GOAL -> . list, $] is in state 0",
+            "[list -> . list pair, $] is in state 0",
+            "[list -> . pair, (] is in state 0",
+            "[pair -> . ( pair ), (] is in state 0",
+        ]);
+        assert_eq!(e.steps[2].reason, "the closure of [list -> . list pair, $], which has list after the dot, and ( ∈ FIRST(pair $)");
+
+        let cc = CanonicalCollection::new(&g);
+        let state = cc.transitions()[&(0, left)];
+        let e = explain_item(&g, state, nested, 1, db.eoi()).unwrap();
+        assert_eq!(e.steps.len(), 1);
+        assert_eq!(e.steps[0].reason, format!("state 0 has [pair -> . ( pair ), $] and goes to state {} on (", state));
+        assert!(explain_item(&g, 0, nested, 1, db.eoi()).is_none());
+    }
+}
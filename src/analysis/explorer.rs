@@ -0,0 +1,223 @@
+use std::fmt::Write;
+
+use crate::action::Action;
+use crate::canonical_collection::CanonicalCollection;
+use crate::grammar::Grammar;
+use crate::lr1_item::LR1Item;
+use crate::parse_tables::{Conflict,ParseTables};
+use crate::symbol::{Symbol,SymbolDb};
+
+// a self-contained html page for browsing the lr(1) automaton of a grammar. the states are listed
+// down the side, and clicking one shows its items, its actions and its gotos (where each target
+// state is a link). the search box narrows the list to the states whose items, actions or gotos
+// mention a symbol. everything is inlined, so the page can be saved and opened without a server
+pub fn automaton_html(grammar: &Grammar) -> String {
+    let symbol_db = grammar.symbol_db();
+    let cc = CanonicalCollection::new(grammar);
+    let tables = ParseTables::new(grammar);
+    let label = |s: &Symbol| symbol_db.label(s).cloned().unwrap_or_else(|| format!("{:?}", s));
+
+    let terminals: Vec<Symbol> = grammar.terminals().iter().collect();
+    let nonterminals: Vec<Symbol> = grammar.nonterminals().iter().collect();
+
+    // the states as a javascript array of { items, actions, gotos }, where actions and
+    // gotos are [symbol, text, target] triples (target is -1 when there's nothing to link to)
+    let mut states = String::new();
+    for (i, items) in cc.sets() {
+        let items: Vec<String> = items.iter().map(|item| js_string(&item_text(item, symbol_db))).collect();
+        let mut actions = Vec::new();
+        for t in &terminals {
+            let (text, target) = match tables.action(*i, *t) {
+                Some(Action::Accept) => ("accept".to_string(), -1),
+                Some(Action::Shift(n)) => (format!("shift {}", n), *n as i64),
+                Some(Action::Reduce(p)) => (format!("reduce {}", grammar.display_production(*p)), -1),
+                None => continue,
+            };
+            actions.push(format!("[{},{},{}]", js_string(&label(t)), js_string(&text), target));
+        }
+        let mut gotos = Vec::new();
+        for nt in &nonterminals {
+            if let Some(n) = tables.transition(*i, *nt) {
+                gotos.push(format!("[{},{},{}]", js_string(&label(nt)), js_string(&format!("goto {}", n)), n));
+            }
+        }
+        writeln!(&mut states, "  {{items:[{}],actions:[{}],gotos:[{}]}},", items.join(","), actions.join(","), gotos.join(",")).unwrap();
+    }
+
+    let mut conflicts = String::new();
+    for c in tables.conflicts() {
+        let state = match c {
+            Conflict::ShiftReduce { state, .. } |
+            Conflict::ReduceReduce { state, .. } => state,
+        };
+        write!(&mut conflicts, "{},", state).unwrap();
+    }
+
+    let mut result = String::new();
+    result.push_str(PAGE_START);
+    writeln!(&mut result, "const states = [\n{}];", states).unwrap();
+    writeln!(&mut result, "const conflicts = new Set([{}]);", conflicts).unwrap();
+    result.push_str(PAGE_END);
+    result
+}
+
+// an item in the usual notation, e.g. [A -> b . C d, $]
+pub(super) fn item_text(item: &LR1Item, symbol_db: &SymbolDb) -> String {
+    let label = |s: &Symbol| symbol_db.label(s).cloned().unwrap_or_else(|| format!("{:?}", s));
+    let rhs = item.production().rhs();
+    let mut parts: Vec<String> = rhs.iter().map(label).collect();
+    parts.insert(item.dot_position(), ".".to_string());
+    format!("[{} -> {}, {}]", label(item.production().lhs()), parts.join(" "), label(item.lookahead()))
+}
+
+// a javascript string literal. < is escaped so that nothing in a label can close the script
+// element
+fn js_string(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '<' => result.push_str("\\u003c"),
+            c if (c as u32) < 0x20 => write!(&mut result, "\\u{:04x}", c as u32).unwrap(),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+const PAGE_START: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>LR(1) automaton</title>
+<style>
+body { font-family: sans-serif; margin: 0; display: flex; height: 100vh; }
+#side { width: 14em; border-right: 1px solid #ccc; display: flex; flex-direction: column; }
+#search { margin: 0.5em; }
+#list { overflow-y: auto; flex: 1; }
+#list a { display: block; padding: 0.2em 0.5em; cursor: pointer; }
+#list a.conflict { color: #c00; }
+#list a.selected { background: #def; }
+#detail { padding: 0 1em; overflow-y: auto; flex: 1; }
+#detail pre { margin: 0; }
+a.target { color: #06c; cursor: pointer; text-decoration: underline; }
+</style>
+</head>
+<body>
+<div id="side">
+<input id="search" placeholder="symbol">
+<div id="list"></div>
+</div>
+<div id="detail"></div>
+<script>
+"#;
+
+const PAGE_END: &str = r#"const list = document.getElementById("list");
+const detail = document.getElementById("detail");
+const search = document.getElementById("search");
+let selected = null;
+
+function mentions(state, symbol) {
+  return state.actions.some(a => a[0] === symbol) ||
+         state.gotos.some(g => g[0] === symbol) ||
+         state.items.some(i => i.split(" ").includes(symbol));
+}
+
+function text(s) {
+  return document.createTextNode(s);
+}
+
+function section(title, rows) {
+  const h = document.createElement("h3");
+  h.appendChild(text(title));
+  detail.appendChild(h);
+  for (const row of rows) {
+    detail.appendChild(row);
+  }
+}
+
+function entry(e) {
+  const div = document.createElement("div");
+  div.appendChild(text(e[0] + ": "));
+  if (e[2] >= 0) {
+    const a = document.createElement("a");
+    a.className = "target";
+    a.appendChild(text(e[1]));
+    a.onclick = () => show(e[2]);
+    div.appendChild(a);
+  } else {
+    div.appendChild(text(e[1]));
+  }
+  return div;
+}
+
+function show(n) {
+  selected = n;
+  const state = states[n];
+  detail.innerHTML = "";
+  const h = document.createElement("h2");
+  h.appendChild(text("state " + n + (conflicts.has(n) ? " (conflict)" : "")));
+  detail.appendChild(h);
+  section("items", state.items.map(i => { const p = document.createElement("pre"); p.appendChild(text(i)); return p; }));
+  section("actions", state.actions.map(entry));
+  section("gotos", state.gotos.map(entry));
+  render();
+}
+
+function render() {
+  const symbol = search.value.trim();
+  list.innerHTML = "";
+  states.forEach((state, n) => {
+    if (symbol !== "" && !mentions(state, symbol)) {
+      return;
+    }
+    const a = document.createElement("a");
+    a.appendChild(text("state " + n));
+    a.className = (conflicts.has(n) ? "conflict" : "") + (n === selected ? " selected" : "");
+    a.onclick = () => show(n);
+    list.appendChild(a);
+  });
+}
+
+search.oninput = render;
+show(0);
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::production::Production;
+
+    /* grammar:
+     *   e -> e + e | x
+     */
+    #[test]
+    fn automaton_html_01() {
+        let mut symbol_db = SymbolDb::new();
+        let e = symbol_db.new_nonterminal("e");
+        let plus = symbol_db.new_terminal("+");
+        let x = symbol_db.new_terminal("x");
+        let lt = symbol_db.new_terminal("</script>");
+        let g = Grammar::new(symbol_db, e, vec![
+            Production::new(e, vec![e, plus, e]),
+            Production::new(e, vec![x]),
+            Production::new(e, vec![lt]),
+        ]);
+        let html = automaton_html(&g);
+        let tables = ParseTables::new(&g);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert_eq!(html.matches("{items:").count(), tables.state_count());
+        assert!(html.contains("\"[GOAL -> . e, $]\""));
+        assert!(html.contains("[\"x\",\"shift "));
+        assert!(html.contains("\"reduce e -> e  +  e\""));
+        // the label can't end the script early
+        assert_eq!(html.matches("</script>").count(), 1);
+        assert!(!html.contains("const conflicts = new Set([]);"));
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+
+use crate::grammar::Grammar;
+
+#[derive(Clone,Copy,Debug,Eq,Hash,Ord,PartialEq,PartialOrd)]
+pub enum TokenCategory {
+    // a reserved word, e.g. if, while, return
+    Keyword,
+    // an operator, e.g. +, ==, ->
+    Operator,
+    // an opening or closing bracket
+    Bracket,
+    // a separator, e.g. , or ;
+    Delimiter,
+    // anything else, typically a class of tokens like identifiers or numbers
+    Other,
+}
+
+impl TokenCategory {
+    // the textmate scope conventionally used for tokens in this category
+    pub fn textmate_scope(&self) -> Option<&'static str> {
+        match self {
+            TokenCategory::Keyword => Some("keyword.control"),
+            TokenCategory::Operator => Some("keyword.operator"),
+            TokenCategory::Bracket => Some("punctuation.section"),
+            TokenCategory::Delimiter => Some("punctuation.separator"),
+            TokenCategory::Other => None,
+        }
+    }
+
+    // the tree-sitter highlight capture conventionally used for tokens in this category
+    pub fn tree_sitter_capture(&self) -> Option<&'static str> {
+        match self {
+            TokenCategory::Keyword => Some("@keyword"),
+            TokenCategory::Operator => Some("@operator"),
+            TokenCategory::Bracket => Some("@punctuation.bracket"),
+            TokenCategory::Delimiter => Some("@punctuation.delimiter"),
+            TokenCategory::Other => None,
+        }
+    }
+
+    // guess a category from a terminal's label. words made only of lowercase letters (and
+    // underscores or digits) are taken to be keywords, while words containing uppercase letters are
+    // assumed to name token classes like NUM or Ident
+    pub fn classify(label: &str) -> TokenCategory {
+        let mut chars = label.chars();
+        let first = match chars.next() {
+            Some(c) => c,
+            None => return TokenCategory::Other,
+        };
+        if label.chars().count() == 1 && "()[]{}".contains(first) {
+            TokenCategory::Bracket
+        } else if label.chars().count() == 1 && ",;.:".contains(first) {
+            TokenCategory::Delimiter
+        } else if label.chars().all(|c| c.is_ascii_punctuation()) {
+            TokenCategory::Operator
+        } else if first.is_ascii_lowercase() &&
+                  chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+            TokenCategory::Keyword
+        } else {
+            TokenCategory::Other
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HighlightInfo {
+    categories: BTreeMap<String,TokenCategory>,
+}
+
+impl HighlightInfo {
+    // categorize every terminal of the grammar (other than the built in $ and ε) by its label
+    pub fn new(grammar: &Grammar) -> HighlightInfo {
+        let symbol_db = grammar.symbol_db();
+        let mut categories = BTreeMap::new();
+        for t in &grammar.declared_terminals() {
+            let label = symbol_db.label(&t).unwrap();
+            categories.insert(label.clone(), TokenCategory::classify(label));
+        }
+        HighlightInfo { categories }
+    }
+
+    pub fn category(&self, label: &str) -> Option<TokenCategory> {
+        self.categories.get(label).copied()
+    }
+
+    // override the guessed category of a terminal
+    pub fn set_category(&mut self, label: &str, category: TokenCategory) {
+        if let Some(c) = self.categories.get_mut(label) {
+            *c = category;
+        }
+    }
+
+    // the labels of all terminals in a category, in sorted order
+    pub fn labels(&self, category: TokenCategory) -> Vec<&str> {
+        self.categories.iter()
+            .filter(|(_, c)| **c == category)
+            .map(|(l, _)| l.as_str())
+            .collect()
+    }
+
+    pub fn keywords(&self) -> Vec<&str> {
+        self.labels(TokenCategory::Keyword)
+    }
+
+    pub fn operators(&self) -> Vec<&str> {
+        self.labels(TokenCategory::Operator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::production::Production;
+    use crate::symbol::SymbolDb;
+
+    #[test]
+    fn classify_01() {
+        assert_eq!(TokenCategory::classify("if"), TokenCategory::Keyword);
+        assert_eq!(TokenCategory::classify("else_if2"), TokenCategory::Keyword);
+        assert_eq!(TokenCategory::classify("+"), TokenCategory::Operator);
+        assert_eq!(TokenCategory::classify("=="), TokenCategory::Operator);
+        assert_eq!(TokenCategory::classify("("), TokenCategory::Bracket);
+        assert_eq!(TokenCategory::classify(";"), TokenCategory::Delimiter);
+        assert_eq!(TokenCategory::classify("NUM"), TokenCategory::Other);
+        assert_eq!(TokenCategory::classify("Ident"), TokenCategory::Other);
+        assert_eq!(TokenCategory::classify(""), TokenCategory::Other);
+    }
+
+    /* grammar:
+     *   stmt -> while ( expr ) stmt | expr ;
+     *   expr -> expr + ID | ID
+     */
+    #[test]
+    fn highlight_info_01() {
+        let mut symbol_db = SymbolDb::new();
+        let stmt = symbol_db.new_nonterminal("stmt");
+        let expr = symbol_db.new_nonterminal("expr");
+        let while_ = symbol_db.new_terminal("while");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let semi = symbol_db.new_terminal(";");
+        let plus = symbol_db.new_terminal("+");
+        let id = symbol_db.new_terminal("ID");
+        let productions = vec![
+            Production::new(stmt, vec![while_, lp, expr, rp, stmt]),
+            Production::new(stmt, vec![expr, semi]),
+            Production::new(expr, vec![expr, plus, id]),
+            Production::new(expr, vec![id]),
+        ];
+        let g = Grammar::new(symbol_db, stmt, productions);
+        let mut info = HighlightInfo::new(&g);
+        assert_eq!(info.keywords(), vec!["while"]);
+        assert_eq!(info.operators(), vec!["+"]);
+        assert_eq!(info.labels(TokenCategory::Bracket), vec!["(", ")"]);
+        assert_eq!(info.labels(TokenCategory::Delimiter), vec![";"]);
+        assert_eq!(info.labels(TokenCategory::Other), vec!["ID"]);
+        assert_eq!(info.category("$"), None);
+        assert_eq!(info.category("while").unwrap().textmate_scope(), Some("keyword.control"));
+
+        info.set_category("while", TokenCategory::Other);
+        assert!(info.keywords().is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeSet,HashMap};
+
+use crate::grammar::Grammar;
+use crate::symbol::Symbol;
+
+// enumerate every sentence (a sequence of terminals, without the trailing $) that can be derived
+// from the start symbol of the grammar and has at most max_length symbols. the result is ordered
+// by length and then by symbol, so two runs over the same grammar always agree
+pub fn sentences(grammar: &Grammar, max_length: usize) -> Vec<Vec<Symbol>> {
+    let mut derivable = derivable_strings(grammar, max_length);
+    let mut result: Vec<Vec<Symbol>> = derivable.remove(grammar.start_symbol())
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    result.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    result
+}
+
+// for each nonterminal, compute the set of terminal strings of length at most max_length that it
+// derives. the sets are bounded, so a simple fixed point iteration terminates
+pub(crate) fn derivable_strings(grammar: &Grammar, max_length: usize) -> HashMap<Symbol,BTreeSet<Vec<Symbol>>> {
+    let symbol_db = grammar.symbol_db();
+
+    let mut derivable: HashMap<Symbol,BTreeSet<Vec<Symbol>>> = HashMap::new();
+    for nt in grammar.nonterminals() {
+        derivable.insert(nt, BTreeSet::new());
+    }
+
+    let mut done = false;
+    while !done {
+        done = true;
+        for nt in grammar.nonterminals() {
+            if let Some(ps) = grammar.productions(&nt) {
+                for p in ps {
+                    // for a production A -> x_1 x_2 ... x_n, build every concatenation of a string
+                    // derived from x_1, a string derived from x_2, and so on, discarding any that
+                    // grow too long
+                    let mut strings: BTreeSet<Vec<Symbol>> = BTreeSet::new();
+                    strings.insert(Vec::new());
+                    for x in p.rhs() {
+                        let mut next = BTreeSet::new();
+                        if symbol_db.is_terminal(x) {
+                            for s in &strings {
+                                if s.len() < max_length {
+                                    let mut s = s.clone();
+                                    s.push(*x);
+                                    next.insert(s);
+                                }
+                            }
+                        } else if let Some(tails) = derivable.get(x) {
+                            for s in &strings {
+                                for t in tails {
+                                    if s.len() + t.len() <= max_length {
+                                        let mut s = s.clone();
+                                        s.extend_from_slice(t);
+                                        next.insert(s);
+                                    }
+                                }
+                            }
+                        }
+                        strings = next;
+                        if strings.is_empty() {
+                            break;
+                        }
+                    }
+                    // add anything new to the set for the production's LHS and keep going until
+                    // nothing changes
+                    let set = derivable.get_mut(p.lhs()).unwrap();
+                    for s in strings {
+                        if set.insert(s) {
+                            done = false;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    derivable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::production::Production;
+    use crate::symbol::SymbolDb;
+
+    /* grammar:
+     *   e1 -> ( e1 ) | ε
+     */
+    #[test]
+    fn sentences_01() {
+        let mut symbol_db = SymbolDb::new();
+        let e1 = symbol_db.new_nonterminal("E1");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let productions = vec![
+            Production::new(e1, vec![lp, e1, rp]),
+            Production::new(e1, vec![]),
+        ];
+        let g = Grammar::new(symbol_db, e1, productions);
+        let result = sentences(&g, 5);
+        assert_eq!(result, vec![vec![], vec![lp, rp], vec![lp, lp, rp, rp]]);
+    }
+
+    /* grammar:
+     *   list -> list pair | pair
+     *   pair -> ( pair ) | ( )
+     */
+    #[test]
+    fn sentences_02() {
+        let mut symbol_db = SymbolDb::new();
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let productions = vec![
+            Production::new(list, vec![list, pair]),
+            Production::new(list, vec![pair]),
+            Production::new(pair, vec![lp, pair, rp]),
+            Production::new(pair, vec![lp, rp]),
+        ];
+        let g = Grammar::new(symbol_db, list, productions);
+        let result = sentences(&g, 4);
+        assert_eq!(result, vec![
+            vec![lp, rp],
+            vec![lp, lp, rp, rp],
+            vec![lp, rp, lp, rp],
+        ]);
+    }
+
+    /* grammar:
+     *   e1 -> id | e2
+     *   e2 -> ( e3 )
+     *   e3 -> e1 e3 | ε
+     */
+    #[test]
+    fn sentences_03() {
+        let mut symbol_db = SymbolDb::new();
+        let e1 = symbol_db.new_nonterminal("E1");
+        let e2 = symbol_db.new_nonterminal("E2");
+        let e3 = symbol_db.new_nonterminal("E3");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let id = symbol_db.new_terminal("id");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(e1, vec![id]),
+            Production::new(e1, vec![e2]),
+            Production::new(e2, vec![lp, e3, rp]),
+            Production::new(e3, vec![e1, e3]),
+            Production::new(e3, vec![]),
+        ];
+        let g = Grammar::new(symbol_db, e1, productions);
+        let result = sentences(&g, 5);
+        assert_eq!(result.len(), 9);
+
+        // every enumerated sentence must be accepted by the parser
+        let p = Parser::new(g);
+        for sentence in result {
+            let mut tokens = sentence.clone();
+            tokens.push(eoi);
+            assert!(p.parse(tokens, |s: &Symbol| *s).is_ok());
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap,BTreeSet};
+use std::fmt::Write;
+
+use crate::canonical_collection::CanonicalCollection;
+use crate::grammar::Grammar;
+use crate::production::Production;
+use crate::symbol::{Symbol,SymbolDb};
+
+#[derive(Clone,Debug,Eq,Ord,PartialEq,PartialOrd)]
+pub struct ShiftSite {
+    pub state: u32,
+    pub production: Production,
+}
+
+#[derive(Clone,Debug,Eq,Ord,PartialEq,PartialOrd)]
+pub struct ReduceSite {
+    pub state: u32,
+    pub production: Production,
+    pub lookahead: Symbol,
+}
+
+#[derive(Debug)]
+pub struct SymbolUsage {
+    // for each terminal, the states (and the productions in them) where it can be shifted
+    pub shifts: BTreeMap<Symbol,Vec<ShiftSite>>,
+    // for each nonterminal, the states where one of its productions can be reduced
+    pub reductions: BTreeMap<Symbol,Vec<ReduceSite>>,
+}
+
+impl SymbolUsage {
+    // terminals that are never shifted in any state. they can't appear in any valid input
+    pub fn unshifted_terminals(&self) -> Vec<Symbol> {
+        self.shifts.iter().filter(|(_, v)| v.is_empty()).map(|(s, _)| *s).collect()
+    }
+
+    // productions that are never reduced in any state. they can be removed without changing the
+    // language
+    pub fn unreduced_productions(&self, grammar: &Grammar) -> Vec<Production> {
+        let reduced: BTreeSet<&Production> = self.reductions.values()
+            .flat_map(|v| v.iter().map(|site| &site.production))
+            .collect();
+        let mut result = Vec::new();
+        for nt in self.reductions.keys() {
+            if let Some(ps) = grammar.productions(nt) {
+                for p in ps {
+                    if !reduced.contains(p) {
+                        result.push(p.clone());
+                    }
+                }
+            }
+        }
+        result.sort();
+        result
+    }
+
+    pub fn to_string(&self, symbol_db: &SymbolDb) -> String {
+        let mut result = String::new();
+        writeln!(&mut result, "shifts").unwrap();
+        for (t, sites) in &self.shifts {
+            writeln!(&mut result, "    {}", symbol_db.label(t).unwrap()).unwrap();
+            for site in sites {
+                writeln!(&mut result, "        state {}: {}", site.state, site.production.display(symbol_db)).unwrap();
+            }
+        }
+        writeln!(&mut result, "reductions").unwrap();
+        for (nt, sites) in &self.reductions {
+            writeln!(&mut result, "    {}", symbol_db.label(nt).unwrap()).unwrap();
+            for site in sites {
+                let l = symbol_db.label(&site.lookahead).unwrap();
+                writeln!(&mut result, "        state {} on {}: {}", site.state, l, site.production.display(symbol_db)).unwrap();
+            }
+        }
+        result
+    }
+}
+
+// walk the canonical collection and record where every terminal is shifted and where every
+// nonterminal is reduced
+pub fn symbol_usage(grammar: &Grammar) -> SymbolUsage {
+    let cc = CanonicalCollection::new(grammar);
+
+    let mut shifts: BTreeMap<Symbol,BTreeSet<ShiftSite>> = BTreeMap::new();
+    let mut reductions: BTreeMap<Symbol,BTreeSet<ReduceSite>> = BTreeMap::new();
+    for t in &grammar.declared_terminals() {
+        shifts.insert(t, BTreeSet::new());
+    }
+    for nt in &grammar.declared_nonterminals() {
+        reductions.insert(nt, BTreeSet::new());
+    }
+
+    for (&state, items) in cc.sets() {
+        for item in items {
+            let unseen = item.symbols_after_dot();
+            let production = item.production().clone();
+            // an item of the form A -> b.tc shifts t
+            if !unseen.is_empty() {
+                if let Some(sites) = shifts.get_mut(&unseen[0]) {
+                    sites.insert(ShiftSite { state, production });
+                }
+            }
+            // an item of the form A -> b. (including A -> . for an ε production) reduces A
+            else if let Some(sites) = reductions.get_mut(production.lhs()) {
+                let lookahead = *item.lookahead();
+                sites.insert(ReduceSite { state, production, lookahead });
+            }
+        }
+    }
+
+    SymbolUsage {
+        shifts: shifts.into_iter().map(|(k, v)| (k, v.into_iter().collect())).collect(),
+        reductions: reductions.into_iter().map(|(k, v)| (k, v.into_iter().collect())).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol::SymbolDb;
+
+    /* grammar:
+     *   S -> a X | b
+     *   X -> c
+     *   Y -> d
+     */
+    #[test]
+    fn symbol_usage_01() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let x = symbol_db.new_nonterminal("X");
+        let y = symbol_db.new_nonterminal("Y");
+        let a = symbol_db.new_terminal("a");
+        let b = symbol_db.new_terminal("b");
+        let c = symbol_db.new_terminal("c");
+        let d = symbol_db.new_terminal("d");
+        let p4 = Production::new(y, vec![d]);
+        let productions = vec![
+            Production::new(s, vec![a, x]),
+            Production::new(s, vec![b]),
+            Production::new(x, vec![c]),
+            p4.clone(),
+        ];
+        let g = Grammar::new(symbol_db, s, productions);
+        let usage = symbol_usage(&g);
+
+        assert_eq!(usage.shifts[&a].len(), 1);
+        assert_eq!(usage.shifts[&b].len(), 1);
+        assert_eq!(usage.shifts[&c].len(), 1);
+        assert_eq!(usage.shifts[&c][0].production, Production::new(x, vec![c]));
+        assert_eq!(usage.unshifted_terminals(), vec![d]);
+
+        assert_eq!(usage.reductions[&s].len(), 2);
+        assert!(usage.reductions[&s].iter().all(|site| site.lookahead == g.symbol_db().eoi()));
+        assert_eq!(usage.reductions[&x].len(), 1);
+        assert!(usage.reductions[&y].is_empty());
+        assert_eq!(usage.unreduced_productions(&g), vec![p4]);
+    }
+
+    /* grammar:
+     *   e1 -> ( e1 ) | ε
+     */
+    #[test]
+    fn symbol_usage_02() {
+        let mut symbol_db = SymbolDb::new();
+        let e1 = symbol_db.new_nonterminal("E1");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let epsilon = symbol_db.epsilon();
+        let productions = vec![
+            Production::new(e1, vec![lp, e1, rp]),
+            Production::new(e1, vec![]),
+        ];
+        let g = Grammar::new(symbol_db, e1, productions);
+        let usage = symbol_usage(&g);
+        assert!(!usage.shifts.contains_key(&epsilon));
+        let lookaheads: BTreeSet<Symbol> = usage.reductions[&e1].iter()
+            .filter(|site| site.production.rhs().is_empty())
+            .map(|site| site.lookahead)
+            .collect();
+        assert_eq!(lookaheads, vec![rp, g.symbol_db().eoi()].into_iter().collect());
+        assert!(usage.unreduced_productions(&g).is_empty());
+        assert!(usage.to_string(g.symbol_db()).contains("state 0 on $: E1 -> ε"));
+    }
+}
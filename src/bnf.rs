@@ -0,0 +1,375 @@
+use std::collections::{HashMap,HashSet};
+use std::fmt;
+
+use super::grammar::{Grammar,GrammarError,GrammarErrors};
+use super::production::Production;
+use super::source_location::SourceLocation;
+use super::source_map::SourceMap;
+use super::symbol::{Symbol,SymbolDb};
+
+// a reason a grammar couldn't be loaded from text
+#[derive(Debug)]
+pub enum BnfError {
+    // the text isn't in the expected form
+    Syntax { location: SourceLocation, message: String },
+    // the text is fine, but the grammar it describes isn't. the errors carry the locations of the
+    // productions they're about
+    Invalid(Box<GrammarErrors>),
+}
+
+impl fmt::Display for BnfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BnfError::Syntax { location, message } => write!(f, "{}: {}", location, message),
+            BnfError::Invalid(errors) => write!(f, "{}", errors),
+        }
+    }
+}
+
+// a word of the text and where it starts
+struct Word<'a> {
+    text: &'a str,
+    location: SourceLocation,
+}
+
+// an alternative of a rule: the words of its right hand side, where it starts, its features and
+// its label
+struct Alternative<'a> {
+    location: SourceLocation,
+    words: Vec<Word<'a>>,
+    features: Vec<Word<'a>>,
+    label: Option<Word<'a>>,
+}
+
+impl Alternative<'_> {
+    fn new(location: SourceLocation) -> Self {
+        Alternative { location, words: Vec::new(), features: Vec::new(), label: None }
+    }
+}
+
+// the left hand side of a rule and its alternatives
+type Rule<'a> = (Word<'a>, Vec<Alternative<'a>>);
+
+impl Grammar {
+    // load a grammar written one rule per line, e.g.
+    //
+    //   expr -> expr + term | term
+    //   term -> ( expr )
+    //        | x
+    //   list -> ε | list x      # a comment
+    //   stmt -> id = expr #Assign
+    //        | print expr #Print
+    //        | lambda id . expr @lambdas
+    //
+    // symbols are separated by whitespace, a line that starts with | continues the rule above it,
+    // and ε stands for an empty alternative. the symbols on the left of -> are the nonterminals
+    // and every other symbol is a terminal. the start symbol is the left hand side of the first
+    // rule. a # followed straight away by a letter or _ labels the alternative it ends (labels
+    // are unique among the alternatives of a nonterminal), and any other # starts a comment. a
+    // word made of @ and then a letter or _ tags the alternative with a feature (see
+    // Grammar::with_features); features come after the symbols and before the label.
+    // file only names the text in locations, nothing is read from it
+    pub fn from_bnf(text: &str, file: &str) -> Result<Grammar,BnfError> {
+        Grammar::from_bnf_in(SymbolDb::new(), text, file)
+    }
+
+    // from_bnf for a grammar whose built in symbols have other labels (see
+    // SymbolDb::with_special_labels), so that e.g. $ can be a token. the epsilon label is the one
+    // that stands for an empty alternative
+    pub fn from_bnf_with_special_labels(text: &str, file: &str, goal: &str, eoi: &str, epsilon: &str) -> Result<Grammar,BnfError> {
+        Grammar::from_bnf_in(SymbolDb::with_special_labels(goal, eoi, epsilon), text, file)
+    }
+
+    fn from_bnf_in(mut symbol_db: SymbolDb, text: &str, file: &str) -> Result<Grammar,BnfError> {
+        let source_map = SourceMap::new(file, text);
+        let mut rules: Vec<Rule> = Vec::new();
+        for n in 1..=source_map.line_count() {
+            let span = source_map.line_span(n).unwrap();
+            let line = &text[span.clone()];
+            let line = &line[..comment_start(line)];
+            let words = words(line, span.start, &source_map);
+            let mut rest = &words[..];
+            match rest.first() {
+                None => continue,
+                Some(w) if w.text == "|" => {
+                    if rules.is_empty() {
+                        return Err(BnfError::Syntax { location: w.location.clone(), message: "| before the first rule".to_string() });
+                    }
+                },
+                Some(w) => {
+                    if words.get(1).map(|w| w.text) != Some("->") {
+                        return Err(BnfError::Syntax { location: w.location.clone(), message: "expected -> after the left hand side".to_string() });
+                    }
+                    rules.push((Word { text: w.text, location: w.location.clone() }, Vec::new()));
+                    rest = &words[2..];
+                    let location = rest.first().map(|w| w.location.clone()).unwrap_or_else(|| words[1].location.clone());
+                    rules.last_mut().unwrap().1.push(Alternative::new(location));
+                },
+            }
+            let alternatives = &mut rules.last_mut().unwrap().1;
+            for w in rest {
+                if w.text == "|" {
+                    alternatives.push(Alternative::new(w.location.clone()));
+                    continue;
+                }
+                let alternative = alternatives.last_mut().unwrap();
+                if alternative.label.is_some() {
+                    return Err(BnfError::Syntax { location: w.location.clone(), message: "expected | or the end of the line after a label".to_string() });
+                }
+                let word = Word { text: w.text, location: w.location.clone() };
+                if w.text.starts_with('#') {
+                    alternative.label = Some(word);
+                } else if is_feature(w.text) {
+                    alternative.features.push(word);
+                } else if !alternative.features.is_empty() {
+                    return Err(BnfError::Syntax { location: w.location.clone(), message: "expected a feature, a label, | or the end of the line after a feature".to_string() });
+                } else {
+                    if alternative.words.is_empty() {
+                        alternative.location = w.location.clone();
+                    }
+                    alternative.words.push(word);
+                }
+            }
+        }
+
+        // the alternatives of a nonterminal can be spread over several rules
+        let mut seen: HashSet<(&str,&str)> = HashSet::new();
+        for (lhs, alternatives) in &rules {
+            for label in alternatives.iter().filter_map(|a| a.label.as_ref()) {
+                if !seen.insert((lhs.text, label.text)) {
+                    let message = format!("{} labels more than one alternative of {}", label.text, lhs.text);
+                    return Err(BnfError::Syntax { location: label.location.clone(), message });
+                }
+            }
+        }
+        if rules.is_empty() {
+            return Err(BnfError::Syntax { location: SourceLocation::new(file, 1, 1), message: "no rules".to_string() });
+        }
+
+        let mut symbols: HashMap<&str,Symbol> = HashMap::new();
+        let mut symbol_locations: HashMap<Symbol,SourceLocation> = HashMap::new();
+        let epsilon = symbol_db.label(&symbol_db.epsilon()).unwrap().clone();
+        symbols.insert(&epsilon, symbol_db.epsilon());
+        for (lhs, _) in &rules {
+            if !symbols.contains_key(lhs.text) {
+                reserved(&symbol_db, lhs)?;
+                let s = symbol_db.new_nonterminal(lhs.text);
+                symbols.insert(lhs.text, s);
+                symbol_locations.insert(s, lhs.location.clone());
+            }
+        }
+        for (_, alternatives) in &rules {
+            for w in alternatives.iter().flat_map(|a| &a.words) {
+                if !symbols.contains_key(w.text) {
+                    reserved(&symbol_db, w)?;
+                    let s = symbol_db.new_terminal(w.text);
+                    symbols.insert(w.text, s);
+                    symbol_locations.insert(s, w.location.clone());
+                }
+            }
+        }
+
+        let mut productions = Vec::new();
+        let mut locations = Vec::new();
+        let mut labels = Vec::new();
+        let mut features = Vec::new();
+        for (lhs, alternatives) in &rules {
+            for alternative in alternatives {
+                let rhs = alternative.words.iter().map(|w| symbols[w.text]).collect();
+                productions.push(Production::new(symbols[lhs.text], rhs));
+                locations.push(alternative.location.clone());
+                labels.push(alternative.label.as_ref().map(|w| w.text[1..].to_string()));
+                features.push(alternative.features.iter().map(|w| w.text[1..].to_string()).collect());
+            }
+        }
+
+        let start_symbol = symbols[rules[0].0.text];
+        match Grammar::try_new(symbol_db, start_symbol, productions.clone()) {
+            Ok(g) => Ok(g.with_locations(locations.into_iter().map(Some).collect(), symbol_locations).with_labels(labels).with_production_features(features)),
+            Err(mut errors) => {
+                let production_location = |p: &Production| {
+                    productions.iter().position(|q| q == p).map(|i| locations[i].clone())
+                };
+                errors.locate(|e| match e {
+                    GrammarError::TerminalStartSymbol(s) => symbol_locations.get(s).cloned(),
+                    GrammarError::TerminalLhs(p) |
+                    GrammarError::GoalLhs(p) |
+                    GrammarError::MisplacedEpsilon(p) |
+                    GrammarError::MisplacedEndOfInput(p) => production_location(p),
+                });
+                Err(BnfError::Invalid(Box::new(errors)))
+            },
+        }
+    }
+}
+
+// the labels of GOAL and $ can't be used in the text, since the grammar makes those symbols itself
+fn reserved(symbol_db: &SymbolDb, word: &Word) -> Result<(), BnfError> {
+    match symbol_db.reserved(word.text) {
+        Some(_) => Err(BnfError::Syntax { location: word.location.clone(), message: format!("{} is the label of a built in symbol", word.text) }),
+        None => Ok(()),
+    }
+}
+
+// @ followed by a letter or _ starts a feature, any other word starting with @ is a symbol
+fn is_feature(word: &str) -> bool {
+    word.strip_prefix('@').and_then(|w| w.chars().next()).is_some_and(|c| c.is_alphabetic() || c == '_')
+}
+
+// where the comment on a line starts (the length of the line if there isn't one): at the first #
+// that isn't the start of a label
+fn comment_start(line: &str) -> usize {
+    let mut previous = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '#' {
+            let starts_word = previous.is_none_or(char::is_whitespace);
+            let label = chars.peek().is_some_and(|(_, c)| c.is_alphabetic() || *c == '_');
+            if !(starts_word && label) {
+                return i;
+            }
+        }
+        previous = Some(c);
+    }
+    line.len()
+}
+
+// the whitespace separated words of a line, with their locations
+fn words<'a>(line: &'a str, offset: usize, source_map: &SourceMap) -> Vec<Word<'a>> {
+    let mut result = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, c) in line.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                result.push(Word { text: &line[s..i], location: source_map.location(offset + s) });
+                start = None;
+            },
+            (false, None) => start = Some(i),
+            _ => {},
+        }
+    }
+    if let Some(s) = start {
+        result.push(Word { text: &line[s..], location: source_map.location(offset + s) });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostics;
+    use crate::parser::Parser;
+
+    #[test]
+    fn from_bnf_01() {
+        let text = "\
+# expressions
+expr -> expr + term | term | fact
+term -> ( expr )
+     | x
+fact -> x
+";
+        let g = Grammar::from_bnf(text, "expr.bnf").unwrap();
+        let db = g.symbol_db();
+        let expr = db.symbol("expr").unwrap();
+        let x = db.symbol("x").unwrap();
+        assert_eq!(g.start_symbol(), &expr);
+        assert!(db.is_terminal(&x));
+        assert_eq!(g.all_productions().len(), 7);
+        assert_eq!(g.productions_with_ids().map(|(id, _)| g.production_location(id).map(|l| l.to_string())).collect::<Vec<_>>(), vec![
+            Some("expr.bnf:2:9".to_string()),
+            Some("expr.bnf:2:23".to_string()),
+            Some("expr.bnf:2:30".to_string()),
+            Some("expr.bnf:3:9".to_string()),
+            Some("expr.bnf:4:8".to_string()),
+            Some("expr.bnf:5:9".to_string()),
+            None,
+        ]);
+        assert_eq!(g.symbol_location(&x).unwrap().to_string(), "expr.bnf:4:8");
+
+        let mut diagnostics = Diagnostics::new();
+        let _ = Parser::new_with_diagnostics(g, &mut diagnostics);
+        // which of term -> x and fact -> x wins the conflict isn't specified, but the diagnostic
+        // points at the line of the one that lost
+        let location = diagnostics.with_code("reduce-reduce-conflict")[0].location.clone().unwrap();
+        assert!(location.line == 4 || location.line == 5);
+    }
+
+    #[test]
+    fn from_bnf_02() {
+        let errors = Grammar::from_bnf("s -> a s | ε\nt -> a ε b\n", "bad.bnf").err().unwrap();
+        assert_eq!(errors.to_string(), "invalid grammar: bad.bnf:2:6: production t -> a  ε  b uses ε alongside other symbols");
+
+        let g = Grammar::from_bnf("s -> a s | ε\n", "ok.bnf").unwrap();
+        assert!(g.productions(g.start_symbol()).unwrap()[1].rhs().is_empty());
+
+        let error = Grammar::from_bnf("s a\n", "bad.bnf").err().unwrap();
+        assert_eq!(error.to_string(), "bad.bnf:1:1: expected -> after the left hand side");
+        let error = Grammar::from_bnf("\n  | a\n", "bad.bnf").err().unwrap();
+        assert_eq!(error.to_string(), "bad.bnf:2:3: | before the first rule");
+        let error = Grammar::from_bnf("s -> a $\n", "bad.bnf").err().unwrap();
+        assert_eq!(error.to_string(), "bad.bnf:1:8: $ is the label of a built in symbol");
+        let error = Grammar::from_bnf("s -> GOAL\nGOAL -> a\n", "bad.bnf").err().unwrap();
+        assert_eq!(error.to_string(), "bad.bnf:2:1: GOAL is the label of a built in symbol");
+    }
+
+    #[test]
+    fn from_bnf_03() {
+        let text = "\
+e -> e + e #Add    # a comment
+   | e * e #Mul
+   | x #Var | ( e )
+";
+        let g = Grammar::from_bnf(text, "labels.bnf").unwrap();
+        let db = g.symbol_db();
+        let e = db.symbol("e").unwrap();
+        let labels: Vec<Option<&str>> = g.productions_with_ids().map(|(id, _)| g.production_label(id)).collect();
+        assert_eq!(labels, vec![Some("Add"), Some("Mul"), Some("Var"), None, None]);
+        let add = g.labeled_production(&e, "Add").unwrap();
+        assert_eq!(g.display_production(add).to_string(), "e -> e  +  e  #Add");
+        assert_eq!(g.labeled_production(&e, "Sub"), None);
+
+        // the conflicts between + and * mention the labels
+        let mut diagnostics = Diagnostics::new();
+        let parser = Parser::new_with_diagnostics(g, &mut diagnostics);
+        let message = &diagnostics.with_code("shift-reduce-conflict")[0].message;
+        assert!(message.contains("  #Add") || message.contains("  #Mul"));
+
+        let db = parser.grammar().symbol_db();
+        let tokens: Vec<Symbol> = "x + x $".split(' ').map(|l| db.symbol(l).unwrap()).collect();
+        let tree = parser.parse(tokens, |s| *s).unwrap();
+        assert_eq!(tree.label(parser.grammar()), Some("Add"));
+        assert_eq!(tree.children()[0].label(parser.grammar()), Some("Var"));
+        // the terminals weren't reduced from anything
+        assert_eq!(tree.children()[1].production(), None);
+
+        // a # that isn't followed by a letter still starts a comment, wherever it is
+        let g = Grammar::from_bnf("s -> a #1 b\n  | b# c\n", "comments.bnf").unwrap();
+        assert_eq!(g.all_productions().len(), 3);
+        assert!(g.productions_with_ids().all(|(id, _)| g.production_label(id).is_none()));
+
+        let error = Grammar::from_bnf("s -> a #A b\n", "bad.bnf").err().unwrap();
+        assert_eq!(error.to_string(), "bad.bnf:1:11: expected | or the end of the line after a label");
+        let error = Grammar::from_bnf("s -> a #A\ns -> b #A\n", "bad.bnf").err().unwrap();
+        assert_eq!(error.to_string(), "bad.bnf:2:8: #A labels more than one alternative of s");
+    }
+
+    /* grammar:
+     *   money -> $ digits
+     *   digits -> digits d | NONE
+     */
+    #[test]
+    fn from_bnf_04() {
+        let g = Grammar::from_bnf_with_special_labels("money -> $ digits\ndigits -> digits d | NONE", "g", "START", "EOF", "NONE").unwrap();
+        let db = g.symbol_db();
+        let dollar = db.symbol("$").unwrap();
+        assert_ne!(dollar, db.eoi());
+        assert!(g.productions(&db.symbol("digits").unwrap()).unwrap()[1].rhs().is_empty());
+        assert_eq!(g.to_bnf_string(), "money -> $ digits\ndigits -> digits d | NONE\n");
+
+        let parser = Parser::new(g);
+        let db = parser.grammar().symbol_db();
+        let tokens: Vec<Symbol> = "$ d d EOF".split(' ').map(|l| db.symbol(l).unwrap()).collect();
+        assert!(parser.parse(tokens, |s| *s).is_ok());
+    }
+}
@@ -0,0 +1,478 @@
+use std::ops::Range;
+
+use super::grammar::Grammar;
+use super::parse_error::ParseError;
+use super::parse_tree::ParseTree;
+use super::parser::Parser;
+use super::push_parser::PushParser;
+use super::scannerless::{ClassError,push_first};
+use super::spanned::Spanned;
+use super::symbol::Symbol;
+
+// a set of byte values, written as the label of a terminal in a byte grammar: a byte in hex, e.g.
+// 0x7f, a range, e.g. 0x30-0x39, or a comma separated list of those, e.g. 0x09,0x0a,0x20-0x7e
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct ByteClass {
+    ranges: Vec<(u8,u8)>,
+}
+
+impl ByteClass {
+    pub fn parse(label: &str) -> Result<ByteClass,String> {
+        let byte = |s: &str| {
+            s.strip_prefix("0x")
+                .filter(|hex| hex.len() == 2)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| format!("{} isn't a byte like 0x1f", s))
+        };
+        let mut ranges = Vec::new();
+        for item in label.split(',') {
+            let (start, end) = match item.split_once('-') {
+                Some((start, end)) => (byte(start)?, byte(end)?),
+                None => (byte(item)?, byte(item)?),
+            };
+            if end < start {
+                return Err(format!("the range {} is backwards", item));
+            }
+            ranges.push((start, end));
+        }
+        Ok(ByteClass { ranges })
+    }
+
+    pub fn contains(&self, b: u8) -> bool {
+        self.ranges.iter().any(|(start, end)| *start <= b && b <= *end)
+    }
+
+    fn is_single(&self) -> bool {
+        self.ranges.len() == 1 && self.ranges[0].0 == self.ranges[0].1
+    }
+}
+
+// a byte of the input, the token of a byte parse. the end of input is a token with no byte at the
+// offset just past the last byte
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct ByteToken {
+    pub byte: Option<u8>,
+    pub offset: usize,
+}
+
+impl ByteToken {
+    pub fn of(bytes: &[u8]) -> Vec<ByteToken> {
+        bytes.iter().enumerate()
+            .map(|(offset, b)| ByteToken { byte: Some(*b), offset })
+            .chain(std::iter::once(ByteToken { byte: None, offset: bytes.len() }))
+            .collect()
+    }
+}
+
+impl Spanned for ByteToken {
+    fn span(&self) -> Range<usize> {
+        match self.byte {
+            Some(_) => self.offset..self.offset + 1,
+            None => self.offset..self.offset,
+        }
+    }
+}
+
+// a parser for a grammar whose terminals are byte values and ranges, which parses a byte slice
+// directly, for binary formats and protocols, e.g. a sequence of LEB128 numbers
+//
+//   numbers -> ε | numbers number
+//   number -> 0x00-0x7f | 0x80-0xff number
+//
+// as with Scannerless, the classes may overlap: each byte is given to the terminals that contain
+// it in turn, single bytes before ranges, and the first one the parser can shift is taken
+pub struct ByteParser {
+    parser: Parser,
+    terminals: Vec<(Symbol,ByteClass)>,
+}
+
+impl ByteParser {
+    pub fn new(grammar: Grammar) -> Result<ByteParser,ClassError> {
+        let symbol_db = grammar.symbol_db();
+        let mut terminals = Vec::new();
+        for t in &grammar.declared_terminals() {
+            let label = symbol_db.label(&t).cloned().unwrap_or_default();
+            match ByteClass::parse(&label) {
+                Ok(class) => terminals.push((t, class)),
+                Err(message) => return Err(ClassError { label, message }),
+            }
+        }
+        terminals.sort_by_key(|(t, class)| (!class.is_single(), *t));
+        Ok(ByteParser { parser: Parser::new(grammar), terminals })
+    }
+
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    pub fn grammar(&self) -> &Grammar {
+        self.parser.grammar()
+    }
+
+    // parse the bytes, one byte per token, so the positions in errors are byte offsets
+    pub fn parse(&self, bytes: &[u8]) -> Result<ParseTree<ByteToken>,ParseError> {
+        let mut pp = PushParser::new(&self.parser);
+        for token in ByteToken::of(bytes) {
+            self.push(&mut pp, token)?;
+        }
+        pp.finish()
+    }
+
+    // push a byte, or the end of input, to a parse of this parser's grammar
+    fn push<'p>(&'p self, pp: &mut PushParser<'p,ByteToken>, token: ByteToken) -> Result<(),ParseError> {
+        let position = token.offset;
+        match token.byte {
+            Some(b) => {
+                let candidates = self.terminals.iter().filter(|(_, class)| class.contains(b)).map(|(t, _)| *t);
+                push_first(pp, token, candidates, position)
+            },
+            None => pp.push(token, self.grammar().symbol_db().eoi()),
+        }
+    }
+}
+
+// how a stream of bytes is cut into messages
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum Framing {
+    // each message comes after its length in bytes, a big endian number of this many bytes
+    LengthPrefixed(usize),
+    // each message is followed by these bytes, which can't appear in it
+    Delimited(Vec<u8>),
+}
+
+// a message cut from the stream, and its tree. offset is where the message starts in the stream
+// (after any length), while the offsets of its tokens and the position of an error count from the
+// start of the message
+#[derive(Debug)]
+pub struct Frame {
+    pub offset: usize,
+    pub tree: Result<ParseTree<ByteToken>,ParseError>,
+}
+
+// parses the messages of a framed protocol as their bytes arrive, e.g. off a socket. the bytes are
+// handed over in whatever pieces they come in, and go straight into a push parse of the message
+// they belong to, so a message is parsed as it arrives rather than once all of it is there. the
+// only bytes held back are a length that's still coming, or the end of a piece that could be the
+// start of a delimiter
+//
+// a message longer than the maximum length is returned with a FrameTooLong error as soon as it
+// gets too long (or, with a length prefix, as soon as its length is known), and the rest of it is
+// skipped as it arrives
+pub struct FrameParser<'p> {
+    parser: &'p ByteParser,
+    framing: Framing,
+    max_length: usize,
+    buffer: Vec<u8>,
+    // the offset in the stream of the first byte of the buffer
+    offset: usize,
+    // the message the next bytes belong to, once it has started
+    message: Option<Message<'p>>,
+}
+
+// a message that has started to arrive
+struct Message<'p> {
+    // where it starts in the stream
+    offset: usize,
+    // the bytes of it so far
+    length: usize,
+    // the bytes still to come, when the length is known
+    remaining: Option<usize>,
+    progress: Progress<'p>,
+}
+
+enum Progress<'p> {
+    Parsing(PushParser<'p,ByteToken>),
+    // the parse failed, and the rest of the message is skipped
+    Failed(ParseError),
+    // the message was too long, and has been returned already. the rest of it is skipped
+    Returned,
+}
+
+impl<'p> FrameParser<'p> {
+    // the longest message there can be unless with_max_length says otherwise, 16 MiB
+    pub const DEFAULT_MAX_LENGTH: usize = 1 << 24;
+
+    pub fn new(parser: &'p ByteParser, framing: Framing) -> FrameParser<'p> {
+        match &framing {
+            Framing::LengthPrefixed(width) => assert!((1..=8).contains(width), "a length has to be from 1 to 8 bytes"),
+            Framing::Delimited(delimiter) => assert!(!delimiter.is_empty(), "the delimiter can't be empty"),
+        }
+        FrameParser { parser, framing, max_length: Self::DEFAULT_MAX_LENGTH, buffer: Vec::new(), offset: 0, message: None }
+    }
+
+    // the longest message that's parsed, in bytes. a longer one is an error
+    pub fn with_max_length(mut self, max_length: usize) -> FrameParser<'p> {
+        self.max_length = max_length;
+        self
+    }
+
+    // take the next piece of the stream, returning the messages it completes (or finds to be too
+    // long), in order. a message that doesn't parse is returned with its error, and doesn't stop
+    // the messages after it
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Frame> {
+        self.buffer.extend_from_slice(bytes);
+        let mut frames = Vec::new();
+        loop {
+            let taken = match self.framing {
+                Framing::LengthPrefixed(width) => self.take_length_prefixed(width, &mut frames),
+                Framing::Delimited(_) => self.take_delimited(&mut frames),
+            };
+            if taken == 0 {
+                break;
+            }
+            self.buffer.drain(..taken);
+            self.offset += taken;
+        }
+        frames
+    }
+
+    // the bytes held back, which haven't gone to a message yet
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    // handle what can be handled at the start of the buffer, returning how many bytes that took
+    fn take_length_prefixed(&mut self, width: usize, frames: &mut Vec<Frame>) -> usize {
+        let Some(message) = &mut self.message else {
+            let Some(prefix) = self.buffer.get(..width) else {
+                return 0;
+            };
+            // the length comes from the stream, so it can be too big to address, which makes it too
+            // long too
+            let length = prefix.iter().try_fold(0usize, |n, b| Some(n.checked_mul(256)? | *b as usize));
+            let mut message = self.start(self.offset + width);
+            message.remaining = Some(length.unwrap_or(usize::MAX));
+            if length.is_none_or(|length| length > self.max_length) {
+                frames.push(Frame { offset: message.offset, tree: Err(ParseError::FrameTooLong { limit: self.max_length }) });
+                message.progress = Progress::Returned;
+            }
+            self.message = Some(message);
+            self.finish_complete(frames);
+            return width;
+        };
+        let remaining = message.remaining.unwrap();
+        let taken = remaining.min(self.buffer.len());
+        for i in 0..taken {
+            message.feed(self.parser, self.buffer[i]);
+        }
+        message.remaining = Some(remaining - taken);
+        self.finish_complete(frames);
+        taken
+    }
+
+    fn take_delimited(&mut self, frames: &mut Vec<Frame>) -> usize {
+        let Framing::Delimited(delimiter) = &self.framing else {
+            unreachable!();
+        };
+        let width = delimiter.len();
+        if self.buffer.is_empty() {
+            return 0;
+        }
+        let found = self.buffer.windows(width).position(|w| w == delimiter.as_slice());
+        // without a delimiter, the bytes that can't be the start of one
+        let (end, taken) = match found {
+            Some(end) => (end, end + width),
+            None => {
+                let end = self.buffer.len().saturating_sub(width - 1);
+                (end, end)
+            },
+        };
+        if self.message.is_none() {
+            self.message = Some(self.start(self.offset));
+        }
+        let message = self.message.as_mut().unwrap();
+        for i in 0..end {
+            if message.length == self.max_length {
+                message.too_long(self.max_length, frames);
+            }
+            message.feed(self.parser, self.buffer[i]);
+        }
+        if found.is_some() {
+            self.message.as_mut().unwrap().remaining = Some(0);
+            self.finish_complete(frames);
+        }
+        taken
+    }
+
+    fn start(&self, offset: usize) -> Message<'p> {
+        Message { offset, length: 0, remaining: None, progress: Progress::Parsing(PushParser::new(self.parser.parser())) }
+    }
+
+    // return the current message if all of it is there
+    fn finish_complete(&mut self, frames: &mut Vec<Frame>) {
+        if self.message.as_ref().is_none_or(|m| m.remaining != Some(0)) {
+            return;
+        }
+        let message = self.message.take().unwrap();
+        let tree = match message.progress {
+            Progress::Parsing(mut pp) => self.parser.push(&mut pp, ByteToken { byte: None, offset: message.length }).and_then(|_| pp.finish()),
+            Progress::Failed(error) => Err(error),
+            Progress::Returned => return,
+        };
+        frames.push(Frame { offset: message.offset, tree });
+    }
+}
+
+impl<'p> Message<'p> {
+    fn feed(&mut self, parser: &'p ByteParser, b: u8) {
+        if let Progress::Parsing(pp) = &mut self.progress {
+            if let Err(error) = parser.push(pp, ByteToken { byte: Some(b), offset: self.length }) {
+                self.progress = Progress::Failed(error);
+            }
+        }
+        self.length += 1;
+    }
+
+    // return the message with an error once it has got too long, which is the error it failed
+    // with if it already has
+    fn too_long(&mut self, limit: usize, frames: &mut Vec<Frame>) {
+        let error = match std::mem::replace(&mut self.progress, Progress::Returned) {
+            Progress::Parsing(_) => ParseError::FrameTooLong { limit },
+            Progress::Failed(error) => error,
+            Progress::Returned => return,
+        };
+        frames.push(Frame { offset: self.offset, tree: Err(error) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_class_01() {
+        let c = ByteClass::parse("0x09,0x20-0x7e").unwrap();
+        assert!(c.contains(0x09) && c.contains(b'a') && !c.contains(0x0a) && !c.contains(0x7f));
+        assert!(ByteClass::parse("0xff").unwrap().contains(0xff));
+        assert!(ByteClass::parse("0x7").is_err());
+        assert!(ByteClass::parse("0x40-0x30").is_err());
+        assert!(ByteClass::parse("a").is_err());
+    }
+
+    /* grammar:
+     *   file -> 0x89 0x50 0x4e 0x47 numbers
+     *   numbers -> ε | numbers number
+     *   number -> 0x00-0x7f | 0x80-0xff number
+     */
+    #[test]
+    fn byte_parser_01() {
+        let bnf = "
+            file -> 0x89 0x50 0x4e 0x47 numbers
+            numbers -> ε | numbers number
+            number -> 0x00-0x7f | 0x80-0xff number
+        ";
+        let p = ByteParser::new(Grammar::from_bnf(bnf, "leb128").unwrap()).unwrap();
+        assert!(p.parser().parse_tables().conflicts().is_empty());
+
+        let bytes = [0x89, b'P', b'N', b'G', 0x05, 0xe5, 0x8e, 0x26, 0x89, 0x01];
+        let tree = p.parse(&bytes).unwrap();
+        let number = p.grammar().symbol_db().symbol("number").unwrap();
+        // decode the numbers, least significant group first
+        let values = tree.fold(&mut |node: &ParseTree<ByteToken>, children: Vec<Vec<u64>>| {
+            if *node.symbol() != number {
+                return children.concat();
+            }
+            let low = (node.children()[0].token().byte.unwrap() & 0x7f) as u64;
+            vec![children.concat().first().map_or(low, |rest| low | rest << 7)]
+        });
+        assert_eq!(values, vec![5, 624485, 137]);
+
+        assert_eq!(p.parse(&[0x89, b'P', b'N', b'X']).err().unwrap().position(), 3);
+        let error = p.parse(&[0x89, b'P', b'N', b'G', 0x80]).err().unwrap();
+        assert_eq!(error.span(&ByteToken::of(&[0x89, b'P', b'N', b'G', 0x80])), Some(5..5));
+    }
+
+    /* grammar:
+     *   message -> 0x01 digits | 0x02
+     *   digits -> 0x30-0x39 | digits 0x30-0x39
+     */
+    fn messages() -> ByteParser {
+        let bnf = "
+            message -> 0x01 digits | 0x02
+            digits -> 0x30-0x39 | digits 0x30-0x39
+        ";
+        ByteParser::new(Grammar::from_bnf(bnf, "messages").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn frame_parser_01() {
+        let p = messages();
+        let mut frames = FrameParser::new(&p, Framing::LengthPrefixed(2));
+
+        // a message split across pieces, and two in one piece. the bytes of a message go to its
+        // parse as they come, so nothing is held back but the start of the next length
+        assert!(frames.push(&[0x00, 0x03, 0x01]).is_empty());
+        assert_eq!(frames.buffered(), 0);
+        let result = frames.push(&[b'4', b'2', 0x00, 0x01, 0x02, 0x00]);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].offset, 2);
+        assert_eq!(result[0].tree.as_ref().unwrap().children().len(), 2);
+        assert_eq!(result[1].offset, 7);
+        assert!(result[1].tree.is_ok());
+        assert_eq!(frames.buffered(), 1);
+
+        // a message that doesn't parse doesn't hold up the next one
+        let result = frames.push(&[0x02, 0x01, b'x', 0x00, 0x02, 0x01, b'7']);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].offset, 10);
+        assert_eq!(result[0].tree.as_ref().err().unwrap().position(), 1);
+        assert_eq!(result[1].offset, 14);
+        assert!(result[1].tree.is_ok());
+        assert_eq!(frames.buffered(), 0);
+
+        // a length too big to address, or just more than the limit, is an error as soon as it's
+        // read, and the bytes of the message are skipped rather than kept
+        let mut frames = FrameParser::new(&p, Framing::LengthPrefixed(8));
+        let result = frames.push(&[0xff; 9]);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].tree.as_ref().err(), Some(&ParseError::FrameTooLong { limit: FrameParser::DEFAULT_MAX_LENGTH }));
+        assert!(frames.push(&[0x00; 1000]).is_empty());
+        assert_eq!(frames.buffered(), 0);
+
+        let mut frames = FrameParser::new(&p, Framing::LengthPrefixed(1)).with_max_length(2);
+        let result = frames.push(&[0x03, 0x01, 0x02]);
+        assert_eq!((result.len(), result[0].offset), (1, 1));
+        assert_eq!(result[0].tree.as_ref().err().unwrap().position(), 2);
+        let result = frames.push(&[b'9', 0x01, 0x02]);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].offset, 5);
+        assert!(result[0].tree.is_ok());
+    }
+
+    #[test]
+    fn frame_parser_02() {
+        let p = messages();
+        let mut frames = FrameParser::new(&p, Framing::Delimited(b"\r\n".to_vec()));
+
+        // the delimiter split between pieces
+        assert!(frames.push(&[0x01, b'1', b'2', b'\r']).is_empty());
+        let result = frames.push(&[b'\n', 0x02, b'\r', b'\n', 0x01]);
+        assert_eq!(result.len(), 2);
+        assert_eq!((result[0].offset, result[1].offset), (0, 5));
+        assert!(result.iter().all(|f| f.tree.is_ok()));
+        assert_eq!(frames.buffered(), 1);
+
+        for b in [b'5', b'\r'] {
+            assert!(frames.push(&[b]).is_empty());
+        }
+        let result = frames.push(b"\n\r\n");
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].offset, 8);
+        assert!(result[0].tree.is_ok());
+        // an empty message
+        assert_eq!(result[1].offset, 12);
+        assert!(result[1].tree.is_err());
+        assert_eq!(frames.buffered(), 0);
+
+        // a message that goes on past the limit is an error once it does, and what's left of it
+        // up to the delimiter is skipped
+        let mut frames = FrameParser::new(&p, Framing::Delimited(b";".to_vec())).with_max_length(2);
+        let result = frames.push(&[0x01, b'1', b'2']);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].tree.as_ref().err(), Some(&ParseError::FrameTooLong { limit: 2 }));
+        let result = frames.push(&[b'3', b';', 0x01, b'4', b';']);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].offset, 5);
+        assert!(result[0].tree.is_ok());
+    }
+}
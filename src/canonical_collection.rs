@@ -1,80 +1,290 @@
-use std::collections::{BTreeMap,BTreeSet,HashMap,HashSet};
+use std::collections::{BTreeMap,BTreeSet,HashMap,HashSet,VecDeque};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash,Hasher};
+use std::sync::Arc;
 
+use super::diagnostics::{label,Diagnostics};
 use super::grammar::Grammar;
 use super::lr1_item::LR1Item;
+use super::parse_tables::{self,ParseTables};
+use super::production::{Production,ProductionId};
 use super::first_and_follow::FirstAndFollow;
-use super::production::Production;
-use super::symbol::Symbol;
+use super::symbol::{Symbol,SymbolDb};
+use super::symbol_set::TerminalSet;
+use super::table_hash::TableMap;
 
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 pub struct CanonicalCollection {
+    goal: Symbol,
     next_number: u32,
     int_to_set: BTreeMap<u32,BTreeSet<LR1Item>>,
-    set_to_int: BTreeMap<BTreeSet<LR1Item>,u32>,
-    transitions: HashMap<(u32,Symbol),u32>,
-    unprocessed: Vec<BTreeSet<LR1Item>>
+    // sets keyed by the fingerprint of their kernel. different kernels can share a fingerprint, so
+    // a lookup still has to compare the sets themselves, but only against the few candidates
+    // with the same fingerprint
+    fingerprint_to_int: TableMap<u64,Vec<u32>>,
+    productions: SharedProductions,
+    first_cache: FirstCache,
+    transitions: TableMap<(u32,Symbol),u32>,
+    unprocessed: Vec<u32>,
 }
 
 impl CanonicalCollection {
     pub fn new(grammar: &Grammar) -> CanonicalCollection {
-        build(grammar)
+        build(grammar, &mut Diagnostics::new())
+    }
+
+    // like new, but also reports anything odd the first sets reveal about the grammar
+    pub fn new_with_diagnostics(grammar: &Grammar, diagnostics: &mut Diagnostics) -> CanonicalCollection {
+        build(grammar, diagnostics)
     }
 
     pub fn contains(&self, set: &BTreeSet<LR1Item>) -> bool {
-        self.set_to_int.contains_key(set)
+        self.find(set).is_some()
+    }
+
+    // the number of the set, if it's in the collection
+    pub fn find(&self, set: &BTreeSet<LR1Item>) -> Option<u32> {
+        self.fingerprint_to_int.get(&self.fingerprint(set))?
+            .iter()
+            .find(|n| &self.int_to_set[n] == set)
+            .copied()
     }
 
     pub fn sets(&self) -> &BTreeMap<u32,BTreeSet<LR1Item>> {
         &self.int_to_set
     }
 
-    pub fn transitions(&self) -> &HashMap<(u32,Symbol),u32> {
+    pub fn transitions(&self) -> &TableMap<(u32,Symbol),u32> {
         &self.transitions
     }
 
-    pub fn take_unprocessed(&mut self) -> Vec<BTreeSet<LR1Item>> {
-        std::mem::replace(&mut self.unprocessed, Vec::new())
+    pub fn take_unprocessed(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.unprocessed)
+    }
+
+    // update the collection for a grammar that's just had production p added, given the first
+    // sets from before the addition. a set is the closure of its kernel, and the kernels of the
+    // existing sets stay as they are, so only the sets whose closure can pick up new items need
+    // recomputing: those with the dot before a nonterminal whose expansion changes. those sets
+    // then get their transitions worked out again, which leads to whatever new sets the
+    // production brings. sets that can no longer be reached are dropped at the end
+    pub(crate) fn extend(&mut self, grammar: &Grammar, old_first: &FirstAndFollow, p: &Production) {
+        let symbol_db = grammar.symbol_db();
+        let first_and_follow = FirstAndFollow::new(grammar);
+        let epsilon = symbol_db.epsilon();
+        self.productions = SharedProductions::new(grammar);
+        self.first_cache = FirstCache::default();
+
+        // the nonterminals whose first sets changed (which includes becoming nullable)
+        let changed: HashSet<Symbol> = grammar.nonterminals().iter()
+            .filter(|nt| old_first.first(nt) != first_and_follow.first(nt))
+            .collect();
+        // could the lookaheads that the symbols give to a nonterminal in front of them change?
+        let lookaheads_change = |symbols: &[Symbol]| {
+            for s in symbols {
+                if changed.contains(s) {
+                    return true;
+                }
+                if !first_and_follow.first(s).is_some_and(|f| f.contains(&epsilon)) {
+                    return false;
+                }
+            }
+            false
+        };
+
+        // the nonterminals whose expansion in a closure can change: the lhs of p, and anything
+        // that starts with one of them or with a nonterminal whose lookaheads change
+        let mut dirty: HashSet<Symbol> = HashSet::new();
+        dirty.insert(*p.lhs());
+        loop {
+            let before = dirty.len();
+            for q in grammar.all_productions() {
+                if let Some(s) = q.rhs().first() {
+                    if !symbol_db.is_terminal(s) && (dirty.contains(s) || lookaheads_change(&q.rhs()[1..])) {
+                        dirty.insert(*q.lhs());
+                    }
+                }
+            }
+            if dirty.len() == before {
+                break;
+            }
+        }
+
+        let affected: Vec<u32> = self.int_to_set.iter()
+            .filter(|(_, set)| set.iter().any(|item| {
+                let unseen = item.symbols_after_dot();
+                !unseen.is_empty() && !symbol_db.is_terminal(&unseen[0]) &&
+                    (dirty.contains(&unseen[0]) || lookaheads_change(&unseen[1..]))
+            }))
+            .map(|(n, _)| *n)
+            .collect();
+        for n in &affected {
+            let kernel: BTreeSet<LR1Item> = self.int_to_set[n].iter()
+                .filter(|item| item.dot_position() > 0 || *item.production().lhs() == self.goal)
+                .cloned()
+                .collect();
+            // the kernel is the same, so the set keeps its fingerprint
+            self.int_to_set.insert(*n, closure(&self.productions, &mut self.first_cache, &first_and_follow, grammar, kernel));
+        }
+        let affected: HashSet<u32> = affected.into_iter().collect();
+        self.transitions.retain(|(from, _), _| !affected.contains(from));
+        self.unprocessed.extend(affected);
+
+        self.explore(&first_and_follow, grammar);
+        self.remove_unreachable();
+        self.renumber();
+    }
+
+    // a hash of the set's kernel, i.e. the items that didn't come from taking the closure (the
+    // ones with something before the dot, plus the initial goal item). a set is the closure of its
+    // kernel, so equal sets always have equal fingerprints. the items of a BTreeSet come out in
+    // order, so the encoding doesn't depend on how the set was built
+    fn fingerprint(&self, set: &BTreeSet<LR1Item>) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for item in set {
+            if item.dot_position() > 0 || *item.production().lhs() == self.goal {
+                item.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
     }
 
-    fn add(&mut self, set: BTreeSet<LR1Item>) {
-        if self.set_to_int.contains_key(&set) {
+    fn add(&mut self, set: BTreeSet<LR1Item>) -> u32 {
+        if self.contains(&set) {
             panic!("set is already in CC")
         }
         let n = self.next_number;
-        self.set_to_int.insert(set.clone(), n);
-        self.int_to_set.insert(n, set.clone());
-        self.unprocessed.push(set);
+        self.fingerprint_to_int.entry(self.fingerprint(&set)).or_default().push(n);
+        self.int_to_set.insert(n, set);
+        self.unprocessed.push(n);
         self.next_number = n + 1;
+        n
     }
 
-    fn add_transition(&mut self, from: BTreeSet<LR1Item>, on: Symbol, to: BTreeSet<LR1Item>) {
-        if !self.set_to_int.contains_key(&from) {
-            panic!("[from] not in CC: {:?}", from);
+    // drop the sets that set 0 has no path to
+    fn remove_unreachable(&mut self) {
+        let mut successors: HashMap<u32,Vec<u32>> = HashMap::new();
+        for (&(from, _), &to) in &self.transitions {
+            successors.entry(from).or_default().push(to);
+        }
+        let mut reachable = HashSet::new();
+        let mut pending = vec![0];
+        while let Some(n) = pending.pop() {
+            if reachable.insert(n) {
+                pending.extend(successors.get(&n).into_iter().flatten());
+            }
         }
-        if !self.set_to_int.contains_key(&to) {
-            panic!("[to] not in CC: {:?}", to);
+        self.int_to_set.retain(|n, _| reachable.contains(n));
+        for numbers in self.fingerprint_to_int.values_mut() {
+            numbers.retain(|n| reachable.contains(n));
         }
-        let from_n = *self.set_to_int.get(&from).unwrap();
-        let to_n = *self.set_to_int.get(&to).unwrap();
-        let key = (from_n, on);
-        if let Some(existing) = self.transitions.get(&key) {
-            if *existing != to_n {
-                panic!("attempting to alter an existing transition");
+        self.fingerprint_to_int.retain(|_, numbers| !numbers.is_empty());
+        self.transitions.retain(|(from, _), _| reachable.contains(from));
+    }
+
+    // work out the transitions of the unprocessed sets, adding the sets they lead to (which are
+    // then processed in turn) until there's nothing new. a set is unprocessed from when it's added
+    // (or, in extend, from when its closure changes and its transitions are dropped) until it's
+    // explored, so no set is explored twice
+    fn explore(&mut self, first_and_follow: &FirstAndFollow, grammar: &Grammar) {
+        loop {
+            let unprocessed = self.take_unprocessed();
+            if unprocessed.is_empty() {
+                break;
             }
-        } else {
-            self.transitions.insert(key, to_n);
+            for i in unprocessed {
+                self.explore_set(i, first_and_follow, grammar);
+            }
+        }
+    }
+
+    // work out the transitions of set i, adding the sets they lead to as unprocessed. returns the
+    // transitions that were added, in the order they were found
+    fn explore_set(&mut self, i: u32, first_and_follow: &FirstAndFollow, grammar: &Grammar) -> Vec<(Symbol,u32)> {
+        // the go_to sets are worked out first, while the set is borrowed, rather than from a copy
+        // of it. the kernels of all of them come from one pass over the set, and each is closed
+        // once, whatever number of items it has in common with the set
+        let cc_i = &self.int_to_set[&i];
+        let targets: Vec<(Symbol,BTreeSet<LR1Item>)> = kernels(grammar.symbol_db(), cc_i).into_iter()
+            .map(|(x, kernel)| (x, closure(&self.productions, &mut self.first_cache, first_and_follow, grammar, kernel)))
+            .collect();
+        let mut added = Vec::new();
+        for (x, temp) in targets {
+            // if this set isn't already part of cc, then add it
+            let j = match self.find(&temp) {
+                Some(j) => j,
+                None => self.add(temp),
+            };
+            // record the transition from cc_i on the symbol x to the new set
+            self.add_transition(i, x, j);
+            added.push((x, j));
+        }
+        added
+    }
+
+    // renumber the sets in the order a depth first walk of the transitions (in symbol order) from
+    // set 0 visits them, so that a state and the states it leads to tend to get nearby numbers,
+    // and therefore nearby rows in dense tables. sets are otherwise numbered in the order they
+    // happen to be discovered, which scatters them. sets that can't be reached go at the end
+    fn renumber(&mut self) {
+        // sets may have been removed, so the numbers can have gaps
+        let count = self.next_number as usize;
+        let mut successors: Vec<Vec<(Symbol,u32)>> = vec![Vec::new(); count];
+        for (&(from, symbol), &to) in &self.transitions {
+            successors[from as usize].push((symbol, to));
+        }
+        for s in &mut successors {
+            s.sort();
+        }
+
+        let mut mapping: Vec<Option<u32>> = vec![None; count];
+        let mut next = 0;
+        let mut pending = vec![0];
+        while let Some(n) = pending.pop() {
+            if mapping[n as usize].is_some() {
+                continue;
+            }
+            mapping[n as usize] = Some(next);
+            next += 1;
+            // pushed in reverse so the smallest symbol is visited first
+            pending.extend(successors[n as usize].iter().rev().map(|(_, to)| *to));
+        }
+        for n in self.int_to_set.keys() {
+            if mapping[*n as usize].is_none() {
+                mapping[*n as usize] = Some(next);
+                next += 1;
+            }
+        }
+        self.next_number = next;
+        let map = |n: u32| mapping[n as usize].unwrap();
+
+        self.int_to_set = std::mem::take(&mut self.int_to_set).into_iter()
+            .map(|(n, set)| (map(n), set))
+            .collect();
+        for numbers in self.fingerprint_to_int.values_mut() {
+            for n in numbers.iter_mut() {
+                *n = map(*n);
+            }
+        }
+        self.transitions = self.transitions.drain()
+            .map(|((from, symbol), to)| ((map(from), symbol), map(to)))
+            .collect();
+    }
+
+    // every set is explored once, so its transitions are only ever worked out once
+    fn add_transition(&mut self, from: u32, on: Symbol, to: u32) {
+        if self.transitions.insert((from, on), to).is_some() {
+            panic!("attempt to replace an existing transition");
         }
     }
 }
 
-fn first(grammar: &Grammar, first_and_follow: &FirstAndFollow, symbols: &[Symbol]) -> HashSet<Symbol> {
-    let mut result: HashSet<Symbol> = HashSet::new();
+fn first(grammar: &Grammar, first_and_follow: &FirstAndFollow, symbols: &[Symbol]) -> TerminalSet {
+    let mut result = TerminalSet::new();
     // add the first sets of each individual symbol until a set does not contain epsilon
     for symbol in symbols {
         if let Some(tmp) = first_and_follow.first(symbol) {
-            for s in tmp {
-                result.insert(*s);
-            }
+            result.union_with(tmp);
             if !tmp.contains(&grammar.symbol_db().epsilon()) {
                 break;
             }
@@ -84,7 +294,63 @@ fn first(grammar: &Grammar, first_and_follow: &FirstAndFollow, symbols: &[Symbol
     result
 }
 
-fn closure(first_and_follow: &FirstAndFollow, grammar: &Grammar, items: BTreeSet<LR1Item>) -> BTreeSet<LR1Item> {
+// one shared copy of each production, which all of the items of the production refer to. items are
+// copied from set to set as the collection is built, and the sets keep them all, so an item that
+// carried its own copy of the production would cost an allocation per item
+#[derive(Clone,Debug,Default)]
+struct SharedProductions {
+    by_lhs: TableMap<Symbol,Vec<Arc<Production>>>,
+}
+
+impl SharedProductions {
+    fn new(grammar: &Grammar) -> SharedProductions {
+        let mut by_lhs: TableMap<Symbol,Vec<Arc<Production>>> = TableMap::default();
+        for p in grammar.all_productions() {
+            by_lhs.entry(*p.lhs()).or_default().push(Arc::new(p.clone()));
+        }
+        SharedProductions { by_lhs }
+    }
+
+    fn of(&self, lhs: &Symbol) -> &[Arc<Production>] {
+        self.by_lhs.get(lhs).map(|ps| ps.as_slice()).unwrap_or_default()
+    }
+}
+
+// the first set of what comes after the next symbol of an item, followed by its lookahead: the
+// lookaheads of the items the closure adds for that symbol. the same items turn up in set after
+// set, so with the first-cache feature the sets are kept, keyed by the item's production (by
+// address, which is unique as the items share their productions), dot and lookahead. the cache
+// holds a set for every distinct item the closures see, so it's off by default
+#[derive(Clone,Debug,Default)]
+struct FirstCache {
+    #[cfg(feature = "first-cache")]
+    sets: TableMap<(usize,usize,Symbol),TerminalSet>,
+    #[cfg(not(feature = "first-cache"))]
+    last: TerminalSet,
+}
+
+impl FirstCache {
+    fn lookaheads(&mut self, grammar: &Grammar, first_and_follow: &FirstAndFollow, item: &LR1Item) -> &TerminalSet {
+        // the sentence after the dot minus the leading symbol, with the item's lookahead on the end
+        let compute = || {
+            let mut rest = item.symbols_after_dot().split_off(1);
+            rest.push(*item.lookahead());
+            first(grammar, first_and_follow, &rest)
+        };
+        #[cfg(feature = "first-cache")]
+        {
+            let key = (item.production() as *const Production as usize, item.dot_position(), *item.lookahead());
+            self.sets.entry(key).or_insert_with(compute)
+        }
+        #[cfg(not(feature = "first-cache"))]
+        {
+            self.last = compute();
+            &self.last
+        }
+    }
+}
+
+fn closure(productions: &SharedProductions, cache: &mut FirstCache, first_and_follow: &FirstAndFollow, grammar: &Grammar, items: BTreeSet<LR1Item>) -> BTreeSet<LR1Item> {
     let mut result = BTreeSet::new();
 
     // all items in a set are in its closure
@@ -96,25 +362,18 @@ fn closure(first_and_follow: &FirstAndFollow, grammar: &Grammar, items: BTreeSet
         let mut updates: BTreeSet<LR1Item> = BTreeSet::new();
         // for each of the items in the current set of results
         for i in &result {
-            // get the sentence after the dot
-            let mut unseen = i.symbols_after_dot();
-            // if the sentence is not empty and the first symbol is a non-terminal
-            if !unseen.is_empty() {
-                let s: Symbol = unseen[0];
+            // if the sentence after the dot is not empty and the first symbol is a non-terminal
+            if let Some(s) = i.next_symbol() {
                 if !grammar.symbol_db().is_terminal(&s) {
-                    // append the item's lookahead to the sentence
-                    unseen.push(*i.lookahead());
-                    // and calculate the first of the sentence minus the leading non-terminal
-                    let first = first(grammar, first_and_follow, &unseen[1..]);
+                    // calculate the first of the rest of the sentence and the item's lookahead
+                    let first = cache.lookaheads(grammar, first_and_follow, i);
                     // for every production rule deriving from the non-terminal
-                    if let Some(ps) = grammar.productions(&s) {
-                        for p in ps {
-                            //and every terminal in the previously computed first set
-                            for b in &first {
-                                // add a new item
-                                let prod = LR1Item::new(p.clone(), 0, *b);
-                                updates.insert(prod);
-                            }
+                    for p in productions.of(&s) {
+                        //and every terminal in the previously computed first set
+                        for b in first {
+                            // add a new item
+                            let prod = LR1Item::shared(p.clone(), 0, b);
+                            updates.insert(prod);
                         }
                     }
                 }
@@ -135,71 +394,253 @@ fn closure(first_and_follow: &FirstAndFollow, grammar: &Grammar, items: BTreeSet
     result
 }
 
-fn go_to(first_and_follow: &FirstAndFollow,
-         grammar: &Grammar,
-         items: &BTreeSet<LR1Item>,
-         symbol: &Symbol) -> BTreeSet<LR1Item> {
-    let mut result = BTreeSet::new();
+// the kernel of the go_to set of every symbol that comes after a dot in the items: the items of
+// the form a -> b.xc, with the dot moved past the x. the symbols are in the order their first
+// items come in. the parser accepts rather than shifting the $ of the augmented production, so
+// there's no go_to set for that
+fn kernels(symbol_db: &SymbolDb, items: &BTreeSet<LR1Item>) -> Vec<(Symbol,BTreeSet<LR1Item>)> {
+    let mut result: Vec<(Symbol,BTreeSet<LR1Item>)> = Vec::new();
     for item in items {
-        let unseen = item.symbols_after_dot();
-        if !unseen.is_empty() && &unseen[0] == symbol {
-            result.insert(LR1Item::new(item.production().clone(), item.dot_position() + 1, item.lookahead().clone()));
+        let Some(x) = item.next_symbol() else { continue };
+        if item.is_accepting(symbol_db) {
+            continue;
+        }
+        match result.iter_mut().find(|(s, _)| *s == x) {
+            Some((_, kernel)) => { kernel.insert(item.advanced()); },
+            None => result.push((x, BTreeSet::from([item.advanced()]))),
         }
     }
-    closure(first_and_follow, grammar, result)
+    result
 }
 
-fn build(grammar: &Grammar) -> CanonicalCollection {
-    let symbol_db = grammar.symbol_db();
-    let first_and_follow = FirstAndFollow::new(grammar);
+#[cfg(test)]
+fn go_to(productions: &SharedProductions,
+         first_and_follow: &FirstAndFollow,
+         grammar: &Grammar,
+         items: &BTreeSet<LR1Item>,
+         symbol: &Symbol) -> BTreeSet<LR1Item> {
+    let kernel = kernels(grammar.symbol_db(), items).into_iter()
+        .find(|(x, _)| x == symbol)
+        .map(|(_, kernel)| kernel)
+        .unwrap_or_default();
+    closure(productions, &mut FirstCache::default(), first_and_follow, grammar, kernel)
+}
 
+// a collection holding just the initial set: the whole input has to derive from the augmented
+// production, followed by the end of input
+fn start(grammar: &Grammar, first_and_follow: &FirstAndFollow) -> CanonicalCollection {
+    let symbol_db = grammar.symbol_db();
     let mut cc = CanonicalCollection {
+        goal: symbol_db.goal(),
         next_number: 0,
         int_to_set: BTreeMap::new(),
-        set_to_int: BTreeMap::new(),
-        transitions: HashMap::new(),
+        fingerprint_to_int: TableMap::default(),
+        productions: SharedProductions::new(grammar),
+        first_cache: FirstCache::default(),
+        transitions: TableMap::default(),
         unprocessed: Vec::new(),
     };
 
-    let p = Production::new(symbol_db.goal(), vec![*grammar.start_symbol()]);
     let mut initial = BTreeSet::new();
-    initial.insert(LR1Item::new(p, 0, symbol_db.eoi()));
-
-    let cc0 = closure(&first_and_follow, &grammar, initial);
+    // the augmented production is the only one for the goal
+    for p in cc.productions.of(&symbol_db.goal()) {
+        initial.insert(LR1Item::shared(p.clone(), 0, symbol_db.eoi()));
+    }
 
+    let cc0 = closure(&cc.productions, &mut cc.first_cache, first_and_follow, grammar, initial);
     cc.add(cc0);
+    cc
+}
 
-    let mut done = false;
-    while !done {
-        done = true;
-        // for each unprocessed set in cc
-        for cc_i in cc.take_unprocessed() {
-            // for each item in the set
-            for item in &cc_i {
-                let unseen = item.symbols_after_dot();
-                if !unseen.is_empty() {
-                    // if the item is of the form a -> b.xc
-                    let x = &unseen[0];
-                    // calculate the go_to set for the item and the symbol x
-                    let temp = go_to(&first_and_follow, grammar, &cc_i, x);
-                    // if this set isn't already part of cc, then add it
-                    if !cc.contains(&temp) {
-                        cc.add(temp.clone());
-                        done = false;
-                    }
-                    // record the transition from cc_i on the symbol x to the new set
-                    cc.add_transition(cc_i.clone(), *x, temp);
-                }
+fn build(grammar: &Grammar, diagnostics: &mut Diagnostics) -> CanonicalCollection {
+    let first_and_follow = FirstAndFollow::new(grammar);
+    first_and_follow.diagnose(grammar, diagnostics);
+
+    let mut cc = start(grammar, &first_and_follow);
+    cc.explore(&first_and_follow, grammar);
+    cc.renumber();
+    cc
+}
+
+// like build, but gives up once there are more than limit sets
+pub(crate) fn build_limited(grammar: &Grammar, limit: usize, diagnostics: &mut Diagnostics) -> Result<CanonicalCollection,StateExplosion> {
+    let first_and_follow = FirstAndFollow::new(grammar);
+    first_and_follow.diagnose(grammar, diagnostics);
+
+    let mut cc = start(grammar, &first_and_follow);
+    let mut pending: VecDeque<u32> = cc.take_unprocessed().into();
+    while let Some(i) = pending.pop_front() {
+        cc.explore_set(i, &first_and_follow, grammar);
+        pending.extend(cc.take_unprocessed());
+        if cc.int_to_set.len() > limit {
+            return Err(StateExplosion::new(grammar, &cc, limit));
+        }
+    }
+    cc.renumber();
+    Ok(cc)
+}
+
+// what a build that found more states than it was allowed had found when it stopped: how many
+// states each production and nonterminal accounts for, most first. a state counts towards the
+// productions of its kernel items (the ones it was entered with, as opposed to the ones the
+// closure added) and towards their left hand sides, so the top entries are the rules whose items
+// get split across the most states
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct StateExplosion {
+    pub limit: usize,
+    pub states: usize,
+    pub productions: Vec<(ProductionId,usize)>,
+    pub nonterminals: Vec<(Symbol,usize)>,
+}
+
+impl StateExplosion {
+    fn new(grammar: &Grammar, cc: &CanonicalCollection, limit: usize) -> StateExplosion {
+        let mut productions: BTreeMap<ProductionId,usize> = BTreeMap::new();
+        let mut nonterminals: BTreeMap<Symbol,usize> = BTreeMap::new();
+        for set in cc.int_to_set.values() {
+            // the augmented production isn't something the grammar can be refactored to avoid
+            let kernel: BTreeSet<&Production> = set.iter()
+                .filter(|item| item.dot_position() > 0 && *item.production().lhs() != cc.goal)
+                .map(|item| item.production())
+                .collect();
+            let lhs: BTreeSet<Symbol> = kernel.iter().map(|p| *p.lhs()).collect();
+            for p in kernel {
+                *productions.entry(grammar.production_id(p).unwrap()).or_default() += 1;
+            }
+            for nt in lhs {
+                *nonterminals.entry(nt).or_default() += 1;
             }
         }
+        let mut productions: Vec<(ProductionId,usize)> = productions.into_iter().collect();
+        let mut nonterminals: Vec<(Symbol,usize)> = nonterminals.into_iter().collect();
+        // stable, so ties stay in id order
+        productions.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+        nonterminals.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+        StateExplosion { limit, states: cc.int_to_set.len(), productions, nonterminals }
     }
 
-    cc
+    // the report, listing the top entries of each kind
+    pub fn to_string(&self, grammar: &Grammar, top: usize) -> String {
+        let mut result = format!("more than {} states ({} found before stopping)\n", self.limit, self.states);
+        result.push_str("states by nonterminal:\n");
+        for (nt, n) in self.nonterminals.iter().take(top) {
+            result.push_str(&format!("  {:>6}  {}\n", n, label(grammar.symbol_db(), nt)));
+        }
+        result.push_str("states by production:\n");
+        for (p, n) in self.productions.iter().take(top) {
+            result.push_str(&format!("  {:>6}  {}\n", n, grammar.display_production(*p)));
+        }
+        result
+    }
+}
+
+// something that happened while building the collection. states are numbered in the order they
+// were found, which isn't the numbering of the finished collection: that's worked out once every
+// state is known
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum BuildEvent {
+    // a new state, given by its kernel items as (production, dot position, lookahead)
+    StateAdded { state: u32, kernel: Vec<(ProductionId,usize,Symbol)> },
+    TransitionAdded { from: u32, on: Symbol, to: u32 },
+}
+
+// builds the lr(1) automaton of a grammar a state at a time, as an iterator of the states and
+// transitions it adds, so that a big build can be watched as it goes. a state is announced before
+// the first transition into it. the iterator ends when the automaton is complete, and into_tables
+// finishes whatever is left of the build
+pub struct CollectionBuilder<'g> {
+    grammar: &'g Grammar,
+    first_and_follow: FirstAndFollow,
+    cc: CanonicalCollection,
+    pending: VecDeque<u32>,
+    announced: u32,
+    events: VecDeque<BuildEvent>,
+}
+
+impl<'g> CollectionBuilder<'g> {
+    pub fn new(grammar: &'g Grammar) -> CollectionBuilder<'g> {
+        let first_and_follow = FirstAndFollow::new(grammar);
+        let mut cc = start(grammar, &first_and_follow);
+        let pending = cc.take_unprocessed().into();
+        let mut builder = CollectionBuilder { grammar, first_and_follow, cc, pending, announced: 1, events: VecDeque::new() };
+        let kernel = builder.kernel(0);
+        builder.events.push_back(BuildEvent::StateAdded { state: 0, kernel });
+        builder
+    }
+
+    // the states found so far
+    pub fn state_count(&self) -> usize {
+        self.cc.int_to_set.len()
+    }
+
+    // the transitions found so far
+    pub fn transition_count(&self) -> usize {
+        self.cc.transitions.len()
+    }
+
+    // the states found so far whose transitions haven't been worked out yet
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    // the tables for the grammar, after building the rest of the automaton
+    pub fn into_tables(self) -> ParseTables {
+        let grammar = self.grammar;
+        let cc = self.finish();
+        parse_tables::from_collection(grammar, &cc, &mut Diagnostics::new())
+    }
+
+    fn finish(mut self) -> CanonicalCollection {
+        while self.step() {}
+        self.cc.renumber();
+        self.cc
+    }
+
+    fn kernel(&self, state: u32) -> Vec<(ProductionId,usize,Symbol)> {
+        self.cc.int_to_set[&state].iter()
+            .filter(|item| item.dot_position() > 0 || *item.production().lhs() == self.cc.goal)
+            .map(|item| (self.grammar.production_id(item.production()).unwrap(), item.dot_position(), *item.lookahead()))
+            .collect()
+    }
+
+    // work out the transitions of the next pending state, queueing the events. false if there
+    // was nothing left to do
+    fn step(&mut self) -> bool {
+        let Some(i) = self.pending.pop_front() else {
+            return false;
+        };
+        let added = self.cc.explore_set(i, &self.first_and_follow, self.grammar);
+        self.pending.extend(self.cc.take_unprocessed());
+        for (on, to) in added {
+            // new states are numbered in the order they're found
+            if to >= self.announced {
+                self.announced = to + 1;
+                let kernel = self.kernel(to);
+                self.events.push_back(BuildEvent::StateAdded { state: to, kernel });
+            }
+            self.events.push_back(BuildEvent::TransitionAdded { from: i, on, to });
+        }
+        true
+    }
+}
+
+impl Iterator for CollectionBuilder<'_> {
+    type Item = BuildEvent;
+
+    fn next(&mut self) -> Option<BuildEvent> {
+        while self.events.is_empty() {
+            if !self.step() {
+                return None;
+            }
+        }
+        self.events.pop_front()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::production::Production;
     use crate::symbol::{Symbol,SymbolDb};
 
     fn make_item(lhs: Symbol, rhs: Vec<Symbol>, dot: usize, lookahead: Symbol) -> LR1Item {
@@ -242,7 +683,7 @@ mod tests {
         for item in &closure_items {
             let mut s = BTreeSet::new();
             s.insert(item.clone());
-            let result = closure(&ff, &g, s);
+            let result = closure(&SharedProductions::new(&g), &mut FirstCache::default(), &ff, &g, s);
 
             for i in &result {
                 assert!(closure_items.contains(i))
@@ -284,7 +725,7 @@ mod tests {
         for item in &closure_items {
             let mut s = BTreeSet::new();
             s.insert(item.clone());
-            let result = closure(&ff, &g, s);
+            let result = closure(&SharedProductions::new(&g), &mut FirstCache::default(), &ff, &g, s);
 
             for i in &result {
                 assert!(closure_items.contains(i))
@@ -334,8 +775,244 @@ mod tests {
         cc_1.insert(make_item(pair, vec![left, right], 0, eoi));
         cc_1.insert(make_item(pair, vec![left, right], 0, left));
 
-        let result = go_to(&ff, &g, &cc_0, &list);
+        let result = go_to(&SharedProductions::new(&g), &ff, &g, &cc_0, &list);
         assert_eq!(result, cc_1);
     }
-}
 
+    #[test]
+    fn find_01() {
+        let mut symbol_db = SymbolDb::new();
+        /* grammar:
+         *  list -> list pair | pair
+         *  pair -> ( pair ) | ( )
+         */
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let left = symbol_db.new_terminal("(");
+        let right = symbol_db.new_terminal(")");
+
+        let p1 = Production::new(list, vec![list, pair]);
+        let p2 = Production::new(list, vec![pair]);
+        let p3 = Production::new(pair, vec![left, pair, right]);
+        let p4 = Production::new(pair, vec![left, right]);
+
+        let g = Grammar::new(symbol_db, list, vec![p1, p2, p3, p4]);
+        let ff = FirstAndFollow::new(&g);
+        let cc = CanonicalCollection::new(&g);
+
+        for (n, set) in cc.sets() {
+            assert_eq!(cc.find(set), Some(*n));
+        }
+        let cc_1 = go_to(&SharedProductions::new(&g), &ff, &g, &cc.sets()[&0], &list);
+        assert_eq!(cc.find(&cc_1), cc.transitions().get(&(0, list)).copied());
+
+        // same kernel items but a missing closure item, so a different set
+        let mut partial = cc_1.clone();
+        let removed = partial.iter().find(|i| i.dot_position() == 0).unwrap().clone();
+        partial.remove(&removed);
+        assert_eq!(cc.find(&partial), None);
+    }
+
+    #[test]
+    fn kernels_01() {
+        let mut symbol_db = SymbolDb::new();
+        /* grammar:
+         *  list -> list pair | pair
+         *  pair -> ( pair ) | ( )
+         */
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let left = symbol_db.new_terminal("(");
+        let right = symbol_db.new_terminal(")");
+
+        let p1 = Production::new(list, vec![list, pair]);
+        let p2 = Production::new(list, vec![pair]);
+        let p3 = Production::new(pair, vec![left, pair, right]);
+        let p4 = Production::new(pair, vec![left, right]);
+
+        let g = Grammar::new(symbol_db, list, vec![p1, p2, p3, p4]);
+        let ff = FirstAndFollow::new(&g);
+        let cc = CanonicalCollection::new(&g);
+
+        // a kernel per symbol after a dot, each of which closes to the set its transition leads to
+        let mut count = 0;
+        for (n, set) in cc.sets() {
+            let kernels = kernels(g.symbol_db(), set);
+            let symbols: HashSet<Symbol> = kernels.iter().map(|(x, _)| *x).collect();
+            assert_eq!(symbols.len(), kernels.len());
+            for (x, kernel) in kernels {
+                assert!(kernel.iter().all(|item| item.dot_position() > 0));
+                let to = cc.transitions()[&(*n, x)];
+                assert_eq!(closure(&SharedProductions::new(&g), &mut FirstCache::default(), &ff, &g, kernel), cc.sets()[&to]);
+                count += 1;
+            }
+        }
+        assert_eq!(count, cc.transitions().len());
+    }
+
+    #[test]
+    fn first_cache_01() {
+        let mut symbol_db = SymbolDb::new();
+        /* grammar:
+         *  list -> list pair | pair
+         *  pair -> ( pair ) | ( )
+         */
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let left = symbol_db.new_terminal("(");
+        let right = symbol_db.new_terminal(")");
+
+        let p1 = Production::new(list, vec![list, pair]);
+        let p2 = Production::new(list, vec![pair]);
+        let p3 = Production::new(pair, vec![left, pair, right]);
+        let p4 = Production::new(pair, vec![left, right]);
+
+        let g = Grammar::new(symbol_db, list, vec![p1, p2, p3, p4]);
+        let ff = FirstAndFollow::new(&g);
+        let cc = CanonicalCollection::new(&g);
+
+        // closing every kernel again with one cache (twice over, so that the second time round
+        // everything is in it, if the cache is on) gives the same sets
+        let productions = SharedProductions::new(&g);
+        let mut cache = FirstCache::default();
+        for _ in 0..2 {
+            for set in cc.sets().values() {
+                for (_, kernel) in kernels(g.symbol_db(), set) {
+                    let closed = closure(&productions, &mut cache, &ff, &g, kernel.clone());
+                    assert_eq!(closed, closure(&productions, &mut FirstCache::default(), &ff, &g, kernel));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shared_productions_01() {
+        let mut symbol_db = SymbolDb::new();
+        /* grammar:
+         *  list -> list pair | pair
+         *  pair -> ( pair ) | ( )
+         */
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let left = symbol_db.new_terminal("(");
+        let right = symbol_db.new_terminal(")");
+
+        let p1 = Production::new(list, vec![list, pair]);
+        let p2 = Production::new(list, vec![pair]);
+        let p3 = Production::new(pair, vec![left, pair, right]);
+        let p4 = Production::new(pair, vec![left, right]);
+
+        let g = Grammar::new(symbol_db, list, vec![p1, p2, p3, p4]);
+        let cc = CanonicalCollection::new(&g);
+
+        // every item of a production, in whatever set, refers to the same copy of it
+        let mut copies: HashMap<&Production,HashSet<*const Production>> = HashMap::new();
+        for set in cc.sets().values() {
+            for item in set {
+                copies.entry(item.production()).or_default().insert(item.production() as *const Production);
+            }
+        }
+        assert_eq!(copies.len(), g.all_productions().len());
+        assert!(copies.values().all(|c| c.len() == 1));
+    }
+
+    #[test]
+    fn renumber_01() {
+        let mut symbol_db = SymbolDb::new();
+        /* grammar:
+         *  list -> list pair | pair
+         *  pair -> ( pair ) | ( )
+         */
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let left = symbol_db.new_terminal("(");
+        let right = symbol_db.new_terminal(")");
+
+        let p1 = Production::new(list, vec![list, pair]);
+        let p2 = Production::new(list, vec![pair]);
+        let p3 = Production::new(pair, vec![left, pair, right]);
+        let p4 = Production::new(pair, vec![left, right]);
+
+        let g = Grammar::new(symbol_db, list, vec![p1, p2, p3, p4]);
+        let cc = CanonicalCollection::new(&g);
+
+        // in depth first order, every set other than 0 is entered from a set numbered before it
+        for n in 1..cc.sets().len() as u32 {
+            assert!(cc.transitions().iter().any(|((from, _), to)| *to == n && *from < n));
+        }
+        // and the first move out of set 0 (on the smallest symbol) goes to set 1
+        let first = cc.transitions().keys().filter(|(from, _)| *from == 0).map(|(_, s)| *s).min().unwrap();
+        assert_eq!(cc.transitions()[&(0, first)], 1);
+        for (n, set) in cc.sets() {
+            assert_eq!(cc.find(set), Some(*n));
+        }
+    }
+
+    /* grammar:
+     *   S -> ( S ) | x
+     */
+    #[test]
+    fn collection_builder_01() {
+        let g = Grammar::from_bnf("S -> ( S ) | x", "g").unwrap();
+        let db = g.symbol_db();
+        let cc = CanonicalCollection::new(&g);
+        let events: Vec<BuildEvent> = CollectionBuilder::new(&g).collect();
+
+        let augmented = g.augmented_production_id();
+        assert_eq!(events[0], BuildEvent::StateAdded { state: 0, kernel: vec![(augmented, 0, db.eoi())] });
+        let mut states = 0;
+        let mut transitions = HashSet::new();
+        for event in &events {
+            match event {
+                BuildEvent::StateAdded { state, kernel } => {
+                    assert_eq!(*state, states);
+                    assert!(!kernel.is_empty());
+                    states += 1;
+                },
+                // both ends are always states that have been announced
+                // and each transition is only found once
+                BuildEvent::TransitionAdded { from, on, to } => {
+                    assert!(*from < states && *to < states);
+                    assert!(transitions.insert((*from, *on)));
+                },
+            }
+        }
+        assert_eq!(states as usize, cc.sets().len());
+        assert_eq!(transitions.len(), cc.transitions().len());
+
+        // stopping part of the way through still gives the whole tables
+        let mut builder = CollectionBuilder::new(&g);
+        builder.by_ref().take(4).count();
+        assert!(builder.state_count() < cc.sets().len());
+        assert!(builder.pending_count() > 0);
+        let tables = builder.into_tables();
+        assert_eq!(tables.state_count(), ParseTables::new(&g).state_count());
+        assert!(tables.conflicts().is_empty());
+    }
+
+    /* grammar:
+     *   S -> ( S ) | [ S ] | x
+     */
+    #[test]
+    fn state_explosion_01() {
+        let g = Grammar::from_bnf("S -> ( S ) | [ S ] | x", "g").unwrap();
+        let db = g.symbol_db();
+        let count = CanonicalCollection::new(&g).sets().len();
+        let cc = build_limited(&g, count, &mut Diagnostics::new()).unwrap();
+        assert_eq!(cc.sets().len(), count);
+
+        let explosion = build_limited(&g, 4, &mut Diagnostics::new()).unwrap_err();
+        assert_eq!(explosion.limit, 4);
+        assert!(explosion.states > 4 && explosion.states < count);
+        // GOAL doesn't count
+        assert_eq!(explosion.nonterminals.len(), 1);
+        assert_eq!(explosion.nonterminals[0].0, db.symbol("S").unwrap());
+        assert!(explosion.productions.iter().all(|(p, _)| *g.production(*p).lhs() == db.symbol("S").unwrap()));
+        assert!(explosion.productions.windows(2).all(|w| w[0].1 >= w[1].1));
+        let report = explosion.to_string(&g, 2);
+        assert!(report.starts_with(&format!("more than 4 states ({} found before stopping)\n", explosion.states)));
+        assert!(report.contains("states by nonterminal:\n"));
+        // the header, then the one nonterminal and the top two productions under their headings
+        assert_eq!(report.lines().count(), 1 + 2 + 3);
+    }
+}
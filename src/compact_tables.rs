@@ -0,0 +1,299 @@
+use std::fmt;
+
+use super::action::Action;
+use super::grammar::Grammar;
+use super::parse_tables::ParseTables;
+use super::production::{Production,ProductionId};
+use super::symbol::{Symbol,SymbolDb};
+use super::table_view::{SymbolKind,TABLES};
+
+// an unsigned integer type that the compact tables can store their entries in
+pub trait TableIndex: Copy + fmt::Debug + Eq {
+    const MAX: usize;
+    fn from_usize(n: usize) -> Self;
+    fn to_usize(self) -> usize;
+}
+
+impl TableIndex for u16 {
+    const MAX: usize = u16::MAX as usize;
+    fn from_usize(n: usize) -> u16 { n as u16 }
+    fn to_usize(self) -> usize { self as usize }
+}
+
+impl TableIndex for u32 {
+    const MAX: usize = u32::MAX as usize;
+    fn from_usize(n: usize) -> u32 { n as u32 }
+    fn to_usize(self) -> usize { self as usize }
+}
+
+// the tables need entries larger than the index type can hold
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct IndexOverflow {
+    pub needed: usize,
+    pub max: usize,
+}
+
+impl fmt::Display for IndexOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the tables need index values up to {}, but the index type only holds up to {}", self.needed, self.max)
+    }
+}
+
+#[derive(Debug,Eq,PartialEq)]
+pub enum CompactAction<'a> {
+    Accept,
+    Shift(u32),
+    Reduce(&'a Production),
+}
+
+// the parse tables as two dense arrays (states by terminals for the actions, states by
+// nonterminals for the gotos) of I, which is u16 for grammars small enough and u32 otherwise.
+// every action is encoded as a single number: 0 is an error, 1..=n shifts to state (value - 1),
+// the next p values reduce by one of the p productions, and the value after that accepts
+#[derive(Debug)]
+pub struct CompactTables<I: TableIndex> {
+    state_count: usize,
+    // the column of each symbol (by index) in the action table and in the goto table
+    terminal_columns: Vec<Option<I>>,
+    nonterminal_columns: Vec<Option<I>>,
+    terminal_count: usize,
+    nonterminal_count: usize,
+    productions: Vec<Production>,
+    actions: Vec<I>,
+    gotos: Vec<I>,
+    // of the grammar the tables were built for
+    fingerprint: u64,
+}
+
+impl<I: TableIndex> CompactTables<I> {
+    pub fn new(grammar: &Grammar, tables: &ParseTables) -> Result<CompactTables<I>,IndexOverflow> {
+        let symbol_db = grammar.symbol_db();
+        let state_count = tables.state_count();
+
+        let mut ids: Vec<ProductionId> = tables.actions()
+            .filter_map(|(_, a)| match a {
+                Action::Reduce(p) => Some(*p),
+                _ => None,
+            })
+            .collect();
+        ids.sort();
+        ids.dedup();
+        let productions: Vec<Production> = ids.iter().map(|p| grammar.production(*p).clone()).collect();
+
+        // the largest value stored anywhere is the one for accept
+        let needed = state_count + productions.len() + 1;
+        if needed > I::MAX {
+            return Err(IndexOverflow { needed, max: I::MAX });
+        }
+
+        let terminals: Vec<Symbol> = grammar.terminals().iter().collect();
+        let nonterminals: Vec<Symbol> = grammar.nonterminals().iter().collect();
+        let columns = |symbols: &[Symbol]| {
+            let mut result = vec![None; symbol_db.symbol_count()];
+            for (i, s) in symbols.iter().enumerate() {
+                result[s.index()] = Some(I::from_usize(i));
+            }
+            result
+        };
+        let terminal_columns = columns(&terminals);
+        let nonterminal_columns = columns(&nonterminals);
+
+        let zero = I::from_usize(0);
+        let mut actions = vec![zero; state_count * terminals.len()];
+        for (&(state, symbol), action) in tables.actions() {
+            let slot = state as usize * terminals.len() + terminal_columns[symbol.index()].unwrap().to_usize();
+            let value = match action {
+                Action::Shift(n) => *n as usize + 1,
+                Action::Reduce(p) => state_count + 1 + ids.binary_search(p).unwrap(),
+                Action::Accept => needed,
+            };
+            actions[slot] = I::from_usize(value);
+        }
+
+        let mut gotos = vec![zero; state_count * nonterminals.len()];
+        for (&(state, symbol), &to) in tables.transitions() {
+            let slot = state as usize * nonterminals.len() + nonterminal_columns[symbol.index()].unwrap().to_usize();
+            gotos[slot] = I::from_usize(to as usize + 1);
+        }
+
+        Ok(CompactTables {
+            state_count,
+            terminal_columns,
+            nonterminal_columns,
+            terminal_count: terminals.len(),
+            nonterminal_count: nonterminals.len(),
+            productions,
+            actions,
+            gotos,
+            fingerprint: grammar.fingerprint(),
+        })
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.state_count
+    }
+
+    // the bytes taken up by the dense arrays and the column maps
+    pub fn byte_size(&self) -> usize {
+        let entries = self.actions.len() + self.gotos.len();
+        let columns = self.terminal_columns.len() + self.nonterminal_columns.len();
+        entries * std::mem::size_of::<I>() + columns * std::mem::size_of::<Option<I>>()
+    }
+
+    // serialize the tables (and the symbol labels, so that symbols can be looked up again) in the
+    // layout read by TableView. everything but the labels is a little endian u32, whatever I is
+    pub fn to_bytes(&self, symbol_db: &SymbolDb) -> Vec<u8> {
+        let mut words: Vec<u32> = Vec::new();
+        let symbol_count = self.terminal_columns.len();
+        words.extend([
+            self.state_count,
+            self.terminal_count,
+            self.nonterminal_count,
+            self.productions.len(),
+            symbol_count,
+        ].iter().map(|n| *n as u32));
+        words.extend([self.fingerprint as u32, (self.fingerprint >> 32) as u32]);
+
+        for i in 0..symbol_count {
+            let (kind, column) = match (self.terminal_columns[i], self.nonterminal_columns[i]) {
+                (Some(c), _) => (SymbolKind::Terminal, c.to_usize()),
+                (_, Some(c)) => (SymbolKind::Nonterminal, c.to_usize()),
+                _ => (SymbolKind::Unused, 0),
+            };
+            words.push(kind as u32);
+            words.push(column as u32);
+        }
+
+        words.extend(self.actions.iter().map(|v| v.to_usize() as u32));
+        words.extend(self.gotos.iter().map(|v| v.to_usize() as u32));
+
+        let mut offset = 0;
+        words.push(offset);
+        for p in &self.productions {
+            offset += p.rhs().len() as u32;
+            words.push(offset);
+        }
+        words.extend(self.productions.iter().map(|p| p.lhs().index() as u32));
+        for p in &self.productions {
+            words.extend(p.rhs().iter().map(|s| s.index() as u32));
+        }
+
+        let mut labels: Vec<u8> = Vec::new();
+        words.push(0);
+        for i in 0..symbol_count {
+            if let Some(label) = symbol_db.label(&Symbol::from_index(i)) {
+                labels.extend(label.as_bytes());
+            }
+            words.push(labels.len() as u32);
+        }
+
+        let mut bytes = TABLES.header();
+        for w in words {
+            bytes.extend(w.to_le_bytes());
+        }
+        bytes.extend(labels);
+        bytes
+    }
+
+    fn column(columns: &[Option<I>], symbol: Symbol) -> Option<usize> {
+        columns.get(symbol.index()).copied().flatten().map(|c| c.to_usize())
+    }
+
+    pub fn action(&self, state: u32, symbol: Symbol) -> Option<CompactAction<'_>> {
+        let state = state as usize;
+        if state >= self.state_count {
+            return None;
+        }
+        let column = Self::column(&self.terminal_columns, symbol)?;
+        let value = self.actions[state * self.terminal_count + column].to_usize();
+        if value == 0 {
+            None
+        } else if value <= self.state_count {
+            Some(CompactAction::Shift((value - 1) as u32))
+        } else if value <= self.state_count + self.productions.len() {
+            Some(CompactAction::Reduce(&self.productions[value - self.state_count - 1]))
+        } else {
+            Some(CompactAction::Accept)
+        }
+    }
+
+    pub fn transition(&self, state: u32, symbol: Symbol) -> Option<u32> {
+        let state = state as usize;
+        if state >= self.state_count {
+            return None;
+        }
+        let column = Self::column(&self.nonterminal_columns, symbol)?;
+        match self.gotos[state * self.nonterminal_count + column].to_usize() {
+            0 => None,
+            n => Some((n - 1) as u32),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol::SymbolDb;
+
+    /* grammar:
+     *   list -> list pair | pair
+     *   pair -> ( pair ) | ( )
+     */
+    fn pairs() -> Grammar {
+        let mut symbol_db = SymbolDb::new();
+        let list = symbol_db.new_nonterminal("list");
+        let pair = symbol_db.new_nonterminal("pair");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let productions = vec![
+            Production::new(list, vec![list, pair]),
+            Production::new(list, vec![pair]),
+            Production::new(pair, vec![lp, pair, rp]),
+            Production::new(pair, vec![lp, rp]),
+        ];
+        Grammar::new(symbol_db, list, productions)
+    }
+
+    #[test]
+    fn compact_tables_01() {
+        let g = pairs();
+        let tables = ParseTables::new(&g);
+        let compact: CompactTables<u16> = CompactTables::new(&g, &tables).unwrap();
+        assert_eq!(compact.state_count(), tables.state_count());
+        let mut symbols: Vec<Symbol> = g.terminals().iter().chain(g.nonterminals().iter()).collect();
+        symbols.sort();
+        for state in 0..tables.state_count() as u32 {
+            for s in &symbols {
+                let expected = match tables.action(state, *s) {
+                    None => None,
+                    Some(Action::Accept) => Some(CompactAction::Accept),
+                    Some(Action::Shift(n)) => Some(CompactAction::Shift(*n)),
+                    Some(Action::Reduce(p)) => Some(CompactAction::Reduce(g.production(*p))),
+                };
+                assert_eq!(compact.action(state, *s), expected);
+                assert_eq!(compact.transition(state, *s), tables.transition(state, *s).copied());
+            }
+        }
+        assert_eq!(compact.action(tables.state_count() as u32, symbols[0]), None);
+        assert!(compact.byte_size() >= tables.footprint(&g).dense_bytes(2));
+    }
+
+    #[test]
+    fn compact_tables_02() {
+        // an index type too small for the tables of even this grammar
+        #[derive(Clone,Copy,Debug,Eq,PartialEq)]
+        struct Tiny(u8);
+        impl TableIndex for Tiny {
+            const MAX: usize = 15;
+            fn from_usize(n: usize) -> Tiny { Tiny(n as u8) }
+            fn to_usize(self) -> usize { self.0 as usize }
+        }
+
+        let g = pairs();
+        let tables = ParseTables::new(&g);
+        let error = CompactTables::<Tiny>::new(&g, &tables).err().unwrap();
+        assert_eq!(error.max, 15);
+        assert!(error.needed > 15);
+        assert!(CompactTables::<u32>::new(&g, &tables).is_ok());
+    }
+}
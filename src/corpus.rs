@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::parser::Parser;
+use super::symbol::Symbol;
+
+// what happened when one file of a corpus was parsed
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum CorpusOutcome {
+    Parsed,
+    // the parser rejected the file, at the given token position
+    Rejected { position: usize },
+    // the file couldn't be read or lexed
+    LexError,
+}
+
+impl fmt::Display for CorpusOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CorpusOutcome::Parsed => write!(f, "ok"),
+            CorpusOutcome::Rejected { position } => write!(f, "error {}", position),
+            CorpusOutcome::LexError => write!(f, "lex-error"),
+        }
+    }
+}
+
+// the outcome of every file of a corpus, keyed by the file's path relative to the corpus
+// directory (with / as the separator, so that baselines can be shared between platforms)
+#[derive(Clone,Debug,Default,Eq,PartialEq)]
+pub struct CorpusReport {
+    outcomes: BTreeMap<String,CorpusOutcome>,
+}
+
+impl CorpusReport {
+    // lex and parse every file under dir, including those in subdirectories. new_lexer is called
+    // once per file for a fresh lexer, which is used as in Parser::parse_reader
+    pub fn run<T,L,N,F>(parser: &Parser, dir: &Path, new_lexer: N, token_to_symbol: F) -> io::Result<CorpusReport>
+        where T: Clone,
+              L: FnMut(&mut dyn io::BufRead) -> io::Result<Option<T>>,
+              N: Fn() -> L,
+              F: Fn(&T) -> Symbol {
+        let mut files = Vec::new();
+        collect_files(dir, "", &mut files)?;
+
+        let mut outcomes = BTreeMap::new();
+        for name in files {
+            let outcome = match fs::File::open(dir.join(&name)) {
+                Ok(file) => match parser.parse_reader(file, new_lexer(), &token_to_symbol) {
+                    Ok(Ok(_)) => CorpusOutcome::Parsed,
+                    Ok(Err(e)) => CorpusOutcome::Rejected { position: e.position() },
+                    Err(_) => CorpusOutcome::LexError,
+                },
+                Err(_) => CorpusOutcome::LexError,
+            };
+            outcomes.insert(name, outcome);
+        }
+        Ok(CorpusReport { outcomes })
+    }
+
+    pub fn outcomes(&self) -> &BTreeMap<String,CorpusOutcome> {
+        &self.outcomes
+    }
+
+    pub fn outcome(&self, name: &str) -> Option<&CorpusOutcome> {
+        self.outcomes.get(name)
+    }
+
+    pub fn successes(&self) -> usize {
+        self.outcomes.values().filter(|o| **o == CorpusOutcome::Parsed).count()
+    }
+
+    pub fn failures(&self) -> usize {
+        self.outcomes.len() - self.successes()
+    }
+
+    // the report as text to store next to the corpus: one line per file, holding the file name and
+    // its outcome separated by a tab
+    pub fn to_baseline(&self) -> String {
+        self.outcomes.iter().map(|(name, outcome)| format!("{}\t{}\n", name, outcome)).collect()
+    }
+
+    // read back a report written by to_baseline. blank lines are skipped, and the line number of
+    // the first malformed line is the error
+    pub fn from_baseline(text: &str) -> Result<CorpusReport,usize> {
+        let mut outcomes = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (name, outcome) = line.split_once('\t').ok_or(i + 1)?;
+            let outcome = match outcome.split_once(' ') {
+                None if outcome == "ok" => CorpusOutcome::Parsed,
+                None if outcome == "lex-error" => CorpusOutcome::LexError,
+                Some(("error", position)) => CorpusOutcome::Rejected { position: position.parse().map_err(|_| i + 1)? },
+                _ => return Err(i + 1),
+            };
+            outcomes.insert(name.to_string(), outcome);
+        }
+        Ok(CorpusReport { outcomes })
+    }
+
+    // how this report differs from an earlier one
+    pub fn compare(&self, baseline: &CorpusReport) -> CorpusChanges {
+        let mut changes = CorpusChanges::default();
+        for (name, outcome) in &self.outcomes {
+            match baseline.outcomes.get(name) {
+                None => changes.added.push(name.clone()),
+                Some(old) if old != outcome => {
+                    changes.changed.push((name.clone(), old.clone(), outcome.clone()));
+                },
+                _ => {},
+            }
+        }
+        for name in baseline.outcomes.keys() {
+            if !self.outcomes.contains_key(name) {
+                changes.removed.push(name.clone());
+            }
+        }
+        changes
+    }
+}
+
+// the differences between a corpus run and its baseline, each in file name order
+#[derive(Clone,Debug,Default,Eq,PartialEq)]
+pub struct CorpusChanges {
+    // files that aren't in the baseline
+    pub added: Vec<String>,
+    // files in the baseline that are gone
+    pub removed: Vec<String>,
+    // files whose outcome changed, with the outcome before and after
+    pub changed: Vec<(String,CorpusOutcome,CorpusOutcome)>,
+}
+
+impl CorpusChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    // files that used to parse and no longer do
+    pub fn regressions(&self) -> Vec<&str> {
+        self.changed.iter()
+            .filter(|(_, old, new)| *old == CorpusOutcome::Parsed && *new != CorpusOutcome::Parsed)
+            .map(|(name, _, _)| name.as_str())
+            .collect()
+    }
+}
+
+impl fmt::Display for CorpusChanges {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for name in &self.added {
+            writeln!(f, "added {}", name)?;
+        }
+        for name in &self.removed {
+            writeln!(f, "removed {}", name)?;
+        }
+        for (name, old, new) in &self.changed {
+            writeln!(f, "changed {}: {} -> {}", name, old, new)?;
+        }
+        Ok(())
+    }
+}
+
+// the files under dir, by path relative to the corpus root, in sorted order
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> io::Result<()> {
+    let mut entries: Vec<fs::DirEntry> = fs::read_dir(dir)?.collect::<io::Result<_>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &format!("{}/", name), files)?;
+        } else {
+            files.push(name);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::production::Production;
+    use crate::symbol::SymbolDb;
+
+    #[derive(Clone,Debug)]
+    enum Token {
+        ParenLeft,
+        ParenRight,
+        EndOfFile,
+    }
+
+    /* grammar:
+     *   e1 -> ( e1 ) | ε
+     */
+    #[test]
+    fn corpus_01() {
+        let mut symbol_db = SymbolDb::new();
+        let e1 = symbol_db.new_nonterminal("E1");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(e1, vec![lp, e1, rp]),
+            Production::new(e1, vec![]),
+        ];
+        let p = Parser::new(Grammar::new(symbol_db, e1, productions));
+        let ttos = |token: &Token| match token {
+            Token::ParenLeft => lp,
+            Token::ParenRight => rp,
+            Token::EndOfFile => eoi,
+        };
+        let lexer = || {
+            let mut done = false;
+            move |reader: &mut dyn io::BufRead| -> io::Result<Option<Token>> {
+                loop {
+                    if done {
+                        return Ok(None);
+                    }
+                    let mut byte = [0u8];
+                    if reader.read(&mut byte)? == 0 {
+                        done = true;
+                        return Ok(Some(Token::EndOfFile));
+                    }
+                    match byte[0] {
+                        b'(' => return Ok(Some(Token::ParenLeft)),
+                        b')' => return Ok(Some(Token::ParenRight)),
+                        b' ' | b'\n' => continue,
+                        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "bad character")),
+                    }
+                }
+            }
+        };
+
+        let dir = std::env::temp_dir().join(format!("parser-corpus-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.txt"), "(())").unwrap();
+        fs::write(dir.join("b.txt"), "(()").unwrap();
+        fs::write(dir.join("nested/c.txt"), "(x)").unwrap();
+
+        let report = CorpusReport::run(&p, &dir, lexer, ttos);
+        fs::remove_dir_all(&dir).unwrap();
+        let report = report.unwrap();
+
+        assert_eq!(report.successes(), 1);
+        assert_eq!(report.failures(), 2);
+        assert_eq!(report.outcome("b.txt"), Some(&CorpusOutcome::Rejected { position: 3 }));
+        assert_eq!(report.outcome("nested/c.txt"), Some(&CorpusOutcome::LexError));
+        let baseline = report.to_baseline();
+        assert_eq!(baseline, "a.txt\tok\nb.txt\terror 3\nnested/c.txt\tlex-error\n");
+        assert_eq!(CorpusReport::from_baseline(&baseline), Ok(report.clone()));
+        assert!(report.compare(&report).is_empty());
+
+        let old = CorpusReport::from_baseline("a.txt\tok\nb.txt\tok\n\nd.txt\terror 0\n").unwrap();
+        let changes = report.compare(&old);
+        assert_eq!(changes.added, vec!["nested/c.txt"]);
+        assert_eq!(changes.removed, vec!["d.txt"]);
+        assert_eq!(changes.regressions(), vec!["b.txt"]);
+        assert_eq!(changes.to_string(), "added nested/c.txt\nremoved d.txt\nchanged b.txt: ok -> error 3\n");
+        assert_eq!(CorpusReport::from_baseline("a.txt\tok\nb.txt error 3\n"), Err(2));
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::{BTreeMap,BTreeSet};
+use std::fmt::Write;
+
+use super::grammar::Grammar;
+use super::symbol::{Symbol,SymbolDb};
+
+#[derive(Debug)]
+pub struct DependencyGraph {
+    edges: BTreeMap<Symbol,BTreeSet<Symbol>>,
+}
+
+impl DependencyGraph {
+    pub fn new(grammar: &Grammar) -> DependencyGraph {
+        let symbol_db = grammar.symbol_db();
+        let mut edges: BTreeMap<Symbol,BTreeSet<Symbol>> = BTreeMap::new();
+        // the goal symbol is synthesized by the grammar, so leave it out of the graph
+        for nt in &grammar.declared_nonterminals() {
+            let targets = edges.entry(nt).or_default();
+            if let Some(ps) = grammar.productions(&nt) {
+                for p in ps {
+                    for s in p.rhs() {
+                        if !symbol_db.is_terminal(s) {
+                            targets.insert(*s);
+                        }
+                    }
+                }
+            }
+        }
+        DependencyGraph { edges }
+    }
+
+    pub fn nonterminals(&self) -> impl Iterator<Item=&Symbol> {
+        self.edges.keys()
+    }
+
+    // the nonterminals that appear on the right hand side of some production of nt
+    pub fn references(&self, nt: &Symbol) -> Option<&BTreeSet<Symbol>> {
+        self.edges.get(nt)
+    }
+
+    // the strongly connected components of the graph (tarjan's algorithm). components are
+    // returned in reverse topological order, i.e. a component only references components that
+    // come before it
+    pub fn strongly_connected_components(&self) -> Vec<Vec<Symbol>> {
+        struct State {
+            next_index: usize,
+            index: BTreeMap<Symbol,usize>,
+            low_link: BTreeMap<Symbol,usize>,
+            stack: Vec<Symbol>,
+            on_stack: BTreeSet<Symbol>,
+            components: Vec<Vec<Symbol>>,
+        }
+
+        fn visit(graph: &DependencyGraph, v: Symbol, state: &mut State) {
+            state.index.insert(v, state.next_index);
+            state.low_link.insert(v, state.next_index);
+            state.next_index += 1;
+            state.stack.push(v);
+            state.on_stack.insert(v);
+
+            if let Some(targets) = graph.edges.get(&v) {
+                for w in targets {
+                    if !state.index.contains_key(w) {
+                        visit(graph, *w, state);
+                        let low = state.low_link[&v].min(state.low_link[w]);
+                        state.low_link.insert(v, low);
+                    } else if state.on_stack.contains(w) {
+                        let low = state.low_link[&v].min(state.index[w]);
+                        state.low_link.insert(v, low);
+                    }
+                }
+            }
+
+            // v is the root of a component, so everything above it on the stack belongs to it
+            if state.low_link[&v] == state.index[&v] {
+                let mut component = Vec::new();
+                loop {
+                    let w = state.stack.pop().unwrap();
+                    state.on_stack.remove(&w);
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                component.sort();
+                state.components.push(component);
+            }
+        }
+
+        let mut state = State {
+            next_index: 0,
+            index: BTreeMap::new(),
+            low_link: BTreeMap::new(),
+            stack: Vec::new(),
+            on_stack: BTreeSet::new(),
+            components: Vec::new(),
+        };
+        for v in self.edges.keys() {
+            if !state.index.contains_key(v) {
+                visit(self, *v, &mut state);
+            }
+        }
+        state.components
+    }
+
+    // the groups of nonterminals that are (mutually) recursive. a single nonterminal is only a
+    // recursion cluster if it references itself
+    pub fn recursion_clusters(&self) -> Vec<Vec<Symbol>> {
+        self.strongly_connected_components()
+            .into_iter()
+            .filter(|c| c.len() > 1 || self.edges[&c[0]].contains(&c[0]))
+            .collect()
+    }
+
+    // render the graph in graphviz format. each recursion cluster is drawn inside a box
+    pub fn to_dot(&self, symbol_db: &SymbolDb) -> String {
+        let label = |s: &Symbol| symbol_db.label(s).unwrap().replace('\\', "\\\\").replace('"', "\\\"");
+        let mut result = String::new();
+        writeln!(&mut result, "digraph grammar {{").unwrap();
+        for (i, cluster) in self.recursion_clusters().iter().enumerate() {
+            writeln!(&mut result, "    subgraph cluster_{} {{", i).unwrap();
+            for s in cluster {
+                writeln!(&mut result, "        \"{}\";", label(s)).unwrap();
+            }
+            writeln!(&mut result, "    }}").unwrap();
+        }
+        for (from, targets) in &self.edges {
+            if targets.is_empty() {
+                writeln!(&mut result, "    \"{}\";", label(from)).unwrap();
+            }
+            for to in targets {
+                writeln!(&mut result, "    \"{}\" -> \"{}\";", label(from), label(to)).unwrap();
+            }
+        }
+        writeln!(&mut result, "}}").unwrap();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::production::Production;
+
+    /* grammar:
+     *   expr -> expr + term | term
+     *   term -> term * factor | factor
+     *   factor -> ( expr ) | num
+     *   stmt -> expr ;
+     */
+    fn expression_grammar() -> Grammar {
+        let mut symbol_db = SymbolDb::new();
+        let stmt = symbol_db.new_nonterminal("stmt");
+        let expr = symbol_db.new_nonterminal("expr");
+        let term = symbol_db.new_nonterminal("term");
+        let factor = symbol_db.new_nonterminal("factor");
+        let plus = symbol_db.new_terminal("+");
+        let mult = symbol_db.new_terminal("*");
+        let left = symbol_db.new_terminal("(");
+        let right = symbol_db.new_terminal(")");
+        let semi = symbol_db.new_terminal(";");
+        let num = symbol_db.new_terminal("num");
+        let productions = vec![
+            Production::new(stmt, vec![expr, semi]),
+            Production::new(expr, vec![expr, plus, term]),
+            Production::new(expr, vec![term]),
+            Production::new(term, vec![term, mult, factor]),
+            Production::new(term, vec![factor]),
+            Production::new(factor, vec![left, expr, right]),
+            Production::new(factor, vec![num]),
+        ];
+        Grammar::new(symbol_db, stmt, productions)
+    }
+
+    #[test]
+    fn dependency_graph_01() {
+        let g = expression_grammar();
+        let graph = g.dependency_graph();
+        let db = g.symbol_db();
+        let expr = db.symbol("expr").unwrap();
+        let term = db.symbol("term").unwrap();
+        let stmt = db.symbol("stmt").unwrap();
+        let refs = graph.references(&expr).unwrap();
+        assert_eq!(refs.len(), 2);
+        assert!(refs.contains(&expr));
+        assert!(refs.contains(&term));
+        assert_eq!(graph.references(&stmt).unwrap().len(), 1);
+        assert!(graph.references(&db.goal()).is_none());
+    }
+
+    #[test]
+    fn dependency_graph_02() {
+        let g = expression_grammar();
+        let graph = g.dependency_graph();
+        let db = g.symbol_db();
+        let expr = db.symbol("expr").unwrap();
+        let term = db.symbol("term").unwrap();
+        let factor = db.symbol("factor").unwrap();
+        let stmt = db.symbol("stmt").unwrap();
+        let components = graph.strongly_connected_components();
+        assert_eq!(components.len(), 2);
+        // stmt depends on the expression cluster, so it comes last
+        assert_eq!(components[1], vec![stmt]);
+        let mut cluster = vec![expr, term, factor];
+        cluster.sort();
+        assert_eq!(graph.recursion_clusters(), vec![cluster]);
+    }
+
+    #[test]
+    fn dependency_graph_03() {
+        let g = expression_grammar();
+        let dot = g.dependency_graph().to_dot(g.symbol_db());
+        assert!(dot.starts_with("digraph grammar {"));
+        assert!(dot.contains("subgraph cluster_0 {"));
+        assert!(dot.contains("\"stmt\" -> \"expr\";"));
+        assert!(dot.contains("\"factor\" -> \"expr\";"));
+        assert!(!dot.contains("GOAL"));
+    }
+}
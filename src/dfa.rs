@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use super::regex::{NfaState,Regex};
+
+// a state of a lexer dfa. the transitions are on disjoint ranges of characters, sorted, and a
+// character in none of them has no transition. accept is the rule the text read so far matches,
+// if any does
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub(crate) struct DfaState {
+    pub(crate) transitions: Vec<(char,char,u32)>,
+    pub(crate) accept: Option<usize>,
+}
+
+// the rules of a lexer compiled to a single minimal dfa, so that finding the longest match at a
+// position takes one pass over the text it reads, however many rules there are. state 0 is the
+// start state. where the text read so far matches more than one rule, the state accepts the
+// earliest of them
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub(crate) struct Dfa {
+    pub(crate) states: Vec<DfaState>,
+}
+
+// a state's block and the blocks its transitions go to, which are the same for states that can't
+// be told apart
+type Signature = (usize,Vec<(char,char,usize)>);
+
+impl Dfa {
+    pub(crate) fn new(rules: &[&Regex]) -> Dfa {
+        // one nfa for all the rules, with a start state that has ε transitions to theirs
+        let mut nfa = vec![NfaState::default()];
+        let mut accepts = vec![None];
+        for (i, rule) in rules.iter().enumerate() {
+            let offset = nfa.len();
+            nfa.extend(rule.states.iter().map(|state| NfaState {
+                ranges: state.ranges.iter().map(|(a, b, t)| (*a, *b, t + offset)).collect(),
+                epsilon: state.epsilon.iter().map(|t| t + offset).collect(),
+            }));
+            accepts.resize(nfa.len(), None);
+            accepts[rule.accept + offset] = Some(i);
+            nfa[0].epsilon.push(rule.start + offset);
+        }
+        subsets(&nfa, &accepts).minimize()
+    }
+
+    // the rule that matches the longest prefix of the text, and the length of the prefix in bytes
+    pub(crate) fn longest_match(&self, text: &str) -> Option<(usize,usize)> {
+        let mut state = &self.states[0];
+        let mut longest = state.accept.map(|rule| (rule, 0));
+        for (i, c) in text.char_indices() {
+            match state.next(c) {
+                Some(next) => state = &self.states[next as usize],
+                None => break,
+            }
+            if let Some(rule) = state.accept {
+                longest = Some((rule, i + c.len_utf8()));
+            }
+        }
+        longest
+    }
+
+    // a rust function that runs the dfa, with the same result as longest_match
+    pub(crate) fn to_rust(&self, name: &str) -> String {
+        let char = |c: char| format!("'\\u{{{:x}}}'", c as u32);
+        let mut transitions = String::new();
+        let mut accepts = String::new();
+        for (s, state) in self.states.iter().enumerate() {
+            for (a, b, target) in &state.transitions {
+                let pattern = if a == b { char(*a) } else { format!("{}..={}", char(*a), char(*b)) };
+                transitions.push_str(&format!("            ({}, {}) => {},\n", s, pattern, target));
+            }
+            if let Some(rule) = state.accept {
+                accepts.push_str(&format!("            {} => Some({}),\n", s, rule));
+            }
+        }
+        let start = match self.states[0].accept {
+            Some(rule) => format!("Some(({}, 0))", rule),
+            None => "None".to_string(),
+        };
+        format!("\
+pub fn {name}(text: &str) -> Option<(usize, usize)> {{
+    let mut state: u32 = 0;
+    let mut longest: Option<(usize, usize)> = {start};
+    for (i, c) in text.char_indices() {{
+        state = match (state, c) {{
+{transitions}            _ => break,
+        }};
+        let accept: Option<usize> = match state {{
+{accepts}            _ => None,
+        }};
+        if let Some(rule) = accept {{
+            longest = Some((rule, i + c.len_utf8()));
+        }}
+    }}
+    longest
+}}
+")
+    }
+
+    // merge the states that no text can tell apart, by refining a partition of the states until
+    // the states in each block have transitions into the same blocks on every character
+    fn minimize(self) -> Dfa {
+        let mut blocks: Vec<usize> = Vec::new();
+        let mut initial: HashMap<Option<usize>,usize> = HashMap::new();
+        for state in &self.states {
+            let n = initial.len();
+            blocks.push(*initial.entry(state.accept).or_insert(n));
+        }
+        let mut count = initial.len();
+        loop {
+            let mut refined: HashMap<Signature,usize> = HashMap::new();
+            let next: Vec<usize> = self.states.iter().enumerate()
+                .map(|(s, state)| {
+                    let signature = (blocks[s], state.transitions_into(&blocks));
+                    let n = refined.len();
+                    *refined.entry(signature).or_insert(n)
+                })
+                .collect();
+            blocks = next;
+            if refined.len() == count {
+                break;
+            }
+            count = refined.len();
+        }
+
+        // number the blocks in the order their first states appear, so the start stays at 0
+        let mut numbers: HashMap<usize,u32> = HashMap::new();
+        for block in &blocks {
+            let n = numbers.len() as u32;
+            numbers.entry(*block).or_insert(n);
+        }
+        let mut states: Vec<Option<DfaState>> = vec![None; numbers.len()];
+        for (s, state) in self.states.iter().enumerate() {
+            let number = numbers[&blocks[s]] as usize;
+            if states[number].is_none() {
+                let transitions = state.transitions_into(&blocks).into_iter()
+                    .map(|(a, b, block)| (a, b, numbers[&block]))
+                    .collect();
+                states[number] = Some(DfaState { transitions, accept: state.accept });
+            }
+        }
+        Dfa { states: states.into_iter().map(Option::unwrap).collect() }
+    }
+}
+
+impl DfaState {
+    pub(crate) fn next(&self, c: char) -> Option<u32> {
+        let i = self.transitions.partition_point(|(a, _, _)| *a <= c);
+        let (_, b, target) = self.transitions.get(i.checked_sub(1)?)?;
+        if c <= *b { Some(*target) } else { None }
+    }
+
+    // the transitions with their targets replaced by the targets' blocks, merging neighbouring
+    // ranges that go to the same block
+    fn transitions_into(&self, blocks: &[usize]) -> Vec<(char,char,usize)> {
+        let mut result: Vec<(char,char,usize)> = Vec::new();
+        for (a, b, target) in &self.transitions {
+            let block = blocks[*target as usize];
+            match result.last_mut() {
+                Some((_, end, last)) if *last == block && *end as u32 + 1 == *a as u32 => *end = *b,
+                _ => result.push((*a, *b, block)),
+            }
+        }
+        result
+    }
+}
+
+// the states of an nfa reachable from state by ε transitions
+fn close(nfa: &[NfaState], states: &mut Vec<usize>) {
+    let mut member = vec![false; nfa.len()];
+    let mut stack = std::mem::take(states);
+    while let Some(state) = stack.pop() {
+        if !member[state] {
+            member[state] = true;
+            stack.extend(nfa[state].epsilon.iter().copied());
+        }
+    }
+    *states = (0..nfa.len()).filter(|s| member[*s]).collect();
+}
+
+// the dfa whose states are the sets of nfa states the nfa can be in at once
+fn subsets(nfa: &[NfaState], accepts: &[Option<usize>]) -> Dfa {
+    let mut start = vec![0];
+    close(nfa, &mut start);
+    let mut numbers: HashMap<Vec<usize>,u32> = HashMap::from([(start.clone(), 0)]);
+    let mut sets = vec![start];
+    let mut states = Vec::new();
+    while states.len() < sets.len() {
+        let set = sets[states.len()].clone();
+        let ranges: Vec<(char,char,usize)> = set.iter().flat_map(|s| nfa[*s].ranges.iter().copied()).collect();
+        // split the characters at every point where some range starts or ends, so that each piece
+        // has the same targets throughout
+        let mut points: Vec<u32> = ranges.iter().flat_map(|(a, b, _)| [*a as u32, *b as u32 + 1]).collect();
+        points.sort();
+        points.dedup();
+        let mut transitions: Vec<(char,char,u32)> = Vec::new();
+        for piece in points.windows(2) {
+            let (Some(a), Some(b)) = (first_char_from(piece[0]), last_char_before(piece[1])) else {
+                continue;
+            };
+            if a > b {
+                continue;
+            }
+            let mut targets: Vec<usize> = ranges.iter()
+                .filter(|(start, end, _)| *start <= a && b <= *end)
+                .map(|(_, _, t)| *t)
+                .collect();
+            if targets.is_empty() {
+                continue;
+            }
+            close(nfa, &mut targets);
+            let n = sets.len() as u32;
+            let target = *numbers.entry(targets.clone()).or_insert_with(|| {
+                sets.push(targets);
+                n
+            });
+            match transitions.last_mut() {
+                Some((_, end, last)) if *last == target && *end as u32 + 1 == a as u32 => *end = b,
+                _ => transitions.push((a, b, target)),
+            }
+        }
+        let accept = set.iter().filter_map(|s| accepts[*s]).min();
+        states.push(DfaState { transitions, accept });
+    }
+    Dfa { states }
+}
+
+// the first character from the code point on, skipping over the surrogates
+fn first_char_from(point: u32) -> Option<char> {
+    char::from_u32(if (0xd800..0xe000).contains(&point) { 0xe000 } else { point })
+}
+
+// the character just before the code point, skipping back over the surrogates
+fn last_char_before(point: u32) -> Option<char> {
+    let c = point.checked_sub(1)?;
+    char::from_u32(if (0xd800..0xe000).contains(&c) { 0xd7ff } else { c })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dfa_01() {
+        // the textbook example, whose minimal dfa has four states
+        let regex = Regex::new("(a|b)*abb").unwrap();
+        let dfa = Dfa::new(&[&regex]);
+        assert_eq!(dfa.states.len(), 4);
+        assert_eq!(dfa.longest_match("ababbabbx"), Some((0, 8)));
+        assert_eq!(dfa.longest_match("abab"), None);
+
+        let patterns = ["if", "[a-z]+", "\\d+", "\\d+\\.\\d*", "\\s+", "\"([^\"\\\\]|\\\\.)*\"", "[^\\s]"];
+        let regexes: Vec<Regex> = patterns.iter().map(|p| Regex::new(p).unwrap()).collect();
+        let dfa = Dfa::new(&regexes.iter().collect::<Vec<&Regex>>());
+        // the same result as trying every rule's nfa and taking the longest, earliest match
+        let expected = |text: &str| {
+            let mut longest: Option<(usize,usize)> = None;
+            for (i, r) in regexes.iter().enumerate() {
+                if let Some(len) = r.longest_match(text) {
+                    if longest.is_none_or(|(_, l)| len > l) {
+                        longest = Some((i, len));
+                    }
+                }
+            }
+            longest
+        };
+        for text in ["if", "iffy", "12.5x", "12", "  \t", "\"a\\\"b\" c", "\"abc", "é", "", "\u{e000}", "#"] {
+            assert_eq!(dfa.longest_match(text), expected(text), "{:?}", text);
+        }
+    }
+}
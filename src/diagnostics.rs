@@ -0,0 +1,260 @@
+use std::fmt;
+use std::ops::Range;
+
+use super::source_location::SourceLocation;
+use super::source_map::SourceMap;
+use super::symbol::{Symbol,SymbolDb};
+
+#[derive(Clone,Copy,Debug,Eq,Hash,Ord,PartialEq,PartialOrd)]
+pub enum Severity {
+    // worth knowing, but often intended
+    Note,
+    // probably a mistake
+    Warning,
+    // the input can't be used as it is, e.g. a lexer or parse error
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Note => write!(f, "note"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+// something found in a grammar, its tables or the input being parsed: grammar warnings, lexer
+// and parse errors, and notes about them all take this form, so that an application can report
+// them all the same way. the code identifies the kind of finding (e.g. unused-terminal) so that
+// tools can filter on it, and the symbols are the ones the finding is about. grammars loaded from
+// text also give the location of the offending line, and findings about the input being parsed
+// give the bytes of the input they're about, with related places elsewhere in the input
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub symbols: Vec<Symbol>,
+    pub location: Option<SourceLocation>,
+    pub span: Option<Range<usize>>,
+    pub related: Vec<Related>,
+}
+
+// another place in the input that a diagnostic refers to, e.g. the opening bracket of one that
+// was never closed
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct Related {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: String) -> Diagnostic {
+        Diagnostic { severity, code, message, symbols: Vec::new(), location: None, span: None, related: Vec::new() }
+    }
+
+    // a diagnostic about the bytes of the input in the span
+    pub fn at(severity: Severity, code: &'static str, message: String, span: Range<usize>) -> Diagnostic {
+        Diagnostic { span: Some(span), ..Diagnostic::new(severity, code, message) }
+    }
+
+    pub fn add_related(&mut self, span: Range<usize>, message: String) {
+        self.related.push(Related { span, message });
+    }
+
+    // the diagnostic as a compiler would report it: for one with a span, the location, message
+    // and line of the input under each of its span and related spans, e.g.
+    //
+    //   input.txt:3:7: error[unexpected-symbol]: unexpected ]
+    //     "a": ]
+    //          ^
+    //
+    // and otherwise the same as to_string, on a line of its own
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        let Some(span) = &self.span else {
+            return format!("{}\n", self);
+        };
+        let mut result = source_map.render(span.clone(), &format!("{}[{}]: {}", self.severity, self.code, self.message));
+        for related in &self.related {
+            result.push_str(&source_map.render(related.span.clone(), &format!("{}: {}", Severity::Note, related.message)));
+        }
+        result
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{}: ", location)?;
+        }
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+// collects the diagnostics of every phase of construction. pass the same collector to
+// Grammar::try_new_with_diagnostics and Parser::new_with_diagnostics (or
+// ParseTables::new_with_diagnostics) to get everything in one place, in the order it was found
+#[derive(Clone,Debug,Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics { diagnostics: Vec::new() }
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub(crate) fn note(&mut self, code: &'static str, message: String, symbols: Vec<Symbol>, location: Option<SourceLocation>) {
+        self.push(Diagnostic { symbols, location, ..Diagnostic::new(Severity::Note, code, message) });
+    }
+
+    pub(crate) fn warning(&mut self, code: &'static str, message: String, symbols: Vec<Symbol>, location: Option<SourceLocation>) {
+        self.push(Diagnostic { symbols, location, ..Diagnostic::new(Severity::Warning, code, message) });
+    }
+
+    pub fn diagnostics(&self) -> &Vec<Diagnostic> {
+        &self.diagnostics
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn with_code(&self, code: &str) -> Vec<&Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.code == code).collect()
+    }
+
+    pub fn has_warnings(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Warning)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    // every diagnostic rendered as Diagnostic::render does, in order
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        self.diagnostics.iter().map(|d| d.render(source_map)).collect()
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for d in &self.diagnostics {
+            writeln!(f, "{}", d)?;
+        }
+        Ok(())
+    }
+}
+
+// the label of a symbol for a diagnostic message
+pub(crate) fn label(symbol_db: &SymbolDb, s: &Symbol) -> String {
+    symbol_db.label(s).cloned().unwrap_or_else(|| format!("{:?}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::production::Production;
+
+    /* grammar:
+     *   S -> S + S | x | U | A | B | C
+     *   U -> U y
+     *   A -> ε
+     *   B -> z
+     *   C -> z
+     *   Z -> x
+     */
+    #[test]
+    fn diagnostics_01() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let u = symbol_db.new_nonterminal("U");
+        let a = symbol_db.new_nonterminal("A");
+        let b = symbol_db.new_nonterminal("B");
+        let c = symbol_db.new_nonterminal("C");
+        let z = symbol_db.new_nonterminal("Z");
+        let plus = symbol_db.new_terminal("+");
+        let x = symbol_db.new_terminal("x");
+        let y = symbol_db.new_terminal("y");
+        let zz = symbol_db.new_terminal("z");
+        let unused = symbol_db.new_terminal("w");
+        let productions = vec![
+            Production::new(s, vec![s, plus, s]),
+            Production::new(s, vec![x]),
+            Production::new(s, vec![u]),
+            Production::new(s, vec![a]),
+            Production::new(s, vec![b]),
+            Production::new(s, vec![c]),
+            Production::new(u, vec![u, y]),
+            Production::new(a, vec![]),
+            Production::new(b, vec![zz]),
+            Production::new(c, vec![zz]),
+            Production::new(z, vec![x]),
+        ];
+
+        let mut diagnostics = Diagnostics::new();
+        let g = Grammar::try_new_with_diagnostics(symbol_db, s, productions, &mut diagnostics).unwrap();
+        let _ = Parser::new_with_diagnostics(g, &mut diagnostics);
+
+        assert_eq!(diagnostics.with_code("unused-terminal")[0].symbols, vec![unused]);
+        assert_eq!(diagnostics.with_code("unreachable-nonterminal")[0].symbols, vec![z]);
+        assert_eq!(diagnostics.with_code("unproductive-nonterminal")[0].symbols, vec![u]);
+        assert_eq!(diagnostics.with_code("epsilon-only-nonterminal")[0].to_string(), "note[epsilon-only-nonterminal]: nonterminal A only derives ε");
+        assert!(!diagnostics.with_code("shift-reduce-conflict").is_empty());
+        let rr = diagnostics.with_code("reduce-reduce-conflict");
+        assert!(!rr.is_empty());
+        assert!(rr[0].symbols.contains(&b) && rr[0].symbols.contains(&c));
+        assert_eq!(diagnostics.with_code("unreduced-production").len(), 1);
+        assert!(diagnostics.has_warnings());
+        assert_eq!(diagnostics.to_string().lines().count(), diagnostics.len());
+    }
+
+    #[test]
+    fn diagnostics_02() {
+        let parser = Parser::new(Grammar::from_bnf("S -> ( S ) | x", "g").unwrap());
+        let db = parser.grammar().symbol_db();
+        let mut lexer: Lexer<()> = Lexer::new(db.eoi());
+        lexer.skip("\\s+").unwrap();
+        for t in ["x", "\\(", "\\)"] {
+            lexer.token(t, db.symbol(&t.replace('\\', "")).unwrap()).unwrap();
+        }
+        let source_map = SourceMap::new("in.txt", "((x)\n  x #");
+        let mut diagnostics = Diagnostics::new();
+        let error = lexer.lex(source_map.text()).err().unwrap();
+        diagnostics.push(error.to_diagnostic(source_map.text()));
+        let tokens = lexer.lex("((x)\n  x").unwrap();
+        let error = parser.parse(tokens.clone(), |t| t.symbol).err().unwrap();
+        let mut diagnostic = error.to_diagnostic(&tokens, db);
+        diagnostic.add_related(0..1, "to match this (".to_string());
+        diagnostics.push(diagnostic);
+        diagnostics.warning("made-up", "a warning about the grammar".to_string(), Vec::new(), None);
+
+        assert!(diagnostics.has_errors());
+        assert_eq!(diagnostics.render(&source_map), "\
+in.txt:2:5: error[unexpected-character]: unexpected character
+  x #
+    ^
+in.txt:2:3: error[unexpected-symbol]: unexpected x
+  x #
+  ^
+in.txt:1:1: note: to match this (
+((x)
+^
+warning[made-up]: a warning about the grammar
+");
+    }
+}
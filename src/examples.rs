@@ -0,0 +1,303 @@
+use std::ops::Range;
+
+use super::grammar::Grammar;
+use super::parse_error::ParseError;
+use super::parse_tree::ParseTree;
+use super::parser::Parser;
+use super::source_map::SourceMap;
+use super::spanned::Spanned;
+use super::symbol::Symbol;
+
+// ready-made grammars with lexers to go with them, for tests and as examples of the api. each
+// grammar is written in the text form read by Grammar::from_bnf, and each lexer turns a string
+// into tokens whose symbols are the grammar's terminals, ending with the end of input symbol
+
+// a token from one of the example lexers
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct Token {
+    pub symbol: Symbol,
+    pub text: String,
+    pub span: Range<usize>,
+}
+
+impl Spanned for Token {
+    fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+// the lexer found a character that can't start a token (or an unterminated string) at the given
+// byte offset
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct LexError {
+    pub position: usize,
+}
+
+impl LexError {
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        let end = source_map.text()[self.position..].chars().next().map_or(self.position, |c| self.position + c.len_utf8());
+        source_map.render(self.position..end, "unexpected character")
+    }
+}
+
+// the next token at the start of the text: its terminal (or None for whitespace and other text to
+// skip) and its length in bytes, or None if no token starts there
+type NextToken = fn(&str) -> Option<(Option<&'static str>,usize)>;
+
+pub struct Example {
+    parser: Parser,
+    next_token: NextToken,
+}
+
+impl Example {
+    fn new(bnf: &str, file: &str, next_token: NextToken) -> Example {
+        let grammar = Grammar::from_bnf(bnf, file).expect("example grammars are valid");
+        Example { parser: Parser::new(grammar), next_token }
+    }
+
+    pub fn grammar(&self) -> &Grammar {
+        self.parser.grammar()
+    }
+
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    pub fn lex(&self, text: &str) -> Result<Vec<Token>,LexError> {
+        let symbol_db = self.grammar().symbol_db();
+        let mut tokens = Vec::new();
+        let mut position = 0;
+        while position < text.len() {
+            let (label, len) = (self.next_token)(&text[position..]).ok_or(LexError { position })?;
+            let span = position..position + len;
+            if let Some(label) = label {
+                let symbol = symbol_db.symbol(label).expect("example lexers only produce terminals of their grammar");
+                tokens.push(Token { symbol, text: text[span.clone()].to_string(), span });
+            }
+            position += len;
+        }
+        tokens.push(Token { symbol: symbol_db.eoi(), text: String::new(), span: text.len()..text.len() });
+        Ok(tokens)
+    }
+
+    // lex and parse the text. as with Parser::parse_reader, a lexing error is the outer error
+    pub fn parse(&self, text: &str) -> Result<Result<ParseTree<Token>,ParseError>,LexError> {
+        let tokens = self.lex(text)?;
+        Ok(self.parser.parse(tokens, |t| t.symbol))
+    }
+}
+
+pub const ARITHMETIC: &str = "\
+expr -> expr + term | expr - term | term
+term -> term * factor | term / factor | factor
+factor -> ( expr ) | - factor | NUM
+";
+
+// arithmetic on decimal numbers, e.g. 2 * (3.5 - -1) / 4
+pub fn arithmetic() -> Example {
+    Example::new(ARITHMETIC, "arithmetic.bnf", |s| {
+        let c = s.chars().next()?;
+        match c {
+            c if c.is_whitespace() => Some((None, c.len_utf8())),
+            '0'..='9' => Some((Some("NUM"), number(s))),
+            c => punctuation(c, "+-*/()"),
+        }
+    })
+}
+
+pub const JSON: &str = "\
+value -> object | array | STRING | NUMBER | true | false | null
+object -> { } | { members }
+members -> member | members , member
+member -> STRING : value
+array -> [ ] | [ elements ]
+elements -> value | elements , value
+";
+
+// json as in rfc 8259
+pub fn json() -> Example {
+    Example::new(JSON, "json.bnf", |s| {
+        let c = s.chars().next()?;
+        match c {
+            ' ' | '\t' | '\r' | '\n' => Some((None, 1)),
+            '"' => Some((Some("STRING"), json_string(s)?)),
+            '-' | '0'..='9' => Some((Some("NUMBER"), json_number(s)?)),
+            c if c.is_ascii_alphabetic() => {
+                let len = s.bytes().take_while(|b| b.is_ascii_alphabetic()).count();
+                ["true", "false", "null"].into_iter().find(|k| *k == &s[..len]).map(|k| (Some(k), len))
+            },
+            c => punctuation(c, "{}[],:"),
+        }
+    })
+}
+
+pub const CSV: &str = "\
+records -> record | records NEWLINE record
+record -> field | record , field
+field -> FIELD | STRING | ε
+";
+
+// comma separated values as in rfc 4180. fields may be quoted, with "" standing for a quote
+// inside a quoted field. whitespace is part of the fields, and a trailing line break gives a last
+// record with a single empty field
+pub fn csv() -> Example {
+    Example::new(CSV, "csv.bnf", |s| {
+        let c = s.chars().next()?;
+        match c {
+            ',' => Some((Some(","), 1)),
+            '\n' => Some((Some("NEWLINE"), 1)),
+            '\r' if s[1..].starts_with('\n') => Some((Some("NEWLINE"), 2)),
+            '"' => Some((Some("STRING"), csv_string(s)?)),
+            _ => Some((Some("FIELD"), s.find([',', '"', '\r', '\n']).unwrap_or(s.len()))),
+        }
+    })
+}
+
+pub const IMPERATIVE: &str = "\
+program -> stmts
+stmts -> ε | stmts stmt
+stmt -> ID = expr ;
+     | print expr ;
+     | if ( expr ) block
+     | if ( expr ) block else block
+     | while ( expr ) block
+     | block
+block -> { stmts }
+expr -> expr == sum | expr < sum | sum
+sum -> sum + product | sum - product | product
+product -> product * atom | atom
+atom -> ( expr ) | ID | NUM
+";
+
+// a small imperative language with assignments, conditionals, loops and printing, e.g.
+//
+//   n = 10; i = 0;
+//   while (i < n) { if (i == 3) { print i; } i = i + 1; }
+pub fn imperative() -> Example {
+    Example::new(IMPERATIVE, "imperative.bnf", |s| {
+        let c = s.chars().next()?;
+        match c {
+            c if c.is_whitespace() => Some((None, c.len_utf8())),
+            '0'..='9' => Some((Some("NUM"), digits(s))),
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let len = s.bytes().take_while(|b| b.is_ascii_alphanumeric() || *b == b'_').count();
+                let keyword = ["if", "else", "while", "print"].into_iter().find(|k| *k == &s[..len]);
+                Some((Some(keyword.unwrap_or("ID")), len))
+            },
+            '=' if s[1..].starts_with('=') => Some((Some("=="), 2)),
+            c => punctuation(c, "=<+-*(){};"),
+        }
+    })
+}
+
+// a single character token whose terminal is the character itself
+fn punctuation(c: char, chars: &'static str) -> Option<(Option<&'static str>,usize)> {
+    chars.find(c).map(|i| (Some(&chars[i..i + 1]), 1))
+}
+
+fn digits(s: &str) -> usize {
+    s.bytes().take_while(|b| b.is_ascii_digit()).count()
+}
+
+// digits with an optional fraction
+fn number(s: &str) -> usize {
+    let len = digits(s);
+    match s[len..].strip_prefix('.') {
+        Some(rest) if digits(rest) > 0 => len + 1 + digits(rest),
+        _ => len,
+    }
+}
+
+fn json_number(s: &str) -> Option<usize> {
+    let mut len = usize::from(s.starts_with('-'));
+    if digits(&s[len..]) == 0 {
+        return None;
+    }
+    len += number(&s[len..]);
+    if s[len..].starts_with(['e', 'E']) {
+        let sign = usize::from(s[len + 1..].starts_with(['+', '-']));
+        let exponent = digits(&s[len + 1 + sign..]);
+        if exponent == 0 {
+            return None;
+        }
+        len += 1 + sign + exponent;
+    }
+    Some(len)
+}
+
+// a string in double quotes where a backslash escapes the next character
+fn json_string(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(i + 1),
+            _ => {},
+        }
+    }
+    None
+}
+
+// a string in double quotes where two quotes stand for one
+fn csv_string(s: &str) -> Option<usize> {
+    let mut i = 1;
+    loop {
+        i += s[i..].find('"')? + 1;
+        if !s[i..].starts_with('"') {
+            return Some(i);
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(example: &Example, text: &str) -> Vec<String> {
+        let symbol_db = example.grammar().symbol_db();
+        example.lex(text).unwrap().iter().map(|t| symbol_db.label(&t.symbol).unwrap().clone()).collect()
+    }
+
+    #[test]
+    fn examples_01() {
+        for example in [arithmetic(), json(), csv(), imperative()] {
+            assert!(example.parser().parse_tables().conflicts().is_empty());
+        }
+
+        let e = arithmetic();
+        assert_eq!(labels(&e, "2*(3.5 - -1)"), vec!["NUM", "*", "(", "NUM", "-", "-", "NUM", ")", "$"]);
+        let tree = e.parse("2 * (3.5 - -1) / 4").unwrap().unwrap();
+        assert_eq!(tree.span(), 0..18);
+        assert!(e.parse("2 +").unwrap().is_err());
+        assert_eq!(e.parse("2 % 3"), Err(LexError { position: 2 }));
+        let text = "1 +\n  é";
+        let error = e.lex(text).err().unwrap();
+        assert_eq!(error.render(&SourceMap::new("x", text)), "x:2:3: unexpected character\n  é\n  ^\n");
+        let tokens = e.lex("1 +\n (2").unwrap();
+        let error = e.parser().parse(tokens.clone(), |t| t.symbol).err().unwrap();
+        let rendered = error.render(&tokens, &SourceMap::new("x", "1 +\n (2"), e.grammar().symbol_db());
+        assert_eq!(rendered, "x:2:4: unexpected end of input\n (2\n   ^\n");
+
+        let e = json();
+        let text = r#"{"a": [1, -2.5e+3, true, null], "b\"": {}}"#;
+        assert_eq!(labels(&e, text).len(), 19);
+        assert!(e.parse(text).unwrap().is_ok());
+        assert!(e.parse("[1, 2,]").unwrap().is_err());
+        assert_eq!(e.parse("[nul]"), Err(LexError { position: 1 }));
+        assert_eq!(e.parse("\"abc"), Err(LexError { position: 0 }));
+
+        let e = csv();
+        assert_eq!(labels(&e, "a b,\"x\"\"y\",\r\n,"), vec!["FIELD", ",", "STRING", ",", "NEWLINE", ",", "$"]);
+        assert_eq!(e.lex("\"x\"\"y\"").unwrap()[0].text, "\"x\"\"y\"");
+        assert!(e.parse("a,b\n1,2\n").unwrap().is_ok());
+        assert!(e.parse("a\"b\"").unwrap().is_err());
+
+        let e = imperative();
+        let text = "n = 10; i = 0;\nwhile (i < n) { if (i == 3) { print i; } else { } i = i + 1; }";
+        assert!(e.parse(text).unwrap().is_ok());
+        assert_eq!(labels(&e, "iffy if"), vec!["ID", "if", "$"]);
+        assert!(e.parse("if (x) y = 1;").unwrap().is_err());
+    }
+}
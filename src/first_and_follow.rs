@@ -1,12 +1,14 @@
-use std::collections::{HashMap,HashSet};
+use std::collections::HashMap;
 
+use super::diagnostics::{label,Diagnostics};
 use super::grammar::Grammar;
 use super::symbol::Symbol;
+use super::symbol_set::TerminalSet;
 
 #[derive(Debug)]
 pub struct FirstAndFollow {
-    first: HashMap<Symbol, HashSet<Symbol>>,
-    follow: HashMap<Symbol, HashSet<Symbol>>,
+    first: HashMap<Symbol, TerminalSet>,
+    follow: HashMap<Symbol, TerminalSet>,
 }
 
 impl FirstAndFollow {
@@ -16,29 +18,50 @@ impl FirstAndFollow {
         FirstAndFollow { first , follow }
     }
 
-    pub fn first(&self, s: &Symbol) -> Option<&HashSet<Symbol>> {
+    pub fn first(&self, s: &Symbol) -> Option<&TerminalSet> {
         self.first.get(s)
     }
 
     #[allow(dead_code)]
-    pub fn follow(&self, s: &Symbol) -> Option<&HashSet<Symbol>> {
+    pub fn follow(&self, s: &Symbol) -> Option<&TerminalSet> {
         self.follow.get(s)
     }
+
+    // report nonterminals whose first sets say something odd about them: an empty first set means
+    // the nonterminal derives no string at all (every production recurses forever), and a first
+    // set of just ε means it can only ever match nothing
+    pub fn diagnose(&self, grammar: &Grammar, diagnostics: &mut Diagnostics) {
+        let symbol_db = grammar.symbol_db();
+        let epsilon = symbol_db.epsilon();
+        for nt in grammar.nonterminals() {
+            let first = match self.first.get(&nt) {
+                Some(first) => first,
+                None => continue,
+            };
+            if first.is_empty() {
+                let message = format!("nonterminal {} does not derive any string of terminals", label(symbol_db, &nt));
+                diagnostics.warning("unproductive-nonterminal", message, vec![nt], grammar.symbol_location(&nt).cloned());
+            } else if first.len() == 1 && first.contains(&epsilon) {
+                let message = format!("nonterminal {} only derives ε", label(symbol_db, &nt));
+                diagnostics.note("epsilon-only-nonterminal", message, vec![nt], grammar.symbol_location(&nt).cloned());
+            }
+        }
+    }
 }
 
-fn first(grammar: &Grammar) -> HashMap<Symbol,HashSet<Symbol>> {
-    let mut first: HashMap<Symbol,HashSet<Symbol>> = HashMap::new();
+fn first(grammar: &Grammar) -> HashMap<Symbol,TerminalSet> {
+    let mut first: HashMap<Symbol,TerminalSet> = HashMap::new();
     
     // for each terminal t, first(t) = {t}
     for s in grammar.terminals() {
-        let mut set = HashSet::new();
-        set.insert(*s);
-        first.insert(*s, set);
+        let mut set = TerminalSet::new();
+        set.insert(s);
+        first.insert(s, set);
     }
 
     // for each nonterminal nt, initialize first(nt) to an empty set
     for s in grammar.nonterminals() {
-        first.insert(*s, HashSet::new());
+        first.insert(s, TerminalSet::new());
     }
 
     let mut done = false;
@@ -47,31 +70,32 @@ fn first(grammar: &Grammar) -> HashMap<Symbol,HashSet<Symbol>> {
         // for each of the nonterminals
         for nt in grammar.nonterminals() {
             // iterate through every production
-            if let Some(ps) = grammar.productions(nt) {
+            if let Some(ps) = grammar.productions(&nt) {
                 for p in ps {
-                    let mut new: HashSet<Symbol> = HashSet::new();
-                    // for a production A -> a_1 a_2 ... a_n, add first(a_i) to the
+                    let epsilon = grammar.symbol_db().epsilon();
+                    let mut new = TerminalSet::new();
+                    // for a production A -> a_1 a_2 ... a_n, add first(a_i) minus epsilon to the
                     // set of first items until some first(a_i) does not contain epsilon
+                    let mut nullable = true;
                     for a_i in p.rhs() {
                         if let Some(fs) = first.get(a_i) {
-                            for s in fs {
-                                new.insert(*s);
-                            }
-                            if !fs.contains(&grammar.symbol_db().epsilon()) {
-                                new.remove(&grammar.symbol_db().epsilon());
+                            new.extend(fs.iter().filter(|s| *s != epsilon));
+                            if !fs.contains(&epsilon) {
+                                nullable = false;
                                 break;
                             }
                         }
                     }
+                    // if every a_i can derive epsilon (including when there are none), so can A
+                    if nullable {
+                        new.insert(epsilon);
+                    }
                     // if the computed set contains items that aren't yet in the
                     // first set for this production's LHS, then add those items
                     // and reset the done flag so that the process continues
                     if let Some(fs) = first.get_mut(p.lhs()) {
-                        for s in &new {
-                            if !fs.contains(s) {
-                                fs.insert(*s);
-                                done = false;
-                            }
+                        if fs.union_with(&new) {
+                            done = false;
                         }
                     }
                 }
@@ -82,13 +106,13 @@ fn first(grammar: &Grammar) -> HashMap<Symbol,HashSet<Symbol>> {
     first
 }
 
-fn follow(grammar: &Grammar, first: &HashMap<Symbol,HashSet<Symbol>>) -> HashMap<Symbol,HashSet<Symbol>> {
+fn follow(grammar: &Grammar, first: &HashMap<Symbol,TerminalSet>) -> HashMap<Symbol,TerminalSet> {
     let symbol_db = grammar.symbol_db();
-    let mut follow: HashMap<Symbol,HashSet<Symbol>> = HashMap::new();
+    let mut follow: HashMap<Symbol,TerminalSet> = HashMap::new();
     
     // initialize follow(s) to an empty set for each nonterminal s
     for s in grammar.nonterminals() {
-        follow.insert(*s, HashSet::new());
+        follow.insert(s, TerminalSet::new());
     }
 
     // add $ to follow(goal)
@@ -102,13 +126,13 @@ fn follow(grammar: &Grammar, first: &HashMap<Symbol,HashSet<Symbol>>) -> HashMap
         // for each nonterminal nt
         for nt in grammar.nonterminals() {
             // iterate through every production where nt is the lhs
-            if let Some(ps) = grammar.productions(nt) {
+            if let Some(ps) = grammar.productions(&nt) {
                 for p in ps {
                     // for a production A -> b_1 b_2 ... b_n
-                    let mut tail: HashSet<Symbol> = HashSet::new();
+                    let mut tail = TerminalSet::new();
                     // set an initial tail set to contain follow(A) as calculated so far
-                    if let Some(tmp) = follow.get(nt) {
-                        for s in tmp { tail.insert(*s); }
+                    if let Some(tmp) = follow.get(&nt) {
+                        tail.union_with(tmp);
                     }
                     // go through each b_i in reverse order
                     for b_i in p.rhs().iter().rev() {
@@ -121,14 +145,10 @@ fn follow(grammar: &Grammar, first: &HashMap<Symbol,HashSet<Symbol>>) -> HashMap
                         // if b_i is a nonterminal
                         else {
                             if let Some(follow_b_i) = follow.get_mut(b_i) {
-                                // and tail contains items that are not in follow(b_i)
-                                for x in &tail {
-                                    if !follow_b_i.contains(x) {
-                                        // add the items to follow(b_i)
-                                        follow_b_i.insert(*x);
-                                        // and indicate that the process must continue
-                                        done = false;
-                                    }
+                                // if tail contains items that are not in follow(b_i), add them
+                                // and indicate that the process must continue
+                                if follow_b_i.union_with(&tail) {
+                                    done = false;
                                 }
                             }
                             // if first(b_i) contains epsilon, then add first(b_i) minus
@@ -138,19 +158,13 @@ fn follow(grammar: &Grammar, first: &HashMap<Symbol,HashSet<Symbol>>) -> HashMap
                             if let Some(first_b_i) = first.get(b_i) {
                                 let epsilon = symbol_db.epsilon();
                                 if first_b_i.contains(&epsilon) {
-                                    for x in first_b_i {
-                                        if x != &epsilon {
-                                            tail.insert(*x);
-                                        }
-                                    }
+                                    tail.union_with(first_b_i);
+                                    tail.remove(&epsilon);
                                 }
                                 // if first(b_i) does not contain epsilon, then tail is
                                 // reset to contain first(b_i)
                                 else {
-                                    tail.clear();
-                                    for x in first_b_i {
-                                        tail.insert(*x);
-                                    }
+                                    tail = first_b_i.clone();
                                 }
                             }
                         }
@@ -256,7 +270,7 @@ mod tests {
         let b = symbol_db.new_terminal("b");
         let p1 = Production::new(s, vec![x, b]);
         let p2 = Production::new(x, vec![a]);
-        let p3 = Production::new(x, vec![symbol_db.epsilon()]);
+        let p3 = Production::new(x, vec![]);
         let g = Grammar::new(symbol_db, s, vec![p1, p2, p3]);
         let ff = FirstAndFollow::new(&g);
         let first_s = ff.first(&s).unwrap();
@@ -284,7 +298,7 @@ mod tests {
         let b = symbol_db.new_terminal("b");
         let p1 = Production::new(s, vec![x, y]);
         let p2 = Production::new(x, vec![a]);
-        let p3 = Production::new(x, vec![symbol_db.epsilon()]);
+        let p3 = Production::new(x, vec![]);
         let p4 = Production::new(y, vec![s]);
         let p5 = Production::new(y, vec![b]);
         let g = Grammar::new(symbol_db, s, vec![p1, p2, p3, p4, p5]);
@@ -366,11 +380,11 @@ mod tests {
         let p1 = Production::new(expr, vec![term, expr_]);
         let p2 = Production::new(expr_, vec![plus, term, expr_]);
         let p3 = Production::new(expr_, vec![minus, term, expr_]);
-        let p4 = Production::new(expr_, vec![symbol_db.epsilon()]);
+        let p4 = Production::new(expr_, vec![]);
         let p5 = Production::new(term, vec![factor, term_]);
         let p6 = Production::new(term_, vec![mult, factor, term_]);
         let p7 = Production::new(term_, vec![div, factor, term_]);
-        let p8 = Production::new(term_, vec![symbol_db.epsilon()]);
+        let p8 = Production::new(term_, vec![]);
         let g = Grammar::new(symbol_db, expr, vec![p1, p2, p3, p4, p5, p6, p7, p8]);
         let ff = FirstAndFollow::new(&g);
         let first_expr = ff.first(&expr).unwrap();
@@ -409,9 +423,9 @@ mod tests {
         let c = symbol_db.new_terminal("c");
         let p1 = Production::new(s, vec![x]);
         let p2 = Production::new(x, vec![y]);
-        let p3 = Production::new(x, vec![symbol_db.epsilon()]);
+        let p3 = Production::new(x, vec![]);
         let p4 = Production::new(y, vec![z]);
-        let p5 = Production::new(y, vec![symbol_db.epsilon()]);
+        let p5 = Production::new(y, vec![]);
         let p6 = Production::new(z, vec![x, a]);
         let p7 = Production::new(z, vec![y, b]);
         let p8 = Production::new(z, vec![c]);
@@ -438,11 +452,10 @@ mod tests {
         let y = symbol_db.new_nonterminal("Y");
         let a = symbol_db.new_terminal("a");
         let b = symbol_db.new_terminal("b");
-        let epsilon = symbol_db.epsilon();
         let eoi = symbol_db.eoi();
         let p1 = Production::new(s, vec![x, y]);
         let p2 = Production::new(x, vec![a]);
-        let p3 = Production::new(x, vec![epsilon]);
+        let p3 = Production::new(x, vec![]);
         let p4 = Production::new(y, vec![s]);
         let p5 = Production::new(y, vec![b]);
         let g = Grammar::new(symbol_db, s, vec![p1, p2, p3, p4, p5]);
@@ -491,11 +504,11 @@ mod tests {
         let p1 = Production::new(expr, vec![term, expr_]);
         let p2 = Production::new(expr_, vec![plus, term, expr_]);
         let p3 = Production::new(expr_, vec![minus, term, expr_]);
-        let p4 = Production::new(expr_, vec![symbol_db.epsilon()]);
+        let p4 = Production::new(expr_, vec![]);
         let p5 = Production::new(term, vec![factor, term_]);
         let p6 = Production::new(term_, vec![mult, factor, term_]);
         let p7 = Production::new(term_, vec![div, factor, term_]);
-        let p8 = Production::new(term_, vec![symbol_db.epsilon()]);
+        let p8 = Production::new(term_, vec![]);
         let p9 = Production::new(factor, vec![left, expr, right]);
         let p10 = Production::new(factor, vec![num]);
         let p11 = Production::new(factor, vec![name]);
@@ -0,0 +1,159 @@
+use std::ops::Range;
+
+use super::parse_tree::ParseTree;
+use super::production::ProductionId;
+use super::symbol::Symbol;
+
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct FlatNode<T> {
+    symbol: Symbol,
+    token: T,
+    epsilon: bool,
+    production: Option<ProductionId>,
+    // where this node's children are listed in the tree's child index
+    children: Range<usize>,
+    // the index of the first node of this node's subtree. the subtree is everything from there up
+    // to and including the node itself
+    first: usize,
+}
+
+impl<T> FlatNode<T> {
+    pub fn symbol(&self) -> &Symbol {
+        &self.symbol
+    }
+
+    pub fn token(&self) -> &T {
+        &self.token
+    }
+
+    pub fn is_epsilon(&self) -> bool {
+        self.epsilon
+    }
+
+    pub fn production(&self) -> Option<ProductionId> {
+        self.production
+    }
+}
+
+// a parse tree stored as a single vector of nodes in post-order, so children always come before
+// their parents and the root is the last node. each node's children are found through a range
+// into a second vector of node indices rather than through a vector of their own
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct FlatTree<T> {
+    nodes: Vec<FlatNode<T>>,
+    child_indices: Vec<usize>,
+}
+
+impl<T> FlatTree<T> {
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // a tree always has at least its root, so this is only here to keep len company
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn root(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    pub fn nodes(&self) -> &[FlatNode<T>] {
+        &self.nodes
+    }
+
+    pub fn node(&self, index: usize) -> &FlatNode<T> {
+        &self.nodes[index]
+    }
+
+    // the indices of a node's children, from left to right
+    pub fn children(&self, index: usize) -> &[usize] {
+        &self.child_indices[self.nodes[index].children.clone()]
+    }
+
+    // the indices of all the nodes in a node's subtree, including the node itself
+    pub fn subtree(&self, index: usize) -> Range<usize> {
+        self.nodes[index].first..(index + 1)
+    }
+
+    pub fn into_tree(self) -> ParseTree<T> {
+        // in post-order, a node's children are exactly the last few trees finished before it
+        let mut stack: Vec<ParseTree<T>> = Vec::new();
+        for node in self.nodes {
+            let children = stack.split_off(stack.len() - node.children.len());
+            stack.push(ParseTree::from_parts(node.symbol, node.token, children, node.epsilon, node.production));
+        }
+        stack.pop().unwrap()
+    }
+
+    fn push(&mut self, tree: ParseTree<T>) -> usize {
+        let first = self.nodes.len();
+        let (symbol, token, children, epsilon, production) = tree.into_parts();
+        let indices: Vec<usize> = children.into_iter().map(|c| self.push(c)).collect();
+        let start = self.child_indices.len();
+        self.child_indices.extend(indices);
+        let end = self.child_indices.len();
+        self.nodes.push(FlatNode { symbol, token, epsilon, production, children: start..end, first });
+        self.nodes.len() - 1
+    }
+}
+
+impl<T: Clone> FlatTree<T> {
+    pub fn to_tree(&self) -> ParseTree<T> {
+        self.clone().into_tree()
+    }
+}
+
+impl<T> From<ParseTree<T>> for FlatTree<T> {
+    fn from(tree: ParseTree<T>) -> FlatTree<T> {
+        let mut flat = FlatTree { nodes: Vec::new(), child_indices: Vec::new() };
+        flat.push(tree);
+        flat
+    }
+}
+
+impl<T> From<FlatTree<T>> for ParseTree<T> {
+    fn from(tree: FlatTree<T>) -> ParseTree<T> {
+        tree.into_tree()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol::SymbolDb;
+
+    #[test]
+    fn flat_tree_01() {
+        let mut symbol_db = SymbolDb::new();
+        let e = symbol_db.new_nonterminal("E");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+
+        // E(( E(( E ))) ))
+        let mut inner = ParseTree::new(e, 2);
+        inner.add_child(ParseTree::new(lp, 1));
+        inner.add_child(ParseTree::epsilon(e, 2));
+        inner.add_child(ParseTree::new(rp, 2));
+        let mut tree = ParseTree::new(e, 0);
+        tree.add_child(ParseTree::new(lp, 0));
+        tree.add_child(inner);
+        tree.add_child(ParseTree::new(rp, 3));
+
+        let flat = FlatTree::from(tree.clone());
+        assert_eq!(flat.len(), 7);
+        let root = flat.root();
+        assert_eq!(root, 6);
+        assert_eq!(flat.subtree(root), 0..7);
+        assert_eq!(flat.children(root), &[0, 4, 5]);
+        assert_eq!(flat.children(4), &[1, 2, 3]);
+        assert_eq!(flat.subtree(4), 1..5);
+        assert!(flat.node(2).is_epsilon());
+        assert!(flat.children(2).is_empty());
+        assert_eq!(*flat.node(5).symbol(), rp);
+        assert_eq!(*flat.node(5).token(), 3);
+
+        assert_eq!(flat.to_tree(), tree);
+        assert_eq!(ParseTree::from(flat), tree);
+    }
+}
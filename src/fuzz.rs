@@ -0,0 +1,103 @@
+use super::grammar::Grammar;
+use super::parse_error::ParseError;
+use super::parse_tree::ParseTree;
+use super::parser::{ParseSession,Parser};
+use super::symbol::Symbol;
+
+// turns arbitrary bytes into token sequences for a grammar, so that a fuzzer can explore the
+// parser's behaviour on malformed input without knowing anything about the grammar. each byte
+// picks one of the grammar's terminals (other than ε and $), and the end of input is appended
+pub struct FuzzInput {
+    terminals: Vec<Symbol>,
+    eoi: Symbol,
+}
+
+impl FuzzInput {
+    pub fn new(grammar: &Grammar) -> FuzzInput {
+        let symbol_db = grammar.symbol_db();
+        let terminals: Vec<Symbol> = grammar.declared_terminals().iter().collect();
+        FuzzInput { terminals, eoi: symbol_db.eoi() }
+    }
+
+    pub fn tokens(&self, data: &[u8]) -> Vec<Symbol> {
+        let mut result = Vec::with_capacity(data.len() + 1);
+        if !self.terminals.is_empty() {
+            result.extend(data.iter().map(|b| self.terminals[*b as usize % self.terminals.len()]));
+        }
+        result.push(self.eoi);
+        result
+    }
+}
+
+// the body of a fuzz target, e.g. for cargo fuzz:
+//
+//   fuzz_target!(|data: &[u8]| { let _ = fuzz_parse(&PARSER, data); });
+//
+// whatever the input, the parse has to finish with a tree or an error rather than a panic, and
+// its stacks can't grow past a bound set by the input length and the shape of the grammar. a
+// violation of the bound panics, so the fuzzer reports it like any other crash
+pub fn fuzz_parse(parser: &Parser, data: &[u8]) -> Result<ParseTree<Symbol>,ParseError> {
+    let grammar = parser.grammar();
+    let tokens = FuzzInput::new(grammar).tokens(data);
+
+    let mut session = ParseSession::new();
+    session.enable_stats();
+    let result = session.parse(parser, &tokens, |s| *s);
+
+    // between two shifted tokens the stack can only hold nonterminals that derived nothing, and
+    // there are at most (longest rhs) of those for each level of nesting
+    let longest_rhs = grammar.all_productions().iter().map(|p| p.rhs().len()).max().unwrap_or(0);
+    let bound = (tokens.len() + 1) * (longest_rhs + 1) * (grammar.nonterminals().len() + 1);
+    let depth = session.stats().unwrap().max_stack_depth;
+    assert!(depth <= bound, "stack depth {} exceeds the bound {} for {} tokens", depth, bound, tokens.len());
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::production::Production;
+    use crate::symbol::SymbolDb;
+
+    /* grammar:
+     *   S -> S ; A | A
+     *   A -> ( S ) | x | ε
+     */
+    #[test]
+    fn fuzz_parse_01() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let a = symbol_db.new_nonterminal("A");
+        let semi = symbol_db.new_terminal(";");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let x = symbol_db.new_terminal("x");
+        let productions = vec![
+            Production::new(s, vec![s, semi, a]),
+            Production::new(s, vec![a]),
+            Production::new(a, vec![lp, s, rp]),
+            Production::new(a, vec![x]),
+            Production::new(a, vec![]),
+        ];
+        let p = Parser::new(Grammar::new(symbol_db, s, productions));
+
+        let input = FuzzInput::new(p.grammar());
+        assert_eq!(input.tokens(&[]), vec![p.grammar().symbol_db().eoi()]);
+        assert!(fuzz_parse(&p, &[]).is_ok());
+
+        // a small linear congruential generator stands in for the fuzzer
+        let mut seed: u32 = 12345;
+        let mut accepted = 0;
+        for length in 0..200 {
+            let data: Vec<u8> = (0..length % 40).map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as u8
+            }).collect();
+            if fuzz_parse(&p, &data).is_ok() {
+                accepted += 1;
+            }
+        }
+        assert!(accepted > 0);
+    }
+}
@@ -1,26 +1,159 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt;
-use std::fmt::Error;
+use std::hash::Hasher;
 
-use super::production::Production;
+use super::dependency_graph::DependencyGraph;
+use super::diagnostics::{label,Diagnostics};
+use super::production::{rules,Production,ProductionDisplay,ProductionId};
+use super::source_location::SourceLocation;
 use super::symbol::{Symbol,SymbolDb};
+use super::symbol_set::{NonTerminalSet,TerminalSet};
+use super::table_hash::FxHasher;
 
+#[derive(Clone,Debug,Eq,Hash,Ord,PartialEq,PartialOrd)]
+pub enum GrammarWarning {
+    // a terminal was declared in the symbol db but no production uses it
+    UnusedTerminal(Symbol),
+    // the same production was given more than once
+    DuplicateProduction(Production),
+}
+
+impl GrammarWarning {
+    pub fn to_string(&self, symbol_db: &SymbolDb) -> String {
+        match self {
+            GrammarWarning::UnusedTerminal(s) => {
+                format!("terminal {} is never used", symbol_db.label(s).unwrap())
+            },
+            GrammarWarning::DuplicateProduction(p) => {
+                format!("production {} is duplicated", p.display(symbol_db))
+            },
+        }
+    }
+}
+
+// a reason a grammar can't be built
+#[derive(Clone,Debug,Eq,Hash,Ord,PartialEq,PartialOrd)]
+pub enum GrammarError {
+    // the start symbol has to be a nonterminal
+    TerminalStartSymbol(Symbol),
+    // the left hand side of a production has to be a nonterminal
+    TerminalLhs(Production),
+    // GOAL is reserved for the production the grammar adds itself
+    GoalLhs(Production),
+    // ε stands for an empty right hand side, so it has to be the only symbol there
+    MisplacedEpsilon(Production),
+    // $ can only end the input, so it can only be the last symbol of a right hand side
+    MisplacedEndOfInput(Production),
+}
+
+impl GrammarError {
+    pub fn to_string(&self, symbol_db: &SymbolDb) -> String {
+        match self {
+            GrammarError::TerminalStartSymbol(s) => {
+                format!("start symbol {} is a terminal", symbol_db.label(s).unwrap())
+            },
+            GrammarError::TerminalLhs(p) => {
+                format!("production {} has a terminal on its left hand side", p.display(symbol_db))
+            },
+            GrammarError::GoalLhs(p) => {
+                format!("production {} has the reserved symbol GOAL on its left hand side", p.display(symbol_db))
+            },
+            GrammarError::MisplacedEpsilon(p) => {
+                format!("production {} uses ε alongside other symbols", p.display(symbol_db))
+            },
+            GrammarError::MisplacedEndOfInput(p) => {
+                format!("production {} has $ before the end of its right hand side", p.display(symbol_db))
+            },
+        }
+    }
+}
+
+// everything wrong with a grammar that couldn't be built. the symbol db is handed back so that
+// the errors can still be printed with labels
 #[derive(Debug)]
+pub struct GrammarErrors {
+    symbol_db: SymbolDb,
+    errors: Vec<GrammarError>,
+    // where each error is in the grammar's text, for grammars that were loaded from text
+    locations: Vec<Option<SourceLocation>>,
+}
+
+impl GrammarErrors {
+    pub fn errors(&self) -> &Vec<GrammarError> { &self.errors }
+    pub fn locations(&self) -> &Vec<Option<SourceLocation>> { &self.locations }
+    pub fn symbol_db(&self) -> &SymbolDb { &self.symbol_db }
+    pub fn into_symbol_db(self) -> SymbolDb { self.symbol_db }
+
+    pub(crate) fn locate<F: Fn(&GrammarError) -> Option<SourceLocation>>(&mut self, f: F) {
+        self.locations = self.errors.iter().map(f).collect();
+    }
+}
+
+impl fmt::Display for GrammarErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let errors: Vec<String> = self.errors.iter().zip(&self.locations)
+            .map(|(e, l)| match l {
+                Some(l) => format!("{}: {}", l, e.to_string(&self.symbol_db)),
+                None => e.to_string(&self.symbol_db),
+            })
+            .collect();
+        write!(f, "invalid grammar: {}", errors.join("; "))
+    }
+}
+
+#[derive(Clone,Debug)]
 pub struct Grammar {
     symbol_db: SymbolDb,
     start_symbol: Symbol,
     productions: HashMap<Symbol, Vec<Production>>,
+    // every production in declaration order, indexed by ProductionId
+    all_productions: Vec<Production>,
+    ids: HashMap<Production,ProductionId>,
+    augmented: Production,
+    // where the productions (by id) and symbols were written, for grammars loaded from text
+    production_locations: Vec<Option<SourceLocation>>,
+    symbol_locations: HashMap<Symbol,SourceLocation>,
+    // the names given to the productions (by id), e.g. Add for e -> e + e
+    production_labels: Vec<Option<String>>,
+    // the features (by production id) a production belongs to, e.g. lambdas for the productions
+    // that only a newer version of a language has
+    production_features: Vec<Vec<String>>,
+    // the productions (by id) whose nodes are spliced into their parents
+    inline: Vec<bool>,
+    // the terminal that stands for a stretch of input that didn't parse (see with_error_symbol)
+    error_symbol: Option<Symbol>,
 }
 
 impl Grammar {
+  // like try_new, but panics if the grammar is malformed
   pub fn new(symbol_db: SymbolDb, start_symbol: Symbol, productions: Vec<Production>) -> Grammar {
-      let mut productions = productions;
+      match Grammar::try_new(symbol_db, start_symbol, productions) {
+          Ok(g) => g,
+          Err(errors) => panic!("{}", errors),
+      }
+  }
+
+  // build the grammar, or report everything that's wrong with it (sorted, so the result doesn't
+  // depend on the order of the productions)
+  #[allow(clippy::result_large_err)] // a grammar is just as big as the errors
+  pub fn try_new(symbol_db: SymbolDb, start_symbol: Symbol, productions: Vec<Production>) -> Result<Grammar,GrammarErrors> {
+      let errors = validate(&symbol_db, start_symbol, &productions);
+      if !errors.is_empty() {
+          let locations = vec![None; errors.len()];
+          return Err(GrammarErrors { symbol_db, errors, locations });
+      }
+
+      // an ε production has an empty right hand side. writing it as A -> ε still works, but it's
+      // stored as A -> (nothing) like any other way of writing it
+      let epsilon = symbol_db.epsilon();
+      let mut productions: Vec<Production> = productions.into_iter()
+          .map(|p| if p.rhs() == [epsilon] { Production::new(*p.lhs(), vec![]) } else { p })
+          .collect();
 
-      // add the rule "goal -> start_symbol $"
-      let p = Production::new(symbol_db.goal(), vec![start_symbol, symbol_db.eoi()]);
-      productions.push(p);
+      let augmented = augmentation(&symbol_db, start_symbol, false);
+      productions.push(augmented.clone());
 
-      fn group_by_lhs(ps: &Vec<Production>) -> HashMap<Symbol,Vec<Production>> {
+      fn group_by_lhs(ps: &[Production]) -> HashMap<Symbol,Vec<Production>> {
           let mut result: HashMap<Symbol,Vec<Production>> = HashMap::new();
           ps.iter().for_each(|p| {
               let lhs = p.lhs();
@@ -32,33 +165,665 @@ impl Grammar {
           result
       }
 
-      Grammar {
+      // a duplicated production gets the id of its first occurrence
+      let mut ids = HashMap::new();
+      for (i, p) in productions.iter().enumerate() {
+          ids.entry(p.clone()).or_insert(ProductionId(i));
+      }
+
+      Ok(Grammar {
           symbol_db,
           start_symbol,
           productions: group_by_lhs(&productions),
+          production_locations: vec![None; productions.len()],
+          production_labels: vec![None; productions.len()],
+          production_features: vec![Vec::new(); productions.len()],
+          inline: vec![false; productions.len()],
+          error_symbol: None,
+          all_productions: productions,
+          ids,
+          augmented,
+          symbol_locations: HashMap::new(),
+      })
+  }
+
+  // like try_new, but the warnings about the grammar also go into diagnostics
+  #[allow(clippy::result_large_err)]
+  pub fn try_new_with_diagnostics(symbol_db: SymbolDb, start_symbol: Symbol, productions: Vec<Production>, diagnostics: &mut Diagnostics) -> Result<Grammar,GrammarErrors> {
+      let grammar = Grammar::try_new(symbol_db, start_symbol, productions)?;
+      for w in grammar.warnings() {
+          let message = w.to_string(&grammar.symbol_db);
+          match w {
+              GrammarWarning::UnusedTerminal(t) => {
+                  let location = grammar.symbol_location(&t).cloned();
+                  diagnostics.warning("unused-terminal", message, vec![t], location);
+              },
+              GrammarWarning::DuplicateProduction(p) => {
+                  // point at the second occurrence, since the first one is the original
+                  let location = grammar.productions_with_ids()
+                      .filter(|(_, q)| **q == p)
+                      .nth(1)
+                      .and_then(|(id, _)| grammar.production_location(id).cloned());
+                  diagnostics.warning("duplicate-production", message, vec![*p.lhs()], location);
+              },
+          }
+      }
+      // a nonterminal that the start symbol can't lead to never takes part in a parse
+      let reachable = grammar.reachable();
+      for nt in grammar.nonterminals().iter().filter(|nt| !reachable.contains(nt)) {
+          let message = format!("nonterminal {} is unreachable from the start symbol", label(&grammar.symbol_db, &nt));
+          diagnostics.warning("unreachable-nonterminal", message, vec![nt], grammar.symbol_location(&nt).cloned());
+      }
+      Ok(grammar)
+  }
+
+  // add a production as if it had been declared after all the others, so that the ids of the
+  // existing productions don't change. its symbols have to be in the symbol db already. adding a
+  // production that's already in the grammar changes nothing and gives back the id it has
+  #[allow(clippy::result_large_err)]
+  pub fn extend(&mut self, p: Production) -> Result<ProductionId,GrammarErrors> {
+      let errors = validate(&self.symbol_db, self.start_symbol, std::slice::from_ref(&p));
+      if !errors.is_empty() {
+          let locations = vec![None; errors.len()];
+          return Err(GrammarErrors { symbol_db: self.symbol_db.clone(), errors, locations });
+      }
+      let epsilon = self.symbol_db.epsilon();
+      let p = if p.rhs() == [epsilon] { Production::new(*p.lhs(), vec![]) } else { p };
+      if let Some(id) = self.production_id(&p) {
+          return Ok(id);
       }
+
+      // the augmented production stays last
+      let id = self.augmented_production_id();
+      self.all_productions.insert(id.0, p.clone());
+      self.production_locations.insert(id.0, None);
+      self.production_labels.insert(id.0, None);
+      self.production_features.insert(id.0, Vec::new());
+      self.inline.insert(id.0, false);
+      self.ids.insert(self.augmented.clone(), ProductionId(id.0 + 1));
+      self.ids.insert(p.clone(), id);
+      self.productions.entry(*p.lhs()).or_default().push(p);
+      Ok(id)
+  }
+
+  // record where the productions (in declaration order) and symbols of the grammar were written
+  pub fn with_locations(mut self, productions: Vec<Option<SourceLocation>>, symbols: HashMap<Symbol,SourceLocation>) -> Grammar {
+      let count = self.all_productions.len();
+      self.production_locations = productions;
+      self.production_locations.resize(count, None);
+      self.symbol_locations = symbols;
+      self
+  }
+
+  pub fn production_location(&self, id: ProductionId) -> Option<&SourceLocation> {
+      self.production_locations.get(id.0)?.as_ref()
+  }
+
+  pub fn symbol_location(&self, s: &Symbol) -> Option<&SourceLocation> {
+      self.symbol_locations.get(s)
+  }
+
+  // name the productions (in declaration order), so that trees and reports can refer to the
+  // alternatives of a nonterminal by name rather than by position
+  pub fn with_labels(mut self, labels: Vec<Option<String>>) -> Grammar {
+      let count = self.all_productions.len();
+      self.production_labels = labels;
+      self.production_labels.resize(count, None);
+      self
+  }
+
+  pub fn production_label(&self, id: ProductionId) -> Option<&str> {
+      self.production_labels.get(id.0)?.as_deref()
+  }
+
+  // tag the productions (in declaration order) with the features they belong to, so that
+  // with_features can pick out the grammar of one version or dialect of a language. a production
+  // without features is in every version
+  pub fn with_production_features(mut self, features: Vec<Vec<String>>) -> Grammar {
+      let count = self.all_productions.len();
+      self.production_features = features;
+      self.production_features.resize(count, Vec::new());
+      self
+  }
+
+  pub fn production_features(&self, id: ProductionId) -> &[String] {
+      self.production_features.get(id.0).map_or(&[], |f| f.as_slice())
+  }
+
+  // every feature some production is tagged with, sorted
+  pub fn features(&self) -> BTreeSet<&str> {
+      self.production_features.iter().flatten().map(|f| f.as_str()).collect()
+  }
+
+  // the grammar for a set of features: the productions that have no features, and the ones all
+  // of whose features are in the set. like fragment, it's over a copy of the same symbol db
+  #[allow(clippy::result_large_err)]
+  pub fn with_features(&self, features: &[&str]) -> Result<Grammar,GrammarErrors> {
+      let augmented = self.augmented_production_id();
+      let ids: Vec<ProductionId> = self.productions_with_ids()
+          .filter(|(id, _)| *id != augmented && self.production_features(*id).iter().all(|f| features.contains(&f.as_str())))
+          .map(|(id, _)| id)
+          .collect();
+      self.subset(self.start_symbol, &ids)
+  }
+
+  // mark productions as inline: a node made by one of them doesn't appear in the tree, its children
+  // take its place among the children of its parent. this is for productions that are only there
+  // to help the grammar along (e.g. the rest of a list, or the tail of a left factored rule), so
+  // that the tree keeps the shape the author had in mind. the augmented production can't be inline
+  pub fn with_inline(mut self, productions: &[ProductionId]) -> Grammar {
+      let augmented = self.augmented_production_id();
+      for id in productions {
+          assert!(id.0 < self.inline.len(), "production {} isn't in the grammar", id.0);
+          if *id != augmented {
+              self.inline[id.0] = true;
+          }
+      }
+      self
+  }
+
+  pub fn is_inline(&self, id: ProductionId) -> bool {
+      self.inline[id.0]
+  }
+
+  // make a terminal the error symbol, which in a production matches a stretch of input that
+  // didn't parse, e.g. stmt -> error ; to get past a bad statement by skipping to the next ;.
+  // the lexer never produces it. see Parser::parse_recovering
+  pub fn with_error_symbol(mut self, symbol: Symbol) -> Grammar {
+      if !self.symbol_db.is_terminal(&symbol) {
+          panic!("[{}] isn't a terminal", label(&self.symbol_db, &symbol));
+      }
+      self.error_symbol = Some(symbol);
+      self
+  }
+
+  pub fn error_symbol(&self) -> Option<Symbol> {
+      self.error_symbol
+  }
+
+  // write the end of input into the augmented production, making it GOAL -> start $, for tools
+  // that expect the textbook form of the augmented grammar. the tables are the same either way
+  pub fn with_explicit_end_of_input(mut self, explicit: bool) -> Grammar {
+      let id = self.augmented_production_id();
+      let augmented = augmentation(&self.symbol_db, self.start_symbol, explicit);
+      self.ids.remove(&self.augmented);
+      self.ids.insert(augmented.clone(), id);
+      self.all_productions[id.0] = augmented.clone();
+      self.productions.insert(self.symbol_db.goal(), vec![augmented.clone()]);
+      self.augmented = augmented;
+      self
+  }
+
+  // the grammar written the way from_bnf reads it, one rule per nonterminal with its alternatives
+  // separated by |. the start symbol's rule comes first and the others follow in the order their
+  // first productions were declared. alternatives keep their declaration order, an empty one is
+  // written ε, and features and then labels follow their alternatives. duplicated productions are
+  // left out, and so are the augmented production, locations and inline marks, so the text is the
+  // same for grammars that only differ in those
+  pub fn to_bnf_string(&self) -> String {
+      let symbol_db = &self.symbol_db;
+      let text = |s: &Symbol| label(symbol_db, s);
+      let mut alternatives: Vec<(String,String)> = self.productions_with_ids()
+          .filter(|(id, p)| *p.lhs() != symbol_db.goal() && self.production_id(p) == Some(*id))
+          .map(|(id, p)| {
+              let mut words: Vec<String> = p.rhs().iter().map(text).collect();
+              if words.is_empty() {
+                  words.push(text(&symbol_db.epsilon()));
+              }
+              words.extend(self.production_features(id).iter().map(|f| format!("@{}", f)));
+              if let Some(l) = self.production_label(id) {
+                  words.push(format!("#{}", l));
+              }
+              (text(p.lhs()), words.join(" "))
+          })
+          .collect();
+      // stable, so everything else stays in declaration order
+      let start = text(&self.start_symbol);
+      alternatives.sort_by_key(|(lhs, _)| *lhs != start);
+      let mut result = String::new();
+      for rule in rules(alternatives.iter().map(|(lhs, rhs)| (lhs.as_str(), rhs.as_str()))) {
+          result.push_str(&rule);
+          result.push('\n');
+      }
+      result
+  }
+
+  // relabel a symbol of the grammar, or give it an alias (see SymbolDb::rename and add_alias).
+  // labels only matter to reports and lookups, so nothing else changes
+  pub fn rename_symbol(&mut self, s: Symbol, label: &str) {
+      self.symbol_db.rename(s, label);
+  }
+
+  pub fn add_symbol_alias(&mut self, s: Symbol, alias: &str) {
+      self.symbol_db.add_alias(s, alias);
+  }
+
+  // the part of the grammar that derives from another start symbol, e.g. just the expressions of
+  // a language. it's over a copy of the same symbol db, so the two grammars agree on every symbol
+  // and the tokens for one can be given to the other. the productions keep their locations,
+  // labels and inline marks
+  #[allow(clippy::result_large_err)]
+  pub fn fragment(&self, start_symbol: Symbol) -> Result<Grammar,GrammarErrors> {
+      let reachable = self.reachable_from(start_symbol);
+      let augmented = self.augmented_production_id();
+      let ids: Vec<ProductionId> = self.productions_with_ids()
+          .filter(|(id, p)| *id != augmented && reachable.contains(p.lhs()))
+          .map(|(id, _)| id)
+          .collect();
+      self.subset(start_symbol, &ids)
+  }
+
+  // a grammar over a copy of the symbol db with some of the productions, which keep their
+  // locations, labels, features and inline marks
+  #[allow(clippy::result_large_err)]
+  fn subset(&self, start_symbol: Symbol, ids: &[ProductionId]) -> Result<Grammar,GrammarErrors> {
+      let productions = ids.iter().map(|id| self.production(*id).clone()).collect();
+      let grammar = Grammar::try_new(self.symbol_db.clone(), start_symbol, productions)?;
+      let locations = ids.iter().map(|id| self.production_location(*id).cloned()).collect();
+      let labels = ids.iter().map(|id| self.production_label(*id).map(str::to_string)).collect();
+      let features = ids.iter().map(|id| self.production_features(*id).to_vec()).collect();
+      let inline: Vec<ProductionId> = ids.iter().enumerate()
+          .filter(|(_, id)| self.is_inline(**id))
+          .map(|(i, _)| ProductionId(i))
+          .collect();
+      let explicit_end = self.augmented.rhs().len() > 1;
+      let mut grammar = grammar.with_locations(locations, self.symbol_locations.clone())
+          .with_labels(labels)
+          .with_production_features(features)
+          .with_inline(&inline)
+          .with_explicit_end_of_input(explicit_end);
+      grammar.error_symbol = self.error_symbol;
+      Ok(grammar)
+  }
+
+  // a hash of everything the tables of the grammar depend on: its symbols (their numbers, labels
+  // and kinds), its productions in order, and its start symbol. it's the same on every platform
+  // and every run, so it can be stored with serialized tables to check that they were built from
+  // the grammar a program has (see TableView::embedded)
+  pub fn fingerprint(&self) -> u64 {
+      let mut hasher = FxHasher::default();
+      let symbol_db = &self.symbol_db;
+      hasher.write_usize(symbol_db.symbol_count());
+      for i in 0..symbol_db.symbol_count() {
+          let s = Symbol::from_index(i);
+          hasher.write_u8(symbol_db.is_terminal(&s) as u8);
+          let label = symbol_db.label(&s).map_or("", |l| l.as_str());
+          hasher.write_usize(label.len());
+          hasher.write(label.as_bytes());
+      }
+      for p in &self.all_productions {
+          hasher.write_usize(p.lhs().index());
+          hasher.write_usize(p.rhs().len());
+          for s in p.rhs() {
+              hasher.write_usize(s.index());
+          }
+      }
+      hasher.write_usize(self.start_symbol.index());
+      hasher.finish()
+  }
+
+  // the symbols that both grammars use in their productions but that they don't agree on: the
+  // label or whether it's a terminal differs. grammars made over copies of one symbol db (or
+  // from one another with fragment) agree unless a symbol has been renamed in one of them
+  pub fn symbol_mismatches(&self, other: &Grammar) -> Vec<Symbol> {
+      let used = |g: &Grammar| -> BTreeSet<Symbol> {
+          g.all_productions.iter().flat_map(|p| std::iter::once(*p.lhs()).chain(p.rhs().iter().cloned())).collect()
+      };
+      let theirs = used(other);
+      used(self).into_iter()
+          .filter(|s| theirs.contains(s))
+          .filter(|s| self.symbol_db.label(s) != other.symbol_db.label(s) || self.symbol_db.is_terminal(s) != other.symbol_db.is_terminal(s))
+          .collect()
+  }
+
+  // the production of the nonterminal with the label
+  pub fn labeled_production(&self, lhs: &Symbol, label: &str) -> Option<ProductionId> {
+      self.productions_with_ids()
+          .find(|(id, p)| p.lhs() == lhs && self.production_label(*id) == Some(label))
+          .map(|(id, _)| id)
+  }
+
+  // the symbols that can appear in something derived from the augmented production
+  fn reachable(&self) -> HashSet<Symbol> {
+      self.reachable_from(self.symbol_db.goal())
+  }
+
+  fn reachable_from(&self, s: Symbol) -> HashSet<Symbol> {
+      let mut result = HashSet::new();
+      let mut pending = vec![s];
+      while let Some(s) = pending.pop() {
+          if result.insert(s) {
+              for p in self.productions.get(&s).into_iter().flatten() {
+                  pending.extend(p.rhs().iter().cloned());
+              }
+          }
+      }
+      result
   }
 
   pub fn start_symbol(&self) -> &Symbol { &self.start_symbol }
   pub fn productions(&self, lhs: &Symbol) -> Option<&Vec<Production>> { self.productions.get(lhs) }
-  pub fn terminals(&self) -> &HashSet<Symbol> { &self.symbol_db.terminals() }
-  pub fn nonterminals(&self) -> &HashSet<Symbol> { &self.symbol_db.non_terminals() }
+  pub fn terminals(&self) -> &TerminalSet { self.symbol_db.terminals() }
+  pub fn nonterminals(&self) -> &NonTerminalSet { self.symbol_db.non_terminals() }
+  pub fn declared_terminals(&self) -> TerminalSet { self.symbol_db.declared_terminals() }
+  pub fn declared_nonterminals(&self) -> NonTerminalSet { self.symbol_db.declared_nonterminals() }
   pub fn symbol_db(&self) -> &SymbolDb { &self.symbol_db }
+  // GOAL -> start (or GOAL -> start $), which the grammar adds after the productions it was
+  // given. the tables are built from its item with the end of input as the lookahead, and
+  // reaching the end of it is accepting
+  pub fn augmented_production(&self) -> &Production { &self.augmented }
+  pub fn augmented_production_id(&self) -> ProductionId { ProductionId(self.all_productions.len() - 1) }
+  pub fn all_productions(&self) -> &Vec<Production> { &self.all_productions }
+  pub fn production(&self, id: ProductionId) -> &Production { &self.all_productions[id.0] }
+  pub fn production_id(&self, p: &Production) -> Option<ProductionId> { self.ids.get(p).copied() }
+  pub fn display_production(&self, id: ProductionId) -> ProductionDisplay<'_> { self.production(id).display(&self.symbol_db).with_label(self.production_label(id)) }
+
+  // the id of every production alongside it, in declaration order
+  pub fn productions_with_ids(&self) -> impl Iterator<Item=(ProductionId,&Production)> {
+      self.all_productions.iter().enumerate().map(|(i, p)| (ProductionId(i), p))
+  }
+  pub fn dependency_graph(&self) -> DependencyGraph { DependencyGraph::new(self) }
+
+  // report things that are legal but probably mistakes. the warnings are sorted so that the
+  // result doesn't depend on hash map iteration order
+  pub fn warnings(&self) -> Vec<GrammarWarning> {
+      let mut result = Vec::new();
+
+      // every declared terminal should appear on the right hand side of some production
+      let mut used: HashSet<Symbol> = HashSet::new();
+      for p in &self.all_productions {
+          used.extend(p.rhs().iter().cloned());
+      }
+      for t in &self.declared_terminals() {
+          if !used.contains(&t) {
+              result.push(GrammarWarning::UnusedTerminal(t));
+          }
+      }
+
+      // a production is a duplicate if an identical one was already seen
+      for ps in self.productions.values() {
+          let mut seen: BTreeSet<&Production> = BTreeSet::new();
+          for p in ps {
+              if !seen.insert(p) {
+                  result.push(GrammarWarning::DuplicateProduction(p.clone()));
+              }
+          }
+      }
+
+      result.sort();
+      result
+  }
 }
 
+// the rule the grammar adds to the ones it's given: GOAL -> start_symbol. by default the end of
+// input isn't part of it, it's the lookahead the table construction starts the goal item with, but
+// it can be written in at the end
+fn augmentation(symbol_db: &SymbolDb, start_symbol: Symbol, explicit_end_of_input: bool) -> Production {
+    let mut rhs = vec![start_symbol];
+    if explicit_end_of_input {
+        rhs.push(symbol_db.eoi());
+    }
+    Production::new(symbol_db.goal(), rhs)
+}
+
+fn validate(symbol_db: &SymbolDb, start_symbol: Symbol, productions: &[Production]) -> Vec<GrammarError> {
+    let mut errors = BTreeSet::new();
+    if symbol_db.is_terminal(&start_symbol) {
+        errors.insert(GrammarError::TerminalStartSymbol(start_symbol));
+    }
+    for p in productions {
+        if symbol_db.is_terminal(p.lhs()) {
+            errors.insert(GrammarError::TerminalLhs(p.clone()));
+        }
+        if *p.lhs() == symbol_db.goal() {
+            errors.insert(GrammarError::GoalLhs(p.clone()));
+        }
+        let rhs = p.rhs();
+        if rhs.len() > 1 && rhs.contains(&symbol_db.epsilon()) {
+            errors.insert(GrammarError::MisplacedEpsilon(p.clone()));
+        }
+        if rhs.iter().rev().skip(1).any(|s| *s == symbol_db.eoi()) {
+            errors.insert(GrammarError::MisplacedEndOfInput(p.clone()));
+        }
+    }
+    errors.into_iter().collect()
+}
+
+// the grammar as text that from_bnf reads
 impl fmt::Display for Grammar {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let symbol_db = &self.symbol_db;
-        write!(f, "grammar:\n")?;
-        let s = symbol_db.label(&self.start_symbol()).ok_or(Error)?;
-        write!(f, "  start symbol = {}\n", s)?;
-        write!(f, "  productions =\n")?;
-        for (_,v) in &self.productions {
-            for p in v {
-                write!(f, "    {}\n", p.to_string(symbol_db))?;
-            }
-        }
-        Ok(())
+        write!(f, "{}", self.to_bnf_string())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* grammar:
+     *   S -> a | a | b
+     */
+    #[test]
+    fn warnings_01() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let a = symbol_db.new_terminal("a");
+        let b = symbol_db.new_terminal("b");
+        let c = symbol_db.new_terminal("c");
+        let p1 = Production::new(s, vec![a]);
+        let p2 = Production::new(s, vec![a]);
+        let p3 = Production::new(s, vec![b]);
+        let g = Grammar::new(symbol_db, s, vec![p1.clone(), p2, p3]);
+        let warnings = g.warnings();
+        assert_eq!(warnings, vec![
+            GrammarWarning::UnusedTerminal(c),
+            GrammarWarning::DuplicateProduction(p1),
+        ]);
+        assert_eq!(warnings[0].to_string(g.symbol_db()), "terminal c is never used");
+        assert_eq!(warnings[1].to_string(g.symbol_db()), "production S -> a is duplicated");
+    }
+
+    /* grammar:
+     *   S -> a S | ε
+     */
+    #[test]
+    fn warnings_02() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let a = symbol_db.new_terminal("a");
+        let p1 = Production::new(s, vec![a, s]);
+        let p2 = Production::new(s, vec![]);
+        let g = Grammar::new(symbol_db, s, vec![p1, p2]);
+        assert!(g.warnings().is_empty());
+        let augmented = g.augmented_production();
+        assert_eq!(augmented.display(g.symbol_db()).to_string(), "GOAL -> S");
+        assert_eq!(g.productions(&g.symbol_db().goal()), Some(&vec![augmented.clone()]));
+        assert_eq!(g.production(g.augmented_production_id()), augmented);
+    }
+
+    /* grammar:
+     *   S -> a ε | T $ b
+     *   a -> S
+     *   GOAL -> S
+     */
+    #[test]
+    fn try_new_01() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let t = symbol_db.new_nonterminal("T");
+        let a = symbol_db.new_terminal("a");
+        let b = symbol_db.new_terminal("b");
+        let epsilon = symbol_db.epsilon();
+        let eoi = symbol_db.eoi();
+        let goal = symbol_db.goal();
+        let p1 = Production::new(s, vec![a, epsilon]);
+        let p2 = Production::new(s, vec![t, eoi, b]);
+        let p3 = Production::new(a, vec![s]);
+        let p4 = Production::new(goal, vec![s]);
+        let p5 = Production::new(t, vec![b, eoi]);
+        let errors = Grammar::try_new(symbol_db, a, vec![p1.clone(), p2.clone(), p3.clone(), p4.clone(), p5]).err().unwrap();
+        assert_eq!(errors.errors(), &vec![
+            GrammarError::TerminalStartSymbol(a),
+            GrammarError::TerminalLhs(p3),
+            GrammarError::GoalLhs(p4),
+            GrammarError::MisplacedEpsilon(p1),
+            GrammarError::MisplacedEndOfInput(p2),
+        ]);
+        assert_eq!(errors.errors()[1].to_string(errors.symbol_db()), "production a -> S has a terminal on its left hand side");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid grammar: start symbol a is a terminal")]
+    fn try_new_02() {
+        let mut symbol_db = SymbolDb::new();
+        let a = symbol_db.new_terminal("a");
+        Grammar::new(symbol_db, a, vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "production 99 isn't in the grammar")]
+    fn with_inline_01() {
+        let g = Grammar::from_bnf("S -> a S | ε", "g").unwrap();
+        g.with_inline(&[ProductionId(99)]);
+    }
+
+    /* grammar:
+     *   S -> a S | ε
+     */
+    #[test]
+    fn epsilon_01() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let a = symbol_db.new_terminal("a");
+        let epsilon = symbol_db.epsilon();
+        let p1 = Production::new(s, vec![a, s]);
+        let p2 = Production::new(s, vec![epsilon]);
+        let g = Grammar::new(symbol_db, s, vec![p1, p2]);
+        let ps = g.productions(&s).unwrap();
+        assert!(ps[1].rhs().is_empty());
+        assert_eq!(ps[1].display(g.symbol_db()).to_string(), "S -> ε");
+    }
+
+    /* grammar:
+     *   S -> a S | b | a S
+     */
+    #[test]
+    fn all_productions_01() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let a = symbol_db.new_terminal("a");
+        let b = symbol_db.new_terminal("b");
+        let p1 = Production::new(s, vec![a, s]);
+        let p2 = Production::new(s, vec![b]);
+        let g = Grammar::new(symbol_db, s, vec![p1.clone(), p2.clone(), p1.clone()]);
+        assert_eq!(g.all_productions(), &vec![p1.clone(), p2.clone(), p1.clone(), g.augmented_production().clone()]);
+        assert_eq!(g.production_id(&p1), Some(ProductionId(0)));
+        assert_eq!(g.production_id(&p2), Some(ProductionId(1)));
+        assert_eq!(g.production_id(g.augmented_production()), Some(ProductionId(3)));
+        assert_eq!(g.production_id(&Production::new(s, vec![])), None);
+        assert_eq!(g.production(ProductionId(1)), &p2);
+        let ids: Vec<usize> = g.productions_with_ids().map(|(id, _)| id.index()).collect();
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn display_01() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let a = symbol_db.new_terminal("a");
+        let g = Grammar::new(symbol_db, s, vec![Production::new(s, vec![a, s]), Production::new(s, vec![])]);
+        assert_eq!(format!("{}", g.display_production(ProductionId(0))), "S -> a  S");
+        assert_eq!(format!("[{}]", g.display_production(ProductionId(1))), "[S -> ε]");
+        assert_eq!(format!("{}", g.augmented_production().display(g.symbol_db())), "GOAL -> S");
+    }
+
+    /* grammar:
+     *   stmt -> id = expr ; | stmt stmt
+     *   expr -> expr + atom | atom
+     *   atom -> id | ( expr )
+     */
+    #[test]
+    fn fragment_01() {
+        let text = "stmt -> id = expr ; | stmt stmt\nexpr -> expr + atom #Add | atom\natom -> id | ( expr )\n";
+        let g = Grammar::from_bnf(text, "g").unwrap().with_explicit_end_of_input(true);
+        let db = g.symbol_db();
+        let expr = db.symbol("expr").unwrap();
+        let f = g.fragment(expr).unwrap();
+        assert_eq!(f.to_bnf_string(), "expr -> expr + atom #Add | atom\natom -> id | ( expr )\n");
+        assert_eq!(f.production_location(ProductionId(0)).unwrap().to_string(), "g:2:9");
+        assert_eq!(f.augmented_production().display(f.symbol_db()).to_string(), "GOAL -> expr  $");
+        assert!(g.symbol_mismatches(&f).is_empty());
+
+        // tokens made with one grammar's symbols parse with the other
+        let tokens: Vec<Symbol> = "id + ( id ) $".split(' ').map(|l| db.symbol(l).unwrap()).collect();
+        assert!(crate::parser::Parser::new(f).parse(tokens, |s| *s).is_ok());
+
+        let mut renamed = g.fragment(expr).unwrap();
+        renamed.rename_symbol(db.symbol("+").unwrap(), "plus");
+        assert_eq!(g.symbol_mismatches(&renamed), vec![db.symbol("+").unwrap()]);
+        assert!(g.fragment(db.symbol("id").unwrap()).is_err());
+    }
+
+    /* grammar:
+     *   expr -> id | lambda id . expr | match expr with id | expr ( expr )
+     */
+    #[test]
+    fn with_features_01() {
+        let text = "\
+expr -> id | lambda id . expr @lambdas #Lambda
+     | match expr with id @pattern-match @lambdas
+     | expr ( expr )
+";
+        let g = Grammar::from_bnf(text, "g").unwrap();
+        assert_eq!(g.features().into_iter().collect::<Vec<_>>(), vec!["lambdas", "pattern-match"]);
+        assert_eq!(g.production_features(ProductionId(2)), ["pattern-match", "lambdas"]);
+        assert!(g.production_features(ProductionId(0)).is_empty());
+        assert_eq!(Grammar::from_bnf(&g.to_bnf_string(), "g").unwrap().to_bnf_string(), g.to_bnf_string());
+
+        let base = g.with_features(&[]).unwrap();
+        assert_eq!(base.to_bnf_string(), "expr -> id | expr ( expr )\n");
+        let lambdas = g.with_features(&["lambdas"]).unwrap();
+        assert_eq!(lambdas.to_bnf_string(), "expr -> id | lambda id . expr @lambdas #Lambda | expr ( expr )\n");
+        assert_eq!(lambdas.production_location(ProductionId(1)).unwrap().to_string(), "g:1:14");
+        assert_eq!(g.with_features(&["lambdas", "pattern-match"]).unwrap().all_productions().len(), 5);
+
+        let error = Grammar::from_bnf("s -> a @f b", "bad.bnf").err().unwrap();
+        assert_eq!(error.to_string(), "bad.bnf:1:11: expected a feature, a label, | or the end of the line after a feature");
+        // a lone @ is a symbol
+        assert!(Grammar::from_bnf("s -> a @", "g").unwrap().symbol_db().symbol("@").is_some());
+    }
+
+    #[test]
+    fn to_bnf_string_01() {
+        let text = "\
+term -> x
+expr -> expr + term #Add
+     | term | ε
+expr -> term
+# an unlabeled duplicate of expr -> expr + term
+expr -> expr + term
+";
+        let g = Grammar::from_bnf(text, "g").unwrap();
+        let expected = "term -> x\nexpr -> expr + term #Add | term | ε\n";
+        assert_eq!(g.to_bnf_string(), expected);
+        assert_eq!(g.to_string(), expected);
+        // the text gives back the same grammar
+        let reloaded = Grammar::from_bnf(&g.to_bnf_string(), "g").unwrap();
+        assert_eq!(reloaded.to_bnf_string(), expected);
+        assert_eq!(reloaded.all_productions().len(), 5);
+
+        // the start symbol's rule comes first wherever its productions were declared
+        let mut symbol_db = SymbolDb::new();
+        let a = symbol_db.new_nonterminal("a");
+        let b = symbol_db.new_nonterminal("b");
+        let x = symbol_db.new_terminal("x");
+        let y = symbol_db.new_terminal("y");
+        let mut g = Grammar::new(symbol_db, a, vec![Production::new(b, vec![y]), Production::new(a, vec![b, x])]);
+        assert_eq!(g.to_bnf_string(), "a -> b x\nb -> y\n");
+
+        g.rename_symbol(b, "item");
+        g.add_symbol_alias(x, "X");
+        assert_eq!(g.to_bnf_string(), "a -> item x\nitem -> y\n");
+        assert_eq!(g.symbol_db().symbol("X"), Some(x));
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+
+use super::canonical_collection::CanonicalCollection;
+use super::grammar::{Grammar,GrammarErrors};
+use super::parser::Parser;
+use super::production::Production;
+
+// grammars for the dialects of a language: a base grammar that they all share, and for each
+// dialect the productions it adds. compiling the family builds the canonical collection of the
+// base once, and each dialect starts from a copy of it, so only the states its own productions
+// change are worked out again
+pub struct GrammarFamily {
+    base: Grammar,
+    dialects: Vec<(String,Vec<Production>)>,
+}
+
+impl GrammarFamily {
+    pub fn new(base: Grammar) -> GrammarFamily {
+        GrammarFamily { base, dialects: Vec::new() }
+    }
+
+    // the productions are over the base grammar's symbols
+    pub fn add_dialect(&mut self, name: &str, productions: Vec<Production>) {
+        self.dialects.push((name.to_string(), productions));
+    }
+
+    pub fn compile(self) -> Result<ParserFamily,DialectError> {
+        let collection = CanonicalCollection::new(&self.base);
+        let mut dialects = Vec::new();
+        for (name, productions) in self.dialects {
+            let mut parser = Parser::from_collection(self.base.clone(), collection.clone());
+            for p in productions {
+                if let Err(errors) = parser.extend(p) {
+                    return Err(DialectError { dialect: name, errors: Box::new(errors) });
+                }
+            }
+            dialects.push((name, parser));
+        }
+        let base = Parser::from_collection(self.base, collection);
+        Ok(ParserFamily { base, dialects })
+    }
+}
+
+// the parsers of a compiled family, in the order the dialects were added
+pub struct ParserFamily {
+    base: Parser,
+    dialects: Vec<(String,Parser)>,
+}
+
+impl ParserFamily {
+    pub fn base(&self) -> &Parser {
+        &self.base
+    }
+
+    pub fn dialect(&self, name: &str) -> Option<&Parser> {
+        self.dialects.iter().find(|(n, _)| n == name).map(|(_, p)| p)
+    }
+
+    pub fn dialects(&self) -> impl Iterator<Item=(&str,&Parser)> {
+        self.dialects.iter().map(|(n, p)| (n.as_str(), p))
+    }
+}
+
+// a dialect whose productions don't fit the base grammar
+#[derive(Debug)]
+pub struct DialectError {
+    pub dialect: String,
+    pub errors: Box<GrammarErrors>,
+}
+
+impl fmt::Display for DialectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "dialect {}: {}", self.dialect, self.errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol::Symbol;
+
+    /* grammar:
+     *   e -> e + t | t
+     *   t -> x | ( e ) | - | call
+     * dialects:
+     *   neg: t -> - t
+     *   call: t -> x ( e )
+     */
+    #[test]
+    fn grammar_family_01() {
+        // the dialects' tokens have to be symbols of the base grammar, so it has them on their own
+        let g = Grammar::from_bnf("e -> e + t | t\nt -> x | ( e ) | - | call", "g").unwrap();
+        let db = g.symbol_db().clone();
+        let s = |l: &str| db.symbol(l).unwrap();
+        let mut family = GrammarFamily::new(g);
+        family.add_dialect("neg", vec![Production::new(s("t"), vec![s("-"), s("t")])]);
+        family.add_dialect("call", vec![Production::new(s("t"), vec![s("call"), s("("), s("e"), s(")")])]);
+        let parsers = family.compile().unwrap();
+        assert_eq!(parsers.dialects().map(|(n, _)| n).collect::<Vec<_>>(), vec!["neg", "call"]);
+
+        let tokens = |text: &str| -> Vec<Symbol> { text.split(' ').map(s).collect() };
+        let neg = parsers.dialect("neg").unwrap();
+        assert!(neg.parse(tokens("- - x + x $"), |s| *s).is_ok());
+        assert!(parsers.base().parse(tokens("- - x + x $"), |s| *s).is_err());
+        let call = parsers.dialect("call").unwrap();
+        assert!(call.parse(tokens("call ( x ) + x $"), |s| *s).is_ok());
+        assert!(neg.parse(tokens("call ( x ) $"), |s| *s).is_err());
+        assert!(parsers.dialect("other").is_none());
+
+        // the tables are the ones a parser built from scratch would have
+        let full = Parser::new(neg.grammar().clone());
+        assert_eq!(neg.parse_tables().state_count(), full.parse_tables().state_count());
+        assert_eq!(neg.parse_tables().verify(neg.grammar()), Ok(()));
+
+        // a dialect production has to fit the base grammar
+        let mut family = GrammarFamily::new(parsers.base().grammar().clone());
+        family.add_dialect("bad", vec![Production::new(s("x"), vec![s("t")])]);
+        let error = family.compile().err().unwrap();
+        assert_eq!(error.dialect, "bad");
+        assert!(error.to_string().starts_with("dialect bad: invalid grammar: "));
+    }
+}
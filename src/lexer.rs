@@ -0,0 +1,892 @@
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+use super::dfa::{Dfa,DfaState};
+use super::diagnostics::{Diagnostic,Diagnostics,Severity};
+use super::regex::{Regex,RegexError};
+use super::source_map::SourceMap;
+use super::spanned::Spanned;
+use super::symbol::Symbol;
+use super::table_view::{LEXER,TableFormatError};
+
+// a token produced by a Lexer: its terminal, the bytes of the input it covers, and the value the
+// rule's payload hook made from its text, if the rule has one. leading and trailing are the spans
+// of the skipped text (whitespace, comments) attached to the token before and after it, which
+// are empty unless the lexer is given a TriviaPolicy
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct Lexeme<V> {
+    pub symbol: Symbol,
+    pub span: Range<usize>,
+    pub value: Option<V>,
+    pub leading: Vec<Range<usize>>,
+    pub trailing: Vec<Range<usize>>,
+}
+
+impl<V> Lexeme<V> {
+    // the text of the token, given the input it was lexed from
+    pub fn text<'t>(&self, input: &'t str) -> &'t str {
+        &input[self.span.clone()]
+    }
+}
+
+// which token the text matched by skip rules (trivia) is attached to. formatters need it to put
+// comments back where they were, and documentation tools to find the comments before a definition.
+// whatever the policy, the trivia at the end of the input trails the last token rather than
+// leading the end of input token, which isn't in the parse tree, so that a tree of the tokens
+// holds all of the input (see ParseTree::to_source)
+#[derive(Clone,Copy,Debug,Default,Eq,PartialEq)]
+pub enum TriviaPolicy {
+    // trivia isn't kept
+    #[default]
+    Discard,
+    // trivia is leading trivia of the token after it
+    Leading,
+    // trivia is trailing trivia of the token before it, except before the first token, where
+    // there's no token before it
+    Trailing,
+    // trivia up to and including the first piece with a line break in it trails the token before
+    // it, and the rest leads the token after it. so a comment at the end of a line belongs to the
+    // token on that line, and comments on lines of their own belong to the token below them
+    SameLine,
+}
+
+impl<V> Spanned for Lexeme<V> {
+    fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum LexError {
+    // no rule matches the text at the given byte offset
+    NoMatch { position: usize },
+    // a rule matched, but its payload hook rejected the text, e.g. an integer that overflows
+    Payload { span: Range<usize>, message: String },
+    // a token that leaves a mode when no mode was entered, e.g. a } with no { before it, or (at
+    // the end of the input) the token that entered a mode that was never left, e.g. the " of an
+    // unterminated string
+    Unbalanced { span: Range<usize> },
+}
+
+impl LexError {
+    pub fn position(&self) -> usize {
+        match self {
+            LexError::NoMatch { position } => *position,
+            LexError::Payload { span, .. } |
+            LexError::Unbalanced { span } => span.start,
+        }
+    }
+
+    // the error with the line it's on, as SourceMap::render shows it
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        match self {
+            LexError::NoMatch { position } => {
+                let c = source_map.text()[*position..].chars().next();
+                let end = position + c.map_or(0, |c| c.len_utf8());
+                source_map.render(*position..end, "unexpected character")
+            },
+            LexError::Payload { span, message } => source_map.render(span.clone(), message),
+            LexError::Unbalanced { span } => source_map.render(span.clone(), "unbalanced"),
+        }
+    }
+
+    // the error as a Diagnostic, given the text that was lexed
+    pub fn to_diagnostic(&self, text: &str) -> Diagnostic {
+        match self {
+            LexError::NoMatch { position } => {
+                let end = position + text[*position..].chars().next().map_or(0, |c| c.len_utf8());
+                Diagnostic::at(Severity::Error, "unexpected-character", "unexpected character".to_string(), *position..end)
+            },
+            LexError::Payload { span, message } => Diagnostic::at(Severity::Error, "invalid-token", message.clone(), span.clone()),
+            LexError::Unbalanced { span } => {
+                let message = format!("unbalanced {}", &text[span.clone()]);
+                Diagnostic::at(Severity::Error, "unbalanced-token", message, span.clone())
+            },
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::NoMatch { position } => write!(f, "unexpected character at offset {}", position),
+            LexError::Payload { span, message } => write!(f, "{} at offset {}", message, span.start),
+            LexError::Unbalanced { span } => write!(f, "unbalanced {} at offset {}", if span.is_empty() { "end" } else { "token" }, span.start),
+        }
+    }
+}
+
+// the reserved words of a language and their terminals. rather than a rule per keyword, whose
+// order against the identifier rule would matter, identifiers are matched by one rule and then
+// looked up here (see Lexer::keywords)
+#[derive(Clone,Debug,Default)]
+pub struct KeywordTable {
+    keywords: HashMap<String,Symbol>,
+    case_insensitive: bool,
+}
+
+impl KeywordTable {
+    pub fn new() -> KeywordTable {
+        KeywordTable { keywords: HashMap::new(), case_insensitive: false }
+    }
+
+    // a table whose keywords match in any case, as in sql or pascal, e.g. select, SELECT and
+    // Select are all the same keyword
+    pub fn case_insensitive() -> KeywordTable {
+        KeywordTable { keywords: HashMap::new(), case_insensitive: true }
+    }
+
+    pub fn declare(&mut self, word: &str, symbol: Symbol) {
+        let word = self.key(word);
+        self.keywords.insert(word, symbol);
+    }
+
+    pub fn get(&self, text: &str) -> Option<Symbol> {
+        self.keywords.get(&self.key(text)).copied()
+    }
+
+    fn key(&self, word: &str) -> String {
+        if self.case_insensitive { word.to_lowercase() } else { word.to_string() }
+    }
+}
+
+// turns the matched text of a rule into the value carried by its tokens
+type PayloadHook<'a,V> = Rc<dyn Fn(&str) -> Result<V,String> + 'a>;
+
+// a set of rules that are used together. the lexer starts in Mode::DEFAULT, and rules can enter
+// other modes and leave them again (see Lexer::then_push), for text whose tokens depend on what
+// surrounds it, like the contents of strings with embedded expressions
+#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq)]
+pub struct Mode(usize);
+
+impl Mode {
+    pub const DEFAULT: Mode = Mode(0);
+}
+
+// what matching a rule does to the stack of modes
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+enum Switch {
+    Stay,
+    Push(usize),
+    Pop,
+}
+
+struct Rule<'a,V> {
+    pattern: String,
+    regex: Regex,
+    // None for text that's skipped, like whitespace and comments
+    symbol: Option<Symbol>,
+    payload: Option<PayloadHook<'a,V>>,
+    mode: usize,
+    switch: Switch,
+}
+
+// a lexer made of rules, each a regular expression (see regex.rs for the syntax) and the terminal
+// of the tokens it matches. at each position the rule that matches the longest text wins, and of
+// rules that match text of the same length, the one added first wins. so a rule for the keyword if
+// has to come before the rule for identifiers (or see Lexer::keywords), and iffy is an identifier
+// either way. check reports the pairs of rules that can match the same text, which is where the
+// order matters. rules added with token_with also have a payload hook, which turns the matched
+// text into a value of type V that's kept in the token, so that semantic actions get e.g. numbers
+// rather than digits. V is left to the caller, typically an enum of the kinds of values the
+// language has.
+//
+// the rules can be split into modes, with only the rules of the current mode in play. a rule can
+// enter a mode, pushing it on a stack, or leave the current mode, popping it, which is enough for
+// nested structure that regular expressions can't follow. e.g. for strings with interpolation
+// like "a${b + "c"}d", where an expression can hold strings that hold expressions:
+//
+//   default mode:  " enters the string mode, { enters the default mode again, } leaves
+//   string mode:   [^"$]+ is text, ${ enters the default mode, " leaves
+pub struct Lexer<'a,V> {
+    rules: Vec<Rule<'a,V>>,
+    keywords: Vec<(Symbol,KeywordTable)>,
+    eoi: Symbol,
+    mode_count: usize,
+    // the mode that rules are added to
+    adding_to: usize,
+    // the rules of each mode compiled to a single dfa, built by the first call to lex after the
+    // rules change
+    dfas: OnceCell<Vec<Dfa>>,
+    trivia: TriviaPolicy,
+}
+
+impl<'a,V> Lexer<'a,V> {
+    // eoi is the end of input symbol of the grammar, for the token lex puts at the end
+    pub fn new(eoi: Symbol) -> Lexer<'a,V> {
+        Lexer { rules: Vec::new(), keywords: Vec::new(), eoi, mode_count: 1, adding_to: 0, dfas: OnceCell::new(), trivia: TriviaPolicy::Discard }
+    }
+
+    // keep the text matched by skip rules in the tokens, attached as the policy says
+    pub fn trivia(&mut self, policy: TriviaPolicy) {
+        self.trivia = policy;
+    }
+
+    pub fn new_mode(&mut self) -> Mode {
+        self.mode_count += 1;
+        self.dfas = OnceCell::new();
+        Mode(self.mode_count - 1)
+    }
+
+    // add the rules from here on to the mode
+    pub fn rules_for(&mut self, mode: Mode) {
+        assert!(mode.0 < self.mode_count, "not a mode of this lexer");
+        self.adding_to = mode.0;
+    }
+
+    // make the rule added last enter the mode when it matches. the lexer stays in the mode until
+    // a rule of the mode added with then_pop matches
+    pub fn then_push(&mut self, mode: Mode) {
+        assert!(mode.0 < self.mode_count, "not a mode of this lexer");
+        self.rules.last_mut().expect("no rule to push a mode").switch = Switch::Push(mode.0);
+    }
+
+    // make the rule added last leave the current mode when it matches, going back to the mode the
+    // lexer was in before it was entered
+    pub fn then_pop(&mut self) {
+        self.rules.last_mut().expect("no rule to pop a mode").switch = Switch::Pop;
+    }
+
+    pub fn token(&mut self, pattern: &str, symbol: Symbol) -> Result<(),RegexError> {
+        self.add(pattern, Some(symbol), None)
+    }
+
+    // a rule whose tokens carry the value the payload hook makes from the matched text. the hook
+    // returns an error message for text it can't convert, which lex reports as a LexError
+    pub fn token_with<F>(&mut self, pattern: &str, symbol: Symbol, payload: F) -> Result<(),RegexError>
+        where F: Fn(&str) -> Result<V,String> + 'a {
+        self.add(pattern, Some(symbol), Some(Rc::new(payload)))
+    }
+
+    // a rule for text that isn't passed on to the parser
+    pub fn skip(&mut self, pattern: &str) -> Result<(),RegexError> {
+        self.add(pattern, None, None)
+    }
+
+    // give every rule for the symbol the payload hook, replacing any it had. rules loaded with
+    // from_bytes have no hooks until they're given them this way
+    pub fn payload<F>(&mut self, symbol: Symbol, payload: F)
+        where F: Fn(&str) -> Result<V,String> + 'a {
+        let payload: PayloadHook<'a,V> = Rc::new(payload);
+        for rule in self.rules.iter_mut().filter(|r| r.symbol == Some(symbol)) {
+            rule.payload = Some(payload.clone());
+        }
+    }
+
+    // check the text of each token of the identifier terminal against the table, and give the
+    // tokens that are keywords the keyword's terminal instead. the token keeps its payload
+    pub fn keywords(&mut self, identifier: Symbol, table: KeywordTable) {
+        self.keywords.retain(|(s, _)| *s != identifier);
+        self.keywords.push((identifier, table));
+    }
+
+    fn add(&mut self, pattern: &str, symbol: Option<Symbol>, payload: Option<PayloadHook<'a,V>>) -> Result<(),RegexError> {
+        let regex = Regex::new(pattern)?;
+        if regex.matches_empty() {
+            return Err(RegexError { pattern: pattern.to_string(), position: 0, message: "the pattern matches the empty string".to_string() });
+        }
+        self.rules.push(Rule { pattern: pattern.to_string(), regex, symbol, payload, mode: self.adding_to, switch: Switch::Stay });
+        self.dfas = OnceCell::new();
+        Ok(())
+    }
+
+    // a note for each pair of rules of a mode that can match the same text, saying which rule
+    // wins. that's often intended, as with keywords and identifiers, but when the rule that loses
+    // is for something else entirely, the order is probably wrong
+    pub fn check(&self, diagnostics: &mut Diagnostics) {
+        for (i, first) in self.rules.iter().enumerate() {
+            for second in self.rules[i + 1..].iter().filter(|r| r.mode == first.mode) {
+                if let Some(text) = first.regex.common_match(&second.regex) {
+                    let message = format!("the rules for {} and {} both match {:?}, and the first one wins as it was added first",
+                        first.pattern, second.pattern, text);
+                    let symbols = first.symbol.iter().chain(second.symbol.iter()).copied().collect();
+                    diagnostics.note("lexer-overlap", message, symbols, None);
+                }
+            }
+        }
+    }
+
+    // the dfa of each mode, whose states accept the rules by their index in self.rules
+    fn dfas(&self) -> &Vec<Dfa> {
+        self.dfas.get_or_init(|| (0..self.mode_count).map(|mode| {
+            let rules: Vec<usize> = (0..self.rules.len()).filter(|i| self.rules[*i].mode == mode).collect();
+            let mut dfa = Dfa::new(&rules.iter().map(|i| &self.rules[*i].regex).collect::<Vec<&Regex>>());
+            for state in &mut dfa.states {
+                state.accept = state.accept.map(|rule| rules[rule]);
+            }
+            dfa
+        }).collect())
+    }
+
+    // serialize the rules and their compiled dfas, so that a program can load the lexer with
+    // from_bytes rather than compiling the rules every time it starts. payload hooks and keyword
+    // tables are code, so they aren't included, nor is the trivia policy; give them to the loaded
+    // lexer again. the layout is
+    // the magic number and version (see FORMAT_VERSION), then little endian u32s:
+    //
+    //   eoi, rule count, mode count, state count, transition count, pattern bytes
+    //   for each mode: the end of its states (each mode's dfa starts at its first state)
+    //   for each rule: its terminal (or u32::MAX to skip), the end of its pattern in the pattern
+    //     bytes, its mode, and what it does to the modes (0 nothing, 1 pop, 2 + m push mode m)
+    //   for each state: the rule it accepts (or u32::MAX), the end of its transitions
+    //   for each transition: first character, last character, target state within the mode
+    //
+    // followed by the patterns in utf-8
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let dfas = self.dfas();
+        let none = u32::MAX;
+        let patterns: String = self.rules.iter().map(|r| r.pattern.as_str()).collect();
+        let states: Vec<&DfaState> = dfas.iter().flat_map(|dfa| dfa.states.iter()).collect();
+        let transition_count: usize = states.iter().map(|s| s.transitions.len()).sum();
+        let mut words = vec![self.eoi.index() as u32, self.rules.len() as u32, dfas.len() as u32, states.len() as u32, transition_count as u32, patterns.len() as u32];
+        let mut end = 0;
+        for dfa in dfas {
+            end += dfa.states.len();
+            words.push(end as u32);
+        }
+        let mut end = 0;
+        for rule in &self.rules {
+            end += rule.pattern.len();
+            words.push(rule.symbol.map_or(none, |s| s.index() as u32));
+            words.push(end as u32);
+            words.push(rule.mode as u32);
+            words.push(match rule.switch {
+                Switch::Stay => 0,
+                Switch::Pop => 1,
+                Switch::Push(mode) => 2 + mode as u32,
+            });
+        }
+        let mut end = 0;
+        for state in &states {
+            end += state.transitions.len();
+            words.push(state.accept.map_or(none, |rule| rule as u32));
+            words.push(end as u32);
+        }
+        for state in &states {
+            for (a, b, target) in &state.transitions {
+                words.extend([*a as u32, *b as u32, *target]);
+            }
+        }
+        let mut bytes = LEXER.header();
+        for w in words {
+            bytes.extend(w.to_le_bytes());
+        }
+        bytes.extend(patterns.as_bytes());
+        bytes
+    }
+
+    // load a lexer serialized by to_bytes. the bytes are checked, so that a corrupted file is an
+    // error rather than a lexer that panics
+    pub fn from_bytes(bytes: &[u8]) -> Result<Lexer<'a,V>,TableFormatError> {
+        let (_, start) = LEXER.read_header(bytes)?;
+        let word_count = (bytes.len() - start) / 4;
+        let check = |words: usize| {
+            let expected = start + words * 4;
+            if word_count < words {
+                Err(TableFormatError::Truncated { expected, actual: bytes.len() })
+            } else {
+                Ok(())
+            }
+        };
+        let word = |i: usize| {
+            let at = start + i * 4;
+            u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+        };
+        let invalid = |i: usize| TableFormatError::Invalid { offset: start + i * 4 };
+        check(6)?;
+        let (rule_count, mode_count, state_count) = (word(1) as usize, word(2) as usize, word(3) as usize);
+        let (transition_count, pattern_bytes) = (word(4) as usize, word(5) as usize);
+        let (modes_at, rules_at) = (6, 6 + mode_count);
+        let states_at = rules_at + rule_count * 4;
+        let transitions_at = states_at + state_count * 2;
+        let patterns_at = transitions_at + transition_count * 3;
+        check(patterns_at)?;
+        let expected = start + patterns_at * 4 + pattern_bytes;
+        if bytes.len() < expected {
+            return Err(TableFormatError::Truncated { expected, actual: bytes.len() });
+        }
+        let patterns = std::str::from_utf8(&bytes[expected - pattern_bytes..expected]).map_err(|_| invalid(patterns_at))?;
+        if mode_count == 0 {
+            return Err(invalid(2));
+        }
+
+        let mut lexer = Lexer::new(Symbol::from_index(word(0) as usize));
+        lexer.mode_count = mode_count;
+        let mut start = 0;
+        for i in 0..rule_count {
+            let at = rules_at + i * 4;
+            let end = word(at + 1) as usize;
+            let pattern = patterns.get(start..end).ok_or(invalid(at + 1))?;
+            let regex = Regex::new(pattern).map_err(|_| invalid(at + 1))?;
+            let symbol = match word(at) {
+                u32::MAX => None,
+                index => Some(Symbol::from_index(index as usize)),
+            };
+            let mode = word(at + 2) as usize;
+            if mode >= mode_count {
+                return Err(invalid(at + 2));
+            }
+            let switch = match word(at + 3) as usize {
+                0 => Switch::Stay,
+                1 => Switch::Pop,
+                push if push - 2 < mode_count => Switch::Push(push - 2),
+                _ => return Err(invalid(at + 3)),
+            };
+            lexer.rules.push(Rule { pattern: pattern.to_string(), regex, symbol, payload: None, mode, switch });
+            start = end;
+        }
+        let mut dfas = Vec::new();
+        let (mut first, mut start) = (0, 0);
+        for mode in 0..mode_count {
+            let last = word(modes_at + mode) as usize;
+            if last <= first || last > state_count {
+                return Err(invalid(modes_at + mode));
+            }
+            let mut states = Vec::new();
+            for i in first..last {
+                let at = states_at + i * 2;
+                let accept = match word(at) {
+                    u32::MAX => None,
+                    rule if (rule as usize) < rule_count && lexer.rules[rule as usize].mode == mode => Some(rule as usize),
+                    _ => return Err(invalid(at)),
+                };
+                // no rule matches the empty string (see add), and a start state that accepts would
+                // have lex match nothing over and over without getting anywhere
+                if i == first && accept.is_some() {
+                    return Err(invalid(at));
+                }
+                let end = word(at + 1) as usize;
+                if end < start || end > transition_count {
+                    return Err(invalid(at + 1));
+                }
+                let mut transitions = Vec::new();
+                for t in start..end {
+                    let at = transitions_at + t * 3;
+                    let (a, b, target) = (char::from_u32(word(at)), char::from_u32(word(at + 1)), word(at + 2));
+                    match (a, b) {
+                        (Some(a), Some(b)) if a <= b && (target as usize) < last - first => transitions.push((a, b, target)),
+                        _ => return Err(invalid(at)),
+                    }
+                }
+                states.push(DfaState { transitions, accept });
+                start = end;
+            }
+            dfas.push(Dfa { states });
+            first = last;
+        }
+        lexer.dfas = OnceCell::from(dfas);
+        Ok(lexer)
+    }
+
+    // the compiled dfas as rust functions, which take the text and return the rule (numbered from
+    // 0 in the order the rules were added) that matches the longest prefix of it, and the length
+    // of the prefix in bytes, as the lexer itself does. the function for the default mode has the
+    // given name, and the one for each other mode has the mode's number after it, e.g. name_1.
+    // for generating the matcher of a lexer at build time, so that a program doesn't depend on
+    // this crate at run time at all
+    pub fn to_rust(&self, name: &str) -> String {
+        let mut rules = String::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            let mode = if rule.mode == 0 { String::new() } else { format!(" (mode {})", rule.mode) };
+            rules.push_str(&format!("//   {}: {}{}\n", i, rule.pattern, mode));
+        }
+        let functions: Vec<String> = self.dfas().iter().enumerate()
+            .map(|(mode, dfa)| dfa.to_rust(&if mode == 0 { name.to_string() } else { format!("{}_{}", name, mode) }))
+            .collect();
+        format!("// the longest match of these rules (earliest first where they match the same text):\n//\n{}{}", rules, functions.join("\n"))
+    }
+
+    // the tokens of the text, ending with the end of input. the rules are compiled to a dfa the
+    // first time, so that the time taken is in proportion to the length of the text, however many
+    // rules there are
+    pub fn lex(&self, text: &str) -> Result<Vec<Lexeme<V>>,LexError> {
+        let dfas = self.dfas();
+        // the modes entered, with the spans of the tokens that entered them
+        let mut modes: Vec<(usize,Range<usize>)> = vec![(0, 0..0)];
+        let mut tokens = Vec::new();
+        // the trivia since the last token
+        let mut trivia = Vec::new();
+        let mut position = 0;
+        while position < text.len() {
+            let rest = &text[position..];
+            let mode = modes.last().unwrap().0;
+            let (rule, len) = dfas[mode].longest_match(rest).ok_or(LexError::NoMatch { position })?;
+            let rule = &self.rules[rule];
+            let span = position..position + len;
+            match rule.switch {
+                Switch::Stay => {},
+                Switch::Push(mode) => modes.push((mode, span.clone())),
+                Switch::Pop if modes.len() > 1 => { modes.pop(); },
+                Switch::Pop => return Err(LexError::Unbalanced { span }),
+            }
+            if let Some(mut symbol) = rule.symbol {
+                if let Some((_, table)) = self.keywords.iter().find(|(s, _)| *s == symbol) {
+                    symbol = table.get(&text[span.clone()]).unwrap_or(symbol);
+                }
+                let value = match &rule.payload {
+                    Some(payload) => match payload(&text[span.clone()]) {
+                        Ok(value) => Some(value),
+                        Err(message) => return Err(LexError::Payload { span, message }),
+                    },
+                    None => None,
+                };
+                let mut token = Lexeme { symbol, span, value, leading: Vec::new(), trailing: Vec::new() };
+                self.attach(text, &mut trivia, tokens.last_mut(), &mut token);
+                tokens.push(token);
+            } else if self.trivia != TriviaPolicy::Discard {
+                trivia.push(span);
+            }
+            position += len;
+        }
+        if modes.len() > 1 {
+            return Err(LexError::Unbalanced { span: modes.pop().unwrap().1 });
+        }
+        let mut eoi = Lexeme { symbol: self.eoi, span: text.len()..text.len(), value: None, leading: Vec::new(), trailing: Vec::new() };
+        match tokens.last_mut() {
+            Some(last) => last.trailing.append(&mut trivia),
+            None => eoi.leading.append(&mut trivia),
+        }
+        tokens.push(eoi);
+        Ok(tokens)
+    }
+
+    // give the trivia between two tokens to one or the other of them
+    fn attach(&self, text: &str, trivia: &mut Vec<Range<usize>>, before: Option<&mut Lexeme<V>>, after: &mut Lexeme<V>) {
+        let Some(before) = before else {
+            after.leading.append(trivia);
+            return;
+        };
+        let trailing = match self.trivia {
+            TriviaPolicy::Discard | TriviaPolicy::Leading => 0,
+            TriviaPolicy::Trailing => trivia.len(),
+            TriviaPolicy::SameLine => trivia.iter()
+                .position(|span| text[span.clone()].contains('\n'))
+                .map_or(trivia.len(), |i| i + 1),
+        };
+        after.leading.extend(trivia.drain(trailing..));
+        before.trailing.append(trivia);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::parse_tree::{ParseTree,RoundTripError};
+    use crate::parser::Parser;
+
+    #[derive(Clone,Debug,PartialEq)]
+    enum Value {
+        Int(i64),
+        Str(String),
+    }
+
+    /* grammar:
+     *   call -> ID ( args )
+     *   args -> arg | args , arg
+     *   arg -> INT | STRING
+     */
+    #[test]
+    fn lexer_01() {
+        let parser = Parser::new(Grammar::from_bnf("call -> ID ( args )\nargs -> arg | args , arg\narg -> INT | STRING", "call").unwrap());
+        let db = parser.grammar().symbol_db();
+        let symbol = |label: &str| db.symbol(label).unwrap();
+        let mut lexer = Lexer::new(db.eoi());
+        lexer.skip("\\s+|#.*").unwrap();
+        lexer.token("[a-z]\\w*", symbol("ID")).unwrap();
+        lexer.token_with("-?\\d+", symbol("INT"), |text| {
+            text.parse().map(Value::Int).map_err(|_| format!("{} is too big", text))
+        }).unwrap();
+        lexer.token_with("\"([^\"\\\\]|\\\\.)*\"", symbol("STRING"), |text| {
+            let mut result = String::new();
+            let mut chars = text[1..text.len() - 1].chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => match chars.next() {
+                        Some('n') => result.push('\n'),
+                        Some(c) => result.push(c),
+                        None => {},
+                    },
+                    c => result.push(c),
+                }
+            }
+            Ok(Value::Str(result))
+        }).unwrap();
+        for punctuation in ["\\(", "\\)", ","] {
+            lexer.token(punctuation, symbol(&punctuation.replace('\\', ""))).unwrap();
+        }
+
+        let text = "print(1, \"a\\\"b\\n\",  -20) # done";
+        let tokens = lexer.lex(text).unwrap();
+        assert_eq!(tokens.len(), 9);
+        assert_eq!(tokens[0].text(text), "print");
+        assert_eq!(tokens[0].value, None);
+
+        let tree = parser.parse(tokens, |t| t.symbol).unwrap();
+        let values = tree.fold(&mut |node: &ParseTree<Lexeme<Value>>, children: Vec<Vec<Value>>| {
+            match &node.token().value {
+                Some(value) if children.is_empty() => vec![value.clone()],
+                _ => children.concat(),
+            }
+        });
+        assert_eq!(values, vec![Value::Int(1), Value::Str("a\"b\n".to_string()), Value::Int(-20)]);
+
+        let text = "f(1,\n 99999999999999999999)";
+        let error = lexer.lex(text).err().unwrap();
+        assert_eq!(error, LexError::Payload { span: 6..26, message: "99999999999999999999 is too big".to_string() });
+        assert_eq!(error.render(&SourceMap::new("x", text)), "x:2:2: 99999999999999999999 is too big\n 99999999999999999999)\n ^^^^^^^^^^^^^^^^^^^^\n");
+        assert_eq!(lexer.lex("f(%)").err().unwrap(), LexError::NoMatch { position: 2 });
+
+        assert!(lexer.skip("x*").is_err());
+        assert!(lexer.token("(", symbol("ID")).is_err());
+    }
+
+    /* grammar:
+     *   stmts -> stmt | stmts stmt
+     *   stmt -> SELECT ID FROM ID ; | ID = ID ;
+     */
+    #[test]
+    fn keywords_01() {
+        let parser = Parser::new(Grammar::from_bnf("stmts -> stmt | stmts stmt\nstmt -> SELECT ID FROM ID ; | ID = ID ;", "sql").unwrap());
+        let db = parser.grammar().symbol_db();
+        let symbol = |label: &str| db.symbol(label).unwrap();
+        let new_lexer = |mut table: KeywordTable| {
+            let mut lexer: Lexer<()> = Lexer::new(db.eoi());
+            lexer.skip("\\s+").unwrap();
+            lexer.token("[a-zA-Z_]\\w*", symbol("ID")).unwrap();
+            lexer.token(";", symbol(";")).unwrap();
+            lexer.token("=", symbol("=")).unwrap();
+            table.declare("select", symbol("SELECT"));
+            table.declare("from", symbol("FROM"));
+            lexer.keywords(symbol("ID"), table);
+            lexer
+        };
+        let labels = |lexer: &Lexer<()>, text: &str| -> Vec<String> {
+            lexer.lex(text).unwrap().iter().map(|t| db.label(&t.symbol).unwrap().clone()).collect()
+        };
+
+        let lexer = new_lexer(KeywordTable::new());
+        assert_eq!(labels(&lexer, "select a from selected;"), vec!["SELECT", "ID", "FROM", "ID", ";", "$"]);
+        assert_eq!(labels(&lexer, "SELECT a"), vec!["ID", "ID", "$"]);
+        assert!(parser.parse(lexer.lex("select x from t; from = x;").unwrap(), |t| t.symbol).is_err());
+
+        let lexer = new_lexer(KeywordTable::case_insensitive());
+        assert_eq!(labels(&lexer, "SeLeCt a FROM b;"), vec!["SELECT", "ID", "FROM", "ID", ";", "$"]);
+        assert!(parser.parse(lexer.lex("Select x From t; y = x;").unwrap(), |t| t.symbol).is_ok());
+    }
+
+    /* grammar:
+     *   tokens -> ε | tokens token
+     *   token -> IF | ID | INT | FLOAT | .
+     */
+    #[test]
+    fn longest_match_01() {
+        let parser = Parser::new(Grammar::from_bnf("tokens -> ε | tokens token\ntoken -> IF | ID | INT | FLOAT | .", "t").unwrap());
+        let db = parser.grammar().symbol_db();
+        let symbol = |label: &str| db.symbol(label).unwrap();
+        let mut lexer: Lexer<()> = Lexer::new(db.eoi());
+        lexer.skip(" ").unwrap();
+        lexer.token("if", symbol("IF")).unwrap();
+        lexer.token("[a-z]+", symbol("ID")).unwrap();
+        lexer.token("\\d+", symbol("INT")).unwrap();
+        lexer.token("\\d+\\.\\d*", symbol("FLOAT")).unwrap();
+        lexer.token("\\.", symbol(".")).unwrap();
+        let labels = |text: &str| -> Vec<String> {
+            lexer.lex(text).unwrap().iter().map(|t| db.label(&t.symbol).unwrap().clone()).collect()
+        };
+        assert_eq!(labels("if iffy 12 12.5 3."), vec!["IF", "ID", "INT", "FLOAT", "FLOAT", "$"]);
+        assert_eq!(labels(".5"), vec![".", "INT", "$"]);
+
+        let mut diagnostics = Diagnostics::new();
+        lexer.check(&mut diagnostics);
+        assert_eq!(diagnostics.len(), 1);
+        let d = &diagnostics.diagnostics()[0];
+        assert_eq!(d.code, "lexer-overlap");
+        assert_eq!(d.symbols, vec![symbol("IF"), symbol("ID")]);
+        assert_eq!(d.message, "the rules for if and [a-z]+ both match \"if\", and the first one wins as it was added first");
+    }
+
+    #[test]
+    fn to_bytes_01() {
+        let parser = Parser::new(Grammar::from_bnf("s -> ε | s ID | s INT", "s").unwrap());
+        let db = parser.grammar().symbol_db();
+        let (id, int) = (db.symbol("ID").unwrap(), db.symbol("INT").unwrap());
+        let mut lexer: Lexer<i64> = Lexer::new(db.eoi());
+        lexer.skip("\\s+").unwrap();
+        lexer.token("[a-zé_]\\w*", id).unwrap();
+        lexer.token_with("\\d+", int, |text| text.parse().map_err(|_| "too big".to_string())).unwrap();
+        let text = "abc 12 é9 3";
+        let tokens = lexer.lex(text).unwrap();
+
+        let bytes = lexer.to_bytes();
+        let mut loaded: Lexer<i64> = Lexer::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.dfas.get(), lexer.dfas.get());
+        assert_eq!(loaded.lex(text).unwrap().iter().map(|t| t.value).collect::<Vec<_>>(), vec![None, None, None, None, None]);
+        loaded.payload(int, |text| text.parse().map_err(|_| "too big".to_string()));
+        assert_eq!(loaded.lex(text).unwrap(), tokens);
+        let mut diagnostics = Diagnostics::new();
+        loaded.check(&mut diagnostics);
+        assert!(diagnostics.is_empty());
+
+        assert_eq!(Lexer::<i64>::from_bytes(b"LRT1").err(), Some(TableFormatError::BadMagic));
+        // lexers saved before the format had versions still load, and can be upgraded
+        let legacy = [b"LXT1".as_slice(), &bytes[8..]].concat();
+        assert_eq!(Lexer::<i64>::from_bytes(&legacy).unwrap().dfas.get(), lexer.dfas.get());
+        assert_eq!(crate::table_view::upgrade_format(&legacy).unwrap(), bytes);
+        for length in [4, 10, 30, bytes.len() - 1] {
+            assert!(matches!(Lexer::<i64>::from_bytes(&bytes[..length]), Err(TableFormatError::Truncated { .. })));
+        }
+        // a transition to a state that doesn't exist
+        let mut corrupt = bytes.clone();
+        let state_count = u32::from_le_bytes(bytes[20..24].try_into().unwrap());
+        let target = 8 + (6 + 1 + 3 * 4 + state_count as usize * 2 + 2) * 4;
+        corrupt[target..target + 4].copy_from_slice(&state_count.to_le_bytes());
+        assert_eq!(Lexer::<i64>::from_bytes(&corrupt).err(), Some(TableFormatError::Invalid { offset: target - 8 }));
+        // a start state that accepts, which would match the empty string
+        let mut corrupt = bytes.clone();
+        let accept = 8 + (6 + 1 + 3 * 4) * 4;
+        corrupt[accept..accept + 4].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(Lexer::<i64>::from_bytes(&corrupt).err(), Some(TableFormatError::Invalid { offset: accept }));
+
+        let code = lexer.to_rust("longest_match");
+        assert!(code.starts_with("// the longest match of these rules (earliest first where they match the same text):\n//\n//   0: \\s+\n"));
+        assert!(code.contains("pub fn longest_match(text: &str) -> Option<(usize, usize)> {"));
+        assert!(code.contains("'\\u{e9}'"));
+    }
+
+    #[test]
+    fn unicode_01() {
+        let parser = Parser::new(Grammar::from_bnf("s -> ε | s ID | s NUM", "s").unwrap());
+        let db = parser.grammar().symbol_db();
+        let mut lexer: Lexer<()> = Lexer::new(db.eoi());
+        lexer.skip("\\p{White_Space}+").unwrap();
+        lexer.token("[\\p{L}_][\\p{L}\\p{N}_]*", db.symbol("ID").unwrap()).unwrap();
+        lexer.token("\\p{N}+", db.symbol("NUM").unwrap()).unwrap();
+        let text = "größe\u{3000}変数1 ٣٤ Δx";
+        let tokens = lexer.lex(text).unwrap();
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text(text)).collect();
+        assert_eq!(texts, vec!["größe", "変数1", "٣٤", "Δx", ""]);
+        assert_eq!(tokens[2].symbol, db.symbol("NUM").unwrap());
+        assert_eq!(lexer.lex("a € b").err(), Some(LexError::NoMatch { position: 2 }));
+    }
+
+    /* grammar:
+     *   expr -> expr + atom | atom
+     *   atom -> ID | string | { expr }
+     *   string -> " parts "
+     *   parts -> ε | parts part
+     *   part -> TEXT | ${ expr }
+     */
+    #[test]
+    fn modes_01() {
+        let bnf = "
+            expr -> expr + atom | atom
+            atom -> ID | string | { expr }
+            string -> \" parts \"
+            parts -> ε | parts part
+            part -> TEXT | ${ expr }
+        ";
+        let parser = Parser::new(Grammar::from_bnf(bnf, "template").unwrap());
+        let db = parser.grammar().symbol_db();
+        let symbol = |label: &str| db.symbol(label).unwrap();
+        let mut lexer: Lexer<()> = Lexer::new(db.eoi());
+        let string = lexer.new_mode();
+        lexer.skip("\\s+").unwrap();
+        lexer.token("[a-z]+", symbol("ID")).unwrap();
+        lexer.token("\\+", symbol("+")).unwrap();
+        lexer.token("\\{", symbol("{")).unwrap();
+        lexer.then_push(Mode::DEFAULT);
+        lexer.token("\\}", symbol("}")).unwrap();
+        lexer.then_pop();
+        lexer.token("\"", symbol("\"")).unwrap();
+        lexer.then_push(string);
+        lexer.rules_for(string);
+        lexer.token("[^\"$]+|\\$", symbol("TEXT")).unwrap();
+        lexer.token("\\$\\{", symbol("${")).unwrap();
+        lexer.then_push(Mode::DEFAULT);
+        lexer.token("\"", symbol("\"")).unwrap();
+        lexer.then_pop();
+
+        let text = "\"a ${ x + \"b${{y}}\" } c$\" + z";
+        let tokens = lexer.lex(text).unwrap();
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text(text)).collect();
+        assert_eq!(texts, vec!["\"", "a ", "${", "x", "+", "\"", "b", "${", "{", "y", "}", "}", "\"", "}", " c", "$", "\"", "+", "z", ""]);
+        assert!(parser.parse(tokens.clone(), |t| t.symbol).is_ok());
+        // the whitespace inside strings is text rather than skipped
+        assert_eq!(tokens[14].symbol, symbol("TEXT"));
+
+        assert_eq!(lexer.lex("x }").err(), Some(LexError::Unbalanced { span: 2..3 }));
+        assert_eq!(lexer.lex("\"a ${x").err(), Some(LexError::Unbalanced { span: 3..5 }));
+        assert_eq!(lexer.lex("\"a ${x}").err(), Some(LexError::Unbalanced { span: 0..1 }));
+
+        let mut diagnostics = Diagnostics::new();
+        lexer.check(&mut diagnostics);
+        assert!(diagnostics.is_empty());
+
+        let loaded: Lexer<()> = Lexer::from_bytes(&lexer.to_bytes()).unwrap();
+        assert_eq!(loaded.lex(text).unwrap(), tokens);
+        assert_eq!(loaded.lex("x }").err(), Some(LexError::Unbalanced { span: 2..3 }));
+        let code = lexer.to_rust("m");
+        assert!(code.contains("//   6: [^\"$]+|\\$ (mode 1)\n"));
+        assert!(code.contains("pub fn m(text: &str)") && code.contains("pub fn m_1(text: &str)"));
+    }
+
+    #[test]
+    fn trivia_01() {
+        let parser = Parser::new(Grammar::from_bnf("s -> ε | s ID", "s").unwrap());
+        let db = parser.grammar().symbol_db();
+        let text = "// doc\nf x // arg\n\n// next\ng\n";
+        let attached = |policy: TriviaPolicy| {
+            let mut lexer: Lexer<()> = Lexer::new(db.eoi());
+            lexer.skip("\\s+").unwrap();
+            lexer.skip("//.*").unwrap();
+            lexer.token("[a-z]+", db.symbol("ID").unwrap()).unwrap();
+            lexer.trivia(policy);
+            let tokens = lexer.lex(text).unwrap();
+            assert_eq!(tokens.len(), 4);
+            let texts = |spans: &Vec<Range<usize>>| spans.iter().map(|s| &text[s.clone()]).collect::<String>();
+            tokens.iter().map(|t| (texts(&t.leading), texts(&t.trailing))).collect::<Vec<_>>()
+        };
+        let pairs = |expected: [(&str, &str); 4]| expected.iter().map(|(l, t)| (l.to_string(), t.to_string())).collect::<Vec<_>>();
+        assert_eq!(attached(TriviaPolicy::Discard), pairs([("", ""), ("", ""), ("", ""), ("", "")]));
+        assert_eq!(attached(TriviaPolicy::Leading), pairs([("// doc\n", ""), (" ", ""), (" // arg\n\n// next\n", "\n"), ("", "")]));
+        assert_eq!(attached(TriviaPolicy::Trailing), pairs([("// doc\n", " "), ("", " // arg\n\n// next\n"), ("", "\n"), ("", "")]));
+        assert_eq!(attached(TriviaPolicy::SameLine), pairs([("// doc\n", " "), ("", " // arg\n\n"), ("// next\n", "\n"), ("", "")]));
+    }
+
+    /* grammar:
+     *   list -> ε | list item
+     *   item -> ID = INT ;
+     */
+    #[test]
+    fn to_source_01() {
+        let parser = Parser::new(Grammar::from_bnf("list -> ε | list item\nitem -> ID = INT ;", "list").unwrap());
+        let db = parser.grammar().symbol_db();
+        let mut lexer: Lexer<()> = Lexer::new(db.eoi());
+        lexer.skip("\\s+|//.*").unwrap();
+        lexer.token("[a-z]+", db.symbol("ID").unwrap()).unwrap();
+        lexer.token("\\d+", db.symbol("INT").unwrap()).unwrap();
+        lexer.token("=", db.symbol("=").unwrap()).unwrap();
+        lexer.token(";", db.symbol(";").unwrap()).unwrap();
+        let text = "  // header\na = 1; // one\nb = 2;\n// end\n";
+        assert_eq!(parser.parse(lexer.lex(text).unwrap(), |t| t.symbol).unwrap().to_source_checked(text).err(), Some(RoundTripError { offset: 0 }));
+
+        lexer.trivia(TriviaPolicy::SameLine);
+        let tree = parser.parse(lexer.lex(text).unwrap(), |t| t.symbol).unwrap();
+        assert_eq!(tree.to_source_checked(text).unwrap(), text);
+
+        // swap the items, and the comments go with them
+        let (symbol, token, mut children, epsilon, production) = tree.into_parts();
+        let b = children.pop().unwrap();
+        let (inner_symbol, inner_token, mut inner, inner_epsilon, inner_production) = children.pop().unwrap().into_parts();
+        let a = inner.pop().unwrap();
+        inner.push(b);
+        children.push(ParseTree::from_parts(inner_symbol, inner_token, inner, inner_epsilon, inner_production));
+        children.push(a);
+        let swapped = ParseTree::from_parts(symbol, token, children, epsilon, production);
+        assert_eq!(swapped.to_source(text), "b = 2;\n// end\n  // header\na = 1; // one\n");
+    }
+}
@@ -1,17 +1,71 @@
 mod action;
+mod bnf;
+mod byte_grammar;
 mod canonical_collection;
+mod compact_tables;
+mod corpus;
+mod dependency_graph;
+mod dfa;
+mod diagnostics;
+#[cfg(feature = "examples")]
+pub mod examples;
 mod first_and_follow;
+mod flat_tree;
+mod fuzz;
 mod grammar;
+mod grammar_family;
+mod lexer;
 mod lr1_item;
+mod operator_table;
+mod parse_error;
 mod parse_tables;
 mod parse_tree;
+mod precedence;
 mod production;
+mod push_parser;
+mod regex;
+mod scannerless;
+mod small_vec;
+mod source_location;
+mod source_map;
+mod spanned;
 mod symbol;
+mod symbol_set;
+mod table_hash;
+mod table_view;
+mod tree_diff;
 
+pub mod analysis;
 pub mod parser;
 
-pub use crate::grammar::Grammar;
-pub use crate::parser::Parser;
-pub use crate::production::Production;
+pub use crate::action::{Action,ActionDisplay};
+pub use crate::bnf::BnfError;
+pub use crate::canonical_collection::{BuildEvent,CollectionBuilder,StateExplosion};
+pub use crate::byte_grammar::{ByteClass,ByteParser,ByteToken,Frame,FrameParser,Framing};
+pub use crate::compact_tables::{CompactAction,CompactTables,IndexOverflow,TableIndex};
+pub use crate::corpus::{CorpusChanges,CorpusOutcome,CorpusReport};
+pub use crate::dependency_graph::DependencyGraph;
+pub use crate::diagnostics::{Diagnostic,Diagnostics,Related,Severity};
+pub use crate::flat_tree::{FlatNode,FlatTree};
+pub use crate::fuzz::{FuzzInput,fuzz_parse};
+pub use crate::grammar::{Grammar,GrammarError,GrammarErrors,GrammarWarning};
+pub use crate::grammar_family::{DialectError,GrammarFamily,ParserFamily};
+pub use crate::lexer::{KeywordTable,LexError,Lexeme,Lexer,Mode,TriviaPolicy};
+pub use crate::operator_table::{DynamicOperators,OperatorTable};
+pub use crate::parse_error::ParseError;
+pub use crate::parse_tables::{Conflict,ParseTables,TableError,TableFootprint};
+pub use crate::parse_tree::{ParseTree,RoundTripError,TreeDisplay};
+pub use crate::parser::{Island,LazyParser,ParseObserver,ParseObservers,ParseSession,ParseStack,ParseStats,Parser,Peek,Recovery,RecoveryMetrics,SubParsers};
+pub use crate::precedence::{Arity,Associativity,Operator,expression_productions};
+pub use crate::production::{Production,ProductionDisplay,ProductionId};
+pub use crate::push_parser::{PushParser,Snapshot};
+pub use crate::regex::RegexError;
+pub use crate::scannerless::{CharClass,CharToken,ClassError,Scannerless};
+pub use crate::source_location::SourceLocation;
+pub use crate::source_map::SourceMap;
+pub use crate::spanned::Spanned;
 pub use crate::symbol::{Symbol,SymbolDb};
+pub use crate::symbol_set::{NonTerminalSet,NonTerminals,SetDisplay,SymbolSet,TerminalSet,Terminals};
+pub use crate::table_view::{Aligned,FORMAT_VERSION,TableFormatError,TableView,ViewAction,upgrade_format};
+pub use crate::tree_diff::{DifferenceKind,ExpectedTreeError,TreeDifference};
 
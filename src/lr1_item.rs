@@ -1,19 +1,32 @@
-use std::fmt::Write;
+use std::fmt;
+use std::sync::Arc;
+
 use super::production::Production;
 use super::symbol::{Symbol,SymbolDb};
 
 #[derive(Clone,Debug,Eq,Hash,PartialEq,PartialOrd,Ord)]
 pub struct LR1Item {
-    production: Production,
+    // shared by every item of the production (see canonical_collection::SharedProductions), so
+    // copying an item doesn't copy the production
+    production: Arc<Production>,
     dot_position: usize,
     lookahead: Symbol,
 }
 
 impl LR1Item {
     pub fn new(production: Production, dot_position: usize, lookahead: Symbol) -> LR1Item {
+        LR1Item::shared(Arc::new(production), dot_position, lookahead)
+    }
+
+    pub(crate) fn shared(production: Arc<Production>, dot_position: usize, lookahead: Symbol) -> LR1Item {
         LR1Item { production, dot_position, lookahead }
     }
 
+    // the item with the dot moved past the next symbol
+    pub(crate) fn advanced(&self) -> LR1Item {
+        LR1Item::shared(self.production.clone(), self.dot_position + 1, self.lookahead)
+    }
+
     pub fn production(&self) -> &Production {
         &self.production
     }
@@ -30,6 +43,11 @@ impl LR1Item {
         result
     }
 
+    // the symbol right after the dot, if the dot isn't at the end
+    pub fn next_symbol(&self) -> Option<Symbol> {
+        self.production.rhs().get(self.dot_position).copied()
+    }
+
     pub fn lookahead(&self) -> &Symbol {
         &self.lookahead
     }
@@ -38,14 +56,31 @@ impl LR1Item {
         self.production.lhs() == &symbol_db.goal() && self.lookahead() == &symbol_db.eoi()
     }
 
+    // a target item that the parser accepts in when it sees the end of input: all of the start
+    // symbol is behind the dot, and all that's left is the $ of an augmented production that
+    // writes it out (see Grammar::with_explicit_end_of_input)
+    pub fn is_accepting(&self, symbol_db: &SymbolDb) -> bool {
+        let rest = &self.production.rhs()[self.dot_position..];
+        self.is_target(symbol_db) && (rest.is_empty() || rest == [symbol_db.eoi()])
+    }
+
     #[allow(dead_code)]
-    pub fn to_string(&self, symbol_db: &SymbolDb) -> String {
-        let mut result = String::new();
-        let p = self.production.to_string(symbol_db);
-        let d = self.dot_position;
-        let l = symbol_db.label(&self.lookahead).unwrap();
-        write!(&mut result, "[LR1Item {}, {}, {}]", p, d, l).unwrap();
-        result
+    pub fn display<'a>(&'a self, symbol_db: &'a SymbolDb) -> ItemDisplay<'a> {
+        ItemDisplay { item: self, symbol_db }
+    }
+}
+
+pub struct ItemDisplay<'a> {
+    item: &'a LR1Item,
+    symbol_db: &'a SymbolDb,
+}
+
+impl fmt::Display for ItemDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let p = self.item.production.display(self.symbol_db);
+        let d = self.item.dot_position;
+        let l = self.symbol_db.label(&self.item.lookahead).ok_or(fmt::Error)?;
+        write!(f, "[LR1Item {}, {}, {}]", p, d, l)
     }
 }
 
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use super::parse_tree::ParseTree;
+use super::precedence::Associativity;
+
+// the precedence and associativity of operators, by name. with Parser::parse_with_operators, the
+// table can be changed while parsing, e.g. by declarations like haskell's infixl 6 +++ in the
+// input itself
+#[derive(Clone,Debug,Default)]
+pub struct OperatorTable {
+    operators: HashMap<String,(u32,Associativity)>,
+}
+
+impl OperatorTable {
+    pub fn new() -> OperatorTable {
+        OperatorTable { operators: HashMap::new() }
+    }
+
+    // declare an operator, replacing any earlier declaration of the same name. operators with a
+    // higher precedence bind more tightly
+    pub fn declare(&mut self, name: &str, precedence: u32, associativity: Associativity) {
+        self.operators.insert(name.to_string(), (precedence, associativity));
+    }
+
+    pub fn get(&self, name: &str) -> Option<(u32,Associativity)> {
+        self.operators.get(name).copied()
+    }
+}
+
+// how a shift/reduce conflict between two operators is settled
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub(crate) enum Resolution {
+    Shift,
+    Reduce,
+    // the operators are non-associative and of equal precedence, so neither is allowed
+    Error,
+}
+
+type OperatorName<'a,T> = Box<dyn Fn(&T) -> Option<String> + 'a>;
+type ReduceHook<'a,T> = Box<dyn FnMut(&ParseTree<T>, &mut OperatorTable) + 'a>;
+
+// what Parser::parse_with_operators needs to settle conflicts while parsing: the operator table,
+// a function that names the operator a token stands for (if any), and an optional hook that sees
+// every node built by a reduction and can declare operators from it
+pub struct DynamicOperators<'a,T> {
+    table: OperatorTable,
+    name: OperatorName<'a,T>,
+    on_reduce: Option<ReduceHook<'a,T>>,
+}
+
+impl<'a,T> DynamicOperators<'a,T> {
+    pub fn new<N>(table: OperatorTable, name: N) -> DynamicOperators<'a,T>
+        where N: Fn(&T) -> Option<String> + 'a {
+        DynamicOperators { table, name: Box::new(name), on_reduce: None }
+    }
+
+    pub fn on_reduce<H>(&mut self, hook: H)
+        where H: FnMut(&ParseTree<T>, &mut OperatorTable) + 'a {
+        self.on_reduce = Some(Box::new(hook));
+    }
+
+    pub fn table(&self) -> &OperatorTable {
+        &self.table
+    }
+
+    pub fn table_mut(&mut self) -> &mut OperatorTable {
+        &mut self.table
+    }
+
+    pub(crate) fn reduced(&mut self, tree: &ParseTree<T>) {
+        if let Some(hook) = &mut self.on_reduce {
+            hook(tree, &mut self.table);
+        }
+    }
+
+    // settle a conflict between reducing a handle whose operator token is handle and shifting the
+    // lookahead. None when either token isn't a declared operator
+    pub(crate) fn resolve(&self, handle: &T, lookahead: &T) -> Option<Resolution> {
+        let (left, associativity) = self.table.get(&(self.name)(handle)?)?;
+        let (right, _) = self.table.get(&(self.name)(lookahead)?)?;
+        Some(match (right.cmp(&left), associativity) {
+            (std::cmp::Ordering::Greater, _) => Resolution::Shift,
+            (std::cmp::Ordering::Less, _) => Resolution::Reduce,
+            (_, Associativity::Left) => Resolution::Reduce,
+            (_, Associativity::Right) => Resolution::Shift,
+            (_, Associativity::NonAssoc) => Resolution::Error,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::parser::Parser;
+    use crate::production::Production;
+    use crate::symbol::SymbolDb;
+
+    #[derive(Clone,Debug,PartialEq)]
+    enum Token {
+        Infixl,
+        Infixr,
+        Infix,
+        Num(u32),
+        Op(&'static str),
+        X,
+        Semi,
+        EndOfFile,
+    }
+
+    /* grammar:
+     *   S -> S ; T | T
+     *   T -> D | E
+     *   D -> infixl NUM OP | infixr NUM OP | infix NUM OP
+     *   E -> E OP E | x
+     */
+    #[test]
+    fn parse_with_operators_01() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let t = symbol_db.new_nonterminal("T");
+        let d = symbol_db.new_nonterminal("D");
+        let e = symbol_db.new_nonterminal("E");
+        let semi = symbol_db.new_terminal(";");
+        let infixl = symbol_db.new_terminal("infixl");
+        let infixr = symbol_db.new_terminal("infixr");
+        let infix = symbol_db.new_terminal("infix");
+        let num = symbol_db.new_terminal("NUM");
+        let op = symbol_db.new_terminal("OP");
+        let x = symbol_db.new_terminal("x");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(s, vec![s, semi, t]),
+            Production::new(s, vec![t]),
+            Production::new(t, vec![d]),
+            Production::new(t, vec![e]),
+            Production::new(d, vec![infixl, num, op]),
+            Production::new(d, vec![infixr, num, op]),
+            Production::new(d, vec![infix, num, op]),
+            Production::new(e, vec![e, op, e]),
+            Production::new(e, vec![x]),
+        ];
+        let p = Parser::new(Grammar::new(symbol_db, s, productions));
+        assert!(!p.parse_tables().conflicts().is_empty());
+        let ttos = |token: &Token| match token {
+            Token::Infixl => infixl,
+            Token::Infixr => infixr,
+            Token::Infix => infix,
+            Token::Num(_) => num,
+            Token::Op(_) => op,
+            Token::X => x,
+            Token::Semi => semi,
+            Token::EndOfFile => eoi,
+        };
+
+        let new_operators = || {
+            let mut operators = DynamicOperators::new(OperatorTable::new(), |t: &Token| match t {
+                Token::Op(name) => Some(name.to_string()),
+                _ => None,
+            });
+            operators.on_reduce(move |tree: &ParseTree<Token>, table: &mut OperatorTable| {
+                if *tree.symbol() != d {
+                    return;
+                }
+                let children = tree.children();
+                if let (Token::Num(n), Token::Op(name)) = (children[1].token(), children[2].token()) {
+                    let associativity = match children[0].token() {
+                        Token::Infixl => Associativity::Left,
+                        Token::Infixr => Associativity::Right,
+                        _ => Associativity::NonAssoc,
+                    };
+                    table.declare(name, *n, associativity);
+                }
+            });
+            operators
+        };
+        // each expression statement, with the operator applications in brackets
+        let render = |tree: &ParseTree<Token>| -> Vec<String> {
+            let mut statements = Vec::new();
+            tree.fold(&mut |node: &ParseTree<Token>, mut children: Vec<String>| {
+                let result = match (node.token(), children.len()) {
+                    (Token::Op(name), 0) => name.to_string(),
+                    (_, 0) => "x".to_string(),
+                    (_, 1) => children.pop().unwrap(),
+                    _ => format!("({})", children.join(" ")),
+                };
+                if *node.symbol() == t && *node.children()[0].symbol() == e {
+                    statements.push(result.clone());
+                }
+                result
+            });
+            statements
+        };
+        let parse = |tokens: Vec<Token>| {
+            let mut operators = new_operators();
+            p.parse_with_operators(tokens, ttos, &mut operators).map(|tree| render(&tree))
+        };
+
+        use Token::*;
+        let statements = parse(vec![
+            Infixl, Num(6), Op("+"), Semi,
+            Infixr, Num(8), Op("^"), Semi,
+            X, Op("+"), X, Op("+"), X, Semi,
+            X, Op("+"), X, Op("^"), X, Op("^"), X, Semi,
+            X, Op("^"), X, Op("+"), X, Semi,
+            Infixl, Num(9), Op("+"), Semi,
+            X, Op("+"), X, Op("^"), X,
+            EndOfFile,
+        ]).unwrap();
+        assert_eq!(statements, vec![
+            "((x + x) + x)",
+            "(x + (x ^ (x ^ x)))",
+            "((x ^ x) + x)",
+            // + was redeclared to bind more tightly than ^
+            "((x + x) ^ x)",
+        ]);
+
+        let error = parse(vec![Infix, Num(4), Op("=="), Semi, X, Op("=="), X, Op("=="), X, EndOfFile]).err().unwrap();
+        assert_eq!(error.position(), 7);
+
+        // undeclared operators fall back on the table, which always shifts
+        assert_eq!(parse(vec![X, Op("-"), X, Op("-"), X, EndOfFile]).unwrap(), vec!["(x - (x - x))"]);
+    }
+}
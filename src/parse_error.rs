@@ -0,0 +1,182 @@
+use std::ops::Range;
+
+use super::diagnostics::{Diagnostic,Severity};
+use super::source_map::SourceMap;
+use super::spanned::Spanned;
+use super::symbol::{Symbol,SymbolDb};
+
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum ParseError {
+    // there is no action for the symbol of the token at the given position
+    UnexpectedSymbol { state: u32, symbol: Symbol, position: usize },
+    // the tokens ran out before the end of input symbol was seen
+    UnexpectedEndOfInput { position: usize },
+    // the parse tables have no goto entry for the nonterminal just reduced in the state uncovered
+    // by the reduction, or a reduction names a production the grammar doesn't have or one longer
+    // than the stack. symbol is the nonterminal, or the lookahead if the production is unknown.
+    // this never happens with tables built from a grammar, so it means the tables themselves are
+    // broken rather than the input
+    InternalError { state: u32, symbol: Symbol, position: usize },
+    // in a scannerless or byte parse, the character or byte at the given position isn't in the
+    // class of any terminal
+    UnexpectedCharacter { position: usize },
+    // a recovering parse found more errors than it was allowed to report, and gave up at the one
+    // at the given position instead of trying to get past it
+    TooManyErrors { limit: usize, position: usize },
+    // the classifier given to ParseSession::parse_classified made a different number of symbols
+    // than there are tokens
+    ClassifiedTokens { tokens: usize, symbols: usize },
+    // a message read by a FrameParser was longer than the limit, so it wasn't parsed past that
+    FrameTooLong { limit: usize },
+}
+
+impl ParseError {
+    pub fn position(&self) -> usize {
+        match self {
+            ParseError::UnexpectedSymbol { position, .. } => *position,
+            ParseError::UnexpectedEndOfInput { position } => *position,
+            ParseError::InternalError { position, .. } => *position,
+            ParseError::UnexpectedCharacter { position } => *position,
+            ParseError::TooManyErrors { position, .. } => *position,
+            // the first token without a symbol, or the end of the tokens if there are too many
+            ParseError::ClassifiedTokens { tokens, symbols } => *tokens.min(symbols),
+            // the first byte past the limit
+            ParseError::FrameTooLong { limit } => *limit,
+        }
+    }
+
+    // the bytes of the input where the error happened, given the tokens that were parsed. running
+    // out of tokens is reported as an empty span at the end of the last token
+    pub fn span<T: Spanned>(&self, tokens: &[T]) -> Option<Range<usize>> {
+        match self {
+            ParseError::UnexpectedSymbol { position, .. } |
+            ParseError::InternalError { position, .. } |
+            ParseError::UnexpectedCharacter { position } |
+            ParseError::TooManyErrors { position, .. } => tokens.get(*position).map(|t| t.span()),
+            ParseError::ClassifiedTokens { .. } |
+            ParseError::FrameTooLong { .. } => tokens.get(self.position()).map(|t| t.span()),
+            ParseError::UnexpectedEndOfInput { .. } => {
+                let end = tokens.last().map(|t| t.span().end).unwrap_or(0);
+                Some(end..end)
+            },
+        }
+    }
+
+    // the error as a compiler would report it, with its location in the input and the line it's
+    // on, e.g.
+    //
+    //   input.json:3:7: unexpected ]
+    //     "a": ]
+    //          ^
+    //
+    // the message leaves out the token position, since the location says where the error is
+    pub fn render<T: Spanned>(&self, tokens: &[T], source_map: &SourceMap, symbol_db: &SymbolDb) -> String {
+        let span = self.span(tokens).unwrap_or_else(|| {
+            let end = source_map.text().len();
+            end..end
+        });
+        source_map.render(span, &self.message(symbol_db))
+    }
+
+    // the error as a Diagnostic, given the tokens that were parsed
+    pub fn to_diagnostic<T: Spanned>(&self, tokens: &[T], symbol_db: &SymbolDb) -> Diagnostic {
+        let code = match self {
+            ParseError::UnexpectedSymbol { symbol, .. } if *symbol == symbol_db.eoi() => "unexpected-end-of-input",
+            ParseError::UnexpectedSymbol { .. } => "unexpected-symbol",
+            ParseError::UnexpectedEndOfInput { .. } => "unexpected-end-of-input",
+            ParseError::InternalError { .. } => "internal-error",
+            ParseError::UnexpectedCharacter { .. } => "unexpected-character",
+            ParseError::TooManyErrors { .. } => "too-many-errors",
+            ParseError::ClassifiedTokens { .. } => "classified-tokens",
+            ParseError::FrameTooLong { .. } => "frame-too-long",
+        };
+        let mut diagnostic = Diagnostic::new(Severity::Error, code, self.message(symbol_db));
+        diagnostic.span = self.span(tokens);
+        if let ParseError::UnexpectedSymbol { symbol, .. } | ParseError::InternalError { symbol, .. } = self {
+            diagnostic.symbols.push(*symbol);
+        }
+        diagnostic
+    }
+
+    // the message without the position, for when the position is shown some other way
+    fn message(&self, symbol_db: &SymbolDb) -> String {
+        match self {
+            ParseError::UnexpectedSymbol { symbol, .. } if *symbol == symbol_db.eoi() => "unexpected end of input".to_string(),
+            ParseError::UnexpectedSymbol { symbol, .. } => format!("unexpected {}", symbol_db.label(symbol).unwrap()),
+            ParseError::UnexpectedEndOfInput { .. } => "unexpected end of input".to_string(),
+            ParseError::InternalError { .. } => self.to_string(symbol_db),
+            ParseError::UnexpectedCharacter { .. } => "unexpected character".to_string(),
+            ParseError::TooManyErrors { limit, .. } => format!("more than {} errors, giving up", limit),
+            ParseError::ClassifiedTokens { .. } => self.to_string(symbol_db),
+            ParseError::FrameTooLong { limit } => format!("message longer than {} bytes", limit),
+        }
+    }
+
+    pub fn to_string(&self, symbol_db: &SymbolDb) -> String {
+        match self {
+            ParseError::UnexpectedSymbol { state, symbol, position } => {
+                let s = symbol_db.label(symbol).unwrap();
+                format!("unexpected {} at position {} (state {})", s, position, state)
+            },
+            ParseError::UnexpectedEndOfInput { position } => {
+                format!("unexpected end of input at position {}", position)
+            },
+            ParseError::InternalError { state, symbol, position } => {
+                let s = symbol_db.label(symbol).unwrap();
+                format!("internal error: broken parse tables at {} in state {} at position {}", s, state, position)
+            },
+            ParseError::UnexpectedCharacter { position } => {
+                format!("unexpected character at position {}", position)
+            },
+            ParseError::TooManyErrors { limit, position } => {
+                format!("more than {} errors, giving up at position {}", limit, position)
+            },
+            ParseError::ClassifiedTokens { tokens, symbols } => {
+                format!("{} symbols were classified for {} tokens", symbols, tokens)
+            },
+            ParseError::FrameTooLong { limit } => {
+                format!("message longer than {} bytes at position {}", limit, limit)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::parser::Parser;
+
+    #[derive(Clone,Debug)]
+    struct Token {
+        symbol: Symbol,
+        span: Range<usize>,
+    }
+
+    impl Spanned for Token {
+        fn span(&self) -> Range<usize> {
+            self.span.clone()
+        }
+    }
+
+    /* grammar:
+     *   S -> ( S ) | x
+     */
+    #[test]
+    fn render_01() {
+        let p = Parser::new(Grammar::from_bnf("S -> ( S ) | x", "g").unwrap());
+        let db = p.grammar().symbol_db();
+        let text = "(\n  (x ) x)";
+        let tokens: Vec<Token> = text.char_indices()
+            .filter(|(_, c)| !c.is_whitespace())
+            .map(|(i, c)| Token { symbol: db.symbol(&c.to_string()).unwrap(), span: i..i + 1 })
+            .chain(std::iter::once(Token { symbol: db.eoi(), span: text.len()..text.len() }))
+            .collect();
+        let error = p.parse(tokens.clone(), |t| t.symbol).err().unwrap();
+        let source_map = SourceMap::new("in.txt", text);
+        assert_eq!(error.render(&tokens, &source_map, db), "in.txt:2:8: unexpected x\n  (x ) x)\n       ^\n");
+        let diagnostic = error.to_diagnostic(&tokens, db);
+        assert_eq!(diagnostic.symbols, vec![db.symbol("x").unwrap()]);
+        assert_eq!(diagnostic.render(&source_map), "in.txt:2:8: error[unexpected-symbol]: unexpected x\n  (x ) x)\n       ^\n");
+    }
+}
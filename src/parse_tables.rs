@@ -1,20 +1,154 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap,BTreeSet,HashMap};
+use std::fmt;
 use std::fmt::Write;
+use std::mem::size_of;
 
 use super::action::Action;
-use super::canonical_collection::CanonicalCollection;
+use super::canonical_collection::{self,CanonicalCollection,StateExplosion};
+use super::diagnostics::{label,Diagnostics};
 use super::grammar::Grammar;
+use super::production::{Production,ProductionId};
 use super::symbol::{Symbol,SymbolDb};
+use super::table_hash::TableMap;
+
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum Conflict {
+    // the shift was kept and the reduction by the production was dropped
+    ShiftReduce { state: u32, symbol: Symbol, production: ProductionId },
+    // the reduction by the first production was kept and the second was dropped
+    ReduceReduce { state: u32, symbol: Symbol, kept: ProductionId, dropped: ProductionId },
+}
+
+// an inconsistency found by ParseTables::verify
+#[derive(Clone,Debug,Eq,Ord,PartialEq,PartialOrd)]
+pub enum TableError {
+    // an entry for a state that doesn't exist
+    UnknownState { state: u32 },
+    // an entry for a symbol that isn't in the grammar's symbol db
+    UnknownSymbol { state: u32, symbol: Symbol },
+    // an action on a nonterminal (actions are only taken on terminals)
+    ActionOnNonterminal { state: u32, symbol: Symbol },
+    // a goto on a terminal (gotos are only taken on nonterminals)
+    GotoOnTerminal { state: u32, symbol: Symbol },
+    ShiftToUnknownState { state: u32, symbol: Symbol, target: u32 },
+    GotoToUnknownState { state: u32, symbol: Symbol, target: u32 },
+    // a reduction by a production id that isn't in the grammar
+    UnknownProduction { state: u32, symbol: Symbol, production: ProductionId },
+    // a reduction can uncover the state, but the state has no goto for the reduced nonterminal
+    MissingGoto { state: u32, symbol: Symbol },
+}
+
+impl TableError {
+    pub fn to_string(&self, symbol_db: &SymbolDb) -> String {
+        let label = |s: &Symbol| symbol_db.label(s).cloned().unwrap_or_else(|| format!("{:?}", s));
+        match self {
+            TableError::UnknownState { state } => {
+                format!("state {} does not exist", state)
+            },
+            TableError::UnknownSymbol { state, symbol } => {
+                format!("state {} has an entry for unknown symbol {}", state, label(symbol))
+            },
+            TableError::ActionOnNonterminal { state, symbol } => {
+                format!("state {} has an action on nonterminal {}", state, label(symbol))
+            },
+            TableError::GotoOnTerminal { state, symbol } => {
+                format!("state {} has a goto on terminal {}", state, label(symbol))
+            },
+            TableError::ShiftToUnknownState { state, symbol, target } => {
+                format!("state {} shifts {} to nonexistent state {}", state, label(symbol), target)
+            },
+            TableError::GotoToUnknownState { state, symbol, target } => {
+                format!("state {} goes to nonexistent state {} on {}", state, target, label(symbol))
+            },
+            TableError::UnknownProduction { state, symbol, production } => {
+                format!("state {} reduces on {} by unknown production {}", state, label(symbol), production.index())
+            },
+            TableError::MissingGoto { state, symbol } => {
+                format!("state {} has no goto on {}", state, label(symbol))
+            },
+        }
+    }
+}
+
+// how much memory the tables take up and how full they are
+#[derive(Clone,Debug)]
+pub struct TableFootprint {
+    pub states: usize,
+    pub action_entries: usize,
+    pub goto_entries: usize,
+    // estimates for the hash maps, counting their allocated capacity (a key, a value and a
+    // control byte per slot) but not anything the productions in reduce actions point to
+    pub action_bytes: usize,
+    pub goto_bytes: usize,
+    // the number of actions and gotos in each state
+    pub entries_per_state: Vec<usize>,
+    // the number of cells in dense states by symbols arrays, like the ones in CompactTables
+    pub dense_cells: usize,
+}
+
+impl TableFootprint {
+    pub fn total_bytes(&self) -> usize {
+        self.action_bytes + self.goto_bytes
+    }
+
+    // the fraction of the dense cells that actually hold something
+    pub fn fill_ratio(&self) -> f64 {
+        if self.dense_cells == 0 {
+            0.0
+        } else {
+            (self.action_entries + self.goto_entries) as f64 / self.dense_cells as f64
+        }
+    }
+
+    // the size of the dense arrays with index_width byte entries
+    pub fn dense_bytes(&self, index_width: usize) -> usize {
+        self.dense_cells * index_width
+    }
+
+    pub fn max_entries_per_state(&self) -> usize {
+        self.entries_per_state.iter().copied().max().unwrap_or(0)
+    }
+}
+
+impl fmt::Display for TableFootprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "states: {}", self.states)?;
+        writeln!(f, "actions: {} entries, {} bytes", self.action_entries, self.action_bytes)?;
+        writeln!(f, "gotos: {} entries, {} bytes", self.goto_entries, self.goto_bytes)?;
+        let average = if self.states == 0 { 0.0 } else { (self.action_entries + self.goto_entries) as f64 / self.states as f64 };
+        writeln!(f, "entries per state: {:.1} average, {} max", average, self.max_entries_per_state())?;
+        writeln!(f, "dense: {} cells, {:.1}% full, {} bytes as u16, {} bytes as u32",
+                 self.dense_cells, self.fill_ratio() * 100.0, self.dense_bytes(2), self.dense_bytes(4))
+    }
+}
 
 #[derive(Debug)]
 pub struct ParseTables {
-    action_table: HashMap<(u32,Symbol),Action>,
-    goto_table: HashMap<(u32,Symbol),u32>
+    action_table: TableMap<(u32,Symbol),Action>,
+    goto_table: TableMap<(u32,Symbol),u32>,
+    state_count: usize,
+    conflicts: Vec<Conflict>,
+    // for each state, the production it reduces by whatever the lookahead is, if every action in
+    // the state is a reduction by that one production
+    default_reductions: Vec<Option<ProductionId>>,
 }
 
 impl ParseTables {
     pub fn new(grammar: &Grammar) -> ParseTables {
-        build(grammar)
+        build(grammar, &mut Diagnostics::new())
+    }
+
+    // like new, but reports the conflicts (and what they cost) along with whatever the earlier
+    // phases of construction find
+    pub fn new_with_diagnostics(grammar: &Grammar, diagnostics: &mut Diagnostics) -> ParseTables {
+        build(grammar, diagnostics)
+    }
+
+    // like new_with_diagnostics, but gives up once the automaton has more than limit states,
+    // reporting which rules the states came from
+    pub fn new_with_state_limit(grammar: &Grammar, limit: usize, diagnostics: &mut Diagnostics) -> Result<ParseTables,StateExplosion> {
+        let cc = canonical_collection::build_limited(grammar, limit, diagnostics)?;
+        Ok(from_collection(grammar, &cc, diagnostics))
     }
 
     pub fn action(&self, state: u32, symbol: Symbol) -> Option<&Action> {
@@ -27,6 +161,348 @@ impl ParseTables {
         self.goto_table.get(&key)
     }
 
+    pub fn state_count(&self) -> usize {
+        self.state_count
+    }
+
+    // the parser can reduce by this production without looking at the lookahead at all. an input
+    // error in such a state is only found after the reduction, in the state it leads to
+    pub fn default_reduction(&self, state: u32) -> Option<ProductionId> {
+        *self.default_reductions.get(state as usize)?
+    }
+
+    pub fn conflicts(&self) -> &Vec<Conflict> {
+        &self.conflicts
+    }
+
+    pub fn footprint(&self, grammar: &Grammar) -> TableFootprint {
+        let slot_bytes = |key: usize, value: usize| key + value + 1;
+        let action_bytes = self.action_table.capacity() * slot_bytes(size_of::<(u32,Symbol)>(), size_of::<Action>());
+        let goto_bytes = self.goto_table.capacity() * slot_bytes(size_of::<(u32,Symbol)>(), size_of::<u32>());
+
+        let mut entries_per_state = vec![0; self.state_count];
+        for (state, _) in self.action_table.keys().chain(self.goto_table.keys()) {
+            if let Some(n) = entries_per_state.get_mut(*state as usize) {
+                *n += 1;
+            }
+        }
+
+        TableFootprint {
+            states: self.state_count,
+            action_entries: self.action_table.len(),
+            goto_entries: self.goto_table.len(),
+            action_bytes,
+            goto_bytes,
+            entries_per_state,
+            dense_cells: self.state_count * (grammar.terminals().len() + grammar.nonterminals().len()),
+        }
+    }
+
+    pub(crate) fn actions(&self) -> impl Iterator<Item=(&(u32,Symbol),&Action)> {
+        self.action_table.iter()
+    }
+
+    pub(crate) fn transitions(&self) -> impl Iterator<Item=(&(u32,Symbol),&u32)> {
+        self.goto_table.iter()
+    }
+
+    fn find_default_reductions(&mut self) {
+        let mut candidates: Vec<Option<ProductionId>> = vec![None; self.state_count];
+        let mut ruled_out = vec![false; self.state_count];
+        for (&(state, _), action) in &self.action_table {
+            let state = state as usize;
+            match action {
+                Action::Reduce(p) => match candidates[state] {
+                    None => candidates[state] = Some(*p),
+                    Some(q) if q != *p => ruled_out[state] = true,
+                    _ => {},
+                },
+                _ => ruled_out[state] = true,
+            }
+        }
+        self.default_reductions = candidates.into_iter()
+            .zip(ruled_out)
+            .map(|(p, ruled_out)| if ruled_out { None } else { p })
+            .collect();
+    }
+
+    fn diagnose(&self, grammar: &Grammar, diagnostics: &mut Diagnostics) {
+        let symbol_db = grammar.symbol_db();
+        for c in &self.conflicts {
+            match c {
+                Conflict::ShiftReduce { state, symbol, production } => {
+                    let message = format!("shift/reduce conflict in state {} on {}: kept the shift over reducing by {}",
+                                          state, label(symbol_db, symbol), grammar.display_production(*production));
+                    let symbols = vec![*symbol, *grammar.production(*production).lhs()];
+                    diagnostics.warning("shift-reduce-conflict", message, symbols, grammar.production_location(*production).cloned());
+                },
+                Conflict::ReduceReduce { state, symbol, kept, dropped } => {
+                    let message = format!("reduce/reduce conflict in state {} on {}: kept reducing by {} over {}",
+                                          state, label(symbol_db, symbol), grammar.display_production(*kept), grammar.display_production(*dropped));
+                    let symbols = vec![*symbol, *grammar.production(*kept).lhs(), *grammar.production(*dropped).lhs()];
+                    diagnostics.warning("reduce-reduce-conflict", message, symbols, grammar.production_location(*dropped).cloned());
+                },
+            }
+        }
+
+        // a production whose every reduction lost a conflict can never be used. the augmented
+        // production is accepted rather than reduced, and unreachable productions are reported
+        // with the grammar
+        let reduced: BTreeSet<ProductionId> = self.action_table.values()
+            .filter_map(|a| match a {
+                Action::Reduce(p) => Some(*p),
+                _ => None,
+            })
+            .collect();
+        let dropped: BTreeSet<ProductionId> = self.conflicts.iter()
+            .map(|c| match c {
+                Conflict::ShiftReduce { production, .. } => *production,
+                Conflict::ReduceReduce { dropped, .. } => *dropped,
+            })
+            .collect();
+        for p in dropped.difference(&reduced) {
+            let message = format!("production {} is never reduced because of conflicts", grammar.display_production(*p));
+            let location = grammar.production_location(*p).cloned();
+            diagnostics.warning("unreduced-production", message, vec![*grammar.production(*p).lhs()], location);
+        }
+    }
+
+    // render the automaton in graphviz format: a node per state, and an edge per shift or goto.
+    // conflicts are drawn on top. states with a conflict are outlined in red, the shifts that
+    // took part in one are blue, and each reduction that took part in one is a dashed red edge to
+    // a box for its production. the edges involved in a conflict are labeled with the lookahead
+    pub fn to_dot(&self, grammar: &Grammar) -> String {
+        let symbol_db = grammar.symbol_db();
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let quoted = |s: &Symbol| escape(&label(symbol_db, s));
+
+        // the lookaheads each state has a conflict on
+        let mut conflicted: BTreeMap<u32,BTreeSet<Symbol>> = BTreeMap::new();
+        for c in &self.conflicts {
+            let (Conflict::ShiftReduce { state, symbol, .. } | Conflict::ReduceReduce { state, symbol, .. }) = c;
+            conflicted.entry(*state).or_default().insert(*symbol);
+        }
+
+        let mut result = String::new();
+        writeln!(&mut result, "digraph automaton {{").unwrap();
+        writeln!(&mut result, "    node [shape=circle];").unwrap();
+        for state in 0..self.state_count as u32 {
+            if conflicted.contains_key(&state) {
+                writeln!(&mut result, "    s{} [label=\"{}\", color=red, penwidth=2];", state, state).unwrap();
+            } else {
+                writeln!(&mut result, "    s{} [label=\"{}\"];", state, state).unwrap();
+            }
+        }
+
+        // ordinary edges between the same two states share one edge with all their symbols
+        let mut edges: BTreeMap<(u32,u32),Vec<String>> = BTreeMap::new();
+        let mut shifts: Vec<(&(u32,Symbol),u32)> = self.action_table.iter()
+            .filter_map(|(key, a)| match a {
+                Action::Shift(to) => Some((key, *to)),
+                _ => None,
+            })
+            .collect();
+        shifts.sort();
+        for (&(from, symbol), to) in shifts {
+            if conflicted.get(&from).map(|s| s.contains(&symbol)).unwrap_or(false) {
+                writeln!(&mut result, "    s{} -> s{} [label=\"{}\", color=blue, fontcolor=blue, penwidth=2];", from, to, quoted(&symbol)).unwrap();
+            } else {
+                edges.entry((from, to)).or_default().push(quoted(&symbol));
+            }
+        }
+        let mut gotos: Vec<(&(u32,Symbol),&u32)> = self.goto_table.iter().collect();
+        gotos.sort();
+        for (&(from, symbol), &to) in gotos {
+            edges.entry((from, to)).or_default().push(quoted(&symbol));
+        }
+        for ((from, to), labels) in edges {
+            writeln!(&mut result, "    s{} -> s{} [label=\"{}\"];", from, to, labels.join(" ")).unwrap();
+        }
+
+        // the reductions involved in conflicts, kept or dropped
+        let mut reductions: BTreeSet<(u32,ProductionId,Symbol)> = BTreeSet::new();
+        for c in &self.conflicts {
+            match c {
+                Conflict::ShiftReduce { state, symbol, production } => {
+                    reductions.insert((*state, *production, *symbol));
+                },
+                Conflict::ReduceReduce { state, symbol, kept, dropped } => {
+                    reductions.insert((*state, *kept, *symbol));
+                    reductions.insert((*state, *dropped, *symbol));
+                },
+            }
+        }
+        let productions: BTreeSet<ProductionId> = reductions.iter().map(|(_, p, _)| *p).collect();
+        for p in productions {
+            let text = escape(&grammar.display_production(p).to_string());
+            writeln!(&mut result, "    p{} [shape=box, label=\"{}\", color=red];", p.index(), text).unwrap();
+        }
+        for (state, p, symbol) in reductions {
+            writeln!(&mut result, "    s{} -> p{} [label=\"{}\", style=dashed, color=red, fontcolor=red];", state, p.index(), quoted(&symbol)).unwrap();
+        }
+        writeln!(&mut result, "}}").unwrap();
+        result
+    }
+
+    // the action and goto tables as one csv matrix: a row per state and a column per symbol,
+    // terminals (and the end of input) first. an action is written the way textbooks write them,
+    // s3 for a shift to state 3, r2 for a reduction by production 2, and acc; a goto is just the
+    // state. the productions the reductions refer to are listed in the rows after a blank line
+    pub fn to_csv(&self, grammar: &Grammar) -> String {
+        let symbol_db = grammar.symbol_db();
+        let quoted = |s: &str| if s.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_string()
+        };
+
+        let mut terminals: Vec<Symbol> = symbol_db.declared_terminals().iter().collect();
+        terminals.push(symbol_db.eoi());
+        let nonterminals: Vec<Symbol> = symbol_db.declared_nonterminals().iter().collect();
+
+        let mut result = String::new();
+        let header: Vec<String> = terminals.iter().chain(&nonterminals).map(|s| quoted(&label(symbol_db, s))).collect();
+        writeln!(&mut result, "state,{}", header.join(",")).unwrap();
+        let mut reductions = BTreeSet::new();
+        for state in 0..self.state_count as u32 {
+            let mut row = vec![state.to_string()];
+            for &t in &terminals {
+                row.push(match self.action(state, t) {
+                    Some(Action::Accept) => "acc".to_string(),
+                    Some(Action::Shift(to)) => format!("s{}", to),
+                    Some(Action::Reduce(p)) => {
+                        reductions.insert(*p);
+                        format!("r{}", p.index())
+                    },
+                    None => String::new(),
+                });
+            }
+            for &nt in &nonterminals {
+                row.push(self.transition(state, nt).map(|to| to.to_string()).unwrap_or_default());
+            }
+            writeln!(&mut result, "{}", row.join(",")).unwrap();
+        }
+        if !reductions.is_empty() {
+            writeln!(&mut result).unwrap();
+            writeln!(&mut result, "production,rule").unwrap();
+            for p in reductions {
+                writeln!(&mut result, "r{},{}", p.index(), quoted(&grammar.display_production(p).to_string())).unwrap();
+            }
+        }
+        result
+    }
+
+    // lets tests break the tables on purpose
+    #[cfg(test)]
+    pub(crate) fn goto_table_mut(&mut self) -> &mut TableMap<(u32,Symbol),u32> {
+        &mut self.goto_table
+    }
+
+    #[cfg(test)]
+    pub(crate) fn action_table_mut(&mut self) -> &mut TableMap<(u32,Symbol),Action> {
+        &mut self.action_table
+    }
+
+    // check that the tables are consistent with each other and with the grammar. tables built by
+    // new always are, but tables that came from somewhere else (or were modified) might not be,
+    // and the parser trusts them completely. the errors are sorted so the result doesn't depend
+    // on hash map iteration order
+    pub fn verify(&self, grammar: &Grammar) -> Result<(),Vec<TableError>> {
+        let symbol_db = grammar.symbol_db();
+        let state_count = self.state_count as u32;
+        let mut errors = BTreeSet::new();
+
+        let check_entry = |state: u32, symbol: &Symbol, errors: &mut BTreeSet<TableError>| {
+            if state >= state_count {
+                errors.insert(TableError::UnknownState { state });
+            }
+            if symbol_db.label(symbol).is_none() {
+                errors.insert(TableError::UnknownSymbol { state, symbol: *symbol });
+            }
+        };
+
+        // every transition between states, keyed by target state and symbol. a reduction pops
+        // states by walking these backwards
+        let mut predecessors: HashMap<(u32,Symbol),Vec<u32>> = HashMap::new();
+
+        for (&(state, symbol), action) in &self.action_table {
+            check_entry(state, &symbol, &mut errors);
+            if symbol_db.label(&symbol).is_some() && !symbol_db.is_terminal(&symbol) {
+                errors.insert(TableError::ActionOnNonterminal { state, symbol });
+            }
+            match action {
+                Action::Shift(target) => {
+                    if *target >= state_count {
+                        errors.insert(TableError::ShiftToUnknownState { state, symbol, target: *target });
+                    }
+                    predecessors.entry((*target, symbol)).or_default().push(state);
+                },
+                Action::Reduce(p) => {
+                    if p.index() >= grammar.all_productions().len() {
+                        errors.insert(TableError::UnknownProduction { state, symbol, production: *p });
+                    }
+                },
+                Action::Accept => {},
+            }
+        }
+
+        for (&(state, symbol), &target) in &self.goto_table {
+            check_entry(state, &symbol, &mut errors);
+            if symbol_db.is_terminal(&symbol) {
+                errors.insert(TableError::GotoOnTerminal { state, symbol });
+            }
+            if target >= state_count {
+                errors.insert(TableError::GotoToUnknownState { state, symbol, target });
+            }
+            predecessors.entry((target, symbol)).or_default().push(state);
+        }
+
+        // only reductions in states the parser can actually get to matter. the canonical
+        // collection can contain states that nothing leads to, and those may well lack gotos
+        let mut successors: HashMap<u32,Vec<u32>> = HashMap::new();
+        for (&(target, _), sources) in &predecessors {
+            for source in sources {
+                successors.entry(*source).or_default().push(target);
+            }
+        }
+        let mut reachable: BTreeSet<u32> = BTreeSet::new();
+        let mut pending = vec![0];
+        while let Some(s) = pending.pop() {
+            if reachable.insert(s) {
+                pending.extend(successors.get(&s).into_iter().flatten());
+            }
+        }
+
+        // a reduction by A -> X1 .. Xn in state s uncovers every state that reaches s through
+        // transitions on X1 .. Xn, and each of those needs a goto on A
+        for (&(state, _), action) in &self.action_table {
+            if !reachable.contains(&state) {
+                continue;
+            }
+            if let Some(p) = action_production(grammar, action) {
+                let mut uncovered: BTreeSet<u32> = BTreeSet::new();
+                uncovered.insert(state);
+                for x in p.rhs().iter().rev() {
+                    uncovered = uncovered.iter()
+                        .flat_map(|s| predecessors.get(&(*s, *x)).into_iter().flatten())
+                        .cloned()
+                        .collect();
+                }
+                for s in uncovered {
+                    if !self.goto_table.contains_key(&(s, *p.lhs())) {
+                        errors.insert(TableError::MissingGoto { state: s, symbol: *p.lhs() });
+                    }
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.into_iter().collect())
+        }
+    }
+
     fn add_action(&mut self, state: u32, symbol: Symbol, action: Action) {
         let key = (state, symbol);
         if let Some(other) = self.action_table.get(&key) {
@@ -34,15 +510,16 @@ impl ParseTables {
                 return;
             }
             match (&action, &other) {
-                (Action::Shift(_), Action::Reduce(_)) => {
-                    println!("shift/reduce conflict");
+                (Action::Shift(_), Action::Reduce(production)) => {
+                    self.conflicts.push(Conflict::ShiftReduce { state, symbol, production: *production });
                     self.action_table.insert(key, action);
                 },
-                (Action::Reduce(_), Action::Shift(_)) => {
-                    println!("shift/reduce conflict");
+                (Action::Reduce(production), Action::Shift(_)) => {
+                    self.conflicts.push(Conflict::ShiftReduce { state, symbol, production: *production });
                 },
-                (Action::Reduce(_), Action::Reduce(_)) => {
-                    panic!("reduce/reduce conflict");
+                (Action::Reduce(dropped), Action::Reduce(kept)) => {
+                    let (kept, dropped) = (*kept, *dropped);
+                    self.conflicts.push(Conflict::ReduceReduce { state, symbol, kept, dropped });
                 },
                 (x,y) => panic!("unknown conflict -- {:?} {:?} {:?} {:?}", x, y, state, symbol)
             }
@@ -53,48 +530,67 @@ impl ParseTables {
 
     fn add_transition(&mut self, from: u32, on: Symbol, to: u32) {
         let key = (from, on);
-        if let Some(_) = self.goto_table.get(&key) {
+        if self.goto_table.insert(key, to).is_some() {
             panic!("attempt to replace an existing entry in goto table");
-        } else {
-            self.goto_table.insert(key, to);
         }
     }
 
     #[allow(dead_code)]
-    pub fn to_string(&self, symbol_db: &SymbolDb) -> String {
+    pub fn to_string(&self, grammar: &Grammar) -> String {
+        let symbol_db = grammar.symbol_db();
         let mut result = String::new();
         writeln!(&mut result, "actions").unwrap();
         for ((i,s), a) in &self.action_table {
-            let s = symbol_db.label(&s).unwrap();
-            let a = a.to_string(symbol_db);
+            let s = symbol_db.label(s).unwrap();
+            let a = a.display(grammar);
             writeln!(&mut result, "    ({}, {}) -> {}", i, s, a).unwrap();
         }
         writeln!(&mut result, "goto").unwrap();
         for ((i,s), n) in &self.goto_table {
-            let s = symbol_db.label(&s).unwrap();
+            let s = symbol_db.label(s).unwrap();
             writeln!(&mut result, "    ({}, {}) -> {}", i, s, n).unwrap();
         }
         result
     }
 }
 
-fn build(grammar: &Grammar) -> ParseTables {
+// the production a reduce action reduces by, if it's one the grammar has
+fn action_production<'a>(grammar: &'a Grammar, action: &Action) -> Option<&'a Production> {
+    match action {
+        Action::Reduce(p) => grammar.all_productions().get(p.index()),
+        _ => None,
+    }
+}
+
+fn build(grammar: &Grammar, diagnostics: &mut Diagnostics) -> ParseTables {
+    let cc = CanonicalCollection::new_with_diagnostics(grammar, diagnostics);
+    from_collection(grammar, &cc, diagnostics)
+}
+
+// fill in the tables from the sets of items and the transitions between them
+pub(crate) fn from_collection(grammar: &Grammar, cc: &CanonicalCollection, diagnostics: &mut Diagnostics) -> ParseTables {
     let symbol_db = grammar.symbol_db();
-    let cc = CanonicalCollection::new(grammar);
 
     let mut parse_tables = ParseTables {
-        action_table: HashMap::new(),
-        goto_table: HashMap::new()
+        action_table: TableMap::default(),
+        goto_table: TableMap::default(),
+        state_count: cc.sets().len(),
+        conflicts: Vec::new(),
+        default_reductions: Vec::new(),
     };
 
     for (&i, cc_i) in cc.sets() {
         for item in cc_i {
             let unseen = item.symbols_after_dot();
-            // if the dot isn't at the end of the production (i.e. unseen isn't empty), and
-            // this isn't an epsilon production, and there is a transition from the current state
-            // on the next symbol of the production
-            if !unseen.is_empty() &&
-               unseen[0] != symbol_db.epsilon() &&
+            // if the start symbol has been seen, then instead of reducing the augmented production
+            // (or shifting the $ at the end of it), accept. the item's lookahead is always $,
+            // since that's what the initial item was given
+            if item.is_accepting(symbol_db) {
+                parse_tables.add_action(i, *item.lookahead(), Action::accept());
+            }
+            // if the dot isn't at the end of the production (i.e. unseen isn't empty), and there
+            // is a transition from the current state on the next symbol of the production
+            else if !unseen.is_empty() &&
                cc.transitions().contains_key(&(i,unseen[0])) {
                 let c = unseen[0];
                 // if the next symbol is a terminal, then add a shift action
@@ -103,16 +599,13 @@ fn build(grammar: &Grammar) -> ParseTables {
                     parse_tables.add_action(i, c, Action::shift(*j));
                 }
             }
-            // if there are no unseen symbols and the production represents the target, then add an
-            // accept action
-            else if unseen.is_empty() && item.is_target(grammar.symbol_db()) {
-                parse_tables.add_action(i, symbol_db.eoi(), Action::accept());
-            }
-            // if at the end of a production rule or it's an epsilon production, then add a reduce action 
-            else if unseen.is_empty() || unseen[0] == symbol_db.epsilon() {
-                let action = Action::reduce(item.production().clone());
-                //println!("**** {} {}     {}", i, symbol_db.label(item.lookahead()).unwrap(), item.to_string(symbol_db));
-                parse_tables.add_action(i, item.lookahead().clone(), action);
+            // if at the end of a production rule (which an epsilon production always is), then add
+            // a reduce action
+            else if unseen.is_empty() {
+                let id = grammar.production_id(item.production()).unwrap();
+                let action = Action::reduce(id);
+                //println!("**** {} {}     {}", i, symbol_db.label(item.lookahead()).unwrap(), item.display(symbol_db));
+                parse_tables.add_action(i, *item.lookahead(), action);
             }
             else {
                 panic!("something went terribly wrong while building parse tables");
@@ -120,14 +613,192 @@ fn build(grammar: &Grammar) -> ParseTables {
         }
         // add transitions for the non-terminals
         for nt in grammar.nonterminals() {
-            if let Some(&j) = cc.transitions().get(&(i, nt.clone())) {
-                parse_tables.add_transition(i, nt.clone(), j);
+            if let Some(&j) = cc.transitions().get(&(i, nt)) {
+                parse_tables.add_transition(i, nt, j);
             } else {
                 //println!("there is no transition from {} on a reduction to {}", i, nt);
             }
         }
     }
 
+    parse_tables.find_default_reductions();
+    parse_tables.diagnose(grammar, diagnostics);
     parse_tables
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* grammar:
+     *   e1 -> ( e1 ) | ε
+     */
+    fn parens() -> Grammar {
+        let mut symbol_db = SymbolDb::new();
+        let e1 = symbol_db.new_nonterminal("E1");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let productions = vec![
+            Production::new(e1, vec![lp, e1, rp]),
+            Production::new(e1, vec![]),
+        ];
+        Grammar::new(symbol_db, e1, productions)
+    }
+
+    #[test]
+    fn verify_01() {
+        let g = parens();
+        let tables = ParseTables::new(&g);
+        assert_eq!(tables.verify(&g), Ok(()));
+    }
+
+    #[test]
+    fn verify_02() {
+        let g = parens();
+        let db = g.symbol_db();
+        let e1 = db.symbol("E1").unwrap();
+        let rp = db.symbol(")").unwrap();
+
+        let mut tables = ParseTables::new(&g);
+        tables.goto_table.retain(|(s, _), _| *s != 0);
+        tables.action_table.insert((0, rp), Action::shift(99));
+        tables.action_table.insert((1, e1), Action::reduce(ProductionId(99)));
+
+        let errors = tables.verify(&g).err().unwrap();
+        assert_eq!(errors.len(), 4);
+        assert!(errors.contains(&TableError::MissingGoto { state: 0, symbol: e1 }));
+        assert!(errors.contains(&TableError::ShiftToUnknownState { state: 0, symbol: rp, target: 99 }));
+        assert!(errors.contains(&TableError::ActionOnNonterminal { state: 1, symbol: e1 }));
+        assert!(errors.iter().any(|e| matches!(e, TableError::UnknownProduction { state: 1, .. })));
+        assert_eq!(errors[0].to_string(db), "state 1 has an action on nonterminal E1");
+    }
+
+    #[test]
+    fn footprint_01() {
+        let g = parens();
+        let tables = ParseTables::new(&g);
+        let footprint = tables.footprint(&g);
+        assert_eq!(footprint.states, tables.state_count());
+        assert_eq!(footprint.goto_entries, 3);
+        assert_eq!(footprint.entries_per_state.iter().sum::<usize>(), footprint.action_entries + footprint.goto_entries);
+        // GOAL and E1, plus $, ε, ( and )
+        assert_eq!(footprint.dense_cells, footprint.states * 6);
+        assert!(footprint.fill_ratio() > 0.0 && footprint.fill_ratio() < 1.0);
+        assert!(footprint.total_bytes() > 0);
+        assert!(footprint.to_string().contains("gotos: 3 entries"));
+    }
+
+    #[test]
+    fn explicit_end_of_input_01() {
+        let g = parens();
+        let explicit = parens().with_explicit_end_of_input(true);
+        assert_eq!(explicit.augmented_production().display(explicit.symbol_db()).to_string(), "GOAL -> E1  $");
+        assert_eq!(explicit.production_id(explicit.augmented_production()), Some(explicit.augmented_production_id()));
+        let tables = ParseTables::new(&g);
+        let explicit_tables = ParseTables::new(&explicit);
+        assert_eq!(explicit_tables.verify(&explicit), Ok(()));
+        // the $ is accepted rather than shifted, so the tables come out the same
+        assert_eq!(explicit_tables.state_count(), tables.state_count());
+        for (&(state, symbol), action) in tables.actions() {
+            assert_eq!(explicit_tables.action(state, symbol), Some(action));
+        }
+        assert_eq!(explicit_tables.actions().count(), tables.actions().count());
+        // and it can be taken out again
+        let implicit = explicit.with_explicit_end_of_input(false);
+        assert_eq!(implicit.augmented_production(), g.augmented_production());
+    }
+
+    #[test]
+    fn default_reduction_01() {
+        let g = parens();
+        let tables = ParseTables::new(&g);
+        let e1 = g.symbol_db().symbol("E1").unwrap();
+        let mut found = 0;
+        for state in 0..tables.state_count() as u32 {
+            if let Some(p) = tables.default_reduction(state) {
+                found += 1;
+                assert!(tables.actions()
+                    .filter(|((s, _), _)| *s == state)
+                    .all(|(_, a)| *a == Action::Reduce(p)));
+            }
+        }
+        // the states after ( E1 ) only reduce
+        assert!(found > 0);
+        // state 0 can shift (, so it has no default
+        assert_eq!(tables.default_reduction(0), None);
+        assert!(tables.action(0, g.symbol_db().symbol("(").unwrap()).is_some());
+        assert!(tables.transition(0, e1).is_some());
+    }
+
+    #[test]
+    fn production_id_01() {
+        let g = parens();
+        let tables = ParseTables::new(&g);
+        let e1 = g.symbol_db().symbol("E1").unwrap();
+        let epsilon_production = g.production_id(&Production::new(e1, vec![])).unwrap();
+        // at the end of the input, state 0 reduces by E1 -> ε
+        let eoi = g.symbol_db().eoi();
+        assert_eq!(tables.action(0, eoi), Some(&Action::Reduce(epsilon_production)));
+        for (_, action) in tables.actions() {
+            if let Action::Reduce(p) = action {
+                assert_eq!(g.production(*p).lhs(), &e1);
+            }
+        }
+    }
+
+    /* grammar:
+     *   e -> e + e | x
+     */
+    #[test]
+    fn to_dot_01() {
+        let mut symbol_db = SymbolDb::new();
+        let e = symbol_db.new_nonterminal("e");
+        let plus = symbol_db.new_terminal("+");
+        let x = symbol_db.new_terminal("x");
+        let g = Grammar::new(symbol_db, e, vec![Production::new(e, vec![e, plus, e]), Production::new(e, vec![x])]);
+        let tables = ParseTables::new(&g);
+        assert_eq!(tables.conflicts().len(), 1);
+        let (state, production) = match tables.conflicts()[0] {
+            Conflict::ShiftReduce { state, production, .. } => (state, production),
+            _ => panic!("expected a shift/reduce conflict"),
+        };
+
+        let dot = tables.to_dot(&g);
+        assert!(dot.starts_with("digraph automaton {"));
+        assert!(dot.contains(&format!("    s{} [label=\"{}\", color=red, penwidth=2];", state, state)));
+        assert!(dot.contains(&format!("    p{} [shape=box, label=\"e -> e  +  e\", color=red];", production.index())));
+        assert!(dot.contains(&format!("    s{} -> p{} [label=\"+\", style=dashed, color=red, fontcolor=red];", state, production.index())));
+        let shift = tables.action(state, plus).unwrap();
+        assert!(matches!(shift, Action::Shift(_)));
+        assert!(dot.contains(&format!("    s{} -> s{} [label=\"+\", color=blue", state, match shift { Action::Shift(to) => *to, _ => 0 })));
+        // the shift on x from state 0 isn't part of any conflict
+        assert!(dot.contains(&format!("    s0 -> s{} [label=\"x\"];", match tables.action(0, x).unwrap() { Action::Shift(to) => *to, _ => 0 })));
+    }
+
+    #[test]
+    fn to_csv_01() {
+        /* grammar: list -> list , x | x */
+        let mut symbol_db = SymbolDb::new();
+        let list = symbol_db.new_nonterminal("list");
+        let comma = symbol_db.new_terminal(",");
+        let x = symbol_db.new_terminal("x");
+        let g = Grammar::new(symbol_db, list, vec![Production::new(list, vec![list, comma, x]), Production::new(list, vec![x])]);
+        let tables = ParseTables::new(&g);
+        let csv = tables.to_csv(&g);
+        let lines: Vec<&str> = csv.lines().collect();
+
+        // the comma is quoted in the header and in the production that uses it
+        assert_eq!(lines[0], "state,\",\",x,$,list");
+        assert_eq!(lines.len(), 1 + tables.state_count() + 4);
+        let to = match tables.action(0, x).unwrap() { Action::Shift(to) => *to, _ => panic!() };
+        let list_to = tables.transition(0, list).unwrap();
+        assert_eq!(lines[1], format!("0,,s{},,{}", to, list_to));
+        assert_eq!(lines[1 + *list_to as usize], format!("{},s{},,acc,", list_to, match tables.action(*list_to, comma).unwrap() { Action::Shift(to) => *to, _ => panic!() }));
+        assert_eq!(lines[1 + to as usize], format!("{},r1,,r1,", to));
+        assert_eq!(lines[1 + tables.state_count()], "");
+        assert_eq!(lines[2 + tables.state_count()], "production,rule");
+        assert!(lines.contains(&"r1,list -> x"));
+        assert!(lines.contains(&"r0,\"list -> list  ,  x\""));
+    }
+}
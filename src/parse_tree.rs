@@ -1,15 +1,56 @@
-use super::symbol::Symbol;
+use std::fmt;
+use std::ops::Range;
 
-#[derive(Debug)]
+use super::grammar::Grammar;
+use super::lexer::Lexeme;
+use super::production::ProductionId;
+use super::spanned::Spanned;
+use super::symbol::{Symbol,SymbolDb};
+
+#[derive(Clone,Debug)]
 pub struct ParseTree<T> {
     symbol: Symbol,
     token: T,
-    children: Vec<ParseTree<T>>
+    children: Vec<ParseTree<T>>,
+    epsilon: bool,
+    // the production a parser reduced to make the node. trees put together by hand don't have one
+    production: Option<ProductionId>,
+}
+
+// trees are equal when they have the same shape and tokens. the production is left out, since
+// the symbols of a node and its children already say which one it is, and trees from a table view
+// or put together by hand don't have it
+impl<T: PartialEq> PartialEq for ParseTree<T> {
+    fn eq(&self, other: &ParseTree<T>) -> bool {
+        self.symbol == other.symbol && self.token == other.token && self.epsilon == other.epsilon && self.children == other.children
+    }
 }
 
+impl<T: Eq> Eq for ParseTree<T> {}
+
 impl <T> ParseTree<T> {
     pub fn new(symbol: Symbol, token: T) -> ParseTree<T> {
-        ParseTree { symbol, token, children: Vec::new() }
+        ParseTree { symbol, token, children: Vec::new(), epsilon: false, production: None }
+    }
+
+    // a node for a reduction by an ε production. its token is the lookahead at the time of the
+    // reduction, not something it derived
+    pub fn epsilon(symbol: Symbol, token: T) -> ParseTree<T> {
+        ParseTree { symbol, token, children: Vec::new(), epsilon: true, production: None }
+    }
+
+    pub fn production(&self) -> Option<ProductionId> {
+        self.production
+    }
+
+    // the label of the alternative the node was made from, e.g. Add for a node made by reducing
+    // e -> e + e #Add
+    pub fn label<'g>(&self, grammar: &'g Grammar) -> Option<&'g str> {
+        grammar.production_label(self.production?)
+    }
+
+    pub fn is_epsilon(&self) -> bool {
+        self.epsilon
     }
 
     pub fn token(&self) -> &T {
@@ -27,5 +68,148 @@ impl <T> ParseTree<T> {
     pub fn add_child(&mut self, child: ParseTree<T>) {
         self.children.push(child);
     }
+
+    pub(crate) fn from_parts(symbol: Symbol, token: T, children: Vec<ParseTree<T>>, epsilon: bool, production: Option<ProductionId>) -> ParseTree<T> {
+        ParseTree { symbol, token, children, epsilon, production }
+    }
+
+    // a node for a reduction by the production, whose children are the trees of its right hand
+    // side. the nodes of inline productions among them are replaced by their own children
+    pub(crate) fn reduced(symbol: Symbol, token: T, children: Vec<ParseTree<T>>, production: ProductionId, grammar: &Grammar) -> ParseTree<T> {
+        let inline = |c: &ParseTree<T>| c.production.is_some_and(|p| grammar.is_inline(p));
+        let children = if children.iter().any(inline) {
+            let mut spliced = Vec::with_capacity(children.len());
+            for c in children {
+                if inline(&c) {
+                    spliced.extend(c.children);
+                } else {
+                    spliced.push(c);
+                }
+            }
+            spliced
+        } else {
+            children
+        };
+        // a node left with nothing under it covers nothing, just like an ε production
+        let epsilon = children.is_empty();
+        ParseTree { symbol, token, children, epsilon, production: Some(production) }
+    }
+
+    pub(crate) fn into_parts(self) -> (Symbol, T, Vec<ParseTree<T>>, bool, Option<ProductionId>) {
+        (self.symbol, self.token, self.children, self.epsilon, self.production)
+    }
+
+    // evaluate the tree bottom up, in the manner of semantic actions: f is called once for every
+    // node, children first, with the node and the values computed for its children
+    pub fn fold<R,F>(&self, f: &mut F) -> R where F: FnMut(&ParseTree<T>, Vec<R>) -> R {
+        let values = self.children.iter().map(|child| child.fold(f)).collect();
+        f(self, values)
+    }
+
+    // fold for a tree from Parser::parse_recovering, in which the stretches of input that didn't
+    // parse are nodes for the grammar's error symbol. the value of such a node comes from
+    // placeholder instead, e.g. Expr::Error(node.span()), so that there's a value for every node
+    // that f is given. what's under an error node isn't folded
+    pub fn fold_with_placeholders<R,F,P>(&self, error: Symbol, f: &mut F, placeholder: &mut P) -> R
+        where F: FnMut(&ParseTree<T>, Vec<R>) -> R,
+              P: FnMut(&ParseTree<T>) -> R {
+        if self.symbol == error {
+            return placeholder(self);
+        }
+        let values = self.children.iter().map(|child| child.fold_with_placeholders(error, f, placeholder)).collect();
+        f(self, values)
+    }
+
+    // something that formats the tree in a compact bracketed form, e.g. e(e(x) + e(x))
+    pub fn display<'a>(&'a self, symbol_db: &'a SymbolDb) -> TreeDisplay<'a,T> {
+        TreeDisplay { tree: self, symbol_db }
+    }
+}
+
+pub struct TreeDisplay<'a,T> {
+    tree: &'a ParseTree<T>,
+    symbol_db: &'a SymbolDb,
+}
+
+impl<T> fmt::Display for TreeDisplay<'_,T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol_db.label(&self.tree.symbol).ok_or(fmt::Error)?)?;
+        if self.tree.children.is_empty() {
+            return Ok(());
+        }
+        write!(f, "(")?;
+        for (i, child) in self.tree.children.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", child.display(self.symbol_db))?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl <T: Spanned> ParseTree<T> {
+    // the bytes covered by this node. a node with no children is either a terminal, which covers
+    // its own token, or an ε production, which covers nothing and sits at the start of the token
+    // that followed it
+    pub fn span(&self) -> Range<usize> {
+        match (self.children.first(), self.children.last()) {
+            (Some(first), Some(last)) => first.span().start..last.span().end,
+            _ if self.epsilon => {
+                let start = self.token.span().start;
+                start..start
+            },
+            _ => self.token.span(),
+        }
+    }
 }
 
+// the text a tree reproduced by ParseTree::to_source_checked, where it first differs from the input
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct RoundTripError {
+    pub offset: usize,
+}
+
+impl fmt::Display for RoundTripError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the text of the tree differs from the input at offset {}", self.offset)
+    }
+}
+
+impl<V> ParseTree<Lexeme<V>> {
+    // the text of the tree's tokens with their trivia, in order. for a tree of tokens lexed with a
+    // TriviaPolicy other than Discard that's exactly the input, and after a rewrite of the tree,
+    // e.g. swapping two subtrees, it's the rewritten text, with the comments moved along with the
+    // tokens they're attached to
+    pub fn to_source(&self, input: &str) -> String {
+        let mut source = String::new();
+        self.write_source(input, &mut source);
+        source
+    }
+
+    // to_source, checked to be the input itself, for tests of tools that depend on trees holding
+    // all of their text
+    pub fn to_source_checked(&self, input: &str) -> Result<String,RoundTripError> {
+        let source = self.to_source(input);
+        match source.bytes().zip(input.bytes()).position(|(a, b)| a != b) {
+            Some(offset) => Err(RoundTripError { offset }),
+            None if source.len() != input.len() => Err(RoundTripError { offset: source.len().min(input.len()) }),
+            None => Ok(source),
+        }
+    }
+
+    fn write_source(&self, input: &str, source: &mut String) {
+        if self.epsilon {
+            return;
+        }
+        if self.children.is_empty() {
+            let token = &self.token;
+            for span in token.leading.iter().chain(std::iter::once(&token.span)).chain(token.trailing.iter()) {
+                source.push_str(&input[span.clone()]);
+            }
+        }
+        for child in &self.children {
+            child.write_source(input, source);
+        }
+    }
+}
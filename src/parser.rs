@@ -1,110 +1,1480 @@
+use std::collections::{HashMap,VecDeque};
+use std::io;
+use std::ops::Deref;
+use std::sync::{Arc,OnceLock};
+
 use super::action::Action;
-use super::grammar::Grammar;
-use super::parse_tables::ParseTables;
+use super::canonical_collection::CanonicalCollection;
+use super::diagnostics::Diagnostics;
+use super::first_and_follow::FirstAndFollow;
+use super::grammar::{Grammar,GrammarErrors};
+use super::operator_table::{DynamicOperators,Resolution};
+use super::parse_error::ParseError;
+use super::parse_tables::{self,Conflict,ParseTables};
 use super::parse_tree::ParseTree;
+use super::production::{Production,ProductionId};
 use super::symbol::Symbol;
 
-pub struct Parser {
-    grammar: Grammar,
-    parse_tables: ParseTables,
-}
+// a clone of a parser shares the grammar and the tables with it, so cloning is cheap however big
+// they are. extending a clone leaves the parsers it was cloned from (or to) as they were: the
+// grammar is copied first if it's shared, and the extended parser gets tables of its own
+#[derive(Clone)]
+pub struct Parser {
+    grammar: Arc<Grammar>,
+    parse_tables: Arc<ParseTables>,
+    // kept once the grammar has been extended, so that later extensions can reuse it
+    collection: Option<Arc<CanonicalCollection>>,
+    // the cells of the action table where a shift was kept over a reduction, with every reduction
+    // that competed for the cell, for settling by operator precedence (see parse_with_operators)
+    conflicts: Arc<HashMap<(u32,Symbol),Vec<ProductionId>>>,
+}
+
+impl Parser {
+    pub fn new(grammar: Grammar) -> Parser {
+        let parse_tables = ParseTables::new(&grammar);
+        //println!("{}", parse_tables.to_string(grammar.symbol_db()));
+        Parser::with_tables(grammar, parse_tables, None)
+    }
+
+    // like new, but collects the diagnostics of building the tables
+    pub fn new_with_diagnostics(grammar: Grammar, diagnostics: &mut Diagnostics) -> Parser {
+        let parse_tables = ParseTables::new_with_diagnostics(&grammar, diagnostics);
+        Parser::with_tables(grammar, parse_tables, None)
+    }
+
+    // a parser whose collection was built already, e.g. as a copy of a related grammar's that
+    // extend can bring up to date
+    pub(crate) fn from_collection(grammar: Grammar, collection: CanonicalCollection) -> Parser {
+        let parse_tables = parse_tables::from_collection(&grammar, &collection, &mut Diagnostics::new());
+        Parser::with_tables(grammar, parse_tables, Some(Arc::new(collection)))
+    }
+
+    fn with_tables(grammar: Grammar, parse_tables: ParseTables, collection: Option<Arc<CanonicalCollection>>) -> Parser {
+        let conflicts = Arc::new(shift_reduce_conflicts(&parse_tables));
+        Parser { grammar: Arc::new(grammar), parse_tables: Arc::new(parse_tables), collection, conflicts }
+    }
+
+    // add a production to the grammar (see Grammar::extend) and bring the tables up to date. the
+    // first extension builds the canonical collection again and holds on to it. later ones only
+    // revisit the states the new production can change, which for a small addition to a large
+    // grammar is much cheaper than building a new parser
+    #[allow(clippy::result_large_err)]
+    pub fn extend(&mut self, p: Production) -> Result<ProductionId,GrammarErrors> {
+        let old_first = self.collection.as_ref().map(|_| FirstAndFollow::new(&self.grammar));
+        let count = self.grammar.all_productions().len();
+        let id = Arc::make_mut(&mut self.grammar).extend(p)?;
+        if self.grammar.all_productions().len() == count {
+            return Ok(id);
+        }
+        let collection = match (self.collection.take(), old_first) {
+            (Some(mut collection), Some(old_first)) => {
+                Arc::make_mut(&mut collection).extend(&self.grammar, &old_first, self.grammar.production(id));
+                collection
+            },
+            _ => Arc::new(CanonicalCollection::new(&self.grammar)),
+        };
+        let parse_tables = parse_tables::from_collection(&self.grammar, &collection, &mut Diagnostics::new());
+        self.conflicts = Arc::new(shift_reduce_conflicts(&parse_tables));
+        self.parse_tables = Arc::new(parse_tables);
+        self.collection = Some(collection);
+        Ok(id)
+    }
+
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    pub fn parse_tables(&self) -> &ParseTables {
+        &self.parse_tables
+    }
+
+    pub fn parse<T,F>(&self, tokens: Vec<T>, token_to_symbol: F) -> Result<ParseTree<T>,ParseError>
+        where T: Clone,
+              F: Fn(&T) -> Symbol {
+        self.parse_with(tokens, token_to_symbol, &SubParsers::new())
+    }
+
+    // like parse, but at any point where the grammar allows one of the nonterminals registered in
+    // sub_parsers, the corresponding handler gets a chance to consume a run of the remaining
+    // tokens. if it does, the tree it returns becomes the (only) child of a node for the
+    // nonterminal, which the parser goes on from as if it had reduced it
+    pub fn parse_with<T,F>(&self, tokens: Vec<T>, token_to_symbol: F, sub_parsers: &SubParsers<T>) -> Result<ParseTree<T>,ParseError>
+        where T: Clone,
+              F: Fn(&T) -> Symbol {
+        self.parse_range(&tokens, None, &|i| token_to_symbol(&tokens[i]), sub_parsers, None, None, &mut ParseSession::new())
+    }
+
+    // like parse, but the shift/reduce conflicts of the grammar are settled while parsing instead
+    // of always shifting. this is for grammars like e -> e OP e where the operators and their
+    // precedences aren't known until the input declares them. when the parser could either shift
+    // the lookahead or reduce a handle, and both the lookahead and the last terminal of the handle
+    // name operators in the table, the one with the higher precedence wins, and the associativity
+    // of the handle's operator decides between equals. in every other case the table's shift
+    // stands. the table can be changed by the hook of operators as the parse goes
+    pub fn parse_with_operators<T,F>(&self, tokens: Vec<T>, token_to_symbol: F, operators: &mut DynamicOperators<T>) -> Result<ParseTree<T>,ParseError>
+        where T: Clone,
+              F: Fn(&T) -> Symbol {
+        self.parse_range(&tokens, None, &|i| token_to_symbol(&tokens[i]), &SubParsers::new(), Some(operators), None, &mut ParseSession::new())
+    }
+
+    // like parse, but every step the parser takes is reported to the observers, with the stacks as
+    // they are after the step. for logging, metrics and visualizations of how a parse goes
+    pub fn parse_observed<T,F>(&self, tokens: Vec<T>, token_to_symbol: F, observers: &mut ParseObservers<T>) -> Result<ParseTree<T>,ParseError>
+        where T: Clone,
+              F: Fn(&T) -> Symbol {
+        self.parse_range(&tokens, None, &|i| token_to_symbol(&tokens[i]), &SubParsers::new(), None, Some(observers), &mut ParseSession::new())
+    }
+
+    // like parse, but the symbol of each token is chosen with the tokens after it in view, up to
+    // limit of them. for tokens whose meaning depends on what follows, e.g. a < that opens a list
+    // of type arguments when a > and then a ( come after it and is less than otherwise, without
+    // the lexer having to know about it
+    pub fn parse_peeking<T,F>(&self, tokens: Vec<T>, limit: usize, classify: F) -> Result<ParseTree<T>,ParseError>
+        where T: Clone,
+              F: Fn(&Peek<T>) -> Symbol {
+        self.parse_range(&tokens, None, &|i| classify(&Peek { tokens: &tokens[i..], limit }), &SubParsers::new(), None, None, &mut ParseSession::new())
+    }
+
+    // like parse, but an error doesn't have to end the parse: productions with the grammar's error
+    // symbol (see Grammar::with_error_symbol) say how to get past one, in the manner of yacc. at an
+    // error, states are popped until one of them can shift the error symbol. a node for it is
+    // shifted, holding the trees that were popped, and then tokens are skipped (and put in the
+    // node too) until one can follow it. the parse goes on from there. the errors are returned
+    // along with the tree, which there's only none of when there's no getting past an error: no
+    // state on the stack expects the error symbol, or the input ends while skipping
+    pub fn parse_recovering<T,F>(&self, tokens: Vec<T>, token_to_symbol: F) -> Recovery<T>
+        where T: Clone,
+              F: Fn(&T) -> Symbol {
+        self.parse_recovering_with_limit(tokens, token_to_symbol, usize::MAX)
+    }
+
+    // like parse_recovering, but once limit errors have been found the parse gives up at the next
+    // one, which is reported as TooManyErrors. input that's badly broken (or isn't in the language
+    // at all) can otherwise make an error every few tokens, all the way to the end
+    pub fn parse_recovering_with_limit<T,F>(&self, tokens: Vec<T>, token_to_symbol: F, limit: usize) -> Recovery<T>
+        where T: Clone,
+              F: Fn(&T) -> Symbol {
+        let eoi = self.grammar.symbol_db().eoi();
+        let mut session = ParseSession::new();
+        session.reset();
+        let mut errors = Vec::new();
+        let mut metrics = RecoveryMetrics::default();
+        let mut position = 0;
+        // the position of the last recovery, until a token is shifted after it
+        let mut recovered_at = None;
+        // the tokens shifted since the last error
+        let mut shifted = 0;
+        loop {
+            metrics.tokens = position;
+            let Some(token) = tokens.get(position) else {
+                errors.push(ParseError::UnexpectedEndOfInput { position });
+                return Recovery { tree: None, errors, metrics };
+            };
+            let symbol = token_to_symbol(token);
+            let state = *session.state_stack.last().unwrap();
+            let action = match self.parse_tables.default_reduction(state) {
+                Some(p) => Some(Action::Reduce(p)),
+                None => self.parse_tables.action(state, symbol).copied(),
+            };
+            match action {
+                Some(Action::Reduce(p)) => {
+                    if let Err(error) = self.reduce(p, token, symbol, position, &mut session) {
+                        errors.push(error);
+                        return Recovery { tree: None, errors, metrics };
+                    }
+                },
+                Some(Action::Shift(next_state)) => {
+                    session.parse_stack.push(ParseTree::new(symbol, token.clone()));
+                    session.state_stack.push(next_state);
+                    position += 1;
+                    recovered_at = None;
+                    shifted += 1;
+                },
+                Some(Action::Accept) => {
+                    return Recovery { tree: session.parse_stack.pop(), errors, metrics };
+                },
+                // the token seemed able to follow the error, but after the reductions it led to it
+                // can't. it's skipped as well, as part of the same error
+                None if recovered_at == Some(position) && symbol != eoi => {
+                    position += 1;
+                    metrics.tokens_skipped += 1;
+                },
+                None => {
+                    if errors.len() == limit {
+                        errors.push(ParseError::TooManyErrors { limit, position });
+                        return Recovery { tree: None, errors, metrics };
+                    }
+                    if errors.is_empty() || shifted >= CLUSTER_GAP {
+                        metrics.clusters += 1;
+                    }
+                    shifted = 0;
+                    errors.push(ParseError::UnexpectedSymbol { state, symbol, position });
+                    if !self.recover(&tokens, &token_to_symbol, &mut position, &mut session, &mut metrics) {
+                        return Recovery { tree: None, errors, metrics };
+                    }
+                    recovered_at = Some(position);
+                },
+            }
+        }
+    }
+
+    // get past the error at position (see parse_recovering), returning false if there's no way to
+    fn recover<T,F>(&self, tokens: &[T], token_to_symbol: &F, position: &mut usize, session: &mut ParseSession<T>, metrics: &mut RecoveryMetrics) -> bool
+        where T: Clone,
+              F: Fn(&T) -> Symbol {
+        let Some(error) = self.grammar.error_symbol() else {
+            return false;
+        };
+        let eoi = self.grammar.symbol_db().eoi();
+        let mut children = Vec::new();
+        let next_state = loop {
+            let state = *session.state_stack.last().unwrap();
+            if let Some(Action::Shift(next_state)) = self.parse_tables.action(state, error) {
+                break *next_state;
+            }
+            // the start state is the only one with no tree under it
+            let Some(tree) = session.parse_stack.pop() else {
+                return false;
+            };
+            session.state_stack.pop();
+            children.push(tree);
+        };
+        children.reverse();
+        metrics.trees_discarded += children.len();
+        let first = tokens[*position].clone();
+        while self.parse_tables.default_reduction(next_state).is_none() {
+            let Some(token) = tokens.get(*position) else {
+                return false;
+            };
+            let symbol = token_to_symbol(token);
+            if self.parse_tables.action(next_state, symbol).is_some() {
+                break;
+            }
+            if symbol == eoi {
+                return false;
+            }
+            children.push(ParseTree::new(symbol, token.clone()));
+            *position += 1;
+            metrics.tokens_skipped += 1;
+        }
+        // an error node with nothing in it covers nothing, like an ε production
+        let epsilon = children.is_empty();
+        session.parse_stack.push(ParseTree::from_parts(error, first, children, epsilon, None));
+        session.state_stack.push(next_state);
+        metrics.repairs += 1;
+        true
+    }
+
+    // find every run of tokens that parses as a complete instance of the start symbol, skipping
+    // over anything that doesn't. the tokens don't need to end with an end of input token. at
+    // each position the longest island is taken, and the search resumes after it
+    pub fn parse_islands<T,F>(&self, tokens: &[T], token_to_symbol: F) -> Vec<Island<T>>
+        where T: Clone,
+              F: Fn(&T) -> Symbol {
+        let mut result = Vec::new();
+        let mut start = 0;
+        while start < tokens.len() {
+            match self.longest_island(&tokens[start..], &token_to_symbol) {
+                Some(length) => {
+                    let end = start + length;
+                    let island = &tokens[start..end];
+                    let mut session = ParseSession::new();
+                    if let Ok(tree) = self.parse_range(island, Some(length), &|i| token_to_symbol(&island[i]), &SubParsers::new(), None, None, &mut session) {
+                        result.push(Island { start, end, tree });
+                    }
+                    start = end;
+                },
+                None => start += 1,
+            }
+        }
+        result
+    }
+
+    // run the automaton (without building a tree) over as many tokens as it will take, and return
+    // the largest number of tokens after which the end of input would be accepted
+    fn longest_island<T,F>(&self, tokens: &[T], token_to_symbol: &F) -> Option<usize>
+        where F: Fn(&T) -> Symbol {
+        let mut state_stack: Vec<u32> = vec![0];
+        let mut longest = None;
+        let mut position = 0;
+        while position < tokens.len() {
+            let symbol = token_to_symbol(&tokens[position]);
+            let state = *state_stack.last().unwrap();
+            match self.parse_tables.action(state, symbol) {
+                Some(Action::Reduce(p)) => {
+                    if !self.reduce_states(&mut state_stack, *p) {
+                        break;
+                    }
+                },
+                Some(Action::Shift(next_state)) => {
+                    state_stack.push(*next_state);
+                    position += 1;
+                    if self.accepts_end_of_input(&state_stack) {
+                        longest = Some(position);
+                    }
+                },
+                _ => break,
+            }
+        }
+        longest
+    }
+
+    // would the end of input be accepted if it came next?
+    fn accepts_end_of_input(&self, state_stack: &[u32]) -> bool {
+        let eoi = self.grammar.symbol_db().eoi();
+        let mut state_stack = state_stack.to_vec();
+        loop {
+            let state = *state_stack.last().unwrap();
+            match self.parse_tables.action(state, eoi) {
+                Some(Action::Accept) => return true,
+                Some(Action::Reduce(p)) => {
+                    if !self.reduce_states(&mut state_stack, *p) {
+                        return false;
+                    }
+                },
+                _ => return false,
+            }
+        }
+    }
+
+    // apply a reduction to a bare state stack
+    fn reduce_states(&self, state_stack: &mut Vec<u32>, p: ProductionId) -> bool {
+        let state = *state_stack.last().unwrap();
+        let Ok((lhs, start)) = self.handle(p, state_stack.len() - 1, state, self.grammar.symbol_db().eoi(), 0) else {
+            return false;
+        };
+        state_stack.truncate(start + 1);
+        match self.parse_tables.transition(*state_stack.last().unwrap(), lhs) {
+            Some(next_state) => {
+                state_stack.push(*next_state);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // parse tokens as they are pulled from an iterator, without collecting them first. the last
+    // token must map to the end of input symbol
+    pub fn parse_iter<T,I,F>(&self, tokens: I, token_to_symbol: F) -> Result<ParseTree<T>,ParseError>
+        where T: Clone,
+              I: IntoIterator<Item=T>,
+              F: Fn(&T) -> Symbol {
+        self.parse_symbols(tokens.into_iter().map(|token| {
+            let symbol = token_to_symbol(&token);
+            (token, symbol)
+        }))
+    }
+
+    // parse_iter with the lookahead of parse_peeking. up to limit tokens after the one being
+    // classified are pulled from the iterator ahead of time and held until the parser gets to them
+    pub fn parse_iter_peeking<T,I,F>(&self, tokens: I, limit: usize, classify: F) -> Result<ParseTree<T>,ParseError>
+        where T: Clone,
+              I: IntoIterator<Item=T>,
+              F: Fn(&Peek<T>) -> Symbol {
+        let mut iter = tokens.into_iter();
+        let mut window: VecDeque<T> = VecDeque::with_capacity(limit + 1);
+        self.parse_symbols(std::iter::from_fn(|| {
+            window.extend(iter.by_ref().take(limit + 1 - window.len()));
+            if window.is_empty() {
+                return None;
+            }
+            let symbol = classify(&Peek { tokens: window.make_contiguous(), limit });
+            window.pop_front().map(|token| (token, symbol))
+        }))
+    }
+
+    // the parse loop of parse_iter, for tokens that come with their symbols
+    fn parse_symbols<T,I>(&self, tokens: I) -> Result<ParseTree<T>,ParseError>
+        where T: Clone,
+              I: Iterator<Item=(T,Symbol)> {
+
+        let mut session = ParseSession::new();
+        session.reset();
+
+        let mut iter = tokens;
+        let mut position = 0;
+
+        let (mut token, mut symbol) = iter.next().ok_or(ParseError::UnexpectedEndOfInput { position })?;
+
+        loop {
+            let state = *session.state_stack.last().unwrap();
+
+            if let Some(p) = self.parse_tables.default_reduction(state) {
+                self.reduce(p, &token, symbol, position, &mut session)?;
+                continue;
+            }
+
+            match self.parse_tables.action(state, symbol) {
+                Some(Action::Reduce(p)) => {
+                    self.reduce(*p, &token, symbol, position, &mut session)?;
+                },
+                Some(Action::Shift(next_state)) => {
+                    let (next, next_symbol) = iter.next().ok_or(ParseError::UnexpectedEndOfInput { position: position + 1 })?;
+                    session.parse_stack.push(ParseTree::new(symbol, std::mem::replace(&mut token, next)));
+                    session.state_stack.push(*next_state);
+                    position += 1;
+                    symbol = next_symbol;
+                },
+                Some(Action::Accept) => {
+                    break;
+                },
+                None => {
+                    return Err(ParseError::UnexpectedSymbol { state, symbol, position });
+                }
+            }
+        }
+
+        Ok(session.parse_stack.pop().unwrap())
+    }
+
+    // lex and parse from a reader, one token at a time. lex is called repeatedly with a buffered
+    // view of the reader and returns the next token, or None once the input is exhausted. like
+    // the token vector given to parse, the tokens it produces must end with one that maps to the
+    // end of input symbol. an error from the reader or the lexer is returned as the outer error
+    pub fn parse_reader<R,T,L,F>(&self, reader: R, mut lex: L, token_to_symbol: F) -> io::Result<Result<ParseTree<T>,ParseError>>
+        where R: io::Read,
+              T: Clone,
+              L: FnMut(&mut dyn io::BufRead) -> io::Result<Option<T>>,
+              F: Fn(&T) -> Symbol {
+        let mut reader = io::BufReader::new(reader);
+        let mut error = None;
+        let tokens = std::iter::from_fn(|| {
+            match lex(&mut reader) {
+                Ok(token) => token,
+                Err(e) => {
+                    error = Some(e);
+                    None
+                }
+            }
+        });
+        let result = self.parse_iter(tokens, token_to_symbol);
+        match error {
+            Some(e) => Err(e),
+            None => Ok(result),
+        }
+    }
+
+    // pop the right hand side of the production off of the stacks, and push a new tree for the
+    // production's lhs along with the state to go to after the reduction. symbol is the lookahead
+    fn reduce<T: Clone>(&self, id: ProductionId, token: &T, symbol: Symbol, position: usize, session: &mut ParseSession<T>) -> Result<(),ParseError> {
+        let state = *session.state_stack.last().unwrap();
+        let (lhs, start) = self.handle(id, session.parse_stack.len(), state, symbol, position)?;
+
+        // the children are the top size trees on the stack, already in order, so move them over in
+        // one go into a vector of exactly the right size
+        let children: Vec<ParseTree<T>> = session.parse_stack.drain(start..).collect();
+        session.state_stack.truncate(start + 1);
+
+        session.parse_stack.push(ParseTree::reduced(lhs, token.clone(), children, id, &self.grammar));
+        session.record(|stats| stats.reductions += 1);
+        let current_state = *session.state_stack.last().unwrap();
+        let next_state = match self.parse_tables.transition(current_state, lhs) {
+            Some(next_state) => next_state,
+            None => {
+                session.record(|stats| stats.table_misses += 1);
+                return Err(ParseError::InternalError { state: current_state, symbol: lhs, position });
+            },
+        };
+        session.state_stack.push(*next_state);
+        Ok(())
+    }
+
+    // the left hand side of the production and where its right hand side starts on a stack of depth
+    // trees. the tables were built so that this can't fail. if it does, the tables are broken (e.g.
+    // they were corrupted after being built, or built for another grammar) and there's no
+    // sensible way to keep going
+    pub(crate) fn handle(&self, id: ProductionId, depth: usize, state: u32, symbol: Symbol, position: usize) -> Result<(Symbol,usize),ParseError> {
+        let Some(p) = self.grammar.all_productions().get(id.index()) else {
+            return Err(ParseError::InternalError { state, symbol, position });
+        };
+        // there's one more state than there are trees, so the start state is never popped
+        match depth.checked_sub(p.rhs().len()) {
+            Some(start) => Ok((*p.lhs(), start)),
+            None => Err(ParseError::InternalError { state, symbol: *p.lhs(), position }),
+        }
+    }
+
+    // the parse loop, reporting the error it ends with (if it does) to the observers
+    #[allow(clippy::too_many_arguments)]
+    fn parse_range<T,F>(&self,
+                        tokens: &[T],
+                        end_of_input: Option<usize>,
+                        symbol_at: &F,
+                        sub_parsers: &SubParsers<T>,
+                        operators: Option<&mut DynamicOperators<T>>,
+                        observers: Option<&mut ParseObservers<T>>,
+                        session: &mut ParseSession<T>) -> Result<ParseTree<T>,ParseError>
+        where T: Clone,
+              F: Fn(usize) -> Symbol {
+        let Some(observers) = observers else {
+            return self.run(tokens, end_of_input, symbol_at, sub_parsers, operators, None, session);
+        };
+        let result = self.run(tokens, end_of_input, symbol_at, sub_parsers, operators, Some(&mut *observers), session);
+        if let Err(error) = &result {
+            let stack = ParseStack { states: &session.state_stack, trees: &session.parse_stack };
+            for observer in &mut observers.observers {
+                observer.error(&stack, error, tokens.get(error.position()));
+            }
+        }
+        result
+    }
+
+    // if end_of_input is given, the token at that position (and anything after it) is ignored and
+    // the end of input symbol is used in its place. symbol_at gives the symbol of the token at a
+    // position
+    #[allow(clippy::too_many_arguments)]
+    fn run<T,F>(&self,
+                tokens: &[T],
+                end_of_input: Option<usize>,
+                symbol_at: &F,
+                sub_parsers: &SubParsers<T>,
+                mut operators: Option<&mut DynamicOperators<T>>,
+                mut observers: Option<&mut ParseObservers<T>>,
+                session: &mut ParseSession<T>) -> Result<ParseTree<T>,ParseError>
+        where T: Clone,
+              F: Fn(usize) -> Symbol {
+
+        let next = |position: usize| -> Result<(&T,Symbol),ParseError> {
+            match end_of_input {
+                Some(end) if position >= end && end > 0 => {
+                    Ok((&tokens[end - 1], self.grammar.symbol_db().eoi()))
+                },
+                _ => {
+                    let token = tokens.get(position).ok_or(ParseError::UnexpectedEndOfInput { position })?;
+                    Ok((token, symbol_at(position)))
+                }
+            }
+        };
+
+        session.reset();
+
+        let mut position = 0;
+
+        let (mut token, mut symbol) = next(position)?;
+
+        // the handlers of the sub-parsers for each state that can take their nonterminals
+        let dispatch = sub_parsers.dispatch(&self.parse_tables);
+
+        'parse: loop {
+            let state = *session.state_stack.last().unwrap();
+
+            let depth = session.state_stack.len();
+            session.record(|stats| stats.visit(state, depth));
+
+            // a state that can only reduce by one production doesn't need the action table. this
+            // also runs whole chains of such reductions without a lookup per step. a state with
+            // sub-parsers tries them first, so it isn't taken there
+            let default_reduction = match dispatch.contains_key(&state) {
+                true => None,
+                false => self.parse_tables.default_reduction(state),
+            };
+            if let Some(p) = default_reduction {
+                session.record(|stats| stats.default_reductions += 1);
+                self.reduce(p, token, symbol, position, session)?;
+                if let Some(operators) = &mut operators {
+                    operators.reduced(session.parse_stack.last().unwrap());
+                }
+                if let Some(observers) = &mut observers {
+                    observers.reduced(session, self.grammar.production(p), token);
+                }
+                continue;
+            }
+
+            for (nonterminal, next_state, handler) in dispatch.get(&state).into_iter().flatten() {
+                let Some((child, consumed)) = handler(&tokens[position..]).filter(|(_, consumed)| *consumed > 0) else {
+                    continue;
+                };
+                let mut t = ParseTree::new(*nonterminal, token.clone());
+                t.add_child(child);
+                position += consumed;
+                session.parse_stack.push(t);
+                session.state_stack.push(*next_state);
+                session.record(|stats| stats.shifts += 1);
+                if let Some(observers) = &mut observers {
+                    observers.shifted(session, token);
+                }
+                (token, symbol) = next(position)?;
+                continue 'parse;
+            }
+
+            let mut action = self.parse_tables.action(state, symbol).copied();
+            // precedence only weighs the shift against a single handle, so a cell where more than
+            // one reduction competes with the shift is left to the table
+            if let (Some(operators), Some([p])) = (&operators, self.conflicts.get(&(state, symbol)).map(Vec::as_slice)) {
+                match self.resolve(*p, token, operators, session, state, symbol, position)? {
+                    Some(Resolution::Reduce) => action = Some(Action::Reduce(*p)),
+                    Some(Resolution::Error) => return Err(ParseError::UnexpectedSymbol { state, symbol, position }),
+                    _ => {},
+                }
+            }
+
+            if let Some(action) = action {
+                //let s = self.grammar.symbol_db().label(&symbol).unwrap();
+                //println!("{}, state: {}, action: {}", s, state, action.display(self.grammar));
+                match action {
+                    Action::Reduce(p) => {
+                        self.reduce(p, token, symbol, position, session)?;
+                        if let Some(operators) = &mut operators {
+                            operators.reduced(session.parse_stack.last().unwrap());
+                        }
+                        if let Some(observers) = &mut observers {
+                            observers.reduced(session, self.grammar.production(p), token);
+                        }
+                    },
+                    Action::Shift(next_state) => {
+                        session.parse_stack.push(ParseTree::new(symbol, token.clone()));
+                        session.state_stack.push(next_state);
+                        position += 1;
+                        session.record(|stats| stats.shifts += 1);
+                        if let Some(observers) = &mut observers {
+                            observers.shifted(session, token);
+                        }
+                        (token, symbol) = next(position)?;
+                    },
+                    Action::Accept => {
+                        break;
+                    }
+                }
+            } else {
+                session.record(|stats| stats.table_misses += 1);
+                return Err(ParseError::UnexpectedSymbol { state, symbol, position });
+            }
+        }
+
+        Ok(session.parse_stack.pop().unwrap())
+    }
+
+    // settle a shift/reduce conflict between the lookahead and reducing by p, whose handle is on
+    // top of the parse stack, using the operator of the last terminal in the handle
+    #[allow(clippy::too_many_arguments)]
+    fn resolve<T>(&self,
+                  p: ProductionId,
+                  lookahead: &T,
+                  operators: &DynamicOperators<T>,
+                  session: &ParseSession<T>,
+                  state: u32,
+                  symbol: Symbol,
+                  position: usize) -> Result<Option<Resolution>,ParseError> {
+        let (_, start) = self.handle(p, session.parse_stack.len(), state, symbol, position)?;
+        let handle = &session.parse_stack[start..];
+        let symbol_db = self.grammar.symbol_db();
+        let Some(operator) = handle.iter().rev().find(|t| symbol_db.is_terminal(t.symbol())) else {
+            return Ok(None);
+        };
+        Ok(operators.resolve(operator.token(), lookahead))
+    }
+}
+
+// the cells of the action table where a shift was kept over a reduction, and every production
+// that was dropped for the shift or for another reduction in the cell
+fn shift_reduce_conflicts(parse_tables: &ParseTables) -> HashMap<(u32,Symbol),Vec<ProductionId>> {
+    let mut cells: HashMap<(u32,Symbol),Vec<ProductionId>> = HashMap::new();
+    for c in parse_tables.conflicts() {
+        if let Conflict::ShiftReduce { state, symbol, .. } = c {
+            cells.entry((*state, *symbol)).or_default();
+        }
+    }
+    for c in parse_tables.conflicts() {
+        let (cell, productions) = match c {
+            Conflict::ShiftReduce { state, symbol, production } => ((*state, *symbol), vec![*production]),
+            Conflict::ReduceReduce { state, symbol, kept, dropped } => ((*state, *symbol), vec![*kept, *dropped]),
+        };
+        if let Some(candidates) = cells.get_mut(&cell) {
+            for p in productions {
+                if !candidates.contains(&p) {
+                    candidates.push(p);
+                }
+            }
+        }
+    }
+    cells
+}
+
+// a parser that isn't built until it's first used, for declaring one as a static:
+//
+//     static PARSER: LazyParser = LazyParser::new(|| Grammar::from_bnf(GRAMMAR, "grammar").unwrap());
+//
+// the grammar is made and the tables built once, by whichever thread gets to it first. any others
+// that get there at the same time wait for it rather than building their own
+pub struct LazyParser {
+    parser: OnceLock<Parser>,
+    grammar: fn() -> Grammar,
+}
+
+impl LazyParser {
+    pub const fn new(grammar: fn() -> Grammar) -> LazyParser {
+        LazyParser { parser: OnceLock::new(), grammar }
+    }
+
+    pub fn get(&self) -> &Parser {
+        self.parser.get_or_init(|| Parser::new((self.grammar)()))
+    }
+
+    // whether the parser has been built yet
+    pub fn is_initialized(&self) -> bool {
+        self.parser.get().is_some()
+    }
+}
+
+impl Deref for LazyParser {
+    type Target = Parser;
+
+    fn deref(&self) -> &Parser {
+        self.get()
+    }
+}
+
+// the stacks used while parsing. parse allocates a fresh set for every call;
+// holding on to a session and parsing through it instead lets the buffers be reused, which
+// matters when parsing a very large number of small inputs
+pub struct ParseSession<T> {
+    parse_stack: Vec<ParseTree<T>>,
+    state_stack: Vec<u32>,
+    symbols: Vec<Symbol>,
+    stats: Option<ParseStats>,
+}
+
+impl<T> ParseSession<T> {
+    pub fn new() -> ParseSession<T> {
+        ParseSession {
+            parse_stack: Vec::new(),
+            state_stack: Vec::new(),
+            symbols: Vec::new(),
+            stats: None,
+        }
+    }
+
+    // start counting what each parse through this session does. the counts cover the most recent
+    // parse only
+    pub fn enable_stats(&mut self) {
+        self.stats.get_or_insert_with(ParseStats::default);
+    }
+
+    pub fn stats(&self) -> Option<&ParseStats> {
+        self.stats.as_ref()
+    }
+
+    fn record<G: FnOnce(&mut ParseStats)>(&mut self, g: G) {
+        if let Some(stats) = &mut self.stats {
+            g(stats);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.parse_stack.clear();
+        self.state_stack.clear();
+        self.state_stack.push(0);
+        self.record(|stats| stats.reset());
+    }
+}
+
+impl<T: Clone> ParseSession<T> {
+    pub fn parse<F>(&mut self, parser: &Parser, tokens: &[T], token_to_symbol: F) -> Result<ParseTree<T>,ParseError>
+        where F: Fn(&T) -> Symbol {
+        parser.parse_range(tokens, None, &|i| token_to_symbol(&tokens[i]), &SubParsers::new(), None, None, self)
+    }
+
+    // like parse, but all the tokens are classified up front by a single call to classify, which
+    // must push exactly one symbol per token onto the (empty) vector it's given. the vector is
+    // kept in the session and reused, and classifying the whole slice at once leaves room for
+    // batched or vectorized classification
+    pub fn parse_classified<F>(&mut self, parser: &Parser, tokens: &[T], classify: F) -> Result<ParseTree<T>,ParseError>
+        where F: FnOnce(&[T], &mut Vec<Symbol>) {
+        let mut symbols = std::mem::take(&mut self.symbols);
+        symbols.clear();
+        classify(tokens, &mut symbols);
+        let result = match symbols.len() == tokens.len() {
+            true => parser.parse_range(tokens, None, &|i| symbols[i], &SubParsers::new(), None, None, self),
+            false => Err(ParseError::ClassifiedTokens { tokens: tokens.len(), symbols: symbols.len() }),
+        };
+        self.symbols = symbols;
+        result
+    }
+}
+
+// counters for a single parse run, for benchmarks and for spotting grammars that make the parser do
+// a lot of work
+#[derive(Clone,Debug,Default)]
+pub struct ParseStats {
+    pub shifts: usize,
+    pub reductions: usize,
+    // the reductions (included in reductions) made through a state's default reduction, without
+    // an action lookup
+    pub default_reductions: usize,
+    // action and goto lookups that found nothing, which end the parse
+    pub table_misses: usize,
+    pub max_stack_depth: usize,
+    // the number of distinct states the parser was in
+    pub states_visited: usize,
+    visited: Vec<bool>,
+}
+
+impl ParseStats {
+    fn reset(&mut self) {
+        let mut visited = std::mem::take(&mut self.visited);
+        visited.clear();
+        *self = ParseStats { visited, ..ParseStats::default() };
+    }
+
+    fn visit(&mut self, state: u32, depth: usize) {
+        let state = state as usize;
+        if state >= self.visited.len() {
+            self.visited.resize(state + 1, false);
+        }
+        if !self.visited[state] {
+            self.visited[state] = true;
+            self.states_visited += 1;
+        }
+        self.max_stack_depth = self.max_stack_depth.max(depth);
+    }
+}
+
+impl<T> Default for ParseSession<T> {
+    fn default() -> ParseSession<T> {
+        ParseSession::new()
+    }
+}
+
+// what Parser::parse_recovering made of the input: the tree, if it got to the end, and the errors
+// in the order they were found. the last error is the one that stopped the parse, if it stopped
+#[derive(Debug)]
+pub struct Recovery<T> {
+    pub tree: Option<ParseTree<T>>,
+    pub errors: Vec<ParseError>,
+    pub metrics: RecoveryMetrics,
+}
+
+// the tokens that have to be shifted after an error before another one counts as a new cluster
+const CLUSTER_GAP: usize = 3;
+
+// how much a recovering parse had to make up to get to the end, for judging how far its tree can
+// be trusted. an error soon after another is usually a consequence of it, or of how the parser
+// got past it, so the errors are also counted in clusters: a new cluster starts with an error
+// that comes after at least three tokens have been shifted without one
+#[derive(Clone,Debug,Default,Eq,PartialEq)]
+pub struct RecoveryMetrics {
+    // the tokens the parse got through, skipped ones included
+    pub tokens: usize,
+    // the tokens put in error nodes rather than parsed
+    pub tokens_skipped: usize,
+    // the trees already parsed that were popped off the stack into error nodes
+    pub trees_discarded: usize,
+    // the errors the parse got past, each by shifting an error node
+    pub repairs: usize,
+    pub clusters: usize,
+}
+
+impl RecoveryMetrics {
+    // the share of the tokens that were skipped, from 0 for a parse without errors to 1
+    pub fn skipped_fraction(&self) -> f64 {
+        if self.tokens == 0 {
+            return 0.0;
+        }
+        self.tokens_skipped as f64 / self.tokens as f64
+    }
+}
+
+// a run of tokens[start..end] that parses as the start symbol
+#[derive(Debug)]
+pub struct Island<T> {
+    pub start: usize,
+    pub end: usize,
+    pub tree: ParseTree<T>,
+}
+
+// a token being classified by Parser::parse_peeking, with a limited view of the tokens after it
+pub struct Peek<'t,T> {
+    // the token and (at least) the ones after it that are in view
+    tokens: &'t [T],
+    limit: usize,
+}
+
+impl<'t,T> Peek<'t,T> {
+    pub fn token(&self) -> &'t T {
+        &self.tokens[0]
+    }
+
+    // the kth token after this one, where peek(1) is the next, or None past the end of the tokens.
+    // k can't be more than the limit the parse was given
+    pub fn peek(&self, k: usize) -> Option<&'t T> {
+        assert!(k <= self.limit, "can't peek {} tokens ahead with a limit of {}", k, self.limit);
+        self.tokens.get(k)
+    }
+}
+
+// the stacks of a parse in progress, as an observer sees them. the trees are the ones for the
+// symbols shifted or reduced so far, and the states are the ones the parser went to after each of
+// them, on top of the start state (so there's always one more state than there are trees)
+pub struct ParseStack<'s,T> {
+    states: &'s [u32],
+    trees: &'s [ParseTree<T>],
+}
+
+impl<'s,T> ParseStack<'s,T> {
+    pub fn states(&self) -> &'s [u32] {
+        self.states
+    }
+
+    pub fn trees(&self) -> &'s [ParseTree<T>] {
+        self.trees
+    }
+}
+
+// something that watches a parse step by step. each method is called after the step, and does
+// nothing unless it's overridden
+pub trait ParseObserver<T> {
+    // the token was shifted, and its tree is on top of the stack
+    fn shift(&mut self, _stack: &ParseStack<T>, _token: &T) {}
+
+    // the trees for the right hand side of the production were replaced by one for its left hand
+    // side. token is the lookahead
+    fn reduce(&mut self, _stack: &ParseStack<T>, _production: &Production, _token: &T) {}
+
+    // the parse failed. token is the one it failed at, unless the tokens ran out
+    fn error(&mut self, _stack: &ParseStack<T>, _error: &ParseError, _token: Option<&T>) {}
+}
+
+// so that an observer can be lent to a parse, and looked at afterwards
+impl<T,O: ParseObserver<T> + ?Sized> ParseObserver<T> for &mut O {
+    fn shift(&mut self, stack: &ParseStack<T>, token: &T) {
+        (**self).shift(stack, token);
+    }
+
+    fn reduce(&mut self, stack: &ParseStack<T>, production: &Production, token: &T) {
+        (**self).reduce(stack, production, token);
+    }
+
+    fn error(&mut self, stack: &ParseStack<T>, error: &ParseError, token: Option<&T>) {
+        (**self).error(stack, error, token);
+    }
+}
+
+// the observers of a parse (see Parser::parse_observed), called in the order they're registered
+pub struct ParseObservers<'a,T> {
+    observers: Vec<Box<dyn ParseObserver<T> + 'a>>,
+}
+
+impl<'a,T> ParseObservers<'a,T> {
+    pub fn new() -> ParseObservers<'a,T> {
+        ParseObservers { observers: Vec::new() }
+    }
+
+    pub fn register<O>(&mut self, observer: O) where O: ParseObserver<T> + 'a {
+        self.observers.push(Box::new(observer));
+    }
+
+    fn shifted(&mut self, session: &ParseSession<T>, token: &T) {
+        let stack = ParseStack { states: &session.state_stack, trees: &session.parse_stack };
+        for observer in &mut self.observers {
+            observer.shift(&stack, token);
+        }
+    }
+
+    fn reduced(&mut self, session: &ParseSession<T>, production: &Production, token: &T) {
+        let stack = ParseStack { states: &session.state_stack, trees: &session.parse_stack };
+        for observer in &mut self.observers {
+            observer.reduce(&stack, production, token);
+        }
+    }
+}
+
+impl<'a,T> Default for ParseObservers<'a,T> {
+    fn default() -> ParseObservers<'a,T> {
+        ParseObservers::new()
+    }
+}
+
+// handlers for embedded languages. each one is keyed by a nonterminal of the host grammar that
+// stands for the embedded region, and is tried in the states that can take the nonterminal next
+// (those with a goto on it). the nonterminal doesn't need productions of its own, but without them
+// the host grammar has no lookahead for it, so nothing in front of it can be reduced on the way:
+// it has to come right after a terminal or at the start. a handler is given the remaining tokens
+// and returns the tree for the embedded region and the number of tokens it covers, or None if the
+// tokens don't start an embedded region. a region has to cover at least one token
+type SubParser<'a,T> = Box<dyn Fn(&[T]) -> Option<(ParseTree<T>,usize)> + 'a>;
+
+// the handlers that apply in each state, with their nonterminals and the states they go to
+type Dispatch<'s,'a,T> = HashMap<u32,Vec<(Symbol,u32,&'s SubParser<'a,T>)>>;
+
+pub struct SubParsers<'a,T> {
+    handlers: Vec<(Symbol,SubParser<'a,T>)>,
+}
+
+impl<'a,T> SubParsers<'a,T> {
+    pub fn new() -> SubParsers<'a,T> {
+        SubParsers { handlers: Vec::new() }
+    }
+
+    pub fn register<F>(&mut self, nonterminal: Symbol, handler: F)
+        where F: Fn(&[T]) -> Option<(ParseTree<T>,usize)> + 'a {
+        self.handlers.push((nonterminal, Box::new(handler)));
+    }
+
+    // the handlers that apply in each state, along with the state to go to after their region,
+    // in the order they were registered
+    fn dispatch(&self, parse_tables: &ParseTables) -> Dispatch<'_,'a,T> {
+        let mut result: Dispatch<'_,'a,T> = HashMap::new();
+        if self.handlers.is_empty() {
+            return result;
+        }
+        for ((state, symbol), next_state) in parse_tables.transitions() {
+            for (nonterminal, handler) in &self.handlers {
+                if nonterminal == symbol {
+                    result.entry(*state).or_default().push((*nonterminal, *next_state, handler));
+                }
+            }
+        }
+        // the transitions come in no particular order
+        for handlers in result.values_mut() {
+            handlers.sort_by_key(|(nonterminal, _, _)| self.handlers.iter().position(|(n, _)| n == nonterminal));
+        }
+        result
+    }
+}
+
+impl<'a,T> Default for SubParsers<'a,T> {
+    fn default() -> SubParsers<'a,T> {
+        SubParsers::new()
+    }
+}
+
+// cargo test -- --nocapture
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use super::*;
+    use crate::operator_table::OperatorTable;
+    use crate::production::Production;
+    use crate::push_parser::PushParser;
+    use crate::symbol::{SymbolDb};
+
+    #[derive(Clone,Debug,PartialEq)]
+    enum Token {
+        ParenLeft,
+        ParenRight,
+        Identifier,
+        EndOfFile,
+    }
+
+    #[test]
+    fn test01() {
+        let mut symbol_db = SymbolDb::new();
+        /* grammar:
+         *   e1 -> ( e1 ) | ε
+         */
+        let e1 = symbol_db.new_nonterminal("E1");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(e1, vec![lp, e1, rp]),
+            Production::new(e1, vec![])
+        ];
+        let g = Grammar::new(symbol_db, e1, productions);
+        use Token::*;
+        let ttos = |token: &Token| {
+            match token {
+                ParenLeft => lp,
+                ParenRight => rp,
+                EndOfFile => eoi,
+                _ => eoi,
+            }
+        };
+        let p = Parser::new(g);
+        assert!(p.parse(vec![ParenLeft, ParenLeft, ParenRight, ParenRight, EndOfFile], ttos).is_ok());
+    }
+
+    #[test]
+    fn test02() {
+        let mut symbol_db = SymbolDb::new();
+        /* grammar:
+         *   e1 -> id | e2
+         *   e2 -> ( e3 )
+         *   e3 -> e1 e3 | ε
+         */
+        let e1 = symbol_db.new_nonterminal("E1");
+        let e2 = symbol_db.new_nonterminal("E2");
+        let e3 = symbol_db.new_nonterminal("E3");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let id = symbol_db.new_terminal("id");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(e1, vec![id]),
+            Production::new(e1, vec![e2]),
+            Production::new(e2, vec![lp, e3, rp]),
+            Production::new(e3, vec![e1, e3]),
+            Production::new(e3, vec![]),
+        ];
+        let g = Grammar::new(symbol_db, e1, productions);
+
+        let ttos = |token: &Token| {
+            match token {
+                ParenLeft => lp,
+                ParenRight => rp,
+                Identifier => id,
+                EndOfFile => eoi,
+            }
+        };
+
+        let p = Parser::new(g);
+
+        use Token::*;
+
+        assert!(p.parse(vec![Identifier, EndOfFile], ttos).is_ok());
+        assert!(p.parse(vec![ParenLeft, Identifier, ParenRight, EndOfFile], ttos).is_ok());
+        assert!(p.parse(vec![ParenLeft, Identifier, Identifier, ParenRight, EndOfFile], ttos).is_ok());
+        assert!(p.parse(vec![ParenLeft, Identifier, ParenLeft, Identifier, ParenRight, ParenRight, EndOfFile], ttos).is_ok());
+    }
+
+    #[test]
+    fn test03() {
+        let mut symbol_db = SymbolDb::new();
+        /* grammar:
+         *   e1 -> ( e1 ) | ε
+         */
+        let e1 = symbol_db.new_nonterminal("E1");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(e1, vec![lp, e1, rp]),
+            Production::new(e1, vec![])
+        ];
+        let g = Grammar::new(symbol_db, e1, productions);
+        use Token::*;
+        let ttos = |token: &Token| {
+            match token {
+                ParenLeft => lp,
+                ParenRight => rp,
+                _ => eoi,
+            }
+        };
+        let p = Parser::new(g);
+        let result = p.parse(vec![ParenLeft, ParenRight, ParenRight, EndOfFile], ttos);
+        match result {
+            Err(ParseError::UnexpectedSymbol { symbol, position, .. }) => {
+                assert_eq!(symbol, rp);
+                assert_eq!(position, 2);
+            },
+            _ => panic!("expected a parse error"),
+        }
+        let result = p.parse(vec![ParenLeft, ParenRight], ttos);
+        assert_eq!(result.err(), Some(ParseError::UnexpectedEndOfInput { position: 2 }));
+    }
+
+    #[test]
+    fn test04() {
+        /* inner grammar:
+         *   list -> list id | id
+         */
+        let mut inner_db = SymbolDb::new();
+        let list = inner_db.new_nonterminal("list");
+        let inner_id = inner_db.new_terminal("id");
+        let inner_eoi = inner_db.eoi();
+        let productions = vec![
+            Production::new(list, vec![list, inner_id]),
+            Production::new(list, vec![inner_id]),
+        ];
+        let inner = Parser::new(Grammar::new(inner_db, list, productions));
+
+        /* host grammar, where EMBEDDED is a nonterminal with no productions:
+         *   s -> ( EMBEDDED ) | id
+         */
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let id = symbol_db.new_terminal("id");
+        let embedded = symbol_db.new_nonterminal("EMBEDDED");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(s, vec![lp, embedded, rp]),
+            Production::new(s, vec![id]),
+        ];
+        let host = Parser::new(Grammar::new(symbol_db, s, productions));
+
+        use Token::*;
+        let ttos = |token: &Token| {
+            match token {
+                ParenLeft => lp,
+                ParenRight => rp,
+                Identifier => id,
+                EndOfFile => eoi,
+            }
+        };
 
-impl Parser {
-    pub fn new(grammar: Grammar) -> Parser {
-        let parse_tables = ParseTables::new(&grammar);
-        //println!("{}", parse_tables.to_string(grammar.symbol_db()));
-        Parser { grammar, parse_tables }
+        // the embedded region runs up to the next closing paren
+        let mut sub_parsers = SubParsers::new();
+        sub_parsers.register(embedded, |tokens: &[Token]| {
+            let end = tokens.iter().position(|t| matches!(t, ParenRight))?;
+            let mut region = tokens[..end].to_vec();
+            region.push(EndOfFile);
+            let tree = inner.parse(region, |t: &Token| {
+                match t {
+                    Identifier => inner_id,
+                    _ => inner_eoi,
+                }
+            }).ok()?;
+            Some((tree, end))
+        });
+
+        let tokens = vec![ParenLeft, Identifier, Identifier, Identifier, ParenRight, EndOfFile];
+        let tree = host.parse_with(tokens, ttos, &sub_parsers).unwrap();
+        assert_eq!(tree.children().len(), 3);
+        let region = &tree.children()[1];
+        assert_eq!(*region.symbol(), embedded);
+        assert_eq!(region.children().len(), 1);
+        assert_eq!(*region.children()[0].symbol(), list);
+
+        // without the sub parser the host grammar can't handle the embedded region
+        let tokens = vec![ParenLeft, Identifier, ParenRight, EndOfFile];
+        assert!(host.parse(tokens.clone(), ttos).is_err());
+        assert!(host.parse_with(tokens, ttos, &sub_parsers).is_ok());
+        assert!(host.parse_with(vec![Identifier, EndOfFile], ttos, &sub_parsers).is_ok());
+
+        // the states where the nonterminal can't come aren't table misses
+        let mut session = ParseSession::new();
+        session.enable_stats();
+        let tokens = vec![ParenLeft, Identifier, ParenRight, EndOfFile];
+        host.parse_range(&tokens, None, &|i| ttos(&tokens[i]), &sub_parsers, None, None, &mut session).unwrap();
+        assert_eq!(session.stats().unwrap().table_misses, 0);
     }
 
-    pub fn parse<T,F>(&self, tokens: Vec<T>, token_to_symbol: F) -> Option<ParseTree<T>>
-        where T: Clone,
-              F: Fn(&T) -> Symbol {
+    /* grammar:
+     *   S -> S ; R | R
+     *   R -> x
+     */
+    #[test]
+    fn sub_parsers_01() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let r = symbol_db.new_nonterminal("R");
+        let semi = symbol_db.new_terminal(";");
+        let x = symbol_db.new_terminal("x");
+        // not in the grammar at all, only in the regions the handler takes
+        let y = symbol_db.new_terminal("y");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(s, vec![s, semi, r]),
+            Production::new(s, vec![r]),
+            Production::new(r, vec![x]),
+        ];
+        let p = Parser::new(Grammar::new(symbol_db, s, productions));
+        let db = p.grammar().symbol_db();
 
-        let mut parse_stack: Vec<ParseTree<T>> = Vec::new();
-        let mut state_stack: Vec<u32> = Vec::new();
+        // a run of ys is an R too. where the handler passes, R is parsed by its own production
+        let mut sub_parsers = SubParsers::new();
+        sub_parsers.register(r, |tokens: &[Symbol]| {
+            let n = tokens.iter().take_while(|t| **t == y).count();
+            Some((ParseTree::new(y, y), n))
+        });
+        let tree = p.parse_with(vec![x, semi, y, y, semi, x, eoi], |s| *s, &sub_parsers).unwrap();
+        assert_eq!(tree.display(db).to_string(), "S(S(S(R(x)) ; R(y)) ; R(x))");
+        assert!(p.parse_with(vec![x, semi, semi, x, eoi], |s| *s, &sub_parsers).is_err());
+    }
 
-        state_stack.push(0);
+    /* grammar:
+     *   S -> a EMB | a
+     */
+    #[test]
+    fn sub_parsers_02() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        // only ever parsed by its sub-parser
+        let emb = symbol_db.new_nonterminal("EMB");
+        let a = symbol_db.new_terminal("a");
+        let z = symbol_db.new_terminal("z");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(s, vec![a, emb]),
+            Production::new(s, vec![a]),
+        ];
+        let p = Parser::new(Grammar::new(symbol_db, s, productions));
+        let db = p.grammar().symbol_db();
 
-        let mut iter = tokens.iter();
+        // the state after a reduces by S -> a whatever comes next, but the handler comes first
+        let mut sub_parsers = SubParsers::new();
+        sub_parsers.register(emb, |tokens: &[Symbol]| {
+            let n = tokens.iter().take_while(|t| **t == z).count();
+            Some((ParseTree::new(z, z), n))
+        });
+        let tree = p.parse_with(vec![a, z, z, eoi], |s| *s, &sub_parsers).unwrap();
+        assert_eq!(tree.display(db).to_string(), "S(a EMB(z))");
+        let tree = p.parse_with(vec![a, eoi], |s| *s, &sub_parsers).unwrap();
+        assert_eq!(tree.display(db).to_string(), "S(a)");
+    }
 
-        let mut token: &T = iter.next().unwrap();
-        let mut symbol: Symbol = token_to_symbol(token);
+    #[test]
+    fn test05() {
+        let mut symbol_db = SymbolDb::new();
+        /* grammar:
+         *   pair -> ( pair ) | ( )
+         */
+        let pair = symbol_db.new_nonterminal("pair");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let id = symbol_db.new_terminal("id");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(pair, vec![lp, pair, rp]),
+            Production::new(pair, vec![lp, rp]),
+        ];
+        let g = Grammar::new(symbol_db, pair, productions);
+        use Token::*;
+        let ttos = |token: &Token| {
+            match token {
+                ParenLeft => lp,
+                ParenRight => rp,
+                Identifier => id,
+                EndOfFile => eoi,
+            }
+        };
+        let p = Parser::new(g);
+        let tokens = vec![
+            Identifier, ParenLeft, ParenLeft, ParenRight, ParenRight, Identifier,
+            ParenRight, ParenLeft, ParenRight, ParenLeft,
+        ];
+        let islands = p.parse_islands(&tokens, ttos);
+        assert_eq!(islands.len(), 2);
+        assert_eq!((islands[0].start, islands[0].end), (1, 5));
+        assert_eq!(*islands[0].tree.symbol(), pair);
+        assert_eq!(islands[0].tree.children().len(), 3);
+        assert_eq!((islands[1].start, islands[1].end), (7, 9));
+        assert_eq!(islands[1].tree.children().len(), 2);
 
-        loop {
-            let state = *state_stack.last().unwrap();
+        assert!(p.parse_islands(&[Identifier, ParenRight], ttos).is_empty());
+    }
 
-            if let Some(action) = self.parse_tables.action(state, symbol.clone()) {
-                //let s = self.grammar.symbol_db().label(&symbol).unwrap();
-                //println!("{}, state: {}, action: {}", s, state, action.to_string(self.grammar.symbol_db()));
-                match action {
-                    Action::Reduce(p) => {
-                        let lhs = p.lhs();
-                        let rhs: Vec<Symbol> = p.rhs().iter()
-                            .cloned()
-                            .filter(|s| s != &self.grammar.symbol_db().epsilon())
-                            .collect();
+    #[test]
+    fn test06() {
+        let mut symbol_db = SymbolDb::new();
+        /* grammar:
+         *   e1 -> ( e1 ) | ε
+         */
+        let e1 = symbol_db.new_nonterminal("E1");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(e1, vec![lp, e1, rp]),
+            Production::new(e1, vec![])
+        ];
+        let p = Parser::new(Grammar::new(symbol_db, e1, productions));
+        use Token::*;
+        let ttos = |token: &Token| {
+            match token {
+                ParenLeft => lp,
+                ParenRight => rp,
+                _ => eoi,
+            }
+        };
+
+        // a lexer that reads one byte at a time, skips whitespace, and produces an end of file
+        // token when the reader is exhausted
+        let lexer = || {
+            let mut done = false;
+            move |reader: &mut dyn io::BufRead| -> io::Result<Option<Token>> {
+                loop {
+                    if done {
+                        return Ok(None);
+                    }
+                    let mut byte = [0u8];
+                    if reader.read(&mut byte)? == 0 {
+                        done = true;
+                        return Ok(Some(EndOfFile));
+                    }
+                    match byte[0] {
+                        b'(' => return Ok(Some(ParenLeft)),
+                        b')' => return Ok(Some(ParenRight)),
+                        b' ' | b'\n' => continue,
+                        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "bad character")),
+                    }
+                }
+            }
+        };
 
-                        let size = rhs.len();
+        let tree = p.parse_reader("( ( ) )\n".as_bytes(), lexer(), ttos).unwrap().unwrap();
+        assert_eq!(tree.children().len(), 3);
 
-                        let mut t = ParseTree::new(lhs.clone(), token.clone());
+        let result = p.parse_reader("(()".as_bytes(), lexer(), ttos).unwrap();
+        assert!(matches!(result, Err(ParseError::UnexpectedSymbol { symbol, position: 3, .. }) if symbol == eoi));
 
-                        let mut temp = Vec::new();
+        let result = p.parse_reader("(x)".as_bytes(), lexer(), ttos);
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidData);
 
-                        for _ in 0..size {
-                            state_stack.pop();
-                            temp.push(parse_stack.pop().unwrap());
-                        }
+        assert!(p.parse_iter(vec![ParenLeft, ParenRight, EndOfFile], ttos).is_ok());
+    }
 
-                        for _ in 0..size {
-                            t.add_child(temp.pop().unwrap());
-                        }
+    #[test]
+    fn test07() {
+        use std::ops::Range;
+        use crate::spanned::Spanned;
 
-                        parse_stack.push(t);
-                        let current_state = *state_stack.last().unwrap();
-                        if let Some(next_state) = self.parse_tables.transition(current_state, lhs.clone()) {
-                            state_stack.push(*next_state);
-                        } else {
-                            panic!("no entry in transition table for {}", current_state);
-                        }
-                    },
-                    Action::Shift(next_state) => {
-                        parse_stack.push(ParseTree::new(symbol.clone(), token.clone()));
-                        state_stack.push(*next_state);
-                        token = iter.next().unwrap();
-                        symbol = token_to_symbol(token);
-                    },
-                    Action::Accept => {
-                        break;
-                    }
-                }
-            } else {
-                let s = self.grammar.symbol_db().label(&symbol).unwrap();
-                panic!("no entry in action table for ({},{})", state, s);
+        #[derive(Clone,Debug)]
+        struct SpannedToken(Token, Range<usize>);
+
+        impl Spanned for SpannedToken {
+            fn span(&self) -> Range<usize> {
+                self.1.clone()
             }
         }
-    
-        parse_stack.pop()
-    }
-}
 
-// cargo test -- --nocapture
+        let mut symbol_db = SymbolDb::new();
+        /* grammar:
+         *   e1 -> ( e1 ) | ε
+         */
+        let e1 = symbol_db.new_nonterminal("E1");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(e1, vec![lp, e1, rp]),
+            Production::new(e1, vec![])
+        ];
+        let p = Parser::new(Grammar::new(symbol_db, e1, productions));
+        use Token::*;
+        let ttos = |token: &SpannedToken| {
+            match token.0 {
+                ParenLeft => lp,
+                ParenRight => rp,
+                _ => eoi,
+            }
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::production::Production;
-    use crate::symbol::{SymbolDb};
+        // the input is "( ( ) )"
+        let tokens = vec![
+            SpannedToken(ParenLeft, 0..1),
+            SpannedToken(ParenLeft, 2..3),
+            SpannedToken(ParenRight, 4..5),
+            SpannedToken(ParenRight, 6..7),
+            SpannedToken(EndOfFile, 7..7),
+        ];
+        let tree = p.parse(tokens, ttos).unwrap();
+        assert_eq!(tree.span(), 0..7);
+        let inner = &tree.children()[1];
+        assert_eq!(inner.span(), 2..5);
+        let empty = &inner.children()[1];
+        assert!(empty.is_epsilon());
+        assert_eq!(empty.span(), 4..4);
 
-    #[derive(Clone,Debug)]
-    enum Token {
-        ParenLeft,
-        ParenRight,
-        Identifier,
-        EndOfFile,
+        // the input is "( ) )"
+        let tokens = vec![
+            SpannedToken(ParenLeft, 0..1),
+            SpannedToken(ParenRight, 2..3),
+            SpannedToken(ParenRight, 4..5),
+            SpannedToken(EndOfFile, 5..5),
+        ];
+        let error = p.parse(tokens.clone(), ttos).err().unwrap();
+        assert_eq!(error.span(&tokens), Some(4..5));
+        let error = p.parse(tokens[..2].to_vec(), ttos).err().unwrap();
+        assert_eq!(error.span(&tokens[..2]), Some(3..3));
     }
 
     #[test]
-    fn test01() {
+    fn test08() {
         let mut symbol_db = SymbolDb::new();
         /* grammar:
          *   e1 -> ( e1 ) | ε
@@ -112,69 +1482,544 @@ mod tests {
         let e1 = symbol_db.new_nonterminal("E1");
         let lp = symbol_db.new_terminal("(");
         let rp = symbol_db.new_terminal(")");
-        let epsilon = symbol_db.epsilon();
         let eoi = symbol_db.eoi();
         let productions = vec![
             Production::new(e1, vec![lp, e1, rp]),
-            Production::new(e1, vec![epsilon])
+            Production::new(e1, vec![])
         ];
-        let g = Grammar::new(symbol_db, e1, productions);
+        let p = Parser::new(Grammar::new(symbol_db, e1, productions));
         use Token::*;
         let ttos = |token: &Token| {
             match token {
-                ParenLeft => lp.clone(),
-                ParenRight => rp.clone(),
-                EndOfFile => eoi,
+                ParenLeft => lp,
+                ParenRight => rp,
                 _ => eoi,
             }
         };
-        let p = Parser::new(g);
-        p.parse(vec![ParenLeft, ParenLeft, ParenRight, ParenRight, EndOfFile], ttos);
+
+        let mut session = ParseSession::new();
+        let deep = vec![ParenLeft, ParenLeft, ParenLeft, ParenRight, ParenRight, ParenRight, EndOfFile];
+        let tree = session.parse(&p, &deep, ttos).unwrap();
+        assert_eq!(tree, p.parse(deep.clone(), ttos).unwrap());
+
+        // a failed parse leaves junk on the stacks, which must not leak into the next parse
+        let bad = vec![ParenLeft, ParenRight, ParenRight, EndOfFile];
+        assert!(session.parse(&p, &bad, ttos).is_err());
+
+        let shallow = vec![ParenLeft, ParenRight, EndOfFile];
+        let tree = session.parse(&p, &shallow, ttos).unwrap();
+        assert_eq!(tree, p.parse(shallow.clone(), ttos).unwrap());
+
+        // the same, classifying all the tokens in one go
+        let classify = |tokens: &[Token], symbols: &mut Vec<Symbol>| symbols.extend(tokens.iter().map(ttos));
+        let tree = session.parse_classified(&p, &deep, classify).unwrap();
+        assert_eq!(tree, p.parse(deep.clone(), ttos).unwrap());
+        assert!(session.parse_classified(&p, &bad, classify).is_err());
+        let tree = session.parse_classified(&p, &shallow, classify).unwrap();
+        assert_eq!(tree, p.parse(shallow.clone(), ttos).unwrap());
+
+        // a classifier that loses track of the tokens
+        let short = |tokens: &[Token], symbols: &mut Vec<Symbol>| symbols.extend(tokens.iter().skip(1).map(ttos));
+        let error = session.parse_classified(&p, &shallow, short).err().unwrap();
+        assert_eq!(error, ParseError::ClassifiedTokens { tokens: 3, symbols: 2 });
+        assert_eq!(error.position(), 2);
+        let long = |tokens: &[Token], symbols: &mut Vec<Symbol>| symbols.extend(tokens.iter().chain(tokens).map(ttos));
+        assert_eq!(session.parse_classified(&p, &shallow, long).err().unwrap().position(), 3);
     }
 
     #[test]
-    fn test02() {
+    fn test09() {
         let mut symbol_db = SymbolDb::new();
         /* grammar:
-         *   e1 -> id | e2
-         *   e2 -> ( e3 )
-         *   e3 -> e1 e3 | ε
+         *   e1 -> ( e1 ) | ε
          */
         let e1 = symbol_db.new_nonterminal("E1");
-        let e2 = symbol_db.new_nonterminal("E2");
-        let e3 = symbol_db.new_nonterminal("E3");
         let lp = symbol_db.new_terminal("(");
         let rp = symbol_db.new_terminal(")");
-        let id = symbol_db.new_terminal("id");
-        let epsilon = symbol_db.epsilon();
         let eoi = symbol_db.eoi();
         let productions = vec![
-            Production::new(e1.clone(), vec![id]),
-            Production::new(e1.clone(), vec![e2]),
-            Production::new(e2.clone(), vec![lp, e3, rp]),
-            Production::new(e3.clone(), vec![e1, e3]),
-            Production::new(e3.clone(), vec![epsilon]),
+            Production::new(e1, vec![lp, e1, rp]),
+            Production::new(e1, vec![])
         ];
-        let g = Grammar::new(symbol_db, e1, productions);
-
+        let mut p = Parser::new(Grammar::new(symbol_db, e1, productions));
+        use Token::*;
         let ttos = |token: &Token| {
             match token {
-                ParenLeft => lp.clone(),
-                ParenRight => rp.clone(),
-                Identifier => id.clone(),
-                EndOfFile => eoi.clone(),
+                ParenLeft => lp,
+                ParenRight => rp,
+                _ => eoi,
             }
         };
 
+        // with the transitions on E1 gone, the reduction of the ε production has nowhere to go
+        Arc::get_mut(&mut p.parse_tables).unwrap().goto_table_mut().retain(|(_, s), _| *s != e1);
+        let result = p.parse(vec![ParenLeft, ParenRight, EndOfFile], ttos);
+        match result {
+            Err(ParseError::InternalError { symbol, position, .. }) => {
+                assert_eq!(symbol, e1);
+                assert_eq!(position, 1);
+            },
+            _ => panic!("expected an internal error"),
+        }
+        let message = result.err().unwrap().to_string(p.grammar().symbol_db());
+        assert!(message.starts_with("internal error: broken parse tables at E1"));
+    }
+
+    /* grammar:
+     *   e1 -> ( e1 ) | ε
+     */
+    #[test]
+    fn internal_error_01() {
+        let g = Grammar::from_bnf("E1 -> ( E1 ) | ε", "g").unwrap();
+        let db = g.symbol_db();
+        let (e1, lp, rp, eoi) = (db.symbol("E1").unwrap(), db.symbol("(").unwrap(), db.symbol(")").unwrap(), db.eoi());
+        let long = g.production_id(&Production::new(e1, vec![lp, e1, rp])).unwrap();
+
+        // a reduction by a production the grammar doesn't have
+        let mut broken = Parser::new(g.clone());
+        for (_, action) in Arc::get_mut(&mut broken.parse_tables).unwrap().action_table_mut().iter_mut() {
+            if let Action::Reduce(_) = action {
+                *action = Action::Reduce(ProductionId(99));
+            }
+        }
+        let result = broken.parse(vec![lp, rp, eoi], |s| *s);
+        assert!(matches!(result, Err(ParseError::InternalError { symbol, position: 1, .. }) if symbol == rp));
+        let mut push: PushParser<Symbol> = PushParser::new(&broken);
+        push.push(lp, lp).unwrap();
+        assert!(matches!(push.push(rp, rp), Err(ParseError::InternalError { .. })));
+
+        // and one that pops more than is on the stack
+        let mut broken = Parser::new(g);
+        let actions = Arc::get_mut(&mut broken.parse_tables).unwrap().action_table_mut();
+        actions.insert((0, eoi), Action::Reduce(long));
+        actions.insert((0, rp), Action::Reduce(long));
+        let result = broken.parse(vec![eoi], |s| *s);
+        assert_eq!(result.err(), Some(ParseError::InternalError { state: 0, symbol: e1, position: 0 }));
+        let mut push: PushParser<Symbol> = PushParser::new(&broken);
+        assert_eq!(push.push(eoi, eoi).err(), Some(ParseError::InternalError { state: 0, symbol: e1, position: 0 }));
+        // which the search for islands treats as a dead end, like any other error
+        assert_eq!(broken.parse_islands(&[rp, lp, rp], |s| *s).len(), 1);
+
+        // a conflict to settle by a production longer than the stack
+        Arc::get_mut(&mut broken.conflicts).unwrap().insert((0, lp), vec![long]);
+        let mut operators = DynamicOperators::new(OperatorTable::new(), |_: &Symbol| None);
+        let result = broken.parse_with_operators(vec![lp, rp, eoi], |s| *s, &mut operators);
+        assert_eq!(result.err(), Some(ParseError::InternalError { state: 0, symbol: e1, position: 0 }));
+    }
+
+    /* grammar:
+     *   S -> E o z | F o y
+     *   E -> x o y | x
+     *   F -> x
+     */
+    #[test]
+    fn operator_conflicts_01() {
+        let g = Grammar::from_bnf("S -> E o z | F o y\nE -> x o y | x\nF -> x", "g").unwrap();
+        let db = g.symbol_db();
+        let (e, f, x, o) = (db.symbol("E").unwrap(), db.symbol("F").unwrap(), db.symbol("x").unwrap(), db.symbol("o").unwrap());
+        let reduce_e = g.production_id(&Production::new(e, vec![x])).unwrap();
+        let reduce_f = g.production_id(&Production::new(f, vec![x])).unwrap();
         let p = Parser::new(g);
 
+        // after x, o can be shifted or x reduced to either E or F, and both reductions are kept
+        let mut cells: Vec<_> = p.conflicts.values().cloned().collect();
+        assert_eq!(cells.len(), 1);
+        cells[0].sort();
+        let mut expected = vec![reduce_e, reduce_f];
+        expected.sort();
+        assert_eq!(cells[0], expected);
+        assert!(p.conflicts.keys().all(|(_, symbol)| *symbol == o));
+
+        // which precedence can't choose between, so the shift stands
+        let mut table = OperatorTable::new();
+        table.declare("x", 2, crate::precedence::Associativity::Left);
+        table.declare("o", 1, crate::precedence::Associativity::Left);
+        let mut operators = DynamicOperators::new(table, |s: &Symbol| p.grammar().symbol_db().label(s).map(|l| l.to_string()));
+        let tokens: Vec<Symbol> = ["x", "o", "y", "o", "z", "$"].iter().map(|l| p.grammar().symbol_db().symbol(l).unwrap()).collect();
+        assert_eq!(p.parse_with_operators(tokens.clone(), |s| *s, &mut operators), p.parse(tokens, |s| *s));
+    }
+
+    #[test]
+    fn test10() {
+        let mut symbol_db = SymbolDb::new();
+        /* grammar:
+         *   e1 -> ( e1 ) | ε
+         */
+        let e1 = symbol_db.new_nonterminal("E1");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(e1, vec![lp, e1, rp]),
+            Production::new(e1, vec![])
+        ];
+        let p = Parser::new(Grammar::new(symbol_db, e1, productions));
         use Token::*;
+        let ttos = |token: &Token| {
+            match token {
+                ParenLeft => lp,
+                ParenRight => rp,
+                _ => eoi,
+            }
+        };
+
+        let mut session = ParseSession::new();
+        let tokens = vec![ParenLeft, ParenLeft, ParenRight, ParenRight, EndOfFile];
+        session.parse(&p, &tokens, ttos).unwrap();
+        assert!(session.stats().is_none());
 
-        p.parse(vec![Identifier, EndOfFile], ttos);
-        p.parse(vec![ParenLeft, Identifier, ParenRight, EndOfFile], ttos);
-        p.parse(vec![ParenLeft, Identifier, Identifier, ParenRight, EndOfFile], ttos);
-        p.parse(vec![ParenLeft, Identifier, ParenLeft, Identifier, ParenRight, ParenRight, EndOfFile], ttos);
+        session.enable_stats();
+        session.parse(&p, &tokens, ttos).unwrap();
+        let stats = session.stats().unwrap().clone();
+        assert_eq!(stats.shifts, 4);
+        // one ε reduction and two for the pairs of parentheses
+        assert_eq!(stats.reductions, 3);
+        assert!(stats.default_reductions <= stats.reductions);
+        assert_eq!(stats.table_misses, 0);
+        // state 0, plus ( ( E1 )
+        assert_eq!(stats.max_stack_depth, 5);
+        assert!(stats.states_visited > 1);
+
+        // the counts start over with each parse
+        let bad = vec![ParenRight, EndOfFile];
+        assert!(session.parse(&p, &bad, ttos).is_err());
+        let stats = session.stats().unwrap();
+        assert_eq!(stats.shifts, 0);
+        assert_eq!(stats.table_misses, 1);
+        assert_eq!(stats.states_visited, 1);
     }
 
-}
+    /* grammar:
+     *   S -> S ; E | E
+     *   E -> E + T | T
+     *   T -> x
+     * extended one production at a time with
+     *   T -> ( E ),  T -> - T,  F -> x,  T -> F !,  S -> let x = E,  E -> ε,  T -> x (again)
+     */
+    #[test]
+    fn extend_01() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let e = symbol_db.new_nonterminal("E");
+        let t = symbol_db.new_nonterminal("T");
+        let f = symbol_db.new_nonterminal("F");
+        let semi = symbol_db.new_terminal(";");
+        let plus = symbol_db.new_terminal("+");
+        let minus = symbol_db.new_terminal("-");
+        let bang = symbol_db.new_terminal("!");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let x = symbol_db.new_terminal("x");
+        let let_ = symbol_db.new_terminal("let");
+        let eq = symbol_db.new_terminal("=");
+        let eoi = symbol_db.eoi();
+        let mut productions = vec![
+            Production::new(s, vec![s, semi, e]),
+            Production::new(s, vec![e]),
+            Production::new(e, vec![e, plus, t]),
+            Production::new(e, vec![t]),
+            Production::new(t, vec![x]),
+        ];
+        let mut p = Parser::new(Grammar::new(symbol_db.clone(), s, productions.clone()));
+
+        // the tables, in an order that doesn't depend on hashing
+        let tables = |p: &Parser| {
+            let mut lines: Vec<String> = p.parse_tables().to_string(p.grammar()).lines().map(|l| l.to_string()).collect();
+            lines.sort();
+            lines
+        };
+        let extensions = vec![
+            Production::new(t, vec![lp, e, rp]),
+            Production::new(t, vec![minus, t]),
+            Production::new(f, vec![x]),
+            Production::new(t, vec![f, bang]),
+            Production::new(s, vec![let_, x, eq, e]),
+            Production::new(e, vec![]),
+        ];
+        for (i, q) in extensions.into_iter().enumerate() {
+            assert_eq!(p.extend(q.clone()).unwrap().index(), 5 + i);
+            productions.push(q);
+            let fresh = Parser::new(Grammar::new(symbol_db.clone(), s, productions.clone()));
+            assert_eq!(p.parse_tables().state_count(), fresh.parse_tables().state_count());
+            assert_eq!(tables(&p), tables(&fresh));
+            assert_eq!(p.parse_tables().conflicts().len(), fresh.parse_tables().conflicts().len());
+        }
+
+        assert_eq!(p.extend(Production::new(t, vec![x])).unwrap().index(), 4);
+        assert_eq!(p.grammar().all_productions().len(), 12);
+        assert!(p.extend(Production::new(x, vec![t])).is_err());
+
+        let tokens = vec![let_, x, eq, minus, lp, x, bang, rp, semi, semi, x, plus, x, eoi];
+        assert!(p.parse(tokens, |s| *s).is_ok());
+    }
+
+    /* grammar:
+     *   s -> ID LT ID | ID TOPEN ID > ( ID )
+     */
+    #[test]
+    fn peek_01() {
+        let p = Parser::new(Grammar::from_bnf("s -> ID LT ID | ID TOPEN ID > ( ID )", "generics").unwrap());
+        let db = p.grammar().symbol_db();
+        // a < is the start of type arguments when the tokens after it are a name, > and (
+        let classify = |t: &Peek<&str>| match *t.token() {
+            "<" if t.peek(2) == Some(&">") && t.peek(3) == Some(&"(") => db.symbol("TOPEN").unwrap(),
+            "<" => db.symbol("LT").unwrap(),
+            "$" => db.eoi(),
+            word if word.chars().all(char::is_alphabetic) => db.symbol("ID").unwrap(),
+            word => db.symbol(word).unwrap(),
+        };
+        let call: Vec<&str> = "f < t > ( x ) $".split(' ').collect();
+        let compare: Vec<&str> = "a < b $".split(' ').collect();
+        for tokens in [&call, &compare] {
+            assert!(p.parse_peeking(tokens.clone(), 3, classify).is_ok());
+            assert!(p.parse_iter_peeking(tokens.clone(), 3, classify).is_ok());
+        }
+        let tree = p.parse_iter_peeking(call.clone(), 3, classify).unwrap();
+        assert_eq!(tree.display(db).to_string(), "s(ID TOPEN ID > ( ID ))");
+        // without the ( it's a comparison, and the > is unexpected
+        assert!(p.parse_iter_peeking("f < t > x $".split(' '), 3, classify).is_err());
+    }
+
+    // a record of the steps of a parse, with the depth of the stack after each
+    struct Log<'d> {
+        symbol_db: &'d SymbolDb,
+        lines: Vec<String>,
+    }
+
+    impl ParseObserver<Symbol> for Log<'_> {
+        fn shift(&mut self, stack: &ParseStack<Symbol>, token: &Symbol) {
+            self.lines.push(format!("shift {} {}", self.symbol_db.label(token).unwrap(), stack.trees().len()));
+        }
+
+        fn reduce(&mut self, stack: &ParseStack<Symbol>, production: &Production, _: &Symbol) {
+            assert_eq!(stack.states().len(), stack.trees().len() + 1);
+            self.lines.push(format!("reduce {} {}", self.symbol_db.label(production.lhs()).unwrap(), stack.trees().len()));
+        }
+
+        fn error(&mut self, _: &ParseStack<Symbol>, error: &ParseError, token: Option<&Symbol>) {
+            self.lines.push(format!("error {} {:?}", error.position(), token.map(|t| self.symbol_db.label(t).unwrap())));
+        }
+    }
+
+    // counts the shifts, and nothing else
+    struct Shifts(usize);
+
+    impl<T> ParseObserver<T> for Shifts {
+        fn shift(&mut self, _: &ParseStack<T>, _: &T) {
+            self.0 += 1;
+        }
+    }
+
+    /* grammar:
+     *   S -> ( S ) | x
+     */
+    #[test]
+    fn observers_01() {
+        let p = Parser::new(Grammar::from_bnf("S -> ( S ) | x", "g").unwrap());
+        let db = p.grammar().symbol_db();
+        let tokens = |text: &str| -> Vec<Symbol> {
+            text.chars().map(|c| db.symbol(&c.to_string()).unwrap()).chain(std::iter::once(db.eoi())).collect()
+        };
+        let mut log = Log { symbol_db: db, lines: Vec::new() };
+        let mut shifts = Shifts(0);
+        let mut observers = ParseObservers::new();
+        observers.register(&mut log);
+        observers.register(&mut shifts);
+        p.parse_observed(tokens("(x)"), |s| *s, &mut observers).unwrap();
+        p.parse_observed(tokens("(x"), |s| *s, &mut observers).unwrap_err();
+        drop(observers);
+        assert_eq!(log.lines, vec![
+            "shift ( 1", "shift x 2", "reduce S 2", "shift ) 3", "reduce S 1",
+            "shift ( 1", "shift x 2", "reduce S 2", "error 2 Some(\"$\")",
+        ]);
+        assert_eq!(shifts.0, 5);
+    }
+
+    /* grammar:
+     *   list -> item rest
+     *   rest -> , item rest | ε
+     *   item -> x | [ list ]
+     */
+    #[test]
+    fn inline_01() {
+        let g = Grammar::from_bnf("list -> item rest\nrest -> , item rest | ε\nitem -> x | [ list ]", "g").unwrap();
+        let rest: Vec<ProductionId> = g.productions_with_ids()
+            .filter(|(_, p)| g.symbol_db().label(p.lhs()).unwrap() == "rest")
+            .map(|(id, _)| id)
+            .collect();
+        let p = Parser::new(g.with_inline(&rest));
+        let db = p.grammar().symbol_db();
+        let tokens: Vec<Symbol> = "x , [ x , x ] , x $".split(' ').map(|l| db.symbol(l).unwrap()).collect();
+        let tree = p.parse(tokens, |s| *s).unwrap();
+        assert_eq!(tree.display(db).to_string(), "list(item(x) , item([ list(item(x) , item(x)) ]) , item(x))");
+
+        // a list of one item has nothing left of rest
+        let tree = p.parse(vec![db.symbol("x").unwrap(), db.eoi()], |s| *s).unwrap();
+        assert_eq!(tree.display(db).to_string(), "list(item(x))");
+    }
+
+    /* grammar:
+     *   stmts -> stmts stmt | stmt
+     *   stmt -> x = n ; | error ;
+     */
+    #[test]
+    fn parse_recovering_01() {
+        let g = Grammar::from_bnf("stmts -> stmts stmt | stmt\nstmt -> x = n ; | error ;", "g").unwrap();
+        let error = g.symbol_db().symbol("error").unwrap();
+        let p = Parser::new(g.with_error_symbol(error));
+        let db = p.grammar().symbol_db();
+        // the tokens are the spans of the words of the text, and then an empty span for the end
+        let tokens = |text: &str| -> Vec<Range<usize>> {
+            let mut spans = Vec::new();
+            let mut start = 0;
+            for word in text.split(' ') {
+                spans.push(start..start + word.len());
+                start += word.len() + 1;
+            }
+            spans.push(text.len()..text.len());
+            spans
+        };
+        let parse = |text: &str| {
+            p.parse_recovering(tokens(text), |r| if r.is_empty() { db.eoi() } else { db.symbol(&text[r.clone()]).unwrap() })
+        };
+
+        let result = parse("x = n ; x = = ; x n ; x = n ;");
+        assert_eq!(result.errors.len(), 2);
+        assert_eq!(result.errors.iter().map(|e| e.position()).collect::<Vec<_>>(), vec![6, 9]);
+        // the statements, with a placeholder for each one that didn't parse
+        let stmt = db.symbol("stmt").unwrap();
+        let values = result.tree.unwrap().fold_with_placeholders(error, &mut |node, children: Vec<Vec<String>>| {
+            let children = children.concat();
+            if *node.symbol() == stmt && children.is_empty() {
+                vec!["assign".to_string()]
+            } else {
+                children
+            }
+        }, &mut |node| vec![format!("error at {:?}", node.span())]);
+        assert_eq!(values, vec!["assign", "error at 8..13", "error at 16..19", "assign"]);
+
+        // an error right at the start, where nothing is popped
+        let result = parse("= ; x = n ;");
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.tree.unwrap().display(db).to_string(), "stmts(stmts(stmt(error(=) ;)) stmt(x = n ;))");
+
+        // the input ending before the error can be got past
+        let result = parse("x = n ; x");
+        assert!(result.tree.is_none());
+        assert!(matches!(result.errors[..], [ParseError::UnexpectedSymbol { symbol, position: 5, .. }] if symbol == db.eoi()));
+
+        // and a grammar without an error symbol stops at the first error
+        let plain = Parser::new(Grammar::from_bnf("stmts -> stmts stmt | stmt\nstmt -> x = n ; | error ;", "g").unwrap());
+        let text = "x = = ; x = n ;";
+        let result = plain.parse_recovering(tokens(text), |r| if r.is_empty() { db.eoi() } else { db.symbol(&text[r.clone()]).unwrap() });
+        assert!(result.tree.is_none());
+        assert_eq!(result.errors.len(), 1);
+    }
+
+    /* grammar:
+     *   stmts -> stmts stmt | stmt
+     *   stmt -> x = n ; | error ;
+     */
+    #[test]
+    fn recovery_metrics_01() {
+        let g = Grammar::from_bnf("stmts -> stmts stmt | stmt\nstmt -> x = n ; | error ;", "g").unwrap();
+        let error = g.symbol_db().symbol("error").unwrap();
+        let p = Parser::new(g.with_error_symbol(error));
+        let db = p.grammar().symbol_db();
+        let parse = |text: &str| {
+            let mut tokens: Vec<Symbol> = text.split(' ').map(|l| db.symbol(l).unwrap()).collect();
+            tokens.push(db.eoi());
+            p.parse_recovering(tokens, |s| *s).metrics
+        };
+
+        let metrics = parse("x = n ; x = n ;");
+        assert_eq!(metrics, RecoveryMetrics { tokens: 8, ..RecoveryMetrics::default() });
+        assert_eq!(metrics.skipped_fraction(), 0.0);
+
+        // the second error is only two tokens after the first, so they're one cluster
+        let metrics = parse("x = n ; x = = ; x n ; x = n ;");
+        assert_eq!(metrics, RecoveryMetrics { tokens: 15, tokens_skipped: 2, trees_discarded: 3, repairs: 2, clusters: 1 });
+        let metrics = parse("x = = ; x = n ; x n ;");
+        assert_eq!((metrics.repairs, metrics.clusters), (2, 2));
+        assert_eq!(metrics.skipped_fraction(), 2.0 / 11.0);
+    }
+
+    /* grammar:
+     *   stmts -> stmts stmt | stmt
+     *   stmt -> x = n ; | error ;
+     */
+    #[test]
+    fn parse_recovering_with_limit_01() {
+        let g = Grammar::from_bnf("stmts -> stmts stmt | stmt\nstmt -> x = n ; | error ;", "g").unwrap();
+        let error = g.symbol_db().symbol("error").unwrap();
+        let p = Parser::new(g.with_error_symbol(error));
+        let db = p.grammar().symbol_db();
+        let mut tokens: Vec<Symbol> = "x = = ; x n ; x = n ; n ; x = n ;".split(' ').map(|l| db.symbol(l).unwrap()).collect();
+        tokens.push(db.eoi());
+
+        let result = p.parse_recovering_with_limit(tokens.clone(), |s| *s, 3);
+        assert!(result.tree.is_some());
+        assert_eq!(result.errors.len(), 3);
+
+        // the third error is where the parse gives up
+        let result = p.parse_recovering_with_limit(tokens, |s| *s, 2);
+        assert!(result.tree.is_none());
+        assert_eq!(result.errors.len(), 3);
+        assert_eq!(result.errors[2], ParseError::TooManyErrors { limit: 2, position: 11 });
+        assert_eq!(result.errors[2].to_string(db), "more than 2 errors, giving up at position 11");
+        assert_eq!(result.metrics.repairs, 2);
+    }
+
+    /* grammar:
+     *   S -> ( S ) | x
+     */
+    #[test]
+    fn clone_01() {
+        let p = Parser::new(Grammar::from_bnf("S -> ( S ) | x", "g").unwrap());
+        let mut q = p.clone();
+        assert!(std::ptr::eq(p.grammar(), q.grammar()));
+        assert!(std::ptr::eq(p.parse_tables(), q.parse_tables()));
 
+        // extending the clone gives it a grammar and tables of its own, and leaves the original
+        let db = p.grammar().symbol_db();
+        let (s, lp, x) = (db.symbol("S").unwrap(), db.symbol("(").unwrap(), db.symbol("x").unwrap());
+        q.extend(Production::new(s, vec![lp, x])).unwrap();
+        assert!(!std::ptr::eq(p.parse_tables(), q.parse_tables()));
+        assert_eq!(p.grammar().all_productions().len() + 1, q.grammar().all_productions().len());
+        assert!(p.parse(vec![lp, x, db.eoi()], |s| *s).is_err());
+        assert!(q.parse(vec![lp, x, db.eoi()], |s| *s).is_ok());
+
+        // and a clone of the extended parser shares its collection, so extending that again
+        // doesn't change q either
+        let mut r = q.clone();
+        r.extend(Production::new(s, vec![])).unwrap();
+        assert!(q.parse(vec![db.eoi()], |s| *s).is_err());
+        assert!(r.parse(vec![db.eoi()], |s| *s).is_ok());
+    }
+
+    /* grammar:
+     *   S -> ( S ) | x
+     */
+    #[test]
+    fn lazy_parser_01() {
+        static BUILT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        static PARSER: LazyParser = LazyParser::new(|| {
+            BUILT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Grammar::from_bnf("S -> ( S ) | x", "g").unwrap()
+        });
+        assert!(!PARSER.is_initialized());
+
+        // threads racing to use it share the one parser
+        let parsers: Vec<usize> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4).map(|_| scope.spawn(|| PARSER.get() as *const Parser as usize)).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(parsers.iter().all(|p| *p == parsers[0]));
+        assert_eq!(BUILT.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(PARSER.is_initialized());
+
+        let db = PARSER.grammar().symbol_db();
+        let tokens: Vec<Symbol> = "( x ) $".split(' ').map(|l| db.symbol(l).unwrap()).collect();
+        assert!(PARSER.parse(tokens, |s| *s).is_ok());
+    }
+}
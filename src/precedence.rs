@@ -0,0 +1,168 @@
+use super::production::Production;
+use super::symbol::{Symbol,SymbolDb};
+
+#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq)]
+pub enum Associativity {
+    // a op b op c groups as (a op b) op c
+    Left,
+    // a op b op c groups as a op (b op c)
+    Right,
+    // a op b op c is an error, as is op op a for a unary operator
+    NonAssoc,
+}
+
+#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq)]
+pub enum Arity {
+    // a unary operator written before its operand, e.g. -a
+    Prefix,
+    // a unary operator written after its operand, e.g. a!
+    Postfix,
+    // a binary operator written between its operands, e.g. a + b
+    Infix,
+}
+
+// an entry of an operator table. operators with a higher precedence bind more tightly
+#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq)]
+pub struct Operator {
+    pub symbol: Symbol,
+    pub precedence: u32,
+    pub associativity: Associativity,
+    pub arity: Arity,
+}
+
+impl Operator {
+    pub fn infix(symbol: Symbol, precedence: u32, associativity: Associativity) -> Operator {
+        Operator { symbol, precedence, associativity, arity: Arity::Infix }
+    }
+
+    pub fn prefix(symbol: Symbol, precedence: u32) -> Operator {
+        Operator { symbol, precedence, associativity: Associativity::Right, arity: Arity::Prefix }
+    }
+
+    pub fn postfix(symbol: Symbol, precedence: u32) -> Operator {
+        Operator { symbol, precedence, associativity: Associativity::Left, arity: Arity::Postfix }
+    }
+}
+
+// the productions of the usual stratified expression grammar for an operator table, so that it
+// doesn't have to be written out by hand. there's one nonterminal per precedence level, from expr
+// (the loosest) down to primary (the operands, whose productions are left to the caller, e.g.
+// primary -> NUM | ( expr )). the nonterminals of the levels in between are added to the symbol
+// db, labelled after expr. e.g. for + (left, 1) and ^ (right, 2) the productions are
+//
+//   expr -> expr + expr1 | expr1
+//   expr1 -> primary ^ expr1 | primary
+//
+// operators of different kinds can share a level, but two infix operators with the same precedence
+// and different associativities give a grammar with conflicts, as they would in yacc
+pub fn expression_productions(symbol_db: &mut SymbolDb, expr: Symbol, primary: Symbol, operators: &[Operator]) -> Vec<Production> {
+    let mut precedences: Vec<u32> = operators.iter().map(|o| o.precedence).collect();
+    precedences.sort();
+    precedences.dedup();
+
+    let label = symbol_db.label(&expr).cloned().unwrap_or_default();
+    let mut levels = vec![expr];
+    for i in 1..precedences.len() {
+        let mut name = format!("{}{}", label, i);
+        while symbol_db.symbol(&name).is_some() {
+            name.push('\'');
+        }
+        levels.push(symbol_db.new_nonterminal(&name));
+    }
+    levels.push(primary);
+
+    let mut productions = Vec::new();
+    for (i, precedence) in precedences.iter().enumerate() {
+        let (this, next) = (levels[i], levels[i + 1]);
+        for o in operators.iter().filter(|o| o.precedence == *precedence) {
+            let rhs = match (o.arity, o.associativity) {
+                (Arity::Infix, Associativity::Left) => vec![this, o.symbol, next],
+                (Arity::Infix, Associativity::Right) => vec![next, o.symbol, this],
+                (Arity::Infix, Associativity::NonAssoc) => vec![next, o.symbol, next],
+                (Arity::Prefix, Associativity::NonAssoc) => vec![o.symbol, next],
+                (Arity::Prefix, _) => vec![o.symbol, this],
+                (Arity::Postfix, Associativity::NonAssoc) => vec![next, o.symbol],
+                (Arity::Postfix, _) => vec![this, o.symbol],
+            };
+            productions.push(Production::new(this, rhs));
+        }
+        productions.push(Production::new(this, vec![next]));
+    }
+    if precedences.is_empty() {
+        productions.push(Production::new(expr, vec![primary]));
+    }
+    productions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::parser::Parser;
+
+    /* operators:
+     *   ==     nonassoc 0
+     *   + -    left 1
+     *   *      left 2
+     *   ^      right 3
+     *   -      prefix 4
+     *   !      postfix 5
+     * grammar:
+     *   primary -> x | ( e )
+     */
+    #[test]
+    fn expression_productions_01() {
+        let mut symbol_db = SymbolDb::new();
+        let e = symbol_db.new_nonterminal("e");
+        let primary = symbol_db.new_nonterminal("p");
+        let eq = symbol_db.new_terminal("==");
+        let plus = symbol_db.new_terminal("+");
+        let minus = symbol_db.new_terminal("-");
+        let times = symbol_db.new_terminal("*");
+        let pow = symbol_db.new_terminal("^");
+        let bang = symbol_db.new_terminal("!");
+        let x = symbol_db.new_terminal("x");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let eoi = symbol_db.eoi();
+        // a label that clashes with the first level's
+        symbol_db.new_terminal("e1");
+
+        let operators = vec![
+            Operator::infix(plus, 1, Associativity::Left),
+            Operator::infix(minus, 1, Associativity::Left),
+            Operator::infix(times, 2, Associativity::Left),
+            Operator::infix(pow, 3, Associativity::Right),
+            Operator::prefix(minus, 4),
+            Operator::postfix(bang, 5),
+            Operator::infix(eq, 0, Associativity::NonAssoc),
+        ];
+        let mut productions = expression_productions(&mut symbol_db, e, primary, &operators);
+        assert_eq!(productions.len(), 13);
+        assert!(symbol_db.symbol("e1'").is_some());
+        productions.push(Production::new(primary, vec![x]));
+        productions.push(Production::new(primary, vec![lp, e, rp]));
+        let p = Parser::new(Grammar::new(symbol_db, e, productions));
+        assert!(p.parse_tables().conflicts().is_empty());
+
+        // the trees without the chains of single children between levels
+        let parse = |tokens: Vec<Symbol>| {
+            let tree = p.parse(tokens, |s| *s).ok()?;
+            let db = p.grammar().symbol_db();
+            Some(tree.fold(&mut |node, mut children: Vec<String>| {
+                match children.len() {
+                    0 => db.label(node.symbol()).unwrap().clone(),
+                    1 => children.pop().unwrap(),
+                    _ => format!("({})", children.join(" ")),
+                }
+            }))
+        };
+        assert_eq!(parse(vec![x, minus, x, minus, x, eoi]).unwrap(), "((x - x) - x)");
+        assert_eq!(parse(vec![x, pow, x, pow, x, eoi]).unwrap(), "(x ^ (x ^ x))");
+        assert_eq!(parse(vec![x, plus, x, times, x, eoi]).unwrap(), "(x + (x * x))");
+        assert_eq!(parse(vec![minus, minus, x, bang, pow, x, eoi]).unwrap(), "((- (- (x !))) ^ x)");
+        assert_eq!(parse(vec![lp, x, plus, x, rp, times, x, eoi]).unwrap(), "((( (x + x) )) * x)");
+        assert_eq!(parse(vec![x, eq, x, plus, x, eoi]).unwrap(), "(x == (x + x))");
+        assert!(parse(vec![x, eq, x, eq, x, eoi]).is_none());
+    }
+}
@@ -1,32 +1,87 @@
-use std::fmt::Write;
+use std::fmt;
 
+use super::small_vec::SmallVec;
 use super::symbol::{Symbol,SymbolDb};
 
+// right hand sides of up to this many symbols are stored without a separate allocation
+const INLINE_RHS: usize = 4;
+
+// identifies a production of a grammar by its position in declaration order. the ids of the
+// productions a grammar was given are 0..n, and the augmented production added by the grammar
+// comes after them
+#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq,PartialOrd,Ord)]
+pub struct ProductionId(pub(crate) usize);
+
+impl ProductionId {
+  pub fn index(&self) -> usize { self.0 }
+}
+
 #[derive(Clone,Debug,Eq,Hash,PartialEq,PartialOrd,Ord)]
 pub struct Production {
     lhs: Symbol,
-    rhs: Vec<Symbol>,
+    rhs: SmallVec<Symbol,INLINE_RHS>,
 }
 
 impl Production {
   pub fn new(lhs: Symbol, rhs: Vec<Symbol>) -> Production {
-      Production { lhs, rhs }
+      Production { lhs, rhs: SmallVec::from(rhs) }
   }
 
   pub fn lhs(&self) -> &Symbol { &self.lhs }
-  pub fn rhs(&self) -> &Vec<Symbol> { &self.rhs }
-
-  pub fn to_string(&self, symbol_db: &SymbolDb) -> String {
-      let mut result = String::new();
-      write!(&mut result, "{} -> ", symbol_db.label(&self.lhs).unwrap()).unwrap();
-      let mut iter = self.rhs.iter().peekable();
-      while let Some(s) = iter.next() {
-          write!(&mut result, "{}", symbol_db.label(s).unwrap()).unwrap();
-          if let Some(_) = iter.peek() {
-              write!(&mut result, "  ").unwrap();
-          }
-      }
-      result
+  pub fn rhs(&self) -> &[Symbol] { &self.rhs }
+
+  // something that formats the production with the labels of its symbols
+  pub fn display<'a>(&'a self, symbol_db: &'a SymbolDb) -> ProductionDisplay<'a> {
+      ProductionDisplay { production: self, symbol_db, label: None }
   }
 }
 
+pub struct ProductionDisplay<'a> {
+    production: &'a Production,
+    symbol_db: &'a SymbolDb,
+    // the name of the alternative, shown after it as in bnf, e.g. e -> e  +  e  #Add
+    label: Option<&'a str>,
+}
+
+impl<'a> ProductionDisplay<'a> {
+    pub(crate) fn with_label(mut self, label: Option<&'a str>) -> ProductionDisplay<'a> {
+        self.label = label;
+        self
+    }
+}
+
+impl fmt::Display for ProductionDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol_db = self.symbol_db;
+        let label = |s: &Symbol| symbol_db.label(s).ok_or(fmt::Error);
+        write!(f, "{} -> ", label(&self.production.lhs)?)?;
+        if self.production.rhs.is_empty() {
+            write!(f, "{}", label(&symbol_db.epsilon())?)?;
+        }
+        let mut iter = self.production.rhs.iter().peekable();
+        while let Some(s) = iter.next() {
+            write!(f, "{}", label(s)?)?;
+            if iter.peek().is_some() {
+                write!(f, "  ")?;
+            }
+        }
+        if let Some(label) = self.label {
+            write!(f, "  #{}", label)?;
+        }
+        Ok(())
+    }
+}
+
+// productions written as one rule per left hand side, e.g. e -> e + e | x, given the text of each
+// production's left and right hand sides. the rules are in the order their left hand sides first
+// appear, and the alternatives in the order they're given
+pub(crate) fn rules<'a>(productions: impl IntoIterator<Item=(&'a str,&'a str)>) -> Vec<String> {
+    let mut grouped: Vec<(&str,Vec<&str>)> = Vec::new();
+    for (lhs, rhs) in productions {
+        match grouped.iter_mut().find(|(l, _)| *l == lhs) {
+            Some((_, alternatives)) => alternatives.push(rhs),
+            None => grouped.push((lhs, vec![rhs])),
+        }
+    }
+    grouped.into_iter().map(|(lhs, alternatives)| format!("{} -> {}", lhs, alternatives.join(" | "))).collect()
+}
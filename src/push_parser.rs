@@ -0,0 +1,456 @@
+#[cfg(feature = "async")]
+use std::future::poll_fn;
+#[cfg(feature = "async")]
+use std::pin::pin;
+use std::sync::atomic::{AtomicU64,Ordering};
+
+#[cfg(feature = "async")]
+use futures_core::Stream;
+
+use super::action::Action;
+use super::parse_error::ParseError;
+use super::parse_tree::ParseTree;
+use super::parser::Parser;
+use super::production::ProductionId;
+use super::symbol::Symbol;
+use super::symbol_set::TerminalSet;
+
+// a parser that's handed its tokens one at a time, rather than pulling them from a vector or an
+// iterator, so that the caller stays in control between tokens. that makes room for speculative
+// parsing: take a snapshot, push tokens for one interpretation, and restore the snapshot to try
+// another. the same goes for editors, which can parse up to the cursor, snapshot, and then try
+// out completions. clone gives an independent copy of the whole parse, trees included, for when
+// two interpretations need to be kept side by side
+#[derive(Clone)]
+pub struct PushParser<'a,T> {
+    parser: &'a Parser,
+    state_stack: Vec<u32>,
+    parse_stack: Vec<ParseTree<T>>,
+    position: usize,
+    accepted: bool,
+    // how to undo each step taken since the oldest live snapshot. nothing is recorded until a
+    // snapshot is taken
+    undo_log: Vec<Undo<T>>,
+    recording: bool,
+    // the snapshots that can still be restored, oldest first
+    snapshots: Vec<Snapshot>,
+}
+
+// how to undo a step of the parse
+#[derive(Clone)]
+enum Undo<T> {
+    // pop the tree and the state that a shift pushed
+    Shift,
+    // put back the trees and states a reduction replaced with a single tree and state. the nodes of
+    // inline productions were spliced into the new tree, so they're kept (without their children)
+    // along with where they were and how many children they had, to be put back together
+    Reduce { states: Vec<u32>, inlined: Vec<(usize,ParseTree<T>,usize)> },
+    // the end of input was accepted
+    Accept,
+}
+
+// a point in a push parse that can be returned to. restoring a snapshot also discards any
+// snapshots taken after it
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub struct Snapshot {
+    undo_len: usize,
+    // unique among all the snapshots of all push parsers, so that one that has been discarded (or
+    // belongs to another parse) can't be mistaken for a live one at the same point in the log
+    id: u64,
+}
+
+static NEXT_SNAPSHOT: AtomicU64 = AtomicU64::new(0);
+
+impl<'a,T: Clone> PushParser<'a,T> {
+    pub fn new(parser: &'a Parser) -> PushParser<'a,T> {
+        PushParser {
+            parser,
+            state_stack: vec![0],
+            parse_stack: Vec::new(),
+            position: 0,
+            accepted: false,
+            undo_log: Vec::new(),
+            recording: false,
+            snapshots: Vec::new(),
+        }
+    }
+
+    // the number of tokens shifted so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        self.accepted
+    }
+
+    // the terminals that could come next without an error
+    pub fn expected(&self) -> TerminalSet {
+        let state = *self.state_stack.last().unwrap();
+        let tables = self.parser.parse_tables();
+        self.parser.grammar().terminals().iter()
+            .filter(|t| tables.action(state, *t).is_some())
+            .collect()
+    }
+
+    // make every reduction the token calls for, then shift it. pushing a token that maps to the
+    // end of input accepts the input instead. after an error, the parser is left partway through
+    // the reductions for the token, so restore a snapshot before going on
+    pub fn push(&mut self, token: T, symbol: Symbol) -> Result<(),ParseError> {
+        let tables = self.parser.parse_tables();
+        if self.accepted {
+            return Err(ParseError::UnexpectedSymbol { state: *self.state_stack.last().unwrap(), symbol, position: self.position });
+        }
+        loop {
+            let state = *self.state_stack.last().unwrap();
+            if let Some(p) = tables.default_reduction(state) {
+                self.reduce(p, &token, symbol)?;
+                continue;
+            }
+            match tables.action(state, symbol) {
+                Some(Action::Reduce(p)) => self.reduce(*p, &token, symbol)?,
+                Some(Action::Shift(next_state)) => {
+                    self.parse_stack.push(ParseTree::new(symbol, token));
+                    self.state_stack.push(*next_state);
+                    self.position += 1;
+                    self.log(Undo::Shift);
+                    return Ok(());
+                },
+                Some(Action::Accept) => {
+                    self.accepted = true;
+                    self.log(Undo::Accept);
+                    return Ok(());
+                },
+                None => return Err(ParseError::UnexpectedSymbol { state, symbol, position: self.position }),
+            }
+        }
+    }
+
+    // the tree for the whole input, once the end of input has been accepted
+    pub fn finish(mut self) -> Result<ParseTree<T>,ParseError> {
+        if !self.accepted {
+            return Err(ParseError::UnexpectedEndOfInput { position: self.position });
+        }
+        Ok(self.parse_stack.pop().unwrap())
+    }
+
+    // the current point of the parse. taking a snapshot costs nothing, but from then on each step
+    // keeps enough to be undone, until commit is called
+    pub fn snapshot(&mut self) -> Snapshot {
+        self.recording = true;
+        let snapshot = Snapshot { undo_len: self.undo_log.len(), id: NEXT_SNAPSHOT.fetch_add(1, Ordering::Relaxed) };
+        self.snapshots.push(snapshot);
+        snapshot
+    }
+
+    // go back to a snapshot, undoing every step since. the cost is in proportion to the number of
+    // steps undone, not to the size of the parse
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        let live = self.snapshots.iter().rposition(|s| *s == snapshot);
+        let Some(i) = live else {
+            panic!("the snapshot was discarded by an earlier restore or commit");
+        };
+        self.snapshots.truncate(i + 1);
+        while self.undo_log.len() > snapshot.undo_len {
+            match self.undo_log.pop().unwrap() {
+                Undo::Shift => {
+                    self.parse_stack.pop();
+                    self.state_stack.pop();
+                    self.position -= 1;
+                },
+                Undo::Reduce { states, inlined } => {
+                    self.state_stack.pop();
+                    let (_, _, children, _, _) = self.parse_stack.pop().unwrap().into_parts();
+                    let mut children = children.into_iter();
+                    let mut inlined = inlined.into_iter().peekable();
+                    for i in 0..states.len() {
+                        match inlined.next_if(|(at, _, _)| *at == i) {
+                            Some((_, mut node, count)) => {
+                                for child in children.by_ref().take(count) {
+                                    node.add_child(child);
+                                }
+                                self.parse_stack.push(node);
+                            },
+                            None => self.parse_stack.extend(children.next()),
+                        }
+                    }
+                    self.state_stack.extend(states);
+                },
+                Undo::Accept => self.accepted = false,
+            }
+        }
+    }
+
+    // discard every snapshot, and stop keeping the steps needed to go back to them
+    pub fn commit(&mut self) {
+        self.undo_log.clear();
+        self.recording = false;
+        self.snapshots.clear();
+    }
+
+    fn log(&mut self, undo: Undo<T>) {
+        if self.recording {
+            self.undo_log.push(undo);
+        }
+    }
+
+    // symbol is the lookahead
+    fn reduce(&mut self, p: ProductionId, token: &T, symbol: Symbol) -> Result<(),ParseError> {
+        let state = *self.state_stack.last().unwrap();
+        let (lhs, start) = self.parser.handle(p, self.parse_stack.len(), state, symbol, self.position)?;
+
+        let children: Vec<ParseTree<T>> = self.parse_stack.drain(start..).collect();
+        let states: Vec<u32> = self.state_stack.drain(start + 1..).collect();
+        let state = *self.state_stack.last().unwrap();
+        let next_state = match self.parser.parse_tables().transition(state, lhs) {
+            Some(next_state) => *next_state,
+            None => {
+                // put things back the way they were, so that the error leaves a consistent parse
+                self.parse_stack.extend(children);
+                self.state_stack.extend(states);
+                return Err(ParseError::InternalError { state, symbol: lhs, position: self.position });
+            },
+        };
+        let inlined = match self.recording {
+            true => self.inlined(&children),
+            false => Vec::new(),
+        };
+        self.parse_stack.push(ParseTree::reduced(lhs, token.clone(), children, p, self.parser.grammar()));
+        self.state_stack.push(next_state);
+        self.log(Undo::Reduce { states, inlined });
+        Ok(())
+    }
+
+    // the children that ParseTree::reduced will splice away, for putting them back on a restore
+    fn inlined(&self, children: &[ParseTree<T>]) -> Vec<(usize,ParseTree<T>,usize)> {
+        let grammar = self.parser.grammar();
+        children.iter().enumerate()
+            .filter(|(_, c)| c.production().is_some_and(|p| grammar.is_inline(p)))
+            .map(|(i, c)| {
+                let node = ParseTree::from_parts(*c.symbol(), c.token().clone(), Vec::new(), c.is_epsilon(), c.production());
+                (i, node, c.children().len())
+            })
+            .collect()
+    }
+}
+
+// a push parser never blocks, so it can be driven from async code as it is. the parse holds its
+// stacks and a reference to the parser, which is Sync, so a future that keeps one across an await
+// is Send as long as the tokens are
+#[cfg(feature = "async")]
+impl<T: Clone> PushParser<'_,T> {
+    // push the tokens of a stream as they arrive, stopping when the end of input is accepted
+    // (without waiting for anything more from the stream) or the stream ends. like parse, the
+    // tokens have to include one that maps to the end of input
+    pub async fn push_stream<S,F>(&mut self, tokens: S, token_to_symbol: F) -> Result<(),ParseError>
+        where S: Stream<Item=T>, F: Fn(&T) -> Symbol {
+        let mut tokens = pin!(tokens);
+        while !self.accepted {
+            let Some(token) = poll_fn(|cx| tokens.as_mut().poll_next(cx)).await else {
+                break;
+            };
+            let symbol = token_to_symbol(&token);
+            self.push(token, symbol)?;
+        }
+        Ok(())
+    }
+
+    // the tree for the tokens of a stream
+    pub async fn parse_stream<S,F>(mut self, tokens: S, token_to_symbol: F) -> Result<ParseTree<T>,ParseError>
+        where S: Stream<Item=T>, F: Fn(&T) -> Symbol {
+        self.push_stream(tokens, token_to_symbol).await?;
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::production::Production;
+    use crate::symbol::SymbolDb;
+
+    /* grammar:
+     *   S -> S ; E | E
+     *   E -> E + x | x | ( E )
+     */
+    #[test]
+    fn push_parser_01() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let e = symbol_db.new_nonterminal("E");
+        let semi = symbol_db.new_terminal(";");
+        let plus = symbol_db.new_terminal("+");
+        let x = symbol_db.new_terminal("x");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let eoi = symbol_db.eoi();
+        let productions = vec![
+            Production::new(s, vec![s, semi, e]),
+            Production::new(s, vec![e]),
+            Production::new(e, vec![e, plus, x]),
+            Production::new(e, vec![x]),
+            Production::new(e, vec![lp, e, rp]),
+        ];
+        let p = Parser::new(Grammar::new(symbol_db, s, productions));
+        let db = p.grammar().symbol_db();
+
+        let mut pp: PushParser<Symbol> = PushParser::new(&p);
+        for t in [x, semi, lp, x] {
+            pp.push(t, t).unwrap();
+        }
+        let snapshot = pp.snapshot();
+        assert_eq!(pp.expected().iter().collect::<Vec<_>>(), vec![plus, rp]);
+
+        // one way of going on, which fails partway
+        pp.push(plus, plus).unwrap();
+        pp.push(x, x).unwrap();
+        assert!(pp.push(lp, lp).is_err());
+        pp.restore(snapshot);
+        assert_eq!(pp.position(), 4);
+        assert_eq!(pp.expected().iter().collect::<Vec<_>>(), vec![plus, rp]);
+
+        // and another, which accepts
+        for t in [rp, plus, x, eoi] {
+            pp.push(t, t).unwrap();
+        }
+        assert!(pp.is_accepted());
+        assert!(pp.push(x, x).is_err());
+        pp.restore(snapshot);
+        assert!(!pp.is_accepted());
+
+        // the first attempt again, after the restore
+        pp.push(rp, rp).unwrap();
+        pp.push(eoi, eoi).unwrap();
+        pp.commit();
+        let tree = pp.finish().unwrap();
+        assert_eq!(tree.display(db).to_string(), "S(S(E(x)) ; E(( E(x) )))");
+
+        let expected = p.parse(vec![x, semi, lp, x, rp, eoi], |s| *s).unwrap();
+        assert_eq!(tree, expected);
+
+        let mut pp: PushParser<Symbol> = PushParser::new(&p);
+        pp.push(x, x).unwrap();
+        assert!(pp.finish().is_err());
+    }
+
+    /* grammar:
+     *   S -> A z
+     *   A -> x y      (inline)
+     */
+    #[test]
+    fn push_parser_02() {
+        let mut symbol_db = SymbolDb::new();
+        let s = symbol_db.new_nonterminal("S");
+        let a = symbol_db.new_nonterminal("A");
+        let x = symbol_db.new_terminal("x");
+        let y = symbol_db.new_terminal("y");
+        let z = symbol_db.new_terminal("z");
+        let eoi = symbol_db.eoi();
+        let inline = Production::new(a, vec![x, y]);
+        let g = Grammar::new(symbol_db, s, vec![Production::new(s, vec![a, z]), inline.clone()]);
+        let id = g.production_id(&inline).unwrap();
+        let p = Parser::new(g.with_inline(&[id]));
+        let db = p.grammar().symbol_db();
+
+        // undoing the reduction of S puts back the node for A that it spliced away
+        let mut pp: PushParser<Symbol> = PushParser::new(&p);
+        pp.push(x, x).unwrap();
+        pp.push(y, y).unwrap();
+        let snapshot = pp.snapshot();
+        pp.push(z, z).unwrap();
+        pp.push(eoi, eoi).unwrap();
+        pp.restore(snapshot);
+        pp.push(z, z).unwrap();
+        pp.push(eoi, eoi).unwrap();
+        let tree = pp.finish().unwrap();
+        assert_eq!(tree.display(db).to_string(), "S(x y z)");
+        assert_eq!(tree, p.parse(vec![x, y, z, eoi], |s| *s).unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "the snapshot was discarded by an earlier restore or commit")]
+    fn push_parser_03() {
+        let p = Parser::new(Grammar::from_bnf("S -> x S | y", "g").unwrap());
+        let db = p.grammar().symbol_db();
+        let (x, y) = (db.symbol("x").unwrap(), db.symbol("y").unwrap());
+
+        // a snapshot discarded by restoring an earlier one stays discarded, even once the log is
+        // back to the same length
+        let mut pp: PushParser<Symbol> = PushParser::new(&p);
+        let first = pp.snapshot();
+        pp.push(x, x).unwrap();
+        let second = pp.snapshot();
+        pp.restore(first);
+        pp.push(y, y).unwrap();
+        pp.snapshot();
+        pp.restore(second);
+    }
+
+    // a stream that has each of its items ready on every other poll, to show that the parse
+    // waits rather than giving up when there's nothing yet
+    #[cfg(feature = "async")]
+    struct Trickle<T> {
+        items: std::collections::VecDeque<T>,
+        ready: bool,
+    }
+
+    #[cfg(feature = "async")]
+    impl<T: Unpin> Stream for Trickle<T> {
+        type Item = T;
+
+        fn poll_next(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<T>> {
+            self.ready = !self.ready;
+            if self.ready {
+                std::task::Poll::Ready(self.items.pop_front())
+            } else {
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }
+        }
+    }
+
+    // run a future that doesn't need anything else to make progress
+    #[cfg(feature = "async")]
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    /* grammar:
+     *   S -> ( S ) | x
+     */
+    #[cfg(feature = "async")]
+    #[test]
+    fn parse_stream_01() {
+        let p = Parser::new(Grammar::from_bnf("S -> ( S ) | x", "g").unwrap());
+        let db = p.grammar().symbol_db();
+        let symbols = |text: &str| -> std::collections::VecDeque<Symbol> {
+            text.split(' ').map(|l| db.symbol(l).unwrap()).collect()
+        };
+        fn assert_send<F: Send>(f: F) -> F {
+            f
+        }
+
+        let tokens = Trickle { items: symbols("( ( x ) ) $"), ready: false };
+        let future = assert_send(PushParser::new(&p).parse_stream(tokens, |s| *s));
+        let tree = block_on(future).unwrap();
+        assert_eq!(tree.display(db).to_string(), "S(( S(( S(x) )) ))");
+
+        // the stream isn't read past the end of input
+        let mut tokens = Trickle { items: symbols("x $ x"), ready: false };
+        let mut pp = PushParser::new(&p);
+        block_on(pp.push_stream(&mut tokens, |s| *s)).unwrap();
+        assert!(pp.is_accepted());
+        assert_eq!(tokens.items.len(), 1);
+
+        // a stream that ends early leaves the input unfinished
+        let tokens = Trickle { items: symbols("( x"), ready: false };
+        assert!(block_on(PushParser::new(&p).parse_stream(tokens, |s| *s)).is_err());
+    }
+}
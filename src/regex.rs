@@ -0,0 +1,506 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap,VecDeque};
+use std::fmt;
+use std::sync::OnceLock;
+
+// the regular expressions of lexer rules. the syntax is the usual one, without anchors, counted
+// repetition or backreferences:
+//
+//   a        the character a
+//   \n \t \r \0 \u{hex}  the characters they usually stand for
+//   \. \* \\ etc.        a character that would otherwise mean something
+//   \d \w \s             ascii digits, ascii word characters and whitespace; \D \W \S are the rest
+//   \p{L} \p{N} etc.     the characters with a unicode property (see PROPERTIES); \P{..} is the rest
+//   .        any character but a newline
+//   [a-z_]   any of the characters or ranges; [^...] is any character not listed
+//   ab       a followed by b
+//   a|b      a or b
+//   a* a+ a? zero or more, one or more, or zero or one of a
+//   (a)      grouping
+//
+// a pattern is compiled to an nfa, which is run over the input directly
+
+// a pattern that isn't a valid regular expression. position is the byte offset in the pattern
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct RegexError {
+    pub pattern: String,
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (at offset {} of {})", self.message, self.position, self.pattern)
+    }
+}
+
+// a set of characters as sorted, non-overlapping, non-adjacent ranges
+pub(crate) type Ranges = Vec<(char,char)>;
+
+type StatePair = (usize,usize);
+
+#[derive(Clone,Debug)]
+enum Node {
+    Class(Ranges),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Star(Box<Node>),
+    Plus(Box<Node>),
+    Opt(Box<Node>),
+}
+
+// a state of an nfa, with its transitions on sets of characters and its ε transitions
+#[derive(Clone,Debug,Default)]
+pub(crate) struct NfaState {
+    pub(crate) ranges: Vec<(char,char,usize)>,
+    pub(crate) epsilon: Vec<usize>,
+}
+
+#[derive(Clone,Debug)]
+pub(crate) struct Regex {
+    pub(crate) states: Vec<NfaState>,
+    pub(crate) start: usize,
+    pub(crate) accept: usize,
+}
+
+impl Regex {
+    pub(crate) fn new(pattern: &str) -> Result<Regex,RegexError> {
+        let mut p = PatternParser { pattern, chars: pattern.char_indices().peekable() };
+        let node = p.alternation()?;
+        if let Some((i, c)) = p.chars.next() {
+            return Err(p.error(i, &format!("unexpected {}", c)));
+        }
+        let mut regex = Regex { states: Vec::new(), start: 0, accept: 0 };
+        let (start, accept) = regex.build(&node);
+        regex.start = start;
+        regex.accept = accept;
+        Ok(regex)
+    }
+
+    fn add(&mut self) -> usize {
+        self.states.push(NfaState::default());
+        self.states.len() - 1
+    }
+
+    // the start and accepting states of the fragment for a node
+    fn build(&mut self, node: &Node) -> (usize, usize) {
+        match node {
+            Node::Class(ranges) => {
+                let (s, e) = (self.add(), self.add());
+                self.states[s].ranges = ranges.iter().map(|(a, b)| (*a, *b, e)).collect();
+                (s, e)
+            },
+            Node::Concat(nodes) => {
+                let s = self.add();
+                let mut e = s;
+                for n in nodes {
+                    let (ns, ne) = self.build(n);
+                    self.states[e].epsilon.push(ns);
+                    e = ne;
+                }
+                (s, e)
+            },
+            Node::Alt(nodes) => {
+                let (s, e) = (self.add(), self.add());
+                for n in nodes {
+                    let (ns, ne) = self.build(n);
+                    self.states[s].epsilon.push(ns);
+                    self.states[ne].epsilon.push(e);
+                }
+                (s, e)
+            },
+            Node::Star(n) | Node::Plus(n) | Node::Opt(n) => {
+                let (s, e) = (self.add(), self.add());
+                let (ns, ne) = self.build(n);
+                self.states[s].epsilon.push(ns);
+                self.states[ne].epsilon.push(e);
+                if !matches!(node, Node::Plus(_)) {
+                    self.states[s].epsilon.push(e);
+                }
+                if !matches!(node, Node::Opt(_)) {
+                    self.states[ne].epsilon.push(ns);
+                }
+                (s, e)
+            },
+        }
+    }
+
+    // add the states reachable from state by ε transitions to the set
+    fn close(&self, state: usize, set: &mut Vec<usize>, member: &mut [bool]) {
+        if member[state] {
+            return;
+        }
+        member[state] = true;
+        set.push(state);
+        for next in &self.states[state].epsilon {
+            self.close(*next, set, member);
+        }
+    }
+
+    // the length in bytes of the longest prefix of the text that matches, if any does
+    pub(crate) fn longest_match(&self, text: &str) -> Option<usize> {
+        let mut member = vec![false; self.states.len()];
+        let mut current = Vec::new();
+        self.close(self.start, &mut current, &mut member);
+        let mut longest = if member[self.accept] { Some(0) } else { None };
+        for (i, c) in text.char_indices() {
+            member.iter_mut().for_each(|m| *m = false);
+            let mut next = Vec::new();
+            for state in &current {
+                for (a, b, target) in &self.states[*state].ranges {
+                    if *a <= c && c <= *b {
+                        self.close(*target, &mut next, &mut member);
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            if member[self.accept] {
+                longest = Some(i + c.len_utf8());
+            }
+            current = next;
+        }
+        longest
+    }
+
+    pub(crate) fn matches_empty(&self) -> bool {
+        self.longest_match("") == Some(0)
+    }
+
+    // a text that both expressions match in full, if there is one. the search runs over pairs of
+    // states of the two nfas, so the text found is one of the shortest
+    pub(crate) fn common_match(&self, other: &Regex) -> Option<String> {
+        let start = (self.start, other.start);
+        // the pair each pair was reached from, and the character read on the way
+        let mut previous: HashMap<StatePair,(StatePair,Option<char>)> = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+        previous.insert(start, (start, None));
+        while let Some((a, b)) = queue.pop_front() {
+            if (a, b) == (self.accept, other.accept) {
+                let mut text = Vec::new();
+                let mut pair = (a, b);
+                while pair != start {
+                    let (p, c) = previous[&pair];
+                    text.extend(c);
+                    pair = p;
+                }
+                return Some(text.into_iter().rev().collect());
+            }
+            let mut next = Vec::new();
+            next.extend(self.states[a].epsilon.iter().map(|a2| ((*a2, b), None)));
+            next.extend(other.states[b].epsilon.iter().map(|b2| ((a, *b2), None)));
+            for (a_start, a_end, a2) in &self.states[a].ranges {
+                for (b_start, b_end, b2) in &other.states[b].ranges {
+                    if a_start <= b_end && b_start <= a_end {
+                        next.push(((*a2, *b2), Some(*a_start.max(b_start))));
+                    }
+                }
+            }
+            for (pair, c) in next {
+                if let Entry::Vacant(e) = previous.entry(pair) {
+                    e.insert(((a, b), c));
+                    queue.push_back(pair);
+                }
+            }
+        }
+        None
+    }
+}
+
+type Property = (&'static [&'static str],fn(char) -> bool);
+
+// the unicode properties \p{..} can name, with the other names they go by. they're taken from the
+// standard library's char methods, so L is really the Alphabetic property, which is the letters
+// along with the letter numbers (like roman numerals) and the marks that combine with letters
+const PROPERTIES: [Property; 6] = [
+    (&["L", "Letter", "Alphabetic"], char::is_alphabetic),
+    (&["Lu", "Uppercase", "Uppercase_Letter"], char::is_uppercase),
+    (&["Ll", "Lowercase", "Lowercase_Letter"], char::is_lowercase),
+    (&["N", "Number", "Numeric"], char::is_numeric),
+    (&["White_Space", "Space"], char::is_whitespace),
+    (&["Cc", "Control"], char::is_control),
+];
+
+// the characters with a property, found by testing every character the first time the property is
+// used. that takes a few milliseconds, once per program
+fn property(name: &str) -> Option<&'static Ranges> {
+    static CACHE: [OnceLock<Ranges>; PROPERTIES.len()] = [const { OnceLock::new() }; PROPERTIES.len()];
+    let i = PROPERTIES.iter().position(|(names, _)| names.contains(&name))?;
+    Some(CACHE[i].get_or_init(|| {
+        let has = PROPERTIES[i].1;
+        let mut ranges: Ranges = Vec::new();
+        for c in (0..=char::MAX as u32).filter_map(char::from_u32).filter(|c| has(*c)) {
+            match ranges.last_mut() {
+                Some((_, end)) if *end as u32 + 1 == c as u32 => *end = c,
+                _ => ranges.push((c, c)),
+            }
+        }
+        ranges
+    }))
+}
+
+// sort and merge ranges, so that they don't overlap or touch
+fn normalize(mut ranges: Ranges) -> Ranges {
+    ranges.sort();
+    let mut result: Ranges = Vec::new();
+    for (a, b) in ranges {
+        match result.last_mut() {
+            Some((_, end)) if (*end as u32) + 1 >= a as u32 => *end = (*end).max(b),
+            _ => result.push((a, b)),
+        }
+    }
+    result
+}
+
+// the characters not in the (normalized) ranges
+fn complement(ranges: &Ranges) -> Ranges {
+    let mut result = Vec::new();
+    let mut next = 0u32;
+    for (a, b) in ranges {
+        if (*a as u32) > next {
+            push_range(&mut result, next, *a as u32 - 1);
+        }
+        next = *b as u32 + 1;
+    }
+    if next <= char::MAX as u32 {
+        push_range(&mut result, next, char::MAX as u32);
+    }
+    result
+}
+
+// push the characters from a to b, leaving out the surrogates, which aren't characters
+fn push_range(ranges: &mut Ranges, a: u32, b: u32) {
+    for (a, b) in [(a, b.min(0xd7ff)), (a.max(0xe000), b)] {
+        if a <= b {
+            ranges.push((char::from_u32(a).unwrap(), char::from_u32(b).unwrap()));
+        }
+    }
+}
+
+struct PatternParser<'a> {
+    pattern: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl PatternParser<'_> {
+    fn error(&self, position: usize, message: &str) -> RegexError {
+        RegexError { pattern: self.pattern.to_string(), position, message: message.to_string() }
+    }
+
+    fn end(&self) -> usize {
+        self.pattern.len()
+    }
+
+    fn alternation(&mut self) -> Result<Node,RegexError> {
+        let mut alternatives = vec![self.concatenation()?];
+        while self.chars.next_if(|(_, c)| *c == '|').is_some() {
+            alternatives.push(self.concatenation()?);
+        }
+        Ok(if alternatives.len() == 1 { alternatives.pop().unwrap() } else { Node::Alt(alternatives) })
+    }
+
+    fn concatenation(&mut self) -> Result<Node,RegexError> {
+        let mut nodes = Vec::new();
+        while let Some((_, c)) = self.chars.peek() {
+            if *c == '|' || *c == ')' {
+                break;
+            }
+            nodes.push(self.repetition()?);
+        }
+        Ok(if nodes.len() == 1 { nodes.pop().unwrap() } else { Node::Concat(nodes) })
+    }
+
+    fn repetition(&mut self) -> Result<Node,RegexError> {
+        let mut node = self.atom()?;
+        while let Some((_, c)) = self.chars.next_if(|(_, c)| "*+?".contains(*c)) {
+            node = match c {
+                '*' => Node::Star(Box::new(node)),
+                '+' => Node::Plus(Box::new(node)),
+                _ => Node::Opt(Box::new(node)),
+            };
+        }
+        Ok(node)
+    }
+
+    fn atom(&mut self) -> Result<Node,RegexError> {
+        let (i, c) = self.chars.next().unwrap();
+        match c {
+            '(' => {
+                let node = self.alternation()?;
+                match self.chars.next() {
+                    Some((_, ')')) => Ok(node),
+                    _ => Err(self.error(i, "unclosed (")),
+                }
+            },
+            '[' => self.class(i),
+            '.' => Ok(Node::Class(complement(&vec![('\n', '\n')]))),
+            '*' | '+' | '?' => Err(self.error(i, &format!("nothing to repeat before {}", c))),
+            '\\' => Ok(Node::Class(self.escape(i)?)),
+            c => Ok(Node::Class(vec![(c, c)])),
+        }
+    }
+
+    // the characters an escape stands for, the backslash (at position i) having been read
+    fn escape(&mut self, i: usize) -> Result<Ranges,RegexError> {
+        let c = match self.chars.next() {
+            Some((_, c)) => c,
+            None => return Err(self.error(i, "nothing to escape after \\")),
+        };
+        let single = |c: char| Ok(vec![(c, c)]);
+        let digits = vec![('0', '9')];
+        let word = normalize(vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]);
+        let space = normalize(vec![('\t', '\r'), (' ', ' ')]);
+        match c {
+            'n' => single('\n'),
+            't' => single('\t'),
+            'r' => single('\r'),
+            '0' => single('\0'),
+            'd' => Ok(digits),
+            'D' => Ok(complement(&digits)),
+            'w' => Ok(word),
+            'W' => Ok(complement(&word)),
+            's' => Ok(space),
+            'S' => Ok(complement(&space)),
+            'p' | 'P' => {
+                if self.chars.next_if(|(_, c)| *c == '{').is_none() {
+                    return Err(self.error(i, &format!("expected {{ after \\{}", c)));
+                }
+                let mut name = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some((_, '}')) => break,
+                        Some((_, c)) => name.push(c),
+                        None => return Err(self.error(i, &format!("unclosed \\{}{{", c))),
+                    }
+                }
+                match property(&name) {
+                    Some(ranges) if c == 'p' => Ok(ranges.clone()),
+                    Some(ranges) => Ok(complement(ranges)),
+                    None => Err(self.error(i, &format!("unknown property {}", name))),
+                }
+            },
+            'u' => {
+                if self.chars.next_if(|(_, c)| *c == '{').is_none() {
+                    return Err(self.error(i, "expected { after \\u"));
+                }
+                let mut hex = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some((_, '}')) => break,
+                        Some((_, c)) => hex.push(c),
+                        None => return Err(self.error(i, "unclosed \\u{")),
+                    }
+                }
+                match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    Some(c) => single(c),
+                    None => Err(self.error(i, &format!("\\u{{{}}} isn't a character", hex))),
+                }
+            },
+            c if !c.is_alphanumeric() => single(c),
+            c => Err(self.error(i, &format!("unknown escape \\{}", c))),
+        }
+    }
+
+    // a bracketed class, the [ (at position i) having been read
+    fn class(&mut self, i: usize) -> Result<Node,RegexError> {
+        let negated = self.chars.next_if(|(_, c)| *c == '^').is_some();
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let (j, c) = match self.chars.next() {
+                Some(next) => next,
+                None => return Err(self.error(i, "unclosed [")),
+            };
+            // a ] straight after the [ or [^ is itself
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let start = match c {
+                '\\' => {
+                    let escaped = self.escape(j)?;
+                    if escaped.len() != 1 || escaped[0].0 != escaped[0].1 {
+                        ranges.extend(escaped);
+                        continue;
+                    }
+                    escaped[0].0
+                },
+                c => c,
+            };
+            // a - at the end of the class is itself
+            let dash = self.chars.peek().map(|(_, c)| *c) == Some('-');
+            let mut ahead = self.chars.clone();
+            ahead.next();
+            if !dash || matches!(ahead.peek(), Some((_, ']')) | None) {
+                ranges.push((start, start));
+                continue;
+            }
+            self.chars.next();
+            let end = match self.chars.next() {
+                Some((k, '\\')) => {
+                    let escaped = self.escape(k)?;
+                    if escaped.len() != 1 || escaped[0].0 != escaped[0].1 {
+                        return Err(self.error(k, "a class can't end a range"));
+                    }
+                    escaped[0].0
+                },
+                Some((_, c)) => c,
+                None => return Err(self.error(self.end(), "unclosed [")),
+            };
+            if end < start {
+                return Err(self.error(j, &format!("the range {}-{} is backwards", start, end)));
+            }
+            ranges.push((start, end));
+        }
+        let ranges = normalize(ranges);
+        Ok(Node::Class(if negated { complement(&ranges) } else { ranges }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regex_01() {
+        let longest = |pattern: &str, text: &str| Regex::new(pattern).unwrap().longest_match(text);
+        assert_eq!(longest("abc", "abcd"), Some(3));
+        assert_eq!(longest("abc", "abd"), None);
+        assert_eq!(longest("a|ab|abc", "abx"), Some(2));
+        assert_eq!(longest("(ab)*", "ababa"), Some(4));
+        assert_eq!(longest("(ab)+", "x"), None);
+        assert_eq!(longest("-?\\d+(\\.\\d+)?", "-12.5e"), Some(5));
+        assert_eq!(longest("-?\\d+(\\.\\d+)?", "12."), Some(2));
+        assert_eq!(longest("[a-zA-Z_]\\w*", "foo_1 bar"), Some(5));
+        assert_eq!(longest("\"([^\"\\\\]|\\\\.)*\"", r#""a\"b" c"#), Some(6));
+        assert_eq!(longest("[]a]+", "]a]b"), Some(3));
+        assert_eq!(longest("[+-]+", "+-+x"), Some(3));
+        assert_eq!(longest("[^]]+", "ab]"), Some(2));
+        assert_eq!(longest(".+", "éé\nx"), Some(4));
+        assert_eq!(longest("\\u{e9}\\s", "é x"), Some(3));
+        assert_eq!(longest("[^a]", "\u{10ffff}"), Some(4));
+        assert_eq!(longest("[\\p{L}_][\\p{L}\\p{N}_]*", "naïve_變數2 x"), Some(14));
+        assert_eq!(longest("\\p{N}+", "٣4½x"), Some(5));
+        assert_eq!(longest("\\p{Lu}\\p{Ll}+", "Ωmega"), Some(6));
+        assert_eq!(longest("\\P{L}+", "12 ab"), Some(3));
+        assert_eq!(longest("[^\\p{White_Space}]+", "x\u{a0}y"), Some(1));
+        assert!(Regex::new("a*").unwrap().matches_empty());
+        assert!(!Regex::new("a+").unwrap().matches_empty());
+
+        let common = |a: &str, b: &str| Regex::new(a).unwrap().common_match(&Regex::new(b).unwrap());
+        assert_eq!(common("[a-z]+", "if|while"), Some("if".to_string()));
+        assert_eq!(common("\\d+", "\\d+\\.\\d*"), None);
+        assert_eq!(common("a(bc)*", "ab.*"), Some("abc".to_string()));
+
+        let error = |pattern: &str| Regex::new(pattern).err().unwrap();
+        assert_eq!(error("(ab").message, "unclosed (");
+        assert_eq!(error("ab)").position, 2);
+        assert_eq!(error("[ab").message, "unclosed [");
+        assert_eq!(error("a|*").message, "nothing to repeat before *");
+        assert_eq!(error("[z-a]").to_string(), "the range z-a is backwards (at offset 1 of [z-a])");
+        assert_eq!(error("\\q").message, "unknown escape \\q");
+        assert_eq!(error("\\p{Emoji}").message, "unknown property Emoji");
+        assert_eq!(error("\\pL").message, "expected { after \\p");
+    }
+}
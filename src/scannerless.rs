@@ -0,0 +1,308 @@
+use std::fmt;
+use std::ops::Range;
+
+use super::grammar::Grammar;
+use super::parse_error::ParseError;
+use super::parse_tree::ParseTree;
+use super::parser::Parser;
+use super::push_parser::PushParser;
+use super::spanned::Spanned;
+use super::symbol::Symbol;
+
+// a set of characters, written as the label of a terminal in a scannerless grammar. a label is
+// either a single character, e.g. + or x, or a class in brackets, e.g. [a-zA-Z_] or [^"\\]. a
+// backslash escapes the next character, and \n, \t, \r, \s (a space) and \u{hex} stand for the
+// characters they usually do, so that whitespace can be written in the space separated bnf form.
+// [^] matches any character
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct CharClass {
+    ranges: Vec<(char,char)>,
+    negated: bool,
+}
+
+impl CharClass {
+    pub fn parse(label: &str) -> Result<CharClass,String> {
+        let mut chars = label.chars().peekable();
+        if label.chars().count() > 1 && label.starts_with('[') {
+            if !label.ends_with(']') || label.ends_with("\\]") && !label.ends_with("\\\\]") {
+                return Err("unterminated character class".to_string());
+            }
+            chars.next();
+            let negated = chars.next_if_eq(&'^').is_some();
+            let mut ranges = Vec::new();
+            loop {
+                let start = match chars.next() {
+                    Some(']') if chars.peek().is_none() => break,
+                    Some(c) => escaped(c, &mut chars)?,
+                    None => unreachable!(),
+                };
+                let mut end = start;
+                if chars.peek() == Some(&'-') {
+                    chars.next();
+                    end = match chars.next() {
+                        // a - just before the closing bracket is itself
+                        Some(']') if chars.peek().is_none() => {
+                            ranges.push(('-', '-'));
+                            ranges.push((start, start));
+                            break;
+                        },
+                        Some(c) => escaped(c, &mut chars)?,
+                        None => unreachable!(),
+                    };
+                    if end < start {
+                        return Err(format!("the range {}-{} is backwards", start, end));
+                    }
+                }
+                ranges.push((start, end));
+            }
+            return Ok(CharClass { ranges, negated });
+        }
+        let c = match chars.next() {
+            Some(c) => escaped(c, &mut chars)?,
+            None => return Err("empty label".to_string()),
+        };
+        if chars.next().is_some() {
+            return Err("not a single character or a character class".to_string());
+        }
+        Ok(CharClass { ranges: vec![(c, c)], negated: false })
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|(start, end)| *start <= c && c <= *end) != self.negated
+    }
+
+    // whether the class is a single character rather than a bracketed class
+    fn is_single(&self) -> bool {
+        !self.negated && self.ranges.len() == 1 && self.ranges[0].0 == self.ranges[0].1
+    }
+}
+
+// the character that c (having been read from chars) stands for, reading the rest of an escape
+fn escaped<I: Iterator<Item = char>>(c: char, chars: &mut I) -> Result<char,String> {
+    if c != '\\' {
+        return Ok(c);
+    }
+    match chars.next() {
+        Some('n') => Ok('\n'),
+        Some('t') => Ok('\t'),
+        Some('r') => Ok('\r'),
+        Some('s') => Ok(' '),
+        Some('u') => {
+            if chars.next() != Some('{') {
+                return Err("expected { after \\u".to_string());
+            }
+            let hex: String = chars.by_ref().take_while(|c| *c != '}').collect();
+            u32::from_str_radix(&hex, 16).ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("\\u{{{}}} isn't a character", hex))
+        },
+        Some(c) if !c.is_alphanumeric() => Ok(c),
+        Some(c) => Err(format!("unknown escape \\{}", c)),
+        None => Err("nothing to escape after \\".to_string()),
+    }
+}
+
+// a terminal whose label isn't a valid class, for a scannerless grammar or a byte grammar
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct ClassError {
+    pub label: String,
+    pub message: String,
+}
+
+impl fmt::Display for ClassError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "terminal {}: {}", self.label, self.message)
+    }
+}
+
+// a character of the input, the token of a scannerless parse. the end of input is a token with no
+// character and an empty span at the end of the text
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct CharToken {
+    pub ch: Option<char>,
+    pub span: Range<usize>,
+}
+
+impl CharToken {
+    // the tokens a scannerless parse of the text works on, for mapping errors back to the text
+    pub fn of(text: &str) -> Vec<CharToken> {
+        text.char_indices()
+            .map(|(i, c)| CharToken { ch: Some(c), span: i..i + c.len_utf8() })
+            .chain(std::iter::once(CharToken { ch: None, span: text.len()..text.len() }))
+            .collect()
+    }
+}
+
+impl Spanned for CharToken {
+    fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+// a parser for a grammar whose terminals are characters and character classes, so that the input
+// is parsed as it is, without a lexer. that suits small formats like escape sequences and number
+// literals, where a separate tokenizer would be more work than the grammar, e.g.
+//
+//   number -> digits | digits . digits
+//   digits -> [0-9] | digits [0-9]
+//
+// the classes of different terminals may overlap. each character is given to the terminals that
+// contain it in turn, single characters before classes, and the first one the parser can shift
+// (after any reductions) is taken. so a character is only ambiguous when the parser could go on
+// with more than one of its terminals, which is a conflict in the grammar at the character level
+pub struct Scannerless {
+    parser: Parser,
+    terminals: Vec<(Symbol,CharClass)>,
+}
+
+impl Scannerless {
+    pub fn new(grammar: Grammar) -> Result<Scannerless,ClassError> {
+        let symbol_db = grammar.symbol_db();
+        let mut terminals = Vec::new();
+        for t in &grammar.declared_terminals() {
+            let label = symbol_db.label(&t).cloned().unwrap_or_default();
+            match CharClass::parse(&label) {
+                Ok(class) => terminals.push((t, class)),
+                Err(message) => return Err(ClassError { label, message }),
+            }
+        }
+        terminals.sort_by_key(|(t, class)| (!class.is_single(), *t));
+        Ok(Scannerless { parser: Parser::new(grammar), terminals })
+    }
+
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    pub fn grammar(&self) -> &Grammar {
+        self.parser.grammar()
+    }
+
+    // parse the text, one character per token. the positions in errors count characters, and
+    // CharToken::of gives the tokens to map them back to the text
+    pub fn parse(&self, text: &str) -> Result<ParseTree<CharToken>,ParseError> {
+        let mut pp = PushParser::new(&self.parser);
+        for (position, token) in CharToken::of(text).into_iter().enumerate() {
+            let c = match token.ch {
+                Some(c) => c,
+                None => {
+                    pp.push(token, self.grammar().symbol_db().eoi())?;
+                    break;
+                },
+            };
+            let candidates = self.terminals.iter().filter(|(_, class)| class.contains(c)).map(|(t, _)| *t);
+            push_first(&mut pp, token, candidates, position)?;
+        }
+        pp.finish()
+    }
+}
+
+// push the token as the first of the candidate terminals that the parser can shift, trying them
+// in order. the error is the one for the first candidate when none of them can be shifted
+pub(crate) fn push_first<T,I>(pp: &mut PushParser<T>, token: T, candidates: I, position: usize) -> Result<(),ParseError>
+    where T: Clone, I: Iterator<Item = Symbol> {
+    let mut candidates = candidates;
+    let first = candidates.next().ok_or(ParseError::UnexpectedCharacter { position })?;
+    let rest: Vec<Symbol> = candidates.collect();
+    if rest.is_empty() {
+        return pp.push(token, first);
+    }
+    let snapshot = pp.snapshot();
+    let mut result = pp.push(token.clone(), first);
+    for t in rest {
+        if result.is_ok() {
+            break;
+        }
+        pp.restore(snapshot);
+        if pp.push(token.clone(), t).is_ok() {
+            result = Ok(());
+        }
+    }
+    if result.is_err() {
+        pp.restore(snapshot);
+    }
+    pp.commit();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn char_class_01() {
+        let class = |label: &str| CharClass::parse(label).unwrap();
+        assert!(class("x").contains('x'));
+        assert!(!class("x").contains('y'));
+        assert!(class("[").contains('['));
+        assert!(class("\\s").contains(' '));
+        assert!(class("\\#").contains('#'));
+        assert!(class("\\u{e9}").contains('é'));
+        let c = class("[a-zA-Z_]");
+        assert!(c.contains('q') && c.contains('Q') && c.contains('_') && !c.contains('1'));
+        let c = class("[^\"\\\\]");
+        assert!(c.contains('a') && !c.contains('"') && !c.contains('\\'));
+        let c = class("[+-]");
+        assert!(c.contains('+') && c.contains('-') && !c.contains(','));
+        let c = class("[\\]\\s]");
+        assert!(c.contains(']') && c.contains(' '));
+        assert!(class("[^]").contains('\n'));
+        assert!(CharClass::parse("ab").is_err());
+        assert!(CharClass::parse("[a-").is_err());
+        assert!(CharClass::parse("[z-a]").is_err());
+        assert!(CharClass::parse("\\q").is_err());
+    }
+
+    /* grammar:
+     *   string -> " chars "
+     *   chars -> ε | chars char
+     *   char -> [^"\\] | \\ escape
+     *   escape -> [nt"\\] | u hex hex hex hex
+     *   hex -> [0-9a-f]
+     */
+    #[test]
+    fn scannerless_01() {
+        let bnf = r#"
+            string -> " chars "
+            chars -> ε | chars char
+            char -> [^"\\] | \\ escape
+            escape -> [nt"\\] | u hex hex hex hex
+            hex -> [0-9a-f]
+        "#;
+        let s = Scannerless::new(Grammar::from_bnf(bnf, "string").unwrap()).unwrap();
+        assert!(s.parser().parse_tables().conflicts().is_empty());
+        let text = r#""a\n\"é\u00e9""#;
+        let tree = s.parse(text).unwrap();
+        assert_eq!(tree.span(), 0..text.len());
+        let db = s.grammar().symbol_db();
+        let escape = db.symbol("escape").unwrap();
+        let mut escapes = Vec::new();
+        tree.fold(&mut |node: &ParseTree<CharToken>, _: Vec<()>| {
+            if *node.symbol() == escape {
+                escapes.push(text[node.span()].to_string());
+            }
+        });
+        assert_eq!(escapes, vec!["n", "\"", "u00e9"]);
+
+        let text = "\"a\\q\"";
+        let error = s.parse(text).err().unwrap();
+        assert_eq!(error.position(), 3);
+        assert_eq!(error.span(&CharToken::of(text)), Some(3..4));
+        assert!(s.parse("\"abc").is_err());
+
+        let error = Scannerless::new(Grammar::from_bnf("s -> ab", "g").unwrap()).err().unwrap();
+        assert_eq!(error.to_string(), "terminal ab: not a single character or a character class");
+    }
+
+    /* grammar:
+     *   number -> digits | digits . digits
+     *   digits -> [0-9] | digits [0-9]
+     */
+    #[test]
+    fn scannerless_02() {
+        let s = Scannerless::new(Grammar::from_bnf("number -> digits | digits . digits\ndigits -> [0-9] | digits [0-9]", "n").unwrap()).unwrap();
+        assert!(s.parse("12.50").is_ok());
+        assert!(s.parse("12.").is_err());
+        assert_eq!(s.parse("1x").err().unwrap(), ParseError::UnexpectedCharacter { position: 1 });
+    }
+}
@@ -0,0 +1,109 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash,Hasher};
+use std::ops::Deref;
+
+// an immutable sequence that keeps up to N items inline and only goes to the heap for longer
+// ones. most productions have short right hand sides, and they get cloned into every item that
+// mentions them, so this saves a lot of small allocations while building the tables
+#[derive(Clone)]
+pub enum SmallVec<T: Copy, const N: usize> {
+    Inline { items: [T; N], len: usize },
+    // also used for the empty sequence, since an empty Vec doesn't allocate and there is no item
+    // to pad the inline array with
+    Heap(Vec<T>),
+}
+
+impl<T: Copy, const N: usize> SmallVec<T,N> {
+    pub fn from_slice(items: &[T]) -> SmallVec<T,N> {
+        if items.is_empty() || items.len() > N {
+            SmallVec::Heap(items.to_vec())
+        } else {
+            let mut inline = [items[0]; N];
+            inline[..items.len()].copy_from_slice(items);
+            SmallVec::Inline { items: inline, len: items.len() }
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        match self {
+            SmallVec::Inline { items, len } => &items[..*len],
+            SmallVec::Heap(items) => items,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_inline(&self) -> bool {
+        matches!(self, SmallVec::Inline { .. })
+    }
+}
+
+impl<T: Copy, const N: usize> Deref for SmallVec<T,N> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T: Copy, const N: usize> From<Vec<T>> for SmallVec<T,N> {
+    fn from(items: Vec<T>) -> SmallVec<T,N> {
+        if items.len() > N {
+            SmallVec::Heap(items)
+        } else {
+            SmallVec::from_slice(&items)
+        }
+    }
+}
+
+// the comparisons all go through the slice, so it doesn't matter where the items are stored
+
+impl<T: Copy + PartialEq, const N: usize> PartialEq for SmallVec<T,N> {
+    fn eq(&self, other: &SmallVec<T,N>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Copy + Eq, const N: usize> Eq for SmallVec<T,N> {}
+
+impl<T: Copy + PartialOrd, const N: usize> PartialOrd for SmallVec<T,N> {
+    fn partial_cmp(&self, other: &SmallVec<T,N>) -> Option<Ordering> {
+        self.as_slice().partial_cmp(other.as_slice())
+    }
+}
+
+impl<T: Copy + Ord, const N: usize> Ord for SmallVec<T,N> {
+    fn cmp(&self, other: &SmallVec<T,N>) -> Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+impl<T: Copy + Hash, const N: usize> Hash for SmallVec<T,N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl<T: Copy + fmt::Debug, const N: usize> fmt::Debug for SmallVec<T,N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_vec_01() {
+        let empty: SmallVec<u32,4> = SmallVec::from(vec![]);
+        assert!(empty.is_empty());
+        let short: SmallVec<u32,4> = SmallVec::from(vec![1, 2, 3]);
+        assert!(short.is_inline());
+        assert_eq!(short.as_slice(), &[1, 2, 3]);
+        let long: SmallVec<u32,4> = SmallVec::from(vec![1, 2, 3, 4, 5]);
+        assert!(!long.is_inline());
+        assert_eq!(long.len(), 5);
+        assert!(short < long);
+        assert_eq!(SmallVec::<u32,4>::from_slice(&long[..3]), short);
+    }
+}
@@ -0,0 +1,22 @@
+use std::fmt;
+
+// where something was written in a text file, with lines and columns counted from 1. columns
+// count characters, not bytes
+#[derive(Clone,Debug,Eq,Hash,Ord,PartialEq,PartialOrd)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceLocation {
+    pub fn new(file: &str, line: usize, column: usize) -> SourceLocation {
+        SourceLocation { file: file.to_string(), line, column }
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
@@ -0,0 +1,139 @@
+use std::ops::Range;
+
+use super::source_location::SourceLocation;
+
+// converts byte offsets into an input to lines and columns, for error messages and tools. lines
+// and columns are counted from 1, and columns count characters rather than bytes, so that they
+// agree with what an editor shows for text that isn't ascii
+#[derive(Clone,Debug)]
+pub struct SourceMap {
+    file: String,
+    text: String,
+    // the offset at which each line starts
+    line_starts: Vec<usize>,
+}
+
+impl SourceMap {
+    pub fn new(file: &str, text: &str) -> SourceMap {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        SourceMap { file: file.to_string(), text: text.to_string(), line_starts }
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    // the line and column of the character at the offset. an offset in the middle of a character
+    // counts as that character, and offsets past the end are taken to be at the end
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let start = self.line_starts[line];
+        let column = self.text[start..].char_indices().take_while(|(i, c)| start + i + c.len_utf8() <= offset).count();
+        (line + 1, column + 1)
+    }
+
+    pub fn location(&self, offset: usize) -> SourceLocation {
+        let (line, column) = self.line_column(offset);
+        SourceLocation::new(&self.file, line, column)
+    }
+
+    // the byte offset of a line and column, if there's such a place in the text. the column just
+    // past the end of a line is allowed
+    pub fn offset(&self, line: usize, column: usize) -> Option<usize> {
+        let span = self.line_span(line)?;
+        let line_text = &self.text[span.clone()];
+        match line_text.char_indices().nth(column.checked_sub(1)?) {
+            Some((i, _)) => Some(span.start + i),
+            None if column == line_text.chars().count() + 1 => Some(span.end),
+            None => None,
+        }
+    }
+
+    // the bytes of a line, without its line break (\n or \r\n)
+    pub fn line_span(&self, line: usize) -> Option<Range<usize>> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let mut end = self.line_starts.get(line).map(|next| next - 1).unwrap_or(self.text.len());
+        if self.text[start..end].ends_with('\r') {
+            end -= 1;
+        }
+        Some(start..end)
+    }
+
+    pub fn line(&self, line: usize) -> Option<&str> {
+        self.line_span(line).map(|span| &self.text[span])
+    }
+
+    // a message about a span of the text, in the usual compiler style: the location, the message,
+    // then the first line of the span with the span underlined, e.g.
+    //
+    //   input.txt:2:5: unexpected )
+    //   let x) = 1
+    //       ^
+    pub fn render(&self, span: Range<usize>, message: &str) -> String {
+        let (line, column) = self.line_column(span.start);
+        let line_span = self.line_span(line).unwrap();
+        let line_text = &self.text[line_span.clone()];
+        // a span can start on the line break (the \r of a \r\n), past the end of the line's text
+        let start = span.start.min(line_span.end);
+        let end = span.end.clamp(start, line_span.end);
+        let width = self.text[start..end].chars().count().max(1);
+        format!("{}:{}:{}: {}\n{}\n{}{}\n", self.file, line, column, message, line_text, " ".repeat(column - 1), "^".repeat(width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_map_01() {
+        let map = SourceMap::new("a.txt", "ab\r\nçé x\n\nlast");
+        assert_eq!(map.line_count(), 4);
+        assert_eq!(map.line_column(0), (1, 1));
+        assert_eq!(map.line_column(2), (1, 3));
+        assert_eq!(map.line_column(4), (2, 1));
+        // ç and é are two bytes each
+        assert_eq!(map.line_column(8), (2, 3));
+        assert_eq!(map.line_column(9), (2, 4));
+        assert_eq!(map.line_column(5), (2, 1));
+        assert_eq!(map.line_column(11), (3, 1));
+        assert_eq!(map.line_column(100), (4, 5));
+        assert_eq!(map.location(9).to_string(), "a.txt:2:4");
+
+        assert_eq!(map.line(1), Some("ab"));
+        assert_eq!(map.line(2), Some("çé x"));
+        assert_eq!(map.line(3), Some(""));
+        assert_eq!(map.line(5), None);
+        assert_eq!(map.offset(2, 4), Some(9));
+        assert_eq!(map.offset(2, 5), Some(10));
+        assert_eq!(map.offset(2, 6), None);
+        assert_eq!(map.offset(0, 1), None);
+        for offset in [0, 1, 4, 6, 8, 9, 10, 11, 12, 15] {
+            let (line, column) = map.line_column(offset);
+            assert_eq!(map.offset(line, column), Some(offset));
+        }
+
+        assert_eq!(map.render(9..10, "unexpected x"), "a.txt:2:4: unexpected x\nçé x\n   ^\n");
+        assert_eq!(map.render(4..100, "everything"), "a.txt:2:1: everything\nçé x\n^^^^\n");
+        assert_eq!(map.render(15..15, "end"), "a.txt:4:4: end\nlast\n   ^\n");
+    }
+
+    #[test]
+    fn source_map_02() {
+        // a newline token that takes in the \r\n of a line
+        let map = SourceMap::new("x", "ab\r\ncd");
+        assert_eq!(map.render(2..4, "m"), "x:1:3: m\nab\n  ^\n");
+        assert_eq!(map.render(3..4, "m"), "x:1:4: m\nab\n   ^\n");
+        assert_eq!(map.render(4..6, "m"), "x:2:1: m\ncd\n^^\n");
+    }
+}
@@ -0,0 +1,19 @@
+use std::ops::Range;
+
+// tokens that know which bytes of the input they came from. parse trees built from such tokens
+// can report the span of every node, and parse errors can be mapped back to the input
+pub trait Spanned {
+    fn span(&self) -> Range<usize>;
+}
+
+impl<T: Spanned> Spanned for &T {
+    fn span(&self) -> Range<usize> {
+        (*self).span()
+    }
+}
+
+impl Spanned for Range<usize> {
+    fn span(&self) -> Range<usize> {
+        self.clone()
+    }
+}
@@ -1,82 +1,264 @@
-use std::collections::{HashMap,HashSet};
+use std::collections::HashMap;
+
+use super::symbol_set::{NonTerminalSet,TerminalSet};
 
 #[derive(Clone,Copy,Debug,Eq,Hash,Ord,PartialOrd,PartialEq)]
 pub struct Symbol(usize);
 
-#[derive(Debug)]
+impl Symbol {
+    pub(crate) fn index(&self) -> usize {
+        self.0
+    }
+
+    pub(crate) fn from_index(index: usize) -> Symbol {
+        Symbol(index)
+    }
+}
+
+// the built in symbols, which every db makes first
+const GOAL: Symbol = Symbol(0);
+const EOI: Symbol = Symbol(1);
+const EPSILON: Symbol = Symbol(2);
+
+#[derive(Clone,Debug)]
 pub struct SymbolDb {
     next: usize,
-    from_label: HashMap<String,Symbol>,
+    // terminals and nonterminals have separate labels, so a token and a rule can share a name.
+    // aliases are in here too
+    terminal_labels: HashMap<String,Symbol>,
+    nonterminal_labels: HashMap<String,Symbol>,
     to_label: HashMap<Symbol,String>,
-    terminals: HashSet<Symbol>,
-    non_terminals: HashSet<Symbol>,
+    // symbols are numbered in the order they're made, so these iterate in that order
+    terminals: TerminalSet,
+    non_terminals: NonTerminalSet,
+    // for each base given to fresh_nonterminal, the last number it used
+    fresh: HashMap<String,usize>,
+}
+
+impl Default for SymbolDb {
+    fn default() -> SymbolDb {
+        SymbolDb::new()
+    }
 }
 
 impl SymbolDb {
     pub fn new() -> SymbolDb {
+        SymbolDb::with_capacity(0)
+    }
+
+    // a db with room for this many symbols besides the built in ones, so that making a lot of them
+    // doesn't keep growing the maps
+    pub fn with_capacity(capacity: usize) -> SymbolDb {
+        SymbolDb::make(capacity, "GOAL", "$", "ε")
+    }
+
+    // a db whose built in symbols have other labels than GOAL, $ and ε, for a language that needs
+    // one of those as a token
+    pub fn with_special_labels(goal: &str, eoi: &str, epsilon: &str) -> SymbolDb {
+        SymbolDb::make(0, goal, eoi, epsilon)
+    }
+
+    fn make(capacity: usize, goal: &str, eoi: &str, epsilon: &str) -> SymbolDb {
+        let capacity = capacity + 3;
         let mut s = SymbolDb {
             next: 0,
-            from_label: HashMap::new(),
-            to_label: HashMap::new(),
-            terminals: HashSet::new(),
-            non_terminals: HashSet::new(),
+            terminal_labels: HashMap::with_capacity(capacity),
+            nonterminal_labels: HashMap::with_capacity(capacity),
+            to_label: HashMap::with_capacity(capacity),
+            terminals: TerminalSet::new(),
+            non_terminals: NonTerminalSet::new(),
+            fresh: HashMap::new(),
         };
-        s.new_nonterminal("GOAL");
-        s.new_terminal("$");
-        s.new_terminal("ε");
+        // made first, so that they're always GOAL, EOI and EPSILON whatever they get renamed to
+        s.new_nonterminal(goal);
+        s.new_terminal(eoi);
+        s.new_terminal(epsilon);
         s
     }
 
-    fn new_symbol(&mut self, label: &str) -> Symbol {
-        if self.from_label.contains_key(label) {
-            panic!("the symbol [{}] is already defined", label);
-        }
+    fn new_symbol(&mut self, label: &str, terminal: bool) -> Symbol {
         let s = Symbol(self.next);
-        self.next = self.next + 1;
-        self.from_label.insert(label.to_string(), s);
+        self.claim(label, s, terminal);
+        self.next += 1;
         self.to_label.insert(s, label.to_string());
         s
     }
 
+    // make the label refer to s, among the terminals or the nonterminals. it can't already refer
+    // to something else, and only the built in symbols can have their labels, in either namespace
+    fn claim(&mut self, label: &str, s: Symbol, terminal: bool) {
+        if self.reserved(label).is_some_and(|b| b != s) {
+            panic!("[{}] is the label of a built in symbol", label);
+        }
+        let labels = if terminal { &mut self.terminal_labels } else { &mut self.nonterminal_labels };
+        match labels.get(label) {
+            Some(other) if *other != s => {
+                let kind = if terminal { "terminal" } else { "nonterminal" };
+                panic!("the {} [{}] is already defined", kind, label);
+            },
+            _ => labels.insert(label.to_string(), s),
+        };
+    }
+
+    // give a symbol a new label, e.g. to tidy up a generated grammar for reports. the old label
+    // no longer finds it (unless it's one of the symbol's aliases), and its aliases stay
+    pub fn rename(&mut self, s: Symbol, label: &str) {
+        let terminal = self.is_terminal(&s);
+        let old = self.to_label.get(&s).expect("unknown symbol").clone();
+        self.claim(label, s, terminal);
+        if old != label {
+            let labels = if terminal { &mut self.terminal_labels } else { &mut self.nonterminal_labels };
+            labels.remove(&old);
+        }
+        self.to_label.insert(s, label.to_string());
+    }
+
+    // another label that finds the symbol, alongside the one it's shown with. e.g. a grammar
+    // imported from another tool can be given the names its tokens had there
+    pub fn add_alias(&mut self, s: Symbol, alias: &str) {
+        assert!(self.to_label.contains_key(&s), "unknown symbol");
+        let terminal = self.is_terminal(&s);
+        self.claim(alias, s, terminal);
+    }
+
+    // the labels that find the symbol other than the one it's shown with, sorted
+    pub fn aliases(&self, s: &Symbol) -> Vec<&str> {
+        let labels = if self.is_terminal(s) { &self.terminal_labels } else { &self.nonterminal_labels };
+        let mut result: Vec<&str> = labels.iter()
+            .filter(|(l, t)| *t == s && self.to_label.get(s) != Some(*l))
+            .map(|(l, _)| l.as_str())
+            .collect();
+        result.sort();
+        result
+    }
+
     pub fn new_nonterminal(&mut self, label: &str) -> Symbol {
-        let s = self.new_symbol(label);
+        let s = self.new_symbol(label, false);
         self.non_terminals.insert(s);
         s
     }
 
     pub fn new_terminal(&mut self, label: &str) -> Symbol {
-        let s = self.new_symbol(label);
+        let s = self.new_symbol(label, true);
         self.terminals.insert(s);
         s
     }
 
+    // make a nonterminal with a label no other symbol has: the base followed by a number, e.g.
+    // expr__rep1, then expr__rep2 for the next one with the same base. this is for the helper
+    // nonterminals that rewriting a grammar introduces, which need names that can't clash with
+    // the ones the author chose
+    pub fn fresh_nonterminal(&mut self, base: &str) -> Symbol {
+        let mut n = self.fresh.get(base).copied().unwrap_or(0);
+        let label = loop {
+            n += 1;
+            let label = format!("{}{}", base, n);
+            if self.symbol(&label).is_none() {
+                break label;
+            }
+        };
+        self.fresh.insert(base.to_string(), n);
+        self.new_nonterminal(&label)
+    }
+
+    // make a terminal for each label, e.g. let [plus, minus] = db.add_terminals(&["+", "-"])
+    pub fn add_terminals<const N: usize>(&mut self, labels: &[&str; N]) -> [Symbol; N] {
+        labels.map(|l| self.new_terminal(l))
+    }
+
+    pub fn add_nonterminals<const N: usize>(&mut self, labels: &[&str; N]) -> [Symbol; N] {
+        labels.map(|l| self.new_nonterminal(l))
+    }
+
+    // make a terminal for each label, in order, for when the labels aren't known until run time
+    // (e.g. the token kinds of a generated lexer)
+    pub fn intern_terminals<I,S>(&mut self, labels: I) -> Vec<Symbol> where I: IntoIterator<Item=S>, S: AsRef<str> {
+        let labels = labels.into_iter();
+        self.reserve(labels.size_hint().0);
+        labels.map(|l| self.new_terminal(l.as_ref())).collect()
+    }
+
+    pub fn intern_nonterminals<I,S>(&mut self, labels: I) -> Vec<Symbol> where I: IntoIterator<Item=S>, S: AsRef<str> {
+        let labels = labels.into_iter();
+        self.reserve(labels.size_hint().0);
+        labels.map(|l| self.new_nonterminal(l.as_ref())).collect()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.terminal_labels.reserve(additional);
+        self.nonterminal_labels.reserve(additional);
+        self.to_label.reserve(additional);
+    }
+
     pub fn is_terminal(&self, s: &Symbol) -> bool {
         self.terminals.contains(s)
     }
 
+    // GOAL, $ and ε, which every db has
+    pub fn is_builtin(&self, s: &Symbol) -> bool {
+        [GOAL, EOI, EPSILON].contains(s)
+    }
+
+    // the built in symbol that has the label (or has it as an alias), which no other symbol can
+    // be given
+    pub fn reserved(&self, label: &str) -> Option<Symbol> {
+        [self.terminal(label), self.nonterminal(label)].into_iter().flatten().find(|s| self.is_builtin(s))
+    }
+
     pub fn epsilon(&self) -> Symbol {
-        self.from_label.get("ε").expect("missing epsilon symbol").clone()
+        EPSILON
     }
 
     pub fn goal(&self) -> Symbol {
-        self.from_label.get("GOAL").expect("missing goal symbol").clone()
+        GOAL
     }
 
     pub fn eoi(&self) -> Symbol {
-        self.from_label.get("$").expect("missing end of input symbol").clone()
+        EOI
     }
 
-    pub fn terminals(&self) -> &HashSet<Symbol> {
+    // the terminals, in the order they were made (the built in $ and ε first)
+    pub fn terminals(&self) -> &TerminalSet {
         &self.terminals
     }
 
-    pub fn non_terminals(&self) -> &HashSet<Symbol> {
+    // the nonterminals, in the order they were made (the built in GOAL first)
+    pub fn non_terminals(&self) -> &NonTerminalSet {
         &self.non_terminals
     }
 
+    // the terminals that were made for the grammar, without $ and ε. these are the ones to look at
+    // when checking how the grammar uses its tokens
+    pub fn declared_terminals(&self) -> TerminalSet {
+        self.terminals.iter().filter(|s| !self.is_builtin(s)).collect()
+    }
+
+    // the nonterminals without GOAL
+    pub fn declared_nonterminals(&self) -> NonTerminalSet {
+        self.non_terminals.iter().filter(|s| !self.is_builtin(s)).collect()
+    }
+
     pub fn label(&self, s: &Symbol) -> Option<&String> {
         self.to_label.get(s)
     }
+
+    // the symbol with the label. when a terminal and a nonterminal share it, this is the terminal
+    pub fn symbol(&self, label: &str) -> Option<Symbol> {
+        self.terminal(label).or_else(|| self.nonterminal(label))
+    }
+
+    pub fn terminal(&self, label: &str) -> Option<Symbol> {
+        self.terminal_labels.get(label).copied()
+    }
+
+    pub fn nonterminal(&self, label: &str) -> Option<Symbol> {
+        self.nonterminal_labels.get(label).copied()
+    }
+
+    // symbols are numbered from zero, so every symbol's index is less than this
+    pub(crate) fn symbol_count(&self) -> usize {
+        self.next
+    }
 }
 
 #[cfg(test)]
@@ -104,5 +286,144 @@ mod tests {
         let s = db.new_terminal("foo");
         assert!(db.is_terminal(&s));
     }
-}
 
+    #[test]
+    fn symbol_db_04() {
+        let mut db = SymbolDb::new();
+        let z = db.new_terminal("z");
+        let a = db.new_terminal("a");
+        let y = db.new_nonterminal("y");
+        let b = db.new_nonterminal("b");
+        assert_eq!(db.terminals().iter().collect::<Vec<_>>(), vec![db.eoi(), db.epsilon(), z, a]);
+        assert_eq!(db.non_terminals().iter().collect::<Vec<_>>(), vec![db.goal(), y, b]);
+    }
+
+    #[test]
+    fn symbol_db_05() {
+        let mut db = SymbolDb::with_capacity(100);
+        let [plus, minus] = db.add_terminals(&["+", "-"]);
+        let [e, t] = db.add_nonterminals(&["e", "t"]);
+        assert!(db.is_terminal(&plus) && db.is_terminal(&minus));
+        assert!(!db.is_terminal(&e) && !db.is_terminal(&t));
+        assert_eq!(db.label(&minus).unwrap(), "-");
+
+        let kinds = vec!["id".to_string(), "num".to_string()];
+        let tokens = db.intern_terminals(&kinds);
+        assert_eq!(tokens, vec![db.symbol("id").unwrap(), db.symbol("num").unwrap()]);
+        let rules = db.intern_nonterminals((1..=3).map(|i| format!("r{}", i)));
+        assert_eq!(rules.len(), 3);
+        assert_eq!(db.non_terminals().iter().collect::<Vec<_>>(), vec![db.goal(), e, t, rules[0], rules[1], rules[2]]);
+    }
+
+    #[test]
+    fn fresh_nonterminal_01() {
+        let mut db = SymbolDb::new();
+        // a label that's already taken is skipped
+        db.new_nonterminal("expr__rep2");
+        let a = db.fresh_nonterminal("expr__rep");
+        let b = db.fresh_nonterminal("expr__rep");
+        let c = db.fresh_nonterminal("list__tail");
+        assert_eq!(db.label(&a).unwrap(), "expr__rep1");
+        assert_eq!(db.label(&b).unwrap(), "expr__rep3");
+        assert_eq!(db.label(&c).unwrap(), "list__tail1");
+        assert!(!db.is_terminal(&a));
+    }
+
+    #[test]
+    fn shared_label_01() {
+        let mut db = SymbolDb::new();
+        let token = db.new_terminal("string");
+        let rule = db.new_nonterminal("string");
+        assert_ne!(token, rule);
+        assert_eq!(db.terminal("string"), Some(token));
+        assert_eq!(db.nonterminal("string"), Some(rule));
+        assert_eq!(db.symbol("string"), Some(token));
+        assert_eq!(db.label(&rule).unwrap(), "string");
+        assert_eq!(db.nonterminal("$"), None);
+        // a fresh label isn't taken in either space
+        db.new_terminal("s1");
+        let fresh = db.fresh_nonterminal("s");
+        assert_eq!(db.label(&fresh).unwrap(), "s2");
+    }
+
+    #[test]
+    #[should_panic(expected = "the terminal [x] is already defined")]
+    fn shared_label_02() {
+        let mut db = SymbolDb::new();
+        db.new_terminal("x");
+        db.new_terminal("x");
+    }
+
+    #[test]
+    fn rename_01() {
+        let mut db = SymbolDb::new();
+        let t = db.new_terminal("T_PLUS");
+        let e = db.new_nonterminal("expr_17");
+        db.rename(t, "+");
+        db.rename(e, "expr");
+        assert_eq!(db.label(&t).unwrap(), "+");
+        assert_eq!(db.symbol("+"), Some(t));
+        assert_eq!(db.symbol("T_PLUS"), None);
+        assert_eq!(db.nonterminal("expr"), Some(e));
+
+        db.add_alias(t, "PLUS");
+        db.add_alias(t, "T_PLUS");
+        assert_eq!(db.symbol("PLUS"), Some(t));
+        assert_eq!(db.aliases(&t), vec!["PLUS", "T_PLUS"]);
+        // renaming to an alias makes it the label, and the old label isn't kept
+        db.rename(t, "PLUS");
+        assert_eq!(db.label(&t).unwrap(), "PLUS");
+        assert_eq!(db.aliases(&t), vec!["T_PLUS"]);
+        assert_eq!(db.symbol("+"), None);
+
+        // the built in symbols can be renamed too
+        let eoi = db.eoi();
+        db.rename(eoi, "EOF");
+        assert_eq!(db.eoi(), eoi);
+        assert_eq!(db.label(&db.eoi()).unwrap(), "EOF");
+    }
+
+    #[test]
+    #[should_panic(expected = "the nonterminal [a] is already defined")]
+    fn rename_02() {
+        let mut db = SymbolDb::new();
+        db.new_nonterminal("a");
+        let b = db.new_nonterminal("b");
+        db.add_alias(b, "a");
+    }
+
+    #[test]
+    fn reserved_01() {
+        let mut db = SymbolDb::new();
+        let a = db.new_terminal("a");
+        let s = db.new_nonterminal("s");
+        assert_eq!(db.reserved("$"), Some(db.eoi()));
+        assert_eq!(db.reserved("GOAL"), Some(db.goal()));
+        assert_eq!(db.reserved("a"), None);
+        assert!(db.is_builtin(&db.epsilon()) && !db.is_builtin(&a));
+        assert_eq!(db.declared_terminals().iter().collect::<Vec<_>>(), vec![a]);
+        assert_eq!(db.declared_nonterminals().iter().collect::<Vec<_>>(), vec![s]);
+        // the built in symbols can still be renamed
+        db.rename(db.eoi(), "EOF");
+        assert_eq!(db.reserved("EOF"), Some(db.eoi()));
+        assert_eq!(db.reserved("$"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "[$] is the label of a built in symbol")]
+    fn reserved_02() {
+        let mut db = SymbolDb::new();
+        db.new_nonterminal("$");
+    }
+
+    #[test]
+    fn special_labels_01() {
+        let mut db = SymbolDb::with_special_labels("START", "EOF", "EMPTY");
+        assert_eq!(db.label(&db.eoi()).unwrap(), "EOF");
+        assert_eq!(db.symbol("EMPTY"), Some(db.epsilon()));
+        assert_eq!(db.nonterminal("START"), Some(db.goal()));
+        let dollar = db.new_terminal("$");
+        let epsilon = db.new_terminal("ε");
+        assert_eq!(db.declared_terminals().iter().collect::<Vec<_>>(), vec![dollar, epsilon]);
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use super::symbol::{Symbol,SymbolDb};
+
+// what a set holds. the kind only keeps terminal and nonterminal sets from being mixed up, the
+// symbols themselves aren't checked
+#[derive(Clone,Copy,Debug)]
+pub enum Terminals {}
+#[derive(Clone,Copy,Debug)]
+pub enum NonTerminals {}
+
+pub type TerminalSet = SymbolSet<Terminals>;
+pub type NonTerminalSet = SymbolSet<NonTerminals>;
+
+// a set of symbols as a bit per symbol index. symbols are numbered in the order they're made, so
+// iterating a set gives them in that order
+pub struct SymbolSet<K> {
+    words: Vec<u64>,
+    kind: PhantomData<K>,
+}
+
+impl<K> SymbolSet<K> {
+    pub fn new() -> SymbolSet<K> {
+        SymbolSet { words: Vec::new(), kind: PhantomData }
+    }
+
+    // true if the symbol wasn't in the set already
+    pub fn insert(&mut self, s: Symbol) -> bool {
+        let (word, bit) = (s.index() / 64, 1 << (s.index() % 64));
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let added = self.words[word] & bit == 0;
+        self.words[word] |= bit;
+        added
+    }
+
+    // true if the symbol was in the set
+    pub fn remove(&mut self, s: &Symbol) -> bool {
+        let present = self.contains(s);
+        if present {
+            self.words[s.index() / 64] &= !(1 << (s.index() % 64));
+        }
+        present
+    }
+
+    pub fn contains(&self, s: &Symbol) -> bool {
+        self.words.get(s.index() / 64).is_some_and(|w| w & (1 << (s.index() % 64)) != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|w| *w == 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.words.clear();
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { words: &self.words, word: 0, bits: self.words.first().copied().unwrap_or(0) }
+    }
+
+    // add everything in other, returning true if that added anything
+    pub fn union_with(&mut self, other: &SymbolSet<K>) -> bool {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        let mut changed = false;
+        for (w, o) in self.words.iter_mut().zip(&other.words) {
+            changed |= *o & !*w != 0;
+            *w |= o;
+        }
+        changed
+    }
+
+    pub fn union(&self, other: &SymbolSet<K>) -> SymbolSet<K> {
+        let mut result = self.clone();
+        result.union_with(other);
+        result
+    }
+
+    pub fn intersection(&self, other: &SymbolSet<K>) -> SymbolSet<K> {
+        let words = self.words.iter().zip(&other.words).map(|(a, b)| a & b).collect();
+        SymbolSet { words, kind: PhantomData }
+    }
+
+    pub fn difference(&self, other: &SymbolSet<K>) -> SymbolSet<K> {
+        let words = self.words.iter().enumerate()
+            .map(|(i, a)| a & !other.words.get(i).copied().unwrap_or(0))
+            .collect();
+        SymbolSet { words, kind: PhantomData }
+    }
+
+    pub fn is_subset(&self, other: &SymbolSet<K>) -> bool {
+        self.words.iter().enumerate().all(|(i, a)| a & !other.words.get(i).copied().unwrap_or(0) == 0)
+    }
+
+    // something that formats the set with the labels of its symbols, e.g. {+, x, $}
+    pub fn display<'a>(&'a self, symbol_db: &'a SymbolDb) -> SetDisplay<'a,K> {
+        SetDisplay { set: self, symbol_db }
+    }
+}
+
+impl<K> Default for SymbolSet<K> {
+    fn default() -> SymbolSet<K> {
+        SymbolSet::new()
+    }
+}
+
+// derived impls would want K to implement the traits as well
+impl<K> Clone for SymbolSet<K> {
+    fn clone(&self) -> SymbolSet<K> {
+        SymbolSet { words: self.words.clone(), kind: PhantomData }
+    }
+}
+
+impl<K> fmt::Debug for SymbolSet<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+// sets are equal when they have the same symbols, however many words they've grown to
+impl<K> PartialEq for SymbolSet<K> {
+    fn eq(&self, other: &SymbolSet<K>) -> bool {
+        let n = self.words.len().max(other.words.len());
+        (0..n).all(|i| self.words.get(i).copied().unwrap_or(0) == other.words.get(i).copied().unwrap_or(0))
+    }
+}
+
+impl<K> Eq for SymbolSet<K> {}
+
+impl<K> FromIterator<Symbol> for SymbolSet<K> {
+    fn from_iter<I: IntoIterator<Item=Symbol>>(iter: I) -> SymbolSet<K> {
+        let mut result = SymbolSet::new();
+        result.extend(iter);
+        result
+    }
+}
+
+impl<K> Extend<Symbol> for SymbolSet<K> {
+    fn extend<I: IntoIterator<Item=Symbol>>(&mut self, iter: I) {
+        for s in iter {
+            self.insert(s);
+        }
+    }
+}
+
+impl<'a,K> IntoIterator for &'a SymbolSet<K> {
+    type Item = Symbol;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+// the symbols of a set, in order
+pub struct Iter<'a> {
+    words: &'a [u64],
+    word: usize,
+    // the bits of the current word that haven't been given out yet
+    bits: u64,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Symbol;
+
+    fn next(&mut self) -> Option<Symbol> {
+        while self.bits == 0 {
+            self.word += 1;
+            self.bits = *self.words.get(self.word)?;
+        }
+        let bit = self.bits.trailing_zeros() as usize;
+        self.bits &= self.bits - 1;
+        Some(Symbol::from_index(self.word * 64 + bit))
+    }
+}
+
+pub struct SetDisplay<'a,K> {
+    set: &'a SymbolSet<K>,
+    symbol_db: &'a SymbolDb,
+}
+
+impl<K> fmt::Display for SetDisplay<'_,K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, s) in self.set.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", self.symbol_db.label(&s).ok_or(fmt::Error)?)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbol_set_01() {
+        let mut db = SymbolDb::new();
+        let symbols: Vec<Symbol> = (0..100).map(|i| db.new_terminal(&format!("t{}", i))).collect();
+        let mut a = TerminalSet::new();
+        assert!(a.is_empty());
+        assert!(a.insert(symbols[99]));
+        assert!(!a.insert(symbols[99]));
+        a.insert(symbols[0]);
+        a.insert(db.eoi());
+        assert_eq!(a.len(), 3);
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![db.eoi(), symbols[0], symbols[99]]);
+        assert_eq!(a.display(&db).to_string(), "{$, t0, t99}");
+
+        let b: TerminalSet = symbols[..2].iter().copied().collect();
+        assert_eq!(a.intersection(&b).iter().collect::<Vec<_>>(), vec![symbols[0]]);
+        assert_eq!(a.difference(&b).len(), 2);
+        assert_eq!(b.union(&a).len(), 4);
+        assert!(!a.is_subset(&b));
+        assert!(a.intersection(&b).is_subset(&b));
+
+        let mut c = b.clone();
+        assert!(!c.union_with(&b));
+        assert!(c.union_with(&a));
+        // sets with the same symbols are equal even if one has grown more words
+        assert!(c.remove(&symbols[99]));
+        assert!(!c.remove(&symbols[99]));
+        assert_eq!(c, [db.eoi(), symbols[0], symbols[1]].into_iter().collect());
+        assert!(!c.contains(&symbols[50]));
+        c.clear();
+        assert_eq!(c, TerminalSet::new());
+    }
+}
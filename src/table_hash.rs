@@ -0,0 +1,112 @@
+// without the fast-hash feature, only the tests use FxHasher
+#![cfg_attr(not(feature = "fast-hash"), allow(dead_code))]
+
+use std::collections::HashMap;
+use std::hash::Hasher;
+
+// the maps the parser hits on every token are keyed by small tuples of integers. the standard
+// library's hasher is built to resist denial of service attacks, which is wasted effort for keys
+// like these, so with the fast-hash feature the tables use a much cheaper hasher instead.
+// the standard library also seeds its hasher randomly, so the order the maps iterate in (and
+// with it the order of anything built by walking them) changes from run to run. with the
+// deterministic-hash feature the maps use the same hasher with a fixed seed. FxHasher has no seed
+// at all, so fast-hash is deterministic too
+#[cfg(feature = "fast-hash")]
+pub type TableHasher = std::hash::BuildHasherDefault<FxHasher>;
+#[cfg(all(feature = "deterministic-hash", not(feature = "fast-hash")))]
+pub type TableHasher = std::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+#[cfg(not(any(feature = "fast-hash", feature = "deterministic-hash")))]
+pub type TableHasher = std::collections::hash_map::RandomState;
+
+pub type TableMap<K,V> = HashMap<K,V,TableHasher>;
+
+// the hash function used by rustc (and the firefox code it came from). each word of input is
+// mixed in with a rotate, an xor, and a multiply
+#[derive(Clone,Copy,Debug,Default)]
+pub struct FxHasher {
+    hash: u64,
+}
+
+const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+impl FxHasher {
+    fn add_to_hash(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            self.add_to_hash(u64::from_le_bytes(chunk.try_into().unwrap()));
+        }
+        for b in chunks.remainder() {
+            self.add_to_hash(*b as u64);
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.add_to_hash(i);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::{BuildHasherDefault,Hash};
+
+    fn fx_hash<T: Hash>(t: &T) -> u64 {
+        let mut h = FxHasher::default();
+        t.hash(&mut h);
+        h.finish()
+    }
+
+    #[test]
+    fn fx_hasher_01() {
+        assert_eq!(fx_hash(&(1u32, 2usize)), fx_hash(&(1u32, 2usize)));
+        assert_ne!(fx_hash(&(1u32, 2usize)), fx_hash(&(2u32, 1usize)));
+        assert_ne!(fx_hash(&"ab"), fx_hash(&"ba"));
+
+        let mut map: HashMap<(u32,u32),u32,BuildHasherDefault<FxHasher>> = HashMap::default();
+        for i in 0..100 {
+            map.insert((i, i + 1), i);
+        }
+        assert_eq!(map.get(&(42, 43)), Some(&42));
+        assert_eq!(map.len(), 100);
+    }
+
+    #[cfg(any(feature = "fast-hash", feature = "deterministic-hash"))]
+    #[test]
+    fn table_hasher_01() {
+        use std::hash::BuildHasher;
+
+        // the same entries inserted in the same order come out in the same order every time
+        let build = || {
+            let mut map: TableMap<(u32,u32),u32> = TableMap::default();
+            for i in 0..100 {
+                map.insert((i * 7 % 13, i), i);
+            }
+            map.into_iter().collect::<Vec<_>>()
+        };
+        assert_eq!(build(), build());
+        let hash = |key: &(u32,u32)| TableHasher::default().hash_one(key);
+        assert_eq!(hash(&(1, 2)), hash(&(1, 2)));
+    }
+}
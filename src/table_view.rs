@@ -0,0 +1,631 @@
+use std::fmt;
+
+use super::grammar::Grammar;
+use super::parse_error::ParseError;
+use super::parse_tree::ParseTree;
+use super::symbol::Symbol;
+
+// serialized tables and lexers start with a magic number and then the version of their layout as
+// a little endian u32, so that bytes cached by one release of the crate are either read correctly
+// by a later one or turned away, never misread. the first layout had no version, and its magic
+// numbers ended in 1 (LRT1 and LXT1); bytes with those are read as version 1
+pub const FORMAT_VERSION: u32 = 3;
+
+pub(crate) struct Format {
+    magic: &'static [u8; 4],
+    legacy: &'static [u8; 4],
+}
+
+pub(crate) const TABLES: Format = Format { magic: b"LRTF", legacy: b"LRT1" };
+pub(crate) const LEXER: Format = Format { magic: b"LXTF", legacy: b"LXT1" };
+
+impl Format {
+    // the magic number and the current version
+    pub(crate) fn header(&self) -> Vec<u8> {
+        let mut bytes = self.magic.to_vec();
+        bytes.extend(FORMAT_VERSION.to_le_bytes());
+        bytes
+    }
+
+    // the version of the bytes and where the layout itself starts
+    pub(crate) fn read_header(&self, bytes: &[u8]) -> Result<(u32,usize),TableFormatError> {
+        if bytes.starts_with(self.legacy) {
+            return Ok((1, self.legacy.len()));
+        }
+        if !bytes.starts_with(self.magic) {
+            return Err(TableFormatError::BadMagic);
+        }
+        let start = self.magic.len() + 4;
+        if bytes.len() < start {
+            return Err(TableFormatError::Truncated { expected: start, actual: bytes.len() });
+        }
+        let version = u32::from_le_bytes(bytes[self.magic.len()..start].try_into().unwrap());
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(TableFormatError::UnsupportedVersion { version });
+        }
+        Ok((version, start))
+    }
+}
+
+// serialized tables or a lexer (from CompactTables::to_bytes or Lexer::to_bytes) rewritten in the
+// current version of the format, for a program that caches them to bring its cache up to date
+// after moving to a newer release of the crate. bytes that are already current come back as they
+// are
+pub fn upgrade_format(bytes: &[u8]) -> Result<Vec<u8>,TableFormatError> {
+    let format = [TABLES, LEXER].into_iter()
+        .find(|f| bytes.starts_with(f.magic) || bytes.starts_with(f.legacy))
+        .ok_or(TableFormatError::BadMagic)?;
+    let (mut version, start) = format.read_header(bytes)?;
+    let mut layout = bytes[start..].to_vec();
+    while version < FORMAT_VERSION {
+        layout = upgrade_layout(&format, version, layout)?;
+        version += 1;
+    }
+    let mut result = format.header();
+    result.extend(layout);
+    Ok(result)
+}
+
+// the layout of one version rewritten in the next one
+fn upgrade_layout(format: &Format, version: u32, mut layout: Vec<u8>) -> Result<Vec<u8>,TableFormatError> {
+    match version {
+        // version 2 only added the version number to the header
+        1 => Ok(layout),
+        // version 3 added the grammar's fingerprint to the header of the tables. it isn't known
+        // for old tables, so it's left as 0, which no grammar is checked against
+        2 if format.magic == TABLES.magic => {
+            let at = 5 * 4;
+            if layout.len() < at {
+                return Err(TableFormatError::Truncated { expected: format.magic.len() + 4 + at, actual: format.magic.len() + 4 + layout.len() });
+            }
+            layout.splice(at..at, [0; 8]);
+            Ok(layout)
+        },
+        2 => Ok(layout),
+        _ => unreachable!("no version {} of the format", version),
+    }
+}
+
+// bytes with the alignment of a u32, for embedding serialized tables with include_tables!
+#[repr(C, align(4))]
+pub struct Aligned<B: ?Sized>(pub B);
+
+// the bytes of a file, aligned for TableView::embedded, e.g.
+//
+//   static TABLES: &[u8] = include_tables!("grammar.tables");
+//
+// include_bytes! on its own only guarantees byte alignment
+#[macro_export]
+macro_rules! include_tables {
+    ($path:expr) => {{
+        static ALIGNED: &$crate::Aligned<[u8]> = &$crate::Aligned(*include_bytes!($path));
+        &ALIGNED.0
+    }};
+}
+
+// number of u32 words in the header of the tables' layout. the last two are the fingerprint of the
+// grammar, which versions before 3 didn't have
+const HEADER_WORDS: usize = 7;
+
+#[derive(Clone,Copy)]
+pub(crate) enum SymbolKind {
+    Unused = 0,
+    Terminal = 1,
+    Nonterminal = 2,
+}
+
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum TableFormatError {
+    // the bytes don't start with the magic number, so they aren't serialized tables at all
+    BadMagic,
+    // the bytes end before everything the header promises
+    Truncated { expected: usize, actual: usize },
+    // a value at the given byte offset is out of range, so the bytes have been corrupted
+    Invalid { offset: usize },
+    // the bytes were written by a newer release of the crate, in a version of the format this one
+    // can't read
+    UnsupportedVersion { version: u32 },
+    // embedded tables don't start on a u32 boundary (see include_tables!)
+    Misaligned,
+    // embedded tables were built for a different grammar than the one they're loaded for. the
+    // fingerprint is 0 for tables from before the format had one
+    GrammarMismatch { expected: u64, actual: u64 },
+}
+
+impl fmt::Display for TableFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableFormatError::BadMagic => write!(f, "not serialized tables"),
+            TableFormatError::Truncated { expected, actual } => {
+                write!(f, "serialized tables are truncated ({} bytes, expected {})", actual, expected)
+            },
+            TableFormatError::Invalid { offset } => write!(f, "serialized tables are corrupt at offset {}", offset),
+            TableFormatError::UnsupportedVersion { version } => {
+                write!(f, "serialized tables are in version {} of the format, which is newer than version {}", version, FORMAT_VERSION)
+            },
+            TableFormatError::Misaligned => write!(f, "serialized tables aren't aligned to 4 bytes"),
+            TableFormatError::GrammarMismatch { expected, actual } => {
+                write!(f, "serialized tables are for grammar {:016x}, not {:016x}", actual, expected)
+            },
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum ViewAction {
+    Accept,
+    Shift(u32),
+    // reduce by the production with the given index
+    Reduce(usize),
+}
+
+// read only access to tables serialized by CompactTables::to_bytes, straight from the bytes. the
+// bytes can come from anywhere, but the point is that they can be a memory mapped file: nothing
+// is copied or decoded up front, so opening even a huge table is instant and only the pages that
+// are actually used get read. the standard library has no mmap, so mapping the file is left to
+// the caller
+#[derive(Clone,Copy,Debug)]
+pub struct TableView<'a> {
+    bytes: &'a [u8],
+    version: u32,
+    // where the layout starts, after the magic number and version
+    start: usize,
+    state_count: usize,
+    terminal_count: usize,
+    nonterminal_count: usize,
+    production_count: usize,
+    symbol_count: usize,
+    fingerprint: u64,
+    // where each section starts, in words from the end of the magic number
+    symbols: usize,
+    actions: usize,
+    gotos: usize,
+    rhs_offsets: usize,
+    lhs: usize,
+    rhs: usize,
+    label_offsets: usize,
+    labels: usize,
+}
+
+impl<'a> TableView<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<TableView<'a>,TableFormatError> {
+        let (version, start) = TABLES.read_header(bytes)?;
+        // the counts come from the bytes, so the sizes worked out from them can overflow. one
+        // that does can't fit in any slice, which makes the header corrupt
+        let invalid = TableFormatError::Invalid { offset: start };
+        let check = |words: usize| {
+            let expected = words.checked_mul(4).and_then(|n| n.checked_add(start)).ok_or(invalid.clone())?;
+            if bytes.len() < expected {
+                Err(TableFormatError::Truncated { expected, actual: bytes.len() })
+            } else {
+                Ok(())
+            }
+        };
+        check(5)?;
+        let header = |i: usize| read_u32(bytes, start, i) as usize;
+
+        let state_count = header(0);
+        let terminal_count = header(1);
+        let nonterminal_count = header(2);
+        let production_count = header(3);
+        let symbol_count = header(4);
+        let (header_words, fingerprint) = match version {
+            1 | 2 => (5, 0),
+            _ => {
+                check(HEADER_WORDS)?;
+                (HEADER_WORDS, header(5) as u64 | (header(6) as u64) << 32)
+            },
+        };
+
+        // where a section of count items of size words, and then extra words, ends when it starts
+        // at at
+        let section = |at: usize, count: usize, size: usize, extra: usize| {
+            count.checked_mul(size).and_then(|n| n.checked_add(at)).and_then(|n| n.checked_add(extra)).ok_or(invalid.clone())
+        };
+        let symbols = header_words;
+        let actions = section(symbols, symbol_count, 2, 0)?;
+        let gotos = section(actions, state_count, terminal_count, 0)?;
+        let rhs_offsets = section(gotos, state_count, nonterminal_count, 0)?;
+        let lhs = section(rhs_offsets, production_count, 1, 1)?;
+        let rhs = section(lhs, production_count, 1, 0)?;
+        check(rhs)?;
+        // the last of the offsets is where the last right hand side ends
+        let rhs_length = read_u32(bytes, start, lhs - 1) as usize;
+        let label_offsets = section(rhs, rhs_length, 1, 0)?;
+        let labels = section(label_offsets, symbol_count, 1, 1)?;
+        check(labels)?;
+        let label_bytes = read_u32(bytes, start, labels - 1) as usize;
+        let expected = section(start + labels * 4, label_bytes, 1, 0)?;
+        if bytes.len() < expected {
+            return Err(TableFormatError::Truncated { expected, actual: bytes.len() });
+        }
+
+        Ok(TableView {
+            bytes,
+            version,
+            start,
+            state_count,
+            terminal_count,
+            nonterminal_count,
+            production_count,
+            symbol_count,
+            fingerprint,
+            symbols,
+            actions,
+            gotos,
+            rhs_offsets,
+            lhs,
+            rhs,
+            label_offsets,
+            labels,
+        })
+    }
+
+    // like new, for tables built into the program (see include_tables!). the bytes have to be
+    // aligned like u32s, and the tables have to have been built for the grammar, which is checked
+    // by its fingerprint. that's much cheaper than building the tables, so a program can keep its
+    // grammar in code and still start without doing the work of the table construction
+    pub fn embedded(bytes: &'a [u8], grammar: &Grammar) -> Result<TableView<'a>,TableFormatError> {
+        if !(bytes.as_ptr() as usize).is_multiple_of(std::mem::align_of::<u32>()) {
+            return Err(TableFormatError::Misaligned);
+        }
+        let view = TableView::new(bytes)?;
+        let expected = grammar.fingerprint();
+        if view.fingerprint != expected {
+            return Err(TableFormatError::GrammarMismatch { expected, actual: view.fingerprint });
+        }
+        Ok(view)
+    }
+
+    // the fingerprint of the grammar the tables were built for (see Grammar::fingerprint), or
+    // None for tables from before the format had it
+    pub fn grammar_fingerprint(&self) -> Option<u64> {
+        Some(self.fingerprint).filter(|f| *f != 0)
+    }
+
+    // the i-th word of the layout. new only checks that the sections fit in the bytes, not the
+    // values in them, so a word read at an index taken from the bytes can be out of range
+    fn word(&self, i: usize) -> Option<usize> {
+        let at = i.checked_mul(4)?.checked_add(self.start)?;
+        let word = self.bytes.get(at..at.checked_add(4)?)?;
+        Some(u32::from_le_bytes(word.try_into().unwrap()) as usize)
+    }
+
+    // the version of the format the tables were written in
+    pub fn format_version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.state_count
+    }
+
+    pub fn production_count(&self) -> usize {
+        self.production_count
+    }
+
+    // the symbol with the given label. this is a linear search, so look symbols up once up front
+    pub fn symbol(&self, label: &str) -> Option<Symbol> {
+        (0..self.symbol_count)
+            .map(Symbol::from_index)
+            .find(|s| self.label(s) == Some(label))
+    }
+
+    pub fn label(&self, symbol: &Symbol) -> Option<&'a str> {
+        let i = symbol.index();
+        if i >= self.symbol_count {
+            return None;
+        }
+        let start = self.start + self.labels * 4;
+        let from = start.checked_add(self.word(self.label_offsets + i)?)?;
+        let to = start.checked_add(self.word(self.label_offsets + i + 1)?)?;
+        std::str::from_utf8(self.bytes.get(from..to)?).ok()
+    }
+
+    fn column(&self, symbol: Symbol, kind: SymbolKind) -> Option<usize> {
+        let i = symbol.index();
+        if i >= self.symbol_count || self.word(self.symbols + 2 * i)? != kind as usize {
+            return None;
+        }
+        self.word(self.symbols + 2 * i + 1)
+    }
+
+    pub fn action(&self, state: u32, symbol: Symbol) -> Option<ViewAction> {
+        let state = state as usize;
+        let column = self.column(symbol, SymbolKind::Terminal)?;
+        if state >= self.state_count {
+            return None;
+        }
+        // the same encoding as CompactTables
+        let value = self.word(self.actions + state * self.terminal_count + column)?;
+        if value == 0 {
+            None
+        } else if value <= self.state_count {
+            Some(ViewAction::Shift((value - 1) as u32))
+        } else if value <= self.state_count + self.production_count {
+            Some(ViewAction::Reduce(value - self.state_count - 1))
+        } else {
+            Some(ViewAction::Accept)
+        }
+    }
+
+    pub fn transition(&self, state: u32, symbol: Symbol) -> Option<u32> {
+        let state = state as usize;
+        let column = self.column(symbol, SymbolKind::Nonterminal)?;
+        if state >= self.state_count {
+            return None;
+        }
+        match self.word(self.gotos + state * self.nonterminal_count + column)? {
+            0 => None,
+            n if n > self.state_count => None,
+            n => Some((n - 1) as u32),
+        }
+    }
+
+    // the left hand side of a production, or None if there's no such production or the bytes are
+    // corrupt
+    pub fn production_lhs(&self, production: usize) -> Option<Symbol> {
+        if production >= self.production_count {
+            return None;
+        }
+        self.word(self.lhs + production).map(Symbol::from_index)
+    }
+
+    // the right hand side of a production, or None if there's no such production or the bytes are
+    // corrupt
+    pub fn production_rhs(&self, production: usize) -> Option<impl Iterator<Item=Symbol> + 'a> {
+        if production >= self.production_count {
+            return None;
+        }
+        let view = *self;
+        let from = self.word(self.rhs_offsets + production)?;
+        let to = self.word(self.rhs_offsets + production + 1)?;
+        // new checked that the right hand sides fit in the bytes, up to the last offset
+        let rhs_length = self.word(self.lhs - 1)?;
+        if from > to || to > rhs_length {
+            return None;
+        }
+        Some((from..to).map(move |i| Symbol::from_index(view.word(view.rhs + i).unwrap())))
+    }
+
+    // parse straight from the view. this works like Parser::parse, without sub-parsers
+    pub fn parse<T,F>(&self, tokens: &[T], token_to_symbol: F) -> Result<ParseTree<T>,ParseError>
+        where T: Clone,
+              F: Fn(&T) -> Symbol {
+        let mut parse_stack: Vec<ParseTree<T>> = Vec::new();
+        let mut state_stack: Vec<u32> = vec![0];
+        let mut position = 0;
+        // reductions since the last shift. corrupt tables can reduce forever without shifting, so
+        // this is given a bound that tables built from a grammar never come near
+        let mut reductions = 0;
+        let mut depth = 0;
+
+        loop {
+            let token = tokens.get(position).ok_or(ParseError::UnexpectedEndOfInput { position })?;
+            let symbol = token_to_symbol(token);
+            let state = *state_stack.last().unwrap();
+            match self.action(state, symbol) {
+                Some(ViewAction::Accept) => {
+                    return parse_stack.pop().ok_or(ParseError::InternalError { state, symbol, position });
+                },
+                Some(ViewAction::Shift(next_state)) => {
+                    parse_stack.push(ParseTree::new(symbol, token.clone()));
+                    state_stack.push(next_state);
+                    position += 1;
+                    reductions = 0;
+                    depth = parse_stack.len();
+                },
+                Some(ViewAction::Reduce(p)) => {
+                    // the bytes aren't checked up front, so corrupt tables are caught here
+                    let internal_error = ParseError::InternalError { state, symbol, position };
+                    reductions += 1;
+                    if reductions > (depth + self.state_count + 1) * (self.state_count + 1) {
+                        return Err(internal_error);
+                    }
+                    let lhs = self.production_lhs(p).ok_or(internal_error.clone())?;
+                    let size = self.production_rhs(p).ok_or(internal_error.clone())?.count();
+                    let start = parse_stack.len().checked_sub(size).ok_or(internal_error)?;
+                    let children: Vec<ParseTree<T>> = parse_stack.drain(start..).collect();
+                    state_stack.truncate(state_stack.len() - size);
+                    // the view numbers the productions its own way, so there's no id to give the node
+                    parse_stack.push(ParseTree::from_parts(lhs, token.clone(), children, size == 0, None));
+                    let current_state = *state_stack.last().unwrap();
+                    let next_state = self.transition(current_state, lhs)
+                        .ok_or(ParseError::InternalError { state: current_state, symbol: lhs, position })?;
+                    state_stack.push(next_state);
+                },
+                None => return Err(ParseError::UnexpectedSymbol { state, symbol, position }),
+            }
+        }
+    }
+}
+
+// the i-th u32 of the layout that begins at start
+fn read_u32(bytes: &[u8], start: usize, i: usize) -> u32 {
+    let at = start + i * 4;
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compact_tables::CompactTables;
+    use crate::grammar::Grammar;
+    use crate::parse_tables::ParseTables;
+    use crate::parser::Parser;
+    use crate::production::Production;
+    use crate::symbol::SymbolDb;
+
+    /* grammar:
+     *   e1 -> ( e1 ) | ε
+     */
+    fn parens() -> Grammar {
+        let mut symbol_db = SymbolDb::new();
+        let e1 = symbol_db.new_nonterminal("E1");
+        let lp = symbol_db.new_terminal("(");
+        let rp = symbol_db.new_terminal(")");
+        let productions = vec![
+            Production::new(e1, vec![lp, e1, rp]),
+            Production::new(e1, vec![]),
+        ];
+        Grammar::new(symbol_db, e1, productions)
+    }
+
+    #[test]
+    fn table_view_01() {
+        let g = parens();
+        let tables = ParseTables::new(&g);
+        let bytes = CompactTables::<u16>::new(&g, &tables).unwrap().to_bytes(g.symbol_db());
+        let view = TableView::new(&bytes).unwrap();
+        assert_eq!(view.state_count(), tables.state_count());
+
+        let lp = view.symbol("(").unwrap();
+        let rp = view.symbol(")").unwrap();
+        let eoi = view.symbol("$").unwrap();
+        assert_eq!(Some(lp), g.symbol_db().symbol("("));
+        assert_eq!(view.label(&eoi), Some("$"));
+        assert_eq!(view.symbol("nope"), None);
+
+        let input = "(())";
+        let tokens: Vec<char> = input.chars().chain(std::iter::once('$')).collect();
+        let ttos = |c: &char| match c {
+            '(' => lp,
+            ')' => rp,
+            _ => eoi,
+        };
+        let tree = view.parse(&tokens, ttos).unwrap();
+        let parser = Parser::new(parens());
+        assert_eq!(tree, parser.parse(tokens.clone(), ttos).unwrap());
+
+        let error = view.parse(&tokens[1..], ttos).err().unwrap();
+        assert_eq!(error.position(), 2);
+    }
+
+    #[test]
+    fn table_view_02() {
+        let g = parens();
+        let tables = ParseTables::new(&g);
+        let bytes = CompactTables::<u32>::new(&g, &tables).unwrap().to_bytes(g.symbol_db());
+        assert_eq!(TableView::new(b"nope").err(), Some(TableFormatError::BadMagic));
+        for length in [4, 20, bytes.len() - 1] {
+            assert!(matches!(TableView::new(&bytes[..length]), Err(TableFormatError::Truncated { .. })));
+        }
+
+        // counts so big that the sizes worked out from them overflow
+        let mut oversized = bytes.clone();
+        oversized[8..28].fill(0xff);
+        assert_eq!(TableView::new(&oversized).err(), Some(TableFormatError::Invalid { offset: 8 }));
+        // and big enough to promise far more than there is, without overflowing
+        let mut oversized = bytes.clone();
+        oversized[8..12].fill(0xff);
+        assert!(matches!(TableView::new(&oversized), Err(TableFormatError::Truncated { .. })));
+    }
+
+    #[test]
+    fn table_view_03() {
+        let g = parens();
+        let tables = ParseTables::new(&g);
+        let bytes = CompactTables::<u32>::new(&g, &tables).unwrap().to_bytes(g.symbol_db());
+        let lp = g.symbol_db().symbol("(").unwrap();
+        let rp = g.symbol_db().symbol(")").unwrap();
+        let eoi = g.symbol_db().symbol("$").unwrap();
+        let tokens: Vec<char> = "(())$".chars().collect();
+        let ttos = |c: &char| match c {
+            '(' => lp,
+            ')' => rp,
+            _ => eoi,
+        };
+
+        // corrupt values are turned away by new or make parse fail, but never panic
+        for at in (8..bytes.len() - 3).step_by(4) {
+            let word = u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+            for value in [0, 1, word.wrapping_add(1), word.wrapping_sub(1), 0xffff, u32::MAX] {
+                let mut corrupt = bytes.clone();
+                corrupt[at..at + 4].copy_from_slice(&value.to_le_bytes());
+                if let Ok(view) = TableView::new(&corrupt) {
+                    let _ = view.parse(&tokens, ttos);
+                    let _ = view.label(&lp);
+                }
+            }
+        }
+
+        // the right hand sides swapped around, so that the empty one is read as taking three
+        // symbols when there are only two on the stack
+        let view = TableView::new(&bytes).unwrap();
+        let at = view.start + (view.rhs_offsets + 1) * 4;
+        let mut corrupt = bytes.clone();
+        corrupt[at..at + 4].copy_from_slice(&0u32.to_le_bytes());
+        let view = TableView::new(&corrupt).unwrap();
+        assert_eq!(view.production_rhs(1).map(|rhs| rhs.count()), Some(3));
+        assert!(view.production_rhs(view.production_count()).is_none());
+        assert_eq!(view.production_lhs(view.production_count()), None);
+        let error = view.parse(&tokens, ttos).err().unwrap();
+        assert!(matches!(error, ParseError::InternalError { position: 2, .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn format_version_01() {
+        let g = parens();
+        let tables = ParseTables::new(&g);
+        let bytes = CompactTables::<u16>::new(&g, &tables).unwrap().to_bytes(g.symbol_db());
+        assert_eq!(&bytes[..8], [b"LRTF".as_slice(), &FORMAT_VERSION.to_le_bytes()].concat());
+        assert_eq!(TableView::new(&bytes).unwrap().format_version(), FORMAT_VERSION);
+        assert_eq!(upgrade_format(&bytes).unwrap(), bytes);
+
+        // the first version had no version number, and neither it nor the second had the
+        // grammar's fingerprint
+        let legacy = [b"LRT1".as_slice(), &bytes[8..28], &bytes[36..]].concat();
+        let view = TableView::new(&legacy).unwrap();
+        assert_eq!(view.format_version(), 1);
+        assert_eq!(view.grammar_fingerprint(), None);
+        assert_eq!(view.symbol("("), g.symbol_db().symbol("("));
+        let mut unknown = bytes.clone();
+        unknown[28..36].fill(0);
+        assert_eq!(upgrade_format(&legacy).unwrap(), unknown);
+
+        let mut newer = bytes.clone();
+        newer[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let error = TableView::new(&newer).err().unwrap();
+        assert_eq!(error, TableFormatError::UnsupportedVersion { version: FORMAT_VERSION + 1 });
+        assert_eq!(error.to_string(), format!("serialized tables are in version {} of the format, which is newer than version {}", FORMAT_VERSION + 1, FORMAT_VERSION));
+        assert_eq!(upgrade_format(&newer).err(), Some(error));
+        assert_eq!(upgrade_format(b"nope").err(), Some(TableFormatError::BadMagic));
+    }
+
+    #[test]
+    fn embedded_01() {
+        let g = parens();
+        let tables = ParseTables::new(&g);
+        let bytes = CompactTables::<u16>::new(&g, &tables).unwrap().to_bytes(g.symbol_db());
+        assert_eq!(TableView::new(&bytes).unwrap().grammar_fingerprint(), Some(g.fingerprint()));
+
+        // copies of the bytes at an aligned address and one past it
+        let mut buffer = vec![0u8; bytes.len() + 8];
+        let at = (4 - buffer.as_ptr() as usize % 4) % 4;
+        buffer[at..at + bytes.len()].copy_from_slice(&bytes);
+        let view = TableView::embedded(&buffer[at..at + bytes.len()], &g).unwrap();
+        assert_eq!(view.state_count(), tables.state_count());
+        let mut buffer = vec![0u8; bytes.len() + 8];
+        let at = (4 - buffer.as_ptr() as usize % 4) % 4 + 1;
+        buffer[at..at + bytes.len()].copy_from_slice(&bytes);
+        assert_eq!(TableView::embedded(&buffer[at..at + bytes.len()], &g).err(), Some(TableFormatError::Misaligned));
+
+        // the same productions with the symbols declared in another order aren't the same grammar
+        let mut symbol_db = SymbolDb::new();
+        let rp = symbol_db.new_terminal(")");
+        let lp = symbol_db.new_terminal("(");
+        let e1 = symbol_db.new_nonterminal("E1");
+        let other = Grammar::new(symbol_db, e1, vec![Production::new(e1, vec![lp, e1, rp]), Production::new(e1, vec![])]);
+        assert_ne!(other.fingerprint(), g.fingerprint());
+        assert_eq!(parens().fingerprint(), g.fingerprint());
+        let mut buffer = vec![0u8; bytes.len() + 8];
+        let at = (4 - buffer.as_ptr() as usize % 4) % 4;
+        buffer[at..at + bytes.len()].copy_from_slice(&bytes);
+        let error = TableView::embedded(&buffer[at..at + bytes.len()], &other).err().unwrap();
+        assert!(matches!(error, TableFormatError::GrammarMismatch { .. }));
+
+        let cargo_toml: &[u8] = include_tables!(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"));
+        assert_eq!(cargo_toml.as_ptr() as usize % 4, 0);
+        assert_eq!(TableView::embedded(cargo_toml, &g).err(), Some(TableFormatError::BadMagic));
+    }
+}
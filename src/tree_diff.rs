@@ -0,0 +1,243 @@
+use std::fmt;
+use std::ops::Range;
+
+use super::parse_tree::ParseTree;
+use super::spanned::Spanned;
+use super::symbol::{Symbol,SymbolDb};
+
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum DifferenceKind {
+    // the node has a different symbol than expected
+    Symbol { expected: Symbol, actual: Symbol },
+    // the node has the expected symbol but a different number of children
+    ChildCount { expected: usize, actual: usize },
+}
+
+// the first node (in pre-order) where two trees diverge. the path is the index of the child taken
+// at each level on the way down from the root, so the root itself has an empty path
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct TreeDifference {
+    pub path: Vec<usize>,
+    pub kind: DifferenceKind,
+    // the bytes covered by the diverging node in each tree, when its tokens are spanned
+    pub expected_span: Option<Range<usize>>,
+    pub actual_span: Option<Range<usize>>,
+}
+
+impl TreeDifference {
+    pub fn to_string(&self, symbol_db: &SymbolDb) -> String {
+        let path: Vec<String> = self.path.iter().map(|i| i.to_string()).collect();
+        let mut result = format!("at [{}]", path.join(", "));
+        if let Some(span) = &self.actual_span {
+            result.push_str(&format!(" (bytes {}..{})", span.start, span.end));
+        }
+        match &self.kind {
+            DifferenceKind::Symbol { expected, actual } => {
+                let label = |s: &Symbol| symbol_db.label(s).cloned().unwrap_or_else(|| format!("{:?}", s));
+                result.push_str(&format!(": expected {}, found {}", label(expected), label(actual)));
+            },
+            DifferenceKind::ChildCount { expected, actual } => {
+                result.push_str(&format!(": expected {} children, found {}", expected, actual));
+            },
+        }
+        result
+    }
+}
+
+// a problem with the text of an expected tree
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum ExpectedTreeError {
+    // a label that isn't the label of any symbol
+    UnknownLabel { label: String, position: usize },
+    // a character that can't appear where it was found
+    UnexpectedCharacter { position: usize },
+    // the text ended before the tree did
+    UnexpectedEnd,
+}
+
+impl fmt::Display for ExpectedTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpectedTreeError::UnknownLabel { label, position } => {
+                write!(f, "unknown symbol {} at byte {}", label, position)
+            },
+            ExpectedTreeError::UnexpectedCharacter { position } => {
+                write!(f, "unexpected character at byte {}", position)
+            },
+            ExpectedTreeError::UnexpectedEnd => write!(f, "unexpected end of expected tree"),
+        }
+    }
+}
+
+impl<T> ParseTree<T> {
+    // compare this tree against the one it was expected to be
+    pub fn diff<U>(&self, expected: &ParseTree<U>) -> Option<TreeDifference> {
+        first_difference(expected, self, &mut Vec::new())
+            .map(|(path, kind, _, _)| TreeDifference { path, kind, expected_span: None, actual_span: None })
+    }
+
+    // compare this tree against one written in the bracketed form that display produces, e.g.
+    // e(e(x) + e(x)). a node is the label of its symbol, followed by its children in parentheses
+    // if it has any. a label that contains whitespace, parentheses or backslashes has to escape
+    // them with a backslash, e.g. \( for the terminal (
+    pub fn diff_expected(&self, expected: &str, symbol_db: &SymbolDb) -> Result<Option<TreeDifference>,ExpectedTreeError> {
+        Ok(self.diff(&parse_expected(expected, symbol_db)?))
+    }
+}
+
+impl<T: Spanned> ParseTree<T> {
+    // like diff, but also reports where the diverging node is in each input
+    pub fn diff_spanned<U: Spanned>(&self, expected: &ParseTree<U>) -> Option<TreeDifference> {
+        first_difference(expected, self, &mut Vec::new())
+            .map(|(path, kind, e, a)| TreeDifference { path, kind, expected_span: Some(e.span()), actual_span: Some(a.span()) })
+    }
+
+    // like diff_expected, but also reports where the diverging node is in the input
+    pub fn diff_expected_spanned(&self, expected: &str, symbol_db: &SymbolDb) -> Result<Option<TreeDifference>,ExpectedTreeError> {
+        let expected = parse_expected(expected, symbol_db)?;
+        Ok(first_difference(&expected, self, &mut Vec::new())
+            .map(|(path, kind, _, a)| TreeDifference { path, kind, expected_span: None, actual_span: Some(a.span()) }))
+    }
+}
+
+type Divergence<'a,T,U> = (Vec<usize>, DifferenceKind, &'a ParseTree<T>, &'a ParseTree<U>);
+
+fn first_difference<'a,T,U>(expected: &'a ParseTree<T>, actual: &'a ParseTree<U>, path: &mut Vec<usize>) -> Option<Divergence<'a,T,U>> {
+    if expected.symbol() != actual.symbol() {
+        let kind = DifferenceKind::Symbol { expected: *expected.symbol(), actual: *actual.symbol() };
+        return Some((path.clone(), kind, expected, actual));
+    }
+    if expected.children().len() != actual.children().len() {
+        let kind = DifferenceKind::ChildCount { expected: expected.children().len(), actual: actual.children().len() };
+        return Some((path.clone(), kind, expected, actual));
+    }
+    for (i, (e, a)) in expected.children().iter().zip(actual.children()).enumerate() {
+        path.push(i);
+        if let Some(d) = first_difference(e, a, path) {
+            return Some(d);
+        }
+        path.pop();
+    }
+    None
+}
+
+fn parse_expected(text: &str, symbol_db: &SymbolDb) -> Result<ParseTree<()>,ExpectedTreeError> {
+    let mut parser = ExpectedParser { text, position: 0, symbol_db };
+    let tree = parser.node()?;
+    parser.skip_whitespace();
+    if parser.position < text.len() {
+        return Err(ExpectedTreeError::UnexpectedCharacter { position: parser.position });
+    }
+    Ok(tree)
+}
+
+struct ExpectedParser<'a> {
+    text: &'a str,
+    position: usize,
+    symbol_db: &'a SymbolDb,
+}
+
+impl ExpectedParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.position += c.len_utf8();
+        }
+    }
+
+    fn node(&mut self) -> Result<ParseTree<()>,ExpectedTreeError> {
+        self.skip_whitespace();
+        let start = self.position;
+        let mut label = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || c == '(' || c == ')' {
+                break;
+            }
+            self.position += c.len_utf8();
+            if c == '\\' {
+                let escaped = self.peek().ok_or(ExpectedTreeError::UnexpectedEnd)?;
+                self.position += escaped.len_utf8();
+                label.push(escaped);
+            } else {
+                label.push(c);
+            }
+        }
+        if label.is_empty() {
+            return match self.peek() {
+                Some(_) => Err(ExpectedTreeError::UnexpectedCharacter { position: self.position }),
+                None => Err(ExpectedTreeError::UnexpectedEnd),
+            };
+        }
+        self.skip_whitespace();
+        // only a nonterminal has children, which settles a label shared by a terminal and a
+        // nonterminal
+        let has_children = self.peek() == Some('(');
+        let symbol = has_children.then(|| self.symbol_db.nonterminal(&label)).flatten()
+            .or_else(|| self.symbol_db.symbol(&label))
+            .ok_or(ExpectedTreeError::UnknownLabel { label, position: start })?;
+
+        let mut children = Vec::new();
+        if has_children {
+            self.position += 1;
+            loop {
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(')') => {
+                        self.position += 1;
+                        break;
+                    },
+                    Some(_) => children.push(self.node()?),
+                    None => return Err(ExpectedTreeError::UnexpectedEnd),
+                }
+            }
+        }
+        // a nonterminal without children can only have come from an ε production
+        let epsilon = children.is_empty() && !self.symbol_db.is_terminal(&symbol);
+        Ok(ParseTree::from_parts(symbol, (), children, epsilon, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tree_diff_01() {
+        let mut symbol_db = SymbolDb::new();
+        let e = symbol_db.new_nonterminal("e");
+        let plus = symbol_db.new_terminal("+");
+        let x = symbol_db.new_terminal("x");
+        let lp = symbol_db.new_terminal("(");
+
+        // e(e(x) + e(x)) over the input "x+x"
+        let mut left = ParseTree::new(e, 0..1);
+        left.add_child(ParseTree::new(x, 0..1));
+        let mut right = ParseTree::new(e, 2..3);
+        right.add_child(ParseTree::new(x, 2..3));
+        let mut tree = ParseTree::new(e, 0..3);
+        tree.add_child(left);
+        tree.add_child(ParseTree::new(plus, 1..2));
+        tree.add_child(right);
+
+        assert_eq!(tree.diff(&tree), None);
+        assert_eq!(tree.diff_expected(&tree.display(&symbol_db).to_string(), &symbol_db), Ok(None));
+        assert_eq!(tree.diff_expected("e(e(x) + e(\\())", &symbol_db).unwrap().unwrap().kind,
+                   DifferenceKind::Symbol { expected: lp, actual: x });
+
+        let difference = tree.diff_expected_spanned(" e( e(x) +  e ) ", &symbol_db).unwrap().unwrap();
+        assert_eq!(difference.path, vec![2]);
+        assert_eq!(difference.kind, DifferenceKind::ChildCount { expected: 0, actual: 1 });
+        assert_eq!(difference.actual_span, Some(2..3));
+        assert_eq!(difference.to_string(&symbol_db), "at [2] (bytes 2..3): expected 0 children, found 1");
+
+        let difference = tree.diff_expected("e(e(x) + x)", &symbol_db).unwrap().unwrap();
+        assert_eq!(difference.to_string(&symbol_db), "at [2]: expected x, found e");
+
+        assert_eq!(tree.diff_expected("e(e(y))", &symbol_db), Err(ExpectedTreeError::UnknownLabel { label: "y".to_string(), position: 4 }));
+        assert_eq!(tree.diff_expected("e(e(x)", &symbol_db), Err(ExpectedTreeError::UnexpectedEnd));
+        assert_eq!(tree.diff_expected("e) x", &symbol_db), Err(ExpectedTreeError::UnexpectedCharacter { position: 1 }));
+    }
+}
@@ -0,0 +1,230 @@
+// an end to end json parser built from the pieces of the library: the lexer and grammar from the
+// examples module, a parse, semantic actions that turn the parse tree into a typed value, and a
+// corpus run compared against a baseline
+
+use std::fs;
+use std::io;
+use std::path::{Path,PathBuf};
+
+use parser::examples::{json,Example,Token};
+use parser::{CorpusOutcome,CorpusReport,ParseTree};
+
+#[derive(Clone,Debug,PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String,Json)>),
+}
+
+// the value of a node of the parse tree. the tokens that carry no value (brackets, commas and
+// colons) are Punctuation
+enum Value {
+    Json(Json),
+    Member(String,Json),
+    Elements(Vec<Json>),
+    Members(Vec<(String,Json)>),
+    Punctuation,
+}
+
+impl Value {
+    fn json(self) -> Json {
+        match self {
+            Value::Json(json) => json,
+            _ => panic!("expected a json value"),
+        }
+    }
+}
+
+fn to_json(example: &Example, tree: &ParseTree<Token>) -> Json {
+    let symbol_db = example.grammar().symbol_db();
+    tree.fold(&mut |node: &ParseTree<Token>, values: Vec<Value>| {
+        let label = symbol_db.label(node.symbol()).unwrap().as_str();
+        let mut values = values.into_iter();
+        let mut next = || values.next().unwrap();
+        match label {
+            "null" => Value::Json(Json::Null),
+            "true" => Value::Json(Json::Bool(true)),
+            "false" => Value::Json(Json::Bool(false)),
+            "NUMBER" => Value::Json(Json::Number(node.token().text.parse().unwrap())),
+            "STRING" => Value::Json(Json::String(unescape(&node.token().text))),
+            // value -> object | array | STRING | ...
+            "value" => next(),
+            // object -> { } | { members }
+            "object" => match (next(), next()) {
+                (_, Value::Members(members)) => Value::Json(Json::Object(members)),
+                _ => Value::Json(Json::Object(Vec::new())),
+            },
+            // member -> STRING : value
+            "member" => match (next(), next(), next()) {
+                (Value::Json(Json::String(key)), _, value) => Value::Member(key, value.json()),
+                _ => unreachable!(),
+            },
+            // members -> member | members , member
+            "members" => match next() {
+                Value::Member(key, value) => Value::Members(vec![(key, value)]),
+                Value::Members(mut members) => {
+                    if let (_, Value::Member(key, value)) = (next(), next()) {
+                        members.push((key, value));
+                    }
+                    Value::Members(members)
+                },
+                _ => unreachable!(),
+            },
+            // array -> [ ] | [ elements ]
+            "array" => match (next(), next()) {
+                (_, Value::Elements(elements)) => Value::Json(Json::Array(elements)),
+                _ => Value::Json(Json::Array(Vec::new())),
+            },
+            // elements -> value | elements , value
+            "elements" => match next() {
+                Value::Elements(mut elements) => {
+                    next();
+                    elements.push(next().json());
+                    Value::Elements(elements)
+                },
+                value => Value::Elements(vec![value.json()]),
+            },
+            _ => Value::Punctuation,
+        }
+    }).json()
+}
+
+// the contents of a string token, without the quotes and with the escapes replaced
+fn unescape(token: &str) -> String {
+    let mut result = String::new();
+    let mut chars = token[1..token.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next().unwrap() {
+            'b' => result.push('\u{8}'),
+            'f' => result.push('\u{c}'),
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            't' => result.push('\t'),
+            'u' => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let code = u32::from_str_radix(&hex, 16).unwrap();
+                result.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+// json text for a value, which parses back to the same value
+fn render(json: &Json) -> String {
+    let string = |s: &str| {
+        let mut result = String::from("\"");
+        for c in s.chars() {
+            match c {
+                '"' => result.push_str("\\\""),
+                '\\' => result.push_str("\\\\"),
+                '\n' => result.push_str("\\n"),
+                c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+                c => result.push(c),
+            }
+        }
+        result.push('"');
+        result
+    };
+    match json {
+        Json::Null => "null".to_string(),
+        Json::Bool(b) => b.to_string(),
+        Json::Number(n) => n.to_string(),
+        Json::String(s) => string(s),
+        Json::Array(elements) => {
+            let elements: Vec<String> = elements.iter().map(render).collect();
+            format!("[{}]", elements.join(","))
+        },
+        Json::Object(members) => {
+            let members: Vec<String> = members.iter().map(|(k, v)| format!("{}:{}", string(k), render(v))).collect();
+            format!("{{{}}}", members.join(","))
+        },
+    }
+}
+
+fn parse(example: &Example, text: &str) -> Option<Json> {
+    match example.parse(text) {
+        Ok(Ok(tree)) => Some(to_json(example, &tree)),
+        _ => None,
+    }
+}
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/json/corpus")
+}
+
+#[test]
+fn json_corpus_01() {
+    let example = json();
+    // the example lexer works on whole strings, so read all of a file on the first call and hand
+    // out its tokens one at a time
+    let new_lexer = || {
+        let mut tokens: Option<std::vec::IntoIter<Token>> = None;
+        let example = &example;
+        move |reader: &mut dyn io::BufRead| -> io::Result<Option<Token>> {
+            if tokens.is_none() {
+                let mut text = String::new();
+                reader.read_to_string(&mut text)?;
+                let lexed = example.lex(&text).map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("unexpected character at {}", e.position))
+                })?;
+                tokens = Some(lexed.into_iter());
+            }
+            Ok(tokens.as_mut().unwrap().next())
+        }
+    };
+    let report = CorpusReport::run(example.parser(), &corpus_dir(), new_lexer, |t: &Token| t.symbol).unwrap();
+
+    for (name, outcome) in report.outcomes() {
+        assert_eq!(name.starts_with("pass/"), *outcome == CorpusOutcome::Parsed, "{}: {}", name, outcome);
+    }
+    let baseline = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/json/baseline.txt")).unwrap();
+    let changes = report.compare(&CorpusReport::from_baseline(&baseline).unwrap());
+    assert!(changes.is_empty(), "{}", changes);
+}
+
+#[test]
+fn json_ast_01() {
+    let example = json();
+    let text = fs::read_to_string(corpus_dir().join("pass/nested.json")).unwrap();
+    assert_eq!(parse(&example, &text), Some(Json::Object(vec![
+        ("name".to_string(), Json::String("parser".to_string())),
+        ("version".to_string(), Json::Array(vec![Json::Number(0.0), Json::Number(1.0), Json::Number(0.0)])),
+        ("tags".to_string(), Json::Array(vec![Json::String("lr".to_string()), Json::String("grammar".to_string())])),
+        ("meta".to_string(), Json::Object(vec![
+            ("stable".to_string(), Json::Bool(false)),
+            ("license".to_string(), Json::Null),
+            ("score".to_string(), Json::Number(-125.0)),
+        ])),
+    ])));
+
+    let text = fs::read_to_string(corpus_dir().join("pass/escapes.json")).unwrap();
+    assert_eq!(parse(&example, &text), Some(Json::Array(vec![
+        Json::String("tab\there".to_string()),
+        Json::String("quote \" and slash /".to_string()),
+        Json::String("line\nbreak".to_string()),
+        Json::String("été".to_string()),
+        Json::String("back\\slash".to_string()),
+    ])));
+    assert_eq!(parse(&example, r#""\u0041\u00e9""#), Some(Json::String("Aé".to_string())));
+}
+
+#[test]
+fn json_roundtrip_01() {
+    let example = json();
+    let mut files: Vec<PathBuf> = fs::read_dir(corpus_dir().join("pass")).unwrap().map(|e| e.unwrap().path()).collect();
+    files.sort();
+    for file in files {
+        let json = parse(&example, &fs::read_to_string(&file).unwrap()).unwrap();
+        let text = render(&json);
+        assert_eq!(parse(&example, &text).as_ref(), Some(&json), "{}", text);
+    }
+}